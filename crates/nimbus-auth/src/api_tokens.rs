@@ -0,0 +1,310 @@
+//! API tokens issued to collaborators
+//!
+//! A collaborator token is `nmbs_<id>.<secret>`: the id locates the stored
+//! record and only an argon2 hash of the secret is kept. Scopes are an upper
+//! bound; the collaborator's current grants always apply on top.
+//!
+//! Every API token also carries [`ApiScope`]s saying what kind of thing it
+//! may do at all. Owner tokens get theirs when created; owner tokens from
+//! before scopes existed count as `admin`, and collaborator tokens can read
+//! and write repositories.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use k8s_openapi::ByteString;
+use nimbus_types::access::Actor;
+use nimbus_types::{Permission, Repository};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::secrets::SecretLabels;
+use crate::{AuthError, AuthService};
+
+const TOKEN_PREFIX: &str = "nmbs_";
+
+/// Highest permission a token may use on one repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenScope {
+    pub repository_id: Uuid,
+    pub permission: Permission,
+}
+
+/// What kind of request an API token may make
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "repo:read")]
+    RepoRead,
+    /// Implies `repo:read`
+    #[serde(rename = "repo:write")]
+    RepoWrite,
+    #[serde(rename = "events:publish")]
+    EventsPublish,
+    /// Anything, including managing tokens
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] =
+        [ApiScope::RepoRead, ApiScope::RepoWrite, ApiScope::EventsPublish, ApiScope::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::RepoRead => "repo:read",
+            ApiScope::RepoWrite => "repo:write",
+            ApiScope::EventsPublish => "events:publish",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    /// Whether holding `self` allows what `wanted` does
+    pub fn covers(&self, wanted: ApiScope) -> bool {
+        *self == wanted
+            || *self == ApiScope::Admin
+            || (*self == ApiScope::RepoWrite && wanted == ApiScope::RepoRead)
+    }
+
+    /// Parse a comma separated list such as `repo:read,events:publish`
+    pub fn parse_list(list: &str) -> Result<Vec<ApiScope>, String> {
+        list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::parse).collect()
+    }
+
+    /// The inverse of [`Self::parse_list`]
+    pub fn join(scopes: &[ApiScope]) -> String {
+        scopes.iter().map(ApiScope::as_str).collect::<Vec<_>>().join(",")
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown token scope {:?}", s))
+    }
+}
+
+/// Whether any of `scopes` allows what `wanted` does
+pub fn scopes_cover(scopes: &[ApiScope], wanted: ApiScope) -> bool {
+    scopes.iter().any(|scope| scope.covers(wanted))
+}
+
+/// A newly created token; the plaintext is only available here
+#[derive(Debug, Clone)]
+pub struct IssuedToken {
+    pub id: Uuid,
+    pub token: String,
+}
+
+/// Who a verified API token acts as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenIdentity {
+    pub token_id: String,
+    pub actor: Actor,
+    /// Ignored for the owner, who is never restricted
+    pub scopes: Vec<TokenScope>,
+    /// What kind of request the token may make, for owner tokens too
+    pub api_scopes: Vec<ApiScope>,
+}
+
+impl TokenIdentity {
+    /// Whether the token's API scopes allow what `wanted` does
+    pub fn allows(&self, wanted: ApiScope) -> bool {
+        scopes_cover(&self.api_scopes, wanted)
+    }
+
+    /// Permission the token grants on `repo`
+    ///
+    /// The lower of the token's scope and what the actor currently holds, so
+    /// a token never outlives or exceeds the collaborator's grants. Owner
+    /// tokens without `admin` are capped by their `repo:` API scopes instead.
+    pub fn permission_on(&self, repo: &Repository) -> Option<Permission> {
+        let held = self.actor.permission_on(repo)?;
+        if self.actor == Actor::Owner {
+            return if self.allows(ApiScope::Admin) {
+                Some(held)
+            } else if self.allows(ApiScope::RepoWrite) {
+                Some(held.min(Permission::Write))
+            } else if self.allows(ApiScope::RepoRead) {
+                Some(held.min(Permission::Read))
+            } else {
+                None
+            };
+        }
+
+        let scoped = self
+            .scopes
+            .iter()
+            .find(|scope| scope.repository_id == repo.id)
+            .map(|scope| scope.permission)?;
+        Some(held.min(scoped))
+    }
+}
+
+/// Stored form of a collaborator token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CollaboratorTokenRecord {
+    pub name: String,
+    pub collaborator_id: Uuid,
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: u64,
+}
+
+/// API scopes of a stored owner token; `admin` for tokens from before scopes
+pub(crate) fn stored_api_scopes(data: &crate::secrets::SecretData) -> Vec<ApiScope> {
+    let Some(stored) = data.get("scopes") else {
+        return vec![ApiScope::Admin];
+    };
+    ApiScope::parse_list(&String::from_utf8_lossy(&stored.0)).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring unreadable token scopes: {}", e);
+        Vec::new()
+    })
+}
+
+fn token_secret_name(id: Uuid) -> String {
+    format!("nimbus-token-{}", id.simple())
+}
+
+/// Split `nmbs_<id>.<secret>` into its parts
+fn parse_collaborator_token(token: &str) -> Option<(Uuid, &str)> {
+    let (id, secret) = token.strip_prefix(TOKEN_PREFIX)?.split_once('.')?;
+    Some((Uuid::try_parse(id).ok()?, secret))
+}
+
+impl AuthService {
+    /// Issue an API token that acts as the collaborator, limited to `scopes`
+    pub async fn create_collaborator_token(
+        &self,
+        collaborator_id: Uuid,
+        name: &str,
+        scopes: Vec<TokenScope>,
+    ) -> Result<IssuedToken, AuthError> {
+        let id = Uuid::new_v4();
+        let secret = Uuid::new_v4().simple().to_string();
+        let token_hash = self
+            .hash_password(&secret)
+            .map_err(|e| AuthError::Backend(format!("Failed to hash token: {}", e)))?;
+
+        let record = CollaboratorTokenRecord {
+            name: name.to_string(),
+            collaborator_id,
+            token_hash,
+            scopes,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        };
+        self.store_collaborator_token(id, &record).await?;
+
+        Ok(IssuedToken { id, token: format!("{}{}.{}", TOKEN_PREFIX, id.simple(), secret) })
+    }
+
+    /// Resolve an API token to the identity it acts as
+    ///
+    /// Returns `Ok(None)` for unknown or mismatched tokens.
+    pub async fn verify_api_token(&self, token: &str) -> Result<Option<TokenIdentity>, AuthError> {
+        if let Some((id, secret)) = parse_collaborator_token(token) {
+            let Some(record) = self.load_collaborator_token(id).await? else {
+                return Ok(None);
+            };
+            let valid = self.verify_password(secret, &record.token_hash).unwrap_or(false);
+
+            return Ok(valid.then(|| TokenIdentity {
+                token_id: id.to_string(),
+                actor: Actor::Collaborator { id: record.collaborator_id },
+                scopes: record.scopes,
+                api_scopes: vec![ApiScope::RepoRead, ApiScope::RepoWrite],
+            }));
+        }
+
+        self.verify_owner_api_token(token).await
+    }
+
+    /// Owner tokens from `store_api_token` are compared against the stored value
+    async fn verify_owner_api_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<TokenIdentity>, AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            let local = self.local_owner_tokens.lock().unwrap();
+            return Ok(local.values().find(|stored| stored.token == token).map(|stored| {
+                TokenIdentity {
+                    token_id: stored.id.clone(),
+                    actor: Actor::Owner,
+                    scopes: Vec::new(),
+                    api_scopes: stored.scopes.clone(),
+                }
+            }));
+        };
+
+        let secrets = reader
+            .list("type=api-token", "verify API token")
+            .await
+            .map_err(AuthError::from_secret("Failed to list API tokens"))?;
+
+        Ok(secrets
+            .into_iter()
+            .find(|(_, data)| data.get("token").is_some_and(|stored| stored.0 == token.as_bytes()))
+            .map(|(secret_name, data)| TokenIdentity {
+                token_id: secret_name,
+                actor: Actor::Owner,
+                scopes: Vec::new(),
+                api_scopes: stored_api_scopes(&data),
+            }))
+    }
+
+    async fn store_collaborator_token(
+        &self,
+        id: Uuid,
+        record: &CollaboratorTokenRecord,
+    ) -> Result<(), AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            self.local_api_tokens.lock().unwrap().insert(id, record.clone());
+            return Ok(());
+        };
+
+        let json = serde_json::to_vec(record)
+            .map_err(|e| AuthError::Backend(format!("Failed to encode token: {}", e)))?;
+
+        let mut data = BTreeMap::new();
+        data.insert("record".to_string(), ByteString(json));
+        let mut labels = SecretLabels::new();
+        labels.insert("app".to_string(), "nimbus".to_string());
+        labels.insert("type".to_string(), "api-token".to_string());
+        labels.insert("collaborator".to_string(), record.collaborator_id.simple().to_string());
+
+        reader
+            .create(&token_secret_name(id), &labels, data, "store API token")
+            .await
+            .map_err(AuthError::from_secret("Failed to store API token"))
+    }
+
+    async fn load_collaborator_token(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<CollaboratorTokenRecord>, AuthError> {
+        if self.secret_reader.is_none() {
+            return Ok(self.local_api_tokens.lock().unwrap().get(&id).cloned());
+        }
+
+        let data = self
+            .read_secret(&token_secret_name(id), "verify API token", false)
+            .await
+            .map_err(AuthError::from_secret("Failed to read API token"))?;
+
+        let Some(record) = data.as_ref().and_then(|data| data.get("record")) else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&record.0)
+            .map(Some)
+            .map_err(|e| AuthError::Backend(format!("Malformed API token record: {}", e)))
+    }
+}
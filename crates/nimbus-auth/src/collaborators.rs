@@ -0,0 +1,298 @@
+//! Collaborator accounts
+//!
+//! Each collaborator lives in a `nimbus-collab-<username>` secret: the argon2
+//! `password_hash` checked at login, and the [`Collaborator`] itself as JSON
+//! under `record`. Only the owner registers and removes collaborators; there is no
+//! self-service sign-up. Collaborators manage their own SSH keys, which are
+//! kept in the record.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::ByteString;
+use nimbus_types::{Collaborator, SshKey};
+use uuid::Uuid;
+
+use crate::secrets::{SecretError, SecretLabels};
+use crate::ssh_keys::SshKeyRegistry;
+use crate::{AuthError, AuthService, MAX_CONFLICT_RETRIES, collaborator_secret_name};
+
+/// Stored form of a collaborator when running without Kubernetes
+#[derive(Debug, Clone)]
+pub(crate) struct CollaboratorRecord {
+    pub collaborator: Collaborator,
+    pub password_hash: String,
+}
+
+/// Check that `username` can name a collaborator
+///
+/// Usernames become part of a secret name, so they are limited to what
+/// Kubernetes allows there: lowercase letters, digits and `-`, starting and
+/// ending with a letter or digit.
+pub fn validate_username(username: &str) -> Result<(), AuthError> {
+    let invalid = |reason: &str| {
+        Err(AuthError::InvalidCollaborator(format!("Invalid username {:?}: {}", username, reason)))
+    };
+    if username.is_empty() || username.len() > 40 {
+        return invalid("must be 1 to 40 characters");
+    }
+    if !username.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return invalid("only lowercase letters, digits and '-' are allowed");
+    }
+    if username.starts_with('-') || username.ends_with('-') {
+        return invalid("must start and end with a letter or digit");
+    }
+    Ok(())
+}
+
+impl AuthService {
+    /// Create a collaborator who can log in with `password`
+    ///
+    /// Fails with [`AuthError::CollaboratorExists`] if the username is taken
+    /// and [`AuthError::WeakPassword`] if `password` breaks the policy.
+    pub async fn register_collaborator(
+        &self,
+        username: &str,
+        email: &str,
+        password: &str,
+    ) -> Result<Collaborator, AuthError> {
+        validate_username(username)?;
+        if !email.contains('@') {
+            return Err(AuthError::InvalidCollaborator(format!("Invalid email {:?}", email)));
+        }
+        self.check_password_strength(password)?;
+
+        let collaborator = Collaborator {
+            id: Uuid::new_v4(),
+            username: username.to_string(),
+            email: email.to_string(),
+            ssh_keys: vec![],
+            api_tokens: vec![],
+        };
+        let password_hash = self
+            .hash_password(password)
+            .map_err(|e| AuthError::Backend(format!("Failed to hash password: {}", e)))?;
+
+        let Some(reader) = &self.secret_reader else {
+            let mut local = self.local_collaborators.lock().unwrap();
+            if local.contains_key(username) {
+                return Err(AuthError::CollaboratorExists(username.to_string()));
+            }
+            local.insert(
+                username.to_string(),
+                CollaboratorRecord { collaborator: collaborator.clone(), password_hash },
+            );
+            return Ok(collaborator);
+        };
+
+        let record = serde_json::to_vec(&collaborator)
+            .map_err(|e| AuthError::Backend(format!("Failed to encode collaborator: {}", e)))?;
+        let mut data = BTreeMap::new();
+        data.insert("password_hash".to_string(), ByteString(password_hash.into_bytes()));
+        data.insert("record".to_string(), ByteString(record));
+        let mut labels = SecretLabels::new();
+        labels.insert("app".to_string(), "nimbus".to_string());
+        labels.insert("type".to_string(), "collaborator".to_string());
+
+        let secret_name = collaborator_secret_name(username);
+        match reader.create(&secret_name, &labels, data, "register collaborator").await {
+            Ok(()) => Ok(collaborator),
+            Err(SecretError::AlreadyExists(_)) => {
+                Err(AuthError::CollaboratorExists(username.to_string()))
+            }
+            Err(e) => Err(AuthError::from_secret(format!("Failed to create {}", secret_name))(e)),
+        }
+    }
+
+    /// The collaborator called `username`, if registered
+    pub async fn find_collaborator(
+        &self,
+        username: &str,
+    ) -> Result<Option<Collaborator>, AuthError> {
+        if self.secret_reader.is_none() {
+            let local = self.local_collaborators.lock().unwrap();
+            return Ok(local.get(username).map(|record| record.collaborator.clone()));
+        }
+
+        let data = self
+            .read_secret(&collaborator_secret_name(username), "load collaborator", false)
+            .await
+            .map_err(AuthError::from_secret("Failed to read collaborator"))?;
+        let Some(record) = data.as_ref().and_then(|data| data.get("record")) else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&record.0)
+            .map(Some)
+            .map_err(|e| AuthError::Backend(format!("Malformed collaborator record: {}", e)))
+    }
+
+    /// Every registered collaborator
+    pub async fn list_collaborators(&self) -> Result<Vec<Collaborator>, AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            let local = self.local_collaborators.lock().unwrap();
+            return Ok(local.values().map(|record| record.collaborator.clone()).collect());
+        };
+
+        let secrets = reader
+            .list("type=collaborator", "list collaborators")
+            .await
+            .map_err(AuthError::from_secret("Failed to list collaborators"))?;
+        secrets
+            .into_iter()
+            .filter_map(|(_, data)| data.get("record").cloned())
+            .map(|record| {
+                serde_json::from_slice(&record.0).map_err(|e| {
+                    AuthError::Backend(format!("Malformed collaborator record: {}", e))
+                })
+            })
+            .collect()
+    }
+
+    /// Delete collaborator `username`, returning the removed record
+    ///
+    /// Their password stops working at once. Access tokens already issued to
+    /// them stay valid until they expire.
+    pub async fn remove_collaborator(
+        &self,
+        username: &str,
+    ) -> Result<Option<Collaborator>, AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            let removed = self.local_collaborators.lock().unwrap().remove(username);
+            let removed = removed.map(|record| record.collaborator);
+            self.release_ssh_keys(removed.as_ref());
+            return Ok(removed);
+        };
+
+        let Some(collaborator) = self.find_collaborator(username).await? else {
+            return Ok(None);
+        };
+        let secret_name = collaborator_secret_name(username);
+        // Already gone if removed concurrently, which is just as good
+        reader
+            .delete(&secret_name, "remove collaborator")
+            .await
+            .map_err(AuthError::from_secret(format!("Failed to delete {}", secret_name)))?;
+        self.release_ssh_keys(Some(&collaborator));
+        Ok(Some(collaborator))
+    }
+
+    /// The collaborator an SSH key fingerprint belongs to, for SSH logins
+    pub async fn find_key_owner(&self, fingerprint: &str) -> Result<Option<Uuid>, AuthError> {
+        Ok(self.ssh_key_registry().await?.find_key_owner(fingerprint))
+    }
+
+    /// Add `key` to collaborator `username`
+    ///
+    /// Fails with [`AuthError::SshKey`] if any collaborator already has a
+    /// key with the same fingerprint.
+    pub async fn add_ssh_key(&self, username: &str, key: SshKey) -> Result<SshKey, AuthError> {
+        let registry = self.ssh_key_registry().await?;
+        let collaborator = self.require_collaborator(username).await?;
+        // Claimed before saving, so a concurrent add of the same key fails here
+        registry.claim(&key.fingerprint, collaborator.id)?;
+        let added = self
+            .update_collaborator(username, "add SSH key", |collaborator| {
+                collaborator.ssh_keys.push(key.clone());
+            })
+            .await;
+        if let Err(e) = added {
+            registry.release(&key.fingerprint);
+            return Err(e);
+        }
+        Ok(key)
+    }
+
+    /// Remove key `key_id` from collaborator `username`, returning it
+    pub async fn remove_ssh_key(
+        &self,
+        username: &str,
+        key_id: Uuid,
+    ) -> Result<Option<SshKey>, AuthError> {
+        let registry = self.ssh_key_registry().await?;
+        let removed = self
+            .update_collaborator(username, "remove SSH key", |collaborator| {
+                let position = collaborator.ssh_keys.iter().position(|key| key.id == key_id)?;
+                Some(collaborator.ssh_keys.remove(position))
+            })
+            .await?;
+        if let Some(key) = &removed {
+            registry.release(&key.fingerprint);
+        }
+        Ok(removed)
+    }
+
+    /// The SSH key index, built from every collaborator the first time it's needed
+    async fn ssh_key_registry(&self) -> Result<&SshKeyRegistry, AuthError> {
+        self.ssh_keys
+            .get_or_try_init(|| async {
+                let collaborators = self.list_collaborators().await?;
+                Ok(SshKeyRegistry::from_collaborators(&collaborators)?)
+            })
+            .await
+    }
+
+    /// Free the fingerprints of a removed collaborator's keys
+    fn release_ssh_keys(&self, removed: Option<&Collaborator>) {
+        if let (Some(registry), Some(collaborator)) = (self.ssh_keys.get(), removed) {
+            for key in &collaborator.ssh_keys {
+                registry.release(&key.fingerprint);
+            }
+        }
+    }
+
+    async fn require_collaborator(&self, username: &str) -> Result<Collaborator, AuthError> {
+        self.find_collaborator(username).await?.ok_or_else(|| {
+            AuthError::InvalidCollaborator(format!("No collaborator named {}", username))
+        })
+    }
+
+    /// Apply `change` to the stored record of collaborator `username`
+    ///
+    /// The record is written only if it is unchanged since it was read;
+    /// otherwise it is read again and `change` reapplied, so concurrent
+    /// updates aren't lost.
+    async fn update_collaborator<T>(
+        &self,
+        username: &str,
+        reason: &str,
+        mut change: impl FnMut(&mut Collaborator) -> T,
+    ) -> Result<T, AuthError> {
+        let missing =
+            || AuthError::InvalidCollaborator(format!("No collaborator named {}", username));
+        let Some(reader) = &self.secret_reader else {
+            let mut local = self.local_collaborators.lock().unwrap();
+            let record = local.get_mut(username).ok_or_else(missing)?;
+            return Ok(change(&mut record.collaborator));
+        };
+
+        let secret_name = collaborator_secret_name(username);
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let Some((data, version)) = reader
+                .read_versioned(&secret_name, reason)
+                .await
+                .map_err(AuthError::from_secret(format!("Failed to read {}", secret_name)))?
+            else {
+                return Err(missing());
+            };
+            let record = data.get("record").ok_or_else(missing)?;
+            let mut collaborator: Collaborator = serde_json::from_slice(&record.0)
+                .map_err(|e| AuthError::Backend(format!("Malformed collaborator record: {}", e)))?;
+            let result = change(&mut collaborator);
+
+            let record = serde_json::to_vec(&collaborator)
+                .map_err(|e| AuthError::Backend(format!("Failed to encode collaborator: {}", e)))?;
+            let set = BTreeMap::from([("record".to_string(), ByteString(record))]);
+            match reader.put_if(&secret_name, set, &[], &version, reason).await {
+                Ok(()) => return Ok(result),
+                // Written since we read it; apply the change to the new record
+                Err(SecretError::Conflict(_)) => continue,
+                Err(e) => {
+                    return Err(AuthError::from_secret(format!(
+                        "Failed to update {}",
+                        secret_name
+                    ))(e));
+                }
+            }
+        }
+        Err(AuthError::Backend(format!("{} kept changing, try again", secret_name)))
+    }
+}
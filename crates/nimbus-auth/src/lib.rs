@@ -1,40 +1,387 @@
 //! Authentication for Nimbus
 //!
-//! Uses Kubernetes secrets for stateless auth management
+//! Keeps credentials in a [`secrets::SecretStore`], Kubernetes secrets by
+//! default, for stateless auth management
 
 use argon2::password_hash::{SaltString, rand_core::OsRng};
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::{Algorithm, Argon2, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use k8s_openapi::api::core::v1::Secret;
-use kube::{Api, Client};
+use jsonwebtoken::{
+    Algorithm as JwtAlgorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode,
+};
+use k8s_openapi::ByteString;
+use kube::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+pub mod api_tokens;
+pub mod collaborators;
+pub mod password_policy;
+pub mod secrets;
+pub mod ssh_keys;
+
+use password_policy::{PasswordPolicy, PasswordPolicyError};
+use secrets::{
+    KubeSecretStore, MemorySecretStore, SecretAccessPolicy, SecretError, SecretLabels,
+    SecretReader, SecretStore, SecretStoreKind,
+};
+
+const OWNER_SECRET: &str = "nimbus-owner";
+
+const REFRESH_TOKENS_SECRET: &str = "nimbus-refresh-tokens";
+
+const REVOKED_TOKENS_SECRET: &str = "nimbus-revoked-tokens";
+
+/// Default lifetime of access tokens, overridable with `NIMBUS_TOKEN_TTL_SECS`
+const DEFAULT_ACCESS_TOKEN_TTL_SECS: u64 = 86400; // 24 hours
+/// Lifetime of refresh tokens
+const REFRESH_TOKEN_TTL_SECS: usize = 30 * 86400; // 30 days
+
+/// Conditional secret writes tried before giving up on a busy secret
+const MAX_CONFLICT_RETRIES: usize = 5;
+
+/// Argon2 cost for new password and token hashes
+///
+/// Defaults to the `argon2` crate's; override with `NIMBUS_ARGON2_MCOST`
+/// (memory in KiB), `NIMBUS_ARGON2_TCOST` (iterations) and
+/// `NIMBUS_ARGON2_PCOST` (parallelism). Existing hashes keep verifying after
+/// a change, as each records the parameters it was made with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            m_cost: argon2::Params::DEFAULT_M_COST,
+            t_cost: argon2::Params::DEFAULT_T_COST,
+            p_cost: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Read the costs from the environment, keeping defaults for unset ones
+    ///
+    /// Invalid values, or a combination `argon2` rejects, fall back to the
+    /// defaults with a warning.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let cost = |var: &str, default: u32| match std::env::var(var) {
+            Ok(value) => value.parse::<u32>().unwrap_or_else(|_| {
+                warn!("Invalid {} {:?}, using default", var, value);
+                default
+            }),
+            Err(_) => default,
+        };
+        let params = Self {
+            m_cost: cost("NIMBUS_ARGON2_MCOST", defaults.m_cost),
+            t_cost: cost("NIMBUS_ARGON2_TCOST", defaults.t_cost),
+            p_cost: cost("NIMBUS_ARGON2_PCOST", defaults.p_cost),
+        };
+        match params.to_params() {
+            Ok(_) => params,
+            Err(e) => {
+                warn!("Invalid Argon2 parameters {:?} ({}), using defaults", params, e);
+                defaults
+            }
+        }
+    }
+
+    fn to_params(self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, None)
+    }
+}
+
+/// How access and refresh tokens are signed and verified
+///
+/// HS256 with the shared secret (see [`JwtSigning::hs256_secret`]) unless an
+/// RSA key pair is configured,
+/// either as PEM in `NIMBUS_JWT_PRIVATE_KEY` and `NIMBUS_JWT_PUBLIC_KEY` or
+/// as `private.pem` and `public.pem` in the `nimbus-jwt-keys` secret. With
+/// RS256, other services can verify tokens holding only the public key.
+#[derive(Clone)]
+pub enum JwtSigning {
+    Hs256(String),
+    Rs256 { encoding: EncodingKey, decoding: DecodingKey },
+}
+
+impl JwtSigning {
+    /// RS256 from PEM encoded keys, PKCS#1 or PKCS#8
+    pub fn rs256_from_pem(
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, jsonwebtoken::errors::Error> {
+        Ok(Self::Rs256 {
+            encoding: EncodingKey::from_rsa_pem(private_pem)?,
+            decoding: DecodingKey::from_rsa_pem(public_pem)?,
+        })
+    }
+
+    pub fn algorithm(&self) -> JwtAlgorithm {
+        match self {
+            Self::Hs256(_) => JwtAlgorithm::HS256,
+            Self::Rs256 { .. } => JwtAlgorithm::RS256,
+        }
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        match self {
+            Self::Hs256(secret) => EncodingKey::from_secret(secret.as_bytes()),
+            Self::Rs256 { encoding, .. } => encoding.clone(),
+        }
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        match self {
+            Self::Hs256(secret) => DecodingKey::from_secret(secret.as_bytes()),
+            Self::Rs256 { decoding, .. } => decoding.clone(),
+        }
+    }
+
+    /// The PEM key pair from the environment, else the HS256 secret
+    ///
+    /// Fails if `JWT_SECRET_FILE` is set but can't be used, rather than
+    /// signing with a secret the operator didn't choose.
+    fn from_env() -> Result<Self, JwtSecretError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, JwtSecretError> {
+        match (var("NIMBUS_JWT_PRIVATE_KEY"), var("NIMBUS_JWT_PUBLIC_KEY")) {
+            (Some(private_pem), Some(public_pem)) => {
+                match Self::rs256_from_pem(private_pem.as_bytes(), public_pem.as_bytes()) {
+                    Ok(signing) => return Ok(signing),
+                    Err(e) => warn!("Invalid JWT key pair, falling back to HS256: {}", e),
+                }
+            }
+            (None, None) => {}
+            _ => warn!(
+                "NIMBUS_JWT_PRIVATE_KEY and NIMBUS_JWT_PUBLIC_KEY must be set together, \
+                 falling back to HS256"
+            ),
+        }
+        Self::hs256_secret(var).map(Self::Hs256)
+    }
+
+    /// The HS256 secret, from the first of these that is set:
+    ///
+    /// 1. `JWT_SECRET_FILE`, a file holding the secret, as mounted secrets
+    ///    are; a trailing newline is ignored
+    /// 2. `JWT_SECRET`, the secret itself
+    /// 3. a development default
+    fn hs256_secret(var: impl Fn(&str) -> Option<String>) -> Result<String, JwtSecretError> {
+        if let Some(path) = var("JWT_SECRET_FILE") {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|source| JwtSecretError::Unreadable { path: path.clone(), source })?;
+            let secret = contents.trim_end_matches(['\n', '\r']);
+            if secret.is_empty() {
+                return Err(JwtSecretError::Empty { path });
+            }
+            return Ok(secret.to_string());
+        }
+        Ok(var("JWT_SECRET")
+            .unwrap_or_else(|| "development-secret-change-in-production".to_string()))
+    }
+}
+
+/// Why `JWT_SECRET_FILE` can't be used
+#[derive(Debug, thiserror::Error)]
+pub enum JwtSecretError {
+    #[error("Failed to read JWT_SECRET_FILE {path}: {source}")]
+    Unreadable { path: String, source: std::io::Error },
+
+    #[error("JWT_SECRET_FILE {path} is empty")]
+    Empty { path: String },
+}
+
 #[derive(Clone)]
 pub struct AuthService {
-    jwt_secret: String,
-    kube_client: Option<Client>,
+    jwt_signing: JwtSigning,
+    /// All secret reads and writes go through here (absent in dev mode)
+    secret_reader: Option<Arc<SecretReader>>,
     namespace: String,
+    /// Instance domain used as the JWT issuer and audience
+    instance_domain: String,
+    /// How long access tokens stay valid
+    access_token_ttl: Duration,
+    /// Cost of new password and token hashes
+    argon2_params: Argon2Params,
+    /// Rules new passwords must satisfy
+    password_policy: PasswordPolicy,
+    /// Outstanding refresh token ids when running without Kubernetes
+    local_refresh_tokens: Arc<Mutex<HashSet<String>>>,
+    /// Expiry of access tokens revoked by logout, by id, persisted to K8s when available
+    revoked_tokens: Arc<Mutex<HashMap<String, usize>>>,
+    /// Collaborator API tokens when running without Kubernetes
+    local_api_tokens: Arc<Mutex<HashMap<Uuid, api_tokens::CollaboratorTokenRecord>>>,
+    /// Owner API tokens by id when running without Kubernetes
+    local_owner_tokens: Arc<Mutex<HashMap<String, ApiToken>>>,
+    /// Collaborators by username when running without Kubernetes
+    local_collaborators: Arc<Mutex<HashMap<String, collaborators::CollaboratorRecord>>>,
+    /// Owners of SSH key fingerprints, built from the collaborators on first use
+    ssh_keys: Arc<tokio::sync::OnceCell<ssh_keys::SshKeyRegistry>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+
+    #[error("Expected a {expected} token")]
+    WrongTokenType { expected: TokenType },
+
+    #[error("Refresh token has been revoked or already used")]
+    RefreshTokenRevoked,
+
+    #[error("{0}")]
+    InvalidCollaborator(String),
+
+    #[error("Collaborator {0} already exists")]
+    CollaboratorExists(String),
+
+    #[error(transparent)]
+    WeakPassword(#[from] PasswordPolicyError),
+
+    #[error(transparent)]
+    SshKey(#[from] ssh_keys::SshKeyError),
+
+    #[error("API token {0} already exists")]
+    TokenExists(String),
+
+    #[error("Secret store error: {0}")]
+    Backend(String),
+
+    /// The secret store kept failing; worth retrying later
+    #[error("Secret store temporarily unavailable")]
+    BackendUnavailable,
+}
+
+impl AuthError {
+    /// Map a secret store failure, keeping unavailability distinct
+    pub(crate) fn from_secret(context: impl std::fmt::Display) -> impl FnOnce(SecretError) -> Self {
+        move |e| match e {
+            SecretError::Unavailable(_) => AuthError::BackendUnavailable,
+            e => AuthError::Backend(format!("{}: {}", context, e)),
+        }
+    }
 }
 
 impl std::fmt::Debug for AuthService {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AuthService")
             .field("namespace", &self.namespace)
-            .field("has_kube_client", &self.kube_client.is_some())
+            .field("instance_domain", &self.instance_domain)
+            .field("jwt_algorithm", &self.jwt_signing.algorithm())
+            .field("has_secret_store", &self.secret_reader.is_some())
             .finish()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,  // Subject (user ID)
     pub exp: usize,   // Expiry time
     pub iat: usize,   // Issued at
-    pub role: String, // User role (owner, viewer)
+    pub role: String, // User role (owner, collaborator)
+    pub iss: String,  // Issuer (instance domain)
+    pub aud: String,  // Audience (instance domain)
+    #[serde(default)]
+    pub token_type: TokenType,
+    /// Unique token id, used to track refresh tokens and revoke access tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+/// Access tokens authorize requests, refresh tokens only mint access tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    #[default]
+    Access,
+    Refresh,
+}
+
+impl std::fmt::Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenType::Access => f.write_str("access"),
+            TokenType::Refresh => f.write_str("refresh"),
+        }
+    }
+}
+
+impl Claims {
+    /// Parse the `role` claim, `None` if it isn't a known role
+    pub fn parsed_role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+}
+
+/// Who a successfully authenticated user is on this instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Collaborator,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Collaborator => "collaborator",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owner" => Ok(Role::Owner),
+            "collaborator" => Ok(Role::Collaborator),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+/// Name of the K8s secret holding a collaborator's credentials
+pub fn collaborator_secret_name(username: &str) -> String {
+    format!("nimbus-collab-{}", username.to_lowercase())
+}
+
+/// Seconds since the Unix epoch, as used in JWT claims
+fn unix_now() -> usize {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs() as usize
+}
+
+/// Expiry stored for a revoked token id, `None` if it is unreadable
+fn revoked_expiry(value: &ByteString) -> Option<usize> {
+    std::str::from_utf8(&value.0).ok()?.parse().ok()
+}
+
+/// Outcome of checking credentials against the owner secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnerLogin {
+    Valid,
+    Invalid,
+    /// Username matches but no password has been set yet
+    FirstLogin,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,126 +390,384 @@ pub struct LoginRequest {
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiToken {
     pub id: String,
     pub name: String,
     pub token: String,
     pub created_at: usize,
     pub expires_at: Option<usize>,
+    /// What the token may do, see [`api_tokens::ApiScope`]
+    pub scopes: Vec<api_tokens::ApiScope>,
 }
 
 impl AuthService {
-    pub async fn new() -> Self {
-        // Try to create Kubernetes client (will fail in local dev)
-        let kube_client = Client::try_default().await.ok();
+    /// Create the service on the secret store picked by `NIMBUS_SECRET_STORE`
+    ///
+    /// This is the constructor to use from async code. With the default
+    /// Kubernetes store it falls back to [`AuthService::try_new_local`]
+    /// behaviour when no cluster is reachable. Fails if `JWT_SECRET_FILE` is
+    /// set but can't be used.
+    pub async fn new() -> Result<Self, JwtSecretError> {
+        let service = Self::try_new_local()?;
+
+        let store: Arc<dyn SecretStore> = match SecretStoreKind::from_env() {
+            SecretStoreKind::Kubernetes => {
+                // Try to create Kubernetes client (will fail in local dev)
+                let Ok(client) = Client::try_default().await else {
+                    return Ok(service);
+                };
+                Arc::new(KubeSecretStore::new(client, &service.namespace))
+            }
+            SecretStoreKind::Memory => {
+                warn!("Keeping secrets in memory; they are lost on restart");
+                Arc::new(MemorySecretStore::new())
+            }
+        };
+        let mut service = service.with_secret_store(store);
+        let Some(reader) = service.secret_reader.clone() else {
+            return Ok(service);
+        };
 
+        // A key pair in K8s wins, then one from the environment, then the K8s
+        // secret, then the env/default secret
+        match Self::load_jwt_keys(&reader).await {
+            Ok(Some(signing)) => service.jwt_signing = signing,
+            Ok(None) if matches!(service.jwt_signing, JwtSigning::Rs256 { .. }) => {}
+            Ok(None) => {
+                if let Ok(jwt_secret) = Self::load_jwt_secret(&reader).await {
+                    service.jwt_signing = JwtSigning::Hs256(jwt_secret);
+                }
+            }
+            Err(e) => warn!("Ignoring JWT signing keys: {}", e),
+        }
+        // Logged out tokens must stay rejected across restarts
+        match reader.read(REVOKED_TOKENS_SECRET, "load revoked tokens").await {
+            Ok(Some(data)) => service.revoked_tokens.lock().unwrap().extend(
+                data.iter().filter_map(|(jti, exp)| Some((jti.clone(), revoked_expiry(exp)?))),
+            ),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to load revoked tokens: {}", e),
+        }
+        Ok(service)
+    }
+
+    /// Create a dev-mode service without contacting Kubernetes
+    ///
+    /// Configuration still comes from the environment, but secrets only use
+    /// the local fallbacks. Safe to call from inside a Tokio runtime. If
+    /// `JWT_SECRET_FILE` can't be used the error is logged and tokens are
+    /// signed with a random secret; see [`AuthService::try_new_local`].
+    pub fn new_local() -> Self {
+        let jwt_signing = JwtSigning::from_env().unwrap_or_else(|e| {
+            error!("{}; signing tokens with a random secret", e);
+            JwtSigning::Hs256(format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()))
+        });
+        Self::local(jwt_signing)
+    }
+
+    /// Like [`AuthService::new_local`], but failing if `JWT_SECRET_FILE` is
+    /// set and can't be used
+    pub fn try_new_local() -> Result<Self, JwtSecretError> {
+        Ok(Self::local(JwtSigning::from_env()?))
+    }
+
+    fn local(jwt_signing: JwtSigning) -> Self {
         // Get namespace from env or default
         let namespace = std::env::var("NIMBUS_NAMESPACE").unwrap_or_else(|_| "nimbus".to_string());
 
-        // Try to load JWT secret from K8s, fallback to env/default
-        let jwt_secret = if let Some(client) = &kube_client {
-            Self::load_jwt_secret(client, &namespace)
-                .await
-                .unwrap_or_else(|_| Self::default_jwt_secret())
-        } else {
-            Self::default_jwt_secret()
+        // Tokens are only valid for the instance that minted them
+        let instance_domain =
+            std::env::var("NIMBUS_INSTANCE_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
+
+        Self {
+            jwt_signing,
+            secret_reader: None,
+            namespace,
+            instance_domain,
+            access_token_ttl: Self::access_token_ttl_from_env(),
+            argon2_params: Argon2Params::from_env(),
+            password_policy: PasswordPolicy::from_env(),
+            local_refresh_tokens: Arc::new(Mutex::new(HashSet::new())),
+            revoked_tokens: Arc::new(Mutex::new(HashMap::new())),
+            local_api_tokens: Arc::new(Mutex::new(HashMap::new())),
+            local_owner_tokens: Arc::new(Mutex::new(HashMap::new())),
+            local_collaborators: Arc::new(Mutex::new(HashMap::new())),
+            ssh_keys: Arc::new(tokio::sync::OnceCell::new()),
+        }
+    }
+
+    /// Keep secrets in `store` instead of the dev-mode fallbacks
+    ///
+    /// Unlike [`AuthService::new`] this loads no signing keys or revoked
+    /// tokens from the store.
+    pub fn with_secret_store(mut self, store: Arc<dyn SecretStore>) -> Self {
+        self.secret_reader =
+            Some(Arc::new(SecretReader::new(store, SecretAccessPolicy::default())));
+        // Keys are indexed from the new store's collaborators
+        self.ssh_keys = Arc::new(tokio::sync::OnceCell::new());
+        self
+    }
+
+    /// Override the instance domain tokens are issued for and accepted from
+    pub fn with_instance_domain(mut self, domain: impl Into<String>) -> Self {
+        self.instance_domain = domain.into();
+        self
+    }
+
+    /// Override the access token lifetime
+    pub fn with_token_ttl(mut self, ttl: Duration) -> Self {
+        self.access_token_ttl = ttl;
+        self
+    }
+
+    /// Override how tokens are signed, e.g. to use RS256 without env vars
+    pub fn with_jwt_signing(mut self, signing: JwtSigning) -> Self {
+        self.jwt_signing = signing;
+        self
+    }
+
+    /// Override the cost of new password and token hashes
+    pub fn with_argon2_params(mut self, params: Argon2Params) -> Self {
+        self.argon2_params = params;
+        self
+    }
+
+    /// Override the rules new passwords must satisfy
+    pub fn with_password_policy(mut self, policy: PasswordPolicy) -> Self {
+        self.password_policy = policy;
+        self
+    }
+
+    fn access_token_ttl_from_env() -> Duration {
+        let secs = match std::env::var("NIMBUS_TOKEN_TTL_SECS") {
+            Ok(value) => value.parse::<u64>().unwrap_or_else(|_| {
+                warn!("Invalid NIMBUS_TOKEN_TTL_SECS {:?}, using default", value);
+                DEFAULT_ACCESS_TOKEN_TTL_SECS
+            }),
+            Err(_) => DEFAULT_ACCESS_TOKEN_TTL_SECS,
         };
+        Duration::from_secs(secs)
+    }
 
-        Self { jwt_secret, kube_client, namespace }
+    /// RS256 keys from the `nimbus-jwt-keys` secret, `None` if there isn't one
+    async fn load_jwt_keys(reader: &SecretReader) -> Result<Option<JwtSigning>, String> {
+        let Some(data) = reader
+            .read("nimbus-jwt-keys", "load JWT signing keys")
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(None);
+        };
+        let (Some(private_pem), Some(public_pem)) =
+            (data.get("private.pem"), data.get("public.pem"))
+        else {
+            return Err("nimbus-jwt-keys needs both private.pem and public.pem".to_string());
+        };
+        JwtSigning::rs256_from_pem(&private_pem.0, &public_pem.0)
+            .map(Some)
+            .map_err(|e| e.to_string())
     }
 
-    fn default_jwt_secret() -> String {
-        std::env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "development-secret-change-in-production".to_string())
+    async fn load_jwt_secret(reader: &SecretReader) -> Result<String, String> {
+        let data = reader
+            .read("nimbus-jwt-secret", "load JWT signing secret")
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "JWT secret not found".to_string())?;
+
+        let secret_bytes =
+            data.get("secret").ok_or_else(|| "JWT secret not found in secret data".to_string())?;
+        let decoded = BASE64
+            .decode(&secret_bytes.0)
+            .map_err(|e| format!("Failed to decode secret: {}", e))?;
+        Ok(String::from_utf8_lossy(&decoded).to_string())
     }
 
-    async fn load_jwt_secret(client: &Client, namespace: &str) -> Result<String, kube::Error> {
-        let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
-        let secret = secrets.get("nimbus-jwt-secret").await?;
+    /// Validate credentials against the owner first, then collaborators
+    ///
+    /// Returns the role to mint the JWT with, or `None` for bad credentials.
+    pub async fn validate_login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Role>, AuthError> {
+        if self.validate_owner_login(username, password).await? {
+            return Ok(Some(Role::Owner));
+        }
 
-        if let Some(data) = secret.data {
-            if let Some(secret_bytes) = data.get("secret") {
-                let decoded = BASE64.decode(&secret_bytes.0).map_err(|e| {
-                    kube::Error::Api(kube::error::ErrorResponse {
-                        status: "400".to_string(),
-                        message: format!("Failed to decode secret: {}", e),
-                        reason: "BadRequest".to_string(),
-                        code: 400,
-                    })
-                })?;
-                return Ok(String::from_utf8_lossy(&decoded).to_string());
-            }
+        if self.validate_collaborator_login(username, password).await? {
+            return Ok(Some(Role::Collaborator));
         }
 
-        Err(kube::Error::Api(kube::error::ErrorResponse {
-            status: "404".to_string(),
-            message: "JWT secret not found in secret data".to_string(),
-            reason: "NotFound".to_string(),
-            code: 404,
-        }))
+        Ok(None)
+    }
+
+    pub async fn validate_collaborator_login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<bool, AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            // Local development only knows collaborators registered since start
+            let hash = self
+                .local_collaborators
+                .lock()
+                .unwrap()
+                .get(username)
+                .map(|record| record.password_hash.clone());
+            return match hash {
+                Some(hash) => self.verify_password(password, &hash).map_err(|e| {
+                    AuthError::Backend(format!("Password verification failed: {}", e))
+                }),
+                None => Ok(false),
+            };
+        };
+
+        let data = reader
+            .read(&collaborator_secret_name(username), "collaborator login")
+            .await
+            .map_err(AuthError::from_secret("Failed to access collaborator secret"))?;
+        let Some(data) = data else {
+            return Ok(false);
+        };
+
+        match data.get("password_hash") {
+            Some(stored_hash) if !stored_hash.0.is_empty() => {
+                let hash_str = String::from_utf8_lossy(&stored_hash.0);
+                self.verify_password(password, &hash_str)
+                    .map_err(|e| AuthError::Backend(format!("Password verification failed: {}", e)))
+            }
+            // Collaborators never get a first-login bypass
+            _ => Ok(false),
+        }
     }
 
     pub async fn validate_owner_login(
         &self,
         username: &str,
         password: &str,
-    ) -> Result<bool, String> {
+    ) -> Result<bool, AuthError> {
         // In production, check against K8s secret
-        if let Some(client) = &self.kube_client {
-            let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.namespace);
-
-            match secrets.get("nimbus-owner").await {
-                Ok(secret) => {
-                    if let Some(data) = secret.data {
-                        // Check username
-                        if let Some(stored_username) = data.get("username") {
-                            let decoded_username = BASE64
-                                .decode(&stored_username.0)
-                                .map_err(|e| format!("Failed to decode username: {}", e))?;
-                            let stored_username_str = String::from_utf8_lossy(&decoded_username);
-
-                            if stored_username_str != username {
-                                return Ok(false);
-                            }
-                        }
+        if let Some(reader) = &self.secret_reader {
+            let Some((data, version)) = reader
+                .read_versioned(OWNER_SECRET, "owner login")
+                .await
+                .map_err(AuthError::from_secret("Failed to access owner secret"))?
+            else {
+                // No owner configured (e.g. a fresh memory store): nobody is the
+                // owner, but collaborators can still log in
+                return Ok(false);
+            };
 
-                        // Check password hash
-                        if let Some(stored_hash) = data.get("password_hash") {
-                            let decoded_hash = BASE64
-                                .decode(&stored_hash.0)
-                                .map_err(|e| format!("Failed to decode password hash: {}", e))?;
-                            let hash_str = String::from_utf8_lossy(&decoded_hash);
-
-                            if !hash_str.is_empty() {
-                                return self
-                                    .verify_password(password, &hash_str)
-                                    .map_err(|e| format!("Password verification failed: {}", e));
-                            }
+            return match self
+                .check_owner_credentials(&data, username, password)
+                .map_err(AuthError::Backend)?
+            {
+                OwnerLogin::Valid => Ok(true),
+                OwnerLogin::Invalid => Ok(false),
+                OwnerLogin::FirstLogin => {
+                    // No password has been set yet: the first login chooses it
+                    // and every later login must match it. Of concurrent first
+                    // logins only the one whose write lands on the secret as
+                    // read wins.
+                    let data = self.owner_password_data(password)?;
+                    match reader
+                        .put_if(OWNER_SECRET, data, &[], &version, "set owner password")
+                        .await
+                    {
+                        Ok(()) => {
+                            info!("Owner password set on first login");
+                            Ok(true)
                         }
-
-                        // If no password hash stored, this is first login
-                        // Store the hash for future use
-                        if username == "admin" {
-                            // In a real system, we'd update the secret here
-                            // For now, accept any password on first login
-                            return Ok(true);
+                        Err(SecretError::Conflict(_)) => {
+                            warn!("Owner password was set concurrently, rejecting this login");
+                            Ok(false)
                         }
+                        Err(e) => Err(AuthError::from_secret("Failed to update owner secret")(e)),
                     }
                 }
-                Err(e) => {
-                    return Err(format!("Failed to access owner secret: {}", e));
-                }
-            }
+            };
         }
 
         // Fallback for local development
         Ok(username == "admin" && password == "admin")
     }
 
+    /// Check credentials against the data of the `nimbus-owner` secret
+    pub fn check_owner_credentials(
+        &self,
+        data: &BTreeMap<String, ByteString>,
+        username: &str,
+        password: &str,
+    ) -> Result<OwnerLogin, String> {
+        // Check username
+        match data.get("username") {
+            Some(stored_username) => {
+                let decoded_username = BASE64
+                    .decode(&stored_username.0)
+                    .map_err(|e| format!("Failed to decode username: {}", e))?;
+                if String::from_utf8_lossy(&decoded_username) != username {
+                    return Ok(OwnerLogin::Invalid);
+                }
+            }
+            None if username != "admin" => return Ok(OwnerLogin::Invalid),
+            None => {}
+        }
+
+        // Check password hash
+        if let Some(stored_hash) = data.get("password_hash") {
+            let decoded_hash = BASE64
+                .decode(&stored_hash.0)
+                .map_err(|e| format!("Failed to decode password hash: {}", e))?;
+            let hash_str = String::from_utf8_lossy(&decoded_hash);
+
+            if !hash_str.is_empty() {
+                let valid = self
+                    .verify_password(password, &hash_str)
+                    .map_err(|e| format!("Password verification failed: {}", e))?;
+                return Ok(if valid { OwnerLogin::Valid } else { OwnerLogin::Invalid });
+            }
+        }
+
+        Ok(OwnerLogin::FirstLogin)
+    }
+
+    /// Hash `password` and store it as the owner's `password_hash`
+    ///
+    /// Fails without touching the secret if `password` breaks the
+    /// [`PasswordPolicy`].
+    pub async fn set_owner_password(&self, password: &str) -> Result<(), AuthError> {
+        let data = self.owner_password_data(password)?;
+        let Some(reader) = &self.secret_reader else {
+            return Err(AuthError::Backend("Secret store not available".to_string()));
+        };
+
+        reader
+            .put(OWNER_SECRET, data, &[], "set owner password")
+            .await
+            .map_err(AuthError::from_secret("Failed to update owner secret"))?;
+
+        Ok(())
+    }
+
+    /// Owner secret fields storing `password`, checked against the policy
+    fn owner_password_data(&self, password: &str) -> Result<secrets::SecretData, AuthError> {
+        self.check_password_strength(password)?;
+        let hash = self
+            .hash_password(password)
+            .map_err(|e| AuthError::Backend(format!("Failed to hash password: {}", e)))?;
+
+        // The owner secret stores its fields base64 encoded (see init-password.sh)
+        let mut data = BTreeMap::new();
+        data.insert("password_hash".to_string(), ByteString(BASE64.encode(hash).into_bytes()));
+        Ok(data)
+    }
+
     pub fn hash_password(&self, password: &str) -> Result<String, argon2::password_hash::Error> {
         let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
+        let params = self.argon2_params.to_params()?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
         let password_hash = argon2.hash_password(password.as_bytes(), &salt)?;
         Ok(password_hash.to_string())
     }
@@ -173,6 +778,7 @@ impl AuthService {
         hash: &str,
     ) -> Result<bool, argon2::password_hash::Error> {
         let parsed_hash = PasswordHash::new(hash)?;
+        // Costs come from the hash itself, so any parameters verify
         let argon2 = Argon2::default();
         match argon2.verify_password(password.as_bytes(), &parsed_hash) {
             Ok(_) => Ok(true),
@@ -183,49 +789,287 @@ impl AuthService {
     pub fn generate_token(
         &self,
         user_id: &str,
-        role: &str,
+        role: Role,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let ttl_secs = self.access_token_ttl.as_secs() as usize;
+        let jti = Uuid::new_v4().simple().to_string();
+        self.issue_token(user_id, role, TokenType::Access, ttl_secs, Some(jti))
+    }
+
+    /// Issue an access token plus a refresh token that can renew it
+    ///
+    /// Returns `(access_token, refresh_token)`.
+    pub async fn generate_token_pair(
+        &self,
+        user_id: &str,
+        role: Role,
+    ) -> Result<(String, String), AuthError> {
+        let access = self.generate_token(user_id, role)?;
+
+        let jti = Uuid::new_v4().simple().to_string();
+        let refresh = self.issue_token(
+            user_id,
+            role,
+            TokenType::Refresh,
+            REFRESH_TOKEN_TTL_SECS,
+            Some(jti.clone()),
+        )?;
+        self.remember_refresh_token(&jti).await?;
+
+        Ok((access, refresh))
+    }
+
+    /// Exchange a refresh token for a new access token
+    ///
+    /// Refresh tokens are single use: the presented token is invalidated and
+    /// a rotated one is returned alongside the access token, so a replayed
+    /// refresh token is rejected. Returns `(access_token, refresh_token)`.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<(String, String), AuthError> {
+        let claims = self.decode_claims(refresh_token)?;
+        if claims.token_type != TokenType::Refresh {
+            return Err(AuthError::WrongTokenType { expected: TokenType::Refresh });
+        }
+
+        let jti = claims.jti.as_deref().ok_or(AuthError::RefreshTokenRevoked)?;
+        if !self.consume_refresh_token(jti).await? {
+            return Err(AuthError::RefreshTokenRevoked);
+        }
+
+        let role = claims.parsed_role().ok_or_else(|| {
+            AuthError::InvalidToken(jsonwebtoken::errors::ErrorKind::InvalidToken.into())
+        })?;
+        self.generate_token_pair(&claims.sub, role).await
+    }
+
+    pub fn validate_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let claims = self.decode_claims(token)?;
+
+        // Refresh tokens are long lived and must never authorize requests
+        if claims.token_type != TokenType::Access {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+        }
+
+        if claims
+            .jti
+            .as_ref()
+            .is_some_and(|jti| self.revoked_tokens.lock().unwrap().contains_key(jti))
+        {
+            return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+        }
+
+        Ok(claims)
+    }
+
+    /// Reject the access token with this id until it expires at `exp`, e.g. on logout
+    ///
+    /// Tokens past their expiry are rejected anyway, so their ids are dropped
+    /// from the revocation list whenever it is written.
+    pub async fn revoke_token(&self, jti: &str, exp: usize) -> Result<(), AuthError> {
+        let now = unix_now();
+        {
+            let mut revoked = self.revoked_tokens.lock().unwrap();
+            revoked.retain(|_, expiry| *expiry > now);
+            revoked.insert(jti.to_string(), exp);
+        }
+
+        let Some(reader) = &self.secret_reader else {
+            return Ok(());
+        };
+        let reason = "revoke access token";
+        let entry = BTreeMap::from([(jti.to_string(), ByteString(exp.to_string().into_bytes()))]);
+
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            let Some((data, version)) = reader
+                .read_versioned(REVOKED_TOKENS_SECRET, reason)
+                .await
+                .map_err(AuthError::from_secret("Failed to read revoked tokens"))?
+            else {
+                match reader
+                    .create(REVOKED_TOKENS_SECRET, &SecretLabels::new(), entry.clone(), reason)
+                    .await
+                {
+                    Ok(()) => return Ok(()),
+                    // Created concurrently by another logout; add to that one
+                    Err(SecretError::AlreadyExists(_)) => continue,
+                    Err(e) => return Err(AuthError::from_secret("Failed to revoke token")(e)),
+                }
+            };
+            let expired: Vec<&str> = data
+                .iter()
+                .filter(|(_, expiry)| revoked_expiry(expiry).is_none_or(|expiry| expiry <= now))
+                .map(|(id, _)| id.as_str())
+                .collect();
+
+            match reader
+                .put_if(REVOKED_TOKENS_SECRET, entry.clone(), &expired, &version, reason)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                // Written by a concurrent logout; prune against the new contents
+                Err(SecretError::Conflict(_)) => continue,
+                Err(e) => return Err(AuthError::from_secret("Failed to revoke token")(e)),
+            }
+        }
+        Err(AuthError::Backend(format!("{} kept changing, try again", REVOKED_TOKENS_SECRET)))
+    }
+
+    fn issue_token(
+        &self,
+        user_id: &str,
+        role: Role,
+        token_type: TokenType,
+        ttl_secs: usize,
+        jti: Option<String>,
     ) -> Result<String, jsonwebtoken::errors::Error> {
-        let now =
-            SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs()
-                as usize;
+        let now = unix_now();
 
         let claims = Claims {
             sub: user_id.to_string(),
-            exp: now + 86400, // 24 hours
+            exp: now + ttl_secs,
             iat: now,
-            role: role.to_string(),
+            role: role.as_str().to_string(),
+            iss: self.instance_domain.clone(),
+            aud: self.instance_domain.clone(),
+            token_type,
+            jti,
         };
 
-        encode(&Header::default(), &claims, &EncodingKey::from_secret(self.jwt_secret.as_bytes()))
+        encode(
+            &Header::new(self.jwt_signing.algorithm()),
+            &claims,
+            &self.jwt_signing.encoding_key(),
+        )
     }
 
-    pub fn validate_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map(|data| data.claims)
+    fn decode_claims(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        // Only the configured algorithm, so an HS256 token can't pass for RS256
+        let mut validation = Validation::new(self.jwt_signing.algorithm());
+        // Expiry is exact; short TTLs would be meaningless with the default 60s leeway
+        validation.leeway = 0;
+        // Reject tokens minted by another instance, even one sharing the secret
+        validation.set_issuer(&[&self.instance_domain]);
+        validation.set_audience(&[&self.instance_domain]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+        decode::<Claims>(token, &self.jwt_signing.decoding_key(), &validation)
+            .map(|data| data.claims)
+    }
+
+    /// Record an issued refresh token id so it can be redeemed once
+    async fn remember_refresh_token(&self, jti: &str) -> Result<(), AuthError> {
+        if self.secret_reader.is_none() {
+            self.local_refresh_tokens.lock().unwrap().insert(jti.to_string());
+            return Ok(());
+        }
+
+        self.add_secret_key(REFRESH_TOKENS_SECRET, jti, "record refresh token").await
+    }
+
+    /// Remove a refresh token id, returning whether it was still outstanding
+    ///
+    /// The removal is conditional on the secret being unchanged since it was
+    /// read, so of two concurrent redemptions of one token only one succeeds.
+    async fn consume_refresh_token(&self, jti: &str) -> Result<bool, AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            return Ok(self.local_refresh_tokens.lock().unwrap().remove(jti));
+        };
+
+        for _ in 0..MAX_CONFLICT_RETRIES {
+            // Never trust a cached copy here, or a used token could be replayed
+            let Some((data, version)) = reader
+                .read_versioned(REFRESH_TOKENS_SECRET, "redeem refresh token")
+                .await
+                .map_err(AuthError::from_secret("Failed to read refresh tokens"))?
+            else {
+                return Ok(false);
+            };
+            if !data.contains_key(jti) {
+                return Ok(false);
+            }
+
+            match reader
+                .put_if(
+                    REFRESH_TOKENS_SECRET,
+                    BTreeMap::new(),
+                    &[jti],
+                    &version,
+                    "redeem refresh token",
+                )
+                .await
+            {
+                Ok(()) => return Ok(true),
+                // Written in between, maybe redeeming this very token; look again
+                Err(SecretError::Conflict(_)) => continue,
+                Err(e) => return Err(AuthError::from_secret("Failed to revoke refresh token")(e)),
+            }
+        }
+        Err(AuthError::Backend(format!("{} kept changing, try again", REFRESH_TOKENS_SECRET)))
+    }
+
+    /// Add a key to a flag-style secret, creating the secret if needed
+    async fn add_secret_key(&self, name: &str, key: &str, reason: &str) -> Result<(), AuthError> {
+        let Some(reader) = &self.secret_reader else {
+            return Err(AuthError::Backend("Secret store not available".to_string()));
+        };
+
+        let mut data = BTreeMap::new();
+        data.insert(key.to_string(), ByteString(b"1".to_vec()));
+        let result = match reader.put(name, data.clone(), &[], reason).await {
+            Err(SecretError::NotFound(_)) => {
+                match reader.create(name, &SecretLabels::new(), data.clone(), reason).await {
+                    // Created concurrently by another writer
+                    Err(SecretError::AlreadyExists(_)) => reader.put(name, data, &[], reason).await,
+                    result => result,
+                }
+            }
+            result => result,
+        };
+        result.map_err(AuthError::from_secret(format!("Failed to update {}", name)))
+    }
+
+    /// Read a secret through the audited, rate-limited reader
+    async fn read_secret(
+        &self,
+        name: &str,
+        reason: &str,
+        uncached: bool,
+    ) -> Result<Option<secrets::SecretData>, SecretError> {
+        let Some(reader) = &self.secret_reader else {
+            return Err(SecretError::Failed("Secret store not available".to_string()));
+        };
+        if uncached {
+            reader.read_uncached(name, reason).await
+        } else {
+            reader.read(name, reason).await
+        }
     }
 
     pub fn generate_api_key(&self) -> String {
         format!("nmbs_{}", Uuid::new_v4().to_string().replace("-", ""))
     }
 
-    pub async fn store_api_token(&self, name: &str, token: &str) -> Result<(), String> {
-        if let Some(client) = &self.kube_client {
-            let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.namespace);
-
-            // Create secret name
-            let secret_name = format!("nimbus-token-{}", name.to_lowercase().replace(" ", "-"));
+    /// Store an owner API token limited to `scopes`
+    pub async fn store_api_token(
+        &self,
+        name: &str,
+        token: &str,
+        scopes: &[api_tokens::ApiScope],
+    ) -> Result<(), AuthError> {
+        // Create secret name
+        let secret_name = format!("nimbus-token-{}", name.to_lowercase().replace(" ", "-"));
 
+        if let Some(reader) = &self.secret_reader {
             // Create secret data
             let mut data = BTreeMap::new();
-            data.insert("token".to_string(), k8s_openapi::ByteString(token.as_bytes().to_vec()));
-            data.insert("name".to_string(), k8s_openapi::ByteString(name.as_bytes().to_vec()));
+            data.insert("token".to_string(), ByteString(token.as_bytes().to_vec()));
+            data.insert("name".to_string(), ByteString(name.as_bytes().to_vec()));
+            data.insert(
+                "scopes".to_string(),
+                ByteString(api_tokens::ApiScope::join(scopes).into_bytes()),
+            );
             data.insert(
                 "created_at".to_string(),
-                k8s_openapi::ByteString(
+                ByteString(
                     SystemTime::now()
                         .duration_since(UNIX_EPOCH)
                         .unwrap()
@@ -235,78 +1079,86 @@ impl AuthService {
                         .to_vec(),
                 ),
             );
+            let mut labels = SecretLabels::new();
+            labels.insert("app".to_string(), "nimbus".to_string());
+            labels.insert("type".to_string(), "api-token".to_string());
 
-            let secret = Secret {
-                metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
-                    name: Some(secret_name),
-                    namespace: Some(self.namespace.clone()),
-                    labels: Some({
-                        let mut labels = BTreeMap::new();
-                        labels.insert("app".to_string(), "nimbus".to_string());
-                        labels.insert("type".to_string(), "api-token".to_string());
-                        labels
-                    }),
-                    ..Default::default()
-                },
-                data: Some(data),
-                ..Default::default()
+            match reader.create(&secret_name, &labels, data, "store API token").await {
+                Err(SecretError::AlreadyExists(_)) => Err(AuthError::TokenExists(name.to_string())),
+                result => result.map_err(AuthError::from_secret("Failed to store API token")),
+            }
+        } else {
+            // Local development and tests keep owner tokens in memory
+            let mut local = self.local_owner_tokens.lock().unwrap();
+            if local.contains_key(&secret_name) {
+                return Err(AuthError::TokenExists(name.to_string()));
+            }
+            let created_at =
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize;
+            let record = ApiToken {
+                id: secret_name.clone(),
+                name: name.to_string(),
+                token: token.to_string(),
+                created_at,
+                expires_at: None,
+                scopes: scopes.to_vec(),
             };
-
-            secrets
-                .create(&Default::default(), &secret)
-                .await
-                .map_err(|e| format!("Failed to store API token: {}", e))?;
-
+            local.insert(secret_name, record);
             Ok(())
-        } else {
-            Err("Kubernetes client not available".to_string())
         }
     }
 
-    pub async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, String> {
-        if let Some(client) = &self.kube_client {
-            let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.namespace);
-
+    pub async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, AuthError> {
+        if let Some(reader) = &self.secret_reader {
             // List secrets with label selector
-            let params = kube::api::ListParams::default().labels("type=api-token");
-
-            let secret_list = secrets
-                .list(&params)
+            let secret_list = reader
+                .list("type=api-token", "list API tokens")
                 .await
-                .map_err(|e| format!("Failed to list API tokens: {}", e))?;
+                .map_err(AuthError::from_secret("Failed to list API tokens"))?;
 
             let mut tokens = Vec::new();
-            for secret in secret_list.items {
-                if let Some(data) = secret.data {
-                    if let (Some(token_bytes), Some(name_bytes), Some(created_bytes)) =
-                        (data.get("token"), data.get("name"), data.get("created_at"))
-                    {
-                        let token = String::from_utf8_lossy(&token_bytes.0).to_string();
-                        let name = String::from_utf8_lossy(&name_bytes.0).to_string();
-                        let created_at =
-                            String::from_utf8_lossy(&created_bytes.0).parse::<usize>().unwrap_or(0);
-
-                        tokens.push(ApiToken {
-                            id: secret.metadata.name.unwrap_or_default(),
-                            name,
-                            token: format!("{}...", &token[..8.min(token.len())]), // Only show prefix
-                            created_at,
-                            expires_at: None,
-                        });
-                    }
+            for (secret_name, data) in secret_list {
+                if let (Some(token_bytes), Some(name_bytes), Some(created_bytes)) =
+                    (data.get("token"), data.get("name"), data.get("created_at"))
+                {
+                    let token = String::from_utf8_lossy(&token_bytes.0).to_string();
+                    let name = String::from_utf8_lossy(&name_bytes.0).to_string();
+                    let created_at =
+                        String::from_utf8_lossy(&created_bytes.0).parse::<usize>().unwrap_or(0);
+
+                    tokens.push(ApiToken {
+                        id: secret_name,
+                        name,
+                        token: format!("{}...", &token[..8.min(token.len())]), // Only show prefix
+                        created_at,
+                        expires_at: None,
+                        scopes: api_tokens::stored_api_scopes(&data),
+                    });
                 }
             }
 
             Ok(tokens)
         } else {
-            Ok(Vec::new()) // Return empty list in dev mode
+            let local = self.local_owner_tokens.lock().unwrap();
+            Ok(local
+                .values()
+                .map(|stored| ApiToken {
+                    token: format!("{}...", &stored.token[..8.min(stored.token.len())]),
+                    ..stored.clone()
+                })
+                .collect())
         }
     }
 }
 
+/// Dev-mode service without Kubernetes, see [`AuthService::new_local`]
+///
+/// Production setup must use `AuthService::new().await`.
 impl Default for AuthService {
     fn default() -> Self {
-        // Block on async new() - not ideal but works for now
-        tokio::runtime::Runtime::new().unwrap().block_on(Self::new())
+        Self::new_local()
     }
 }
+
+#[cfg(test)]
+mod tests;
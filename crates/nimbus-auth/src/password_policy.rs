@@ -0,0 +1,109 @@
+//! Password strength rules
+//!
+//! Checked before a password is hashed, when the owner picks one on first
+//! login and when a collaborator is registered. Logins against an existing
+//! hash are never re-checked, so tightening the policy locks nobody out.
+
+use tracing::warn;
+
+use crate::AuthService;
+
+/// Passwords rejected whatever the policy, compared case-insensitively
+const COMMON_PASSWORDS: &[&str] = &[
+    "admin",
+    "administrator",
+    "changeme",
+    "letmein",
+    "nimbus",
+    "passw0rd",
+    "password",
+    "password1",
+    "password123",
+    "qwerty",
+    "qwerty123",
+    "root",
+    "secret",
+    "welcome",
+    "12345678",
+    "123456789",
+    "1234567890",
+];
+
+/// What a new password must satisfy
+///
+/// Configured with `NIMBUS_PASSWORD_MIN_LENGTH` and
+/// `NIMBUS_PASSWORD_REQUIRE_MIXED` (`true` to require both letters and
+/// digits or symbols).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// Fewest characters accepted
+    pub min_length: usize,
+    /// Require at least one letter and one non-letter
+    pub require_mixed: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: 8, require_mixed: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PasswordPolicyError {
+    #[error("Password must be at least {min_length} characters")]
+    TooShort { min_length: usize },
+
+    #[error("Password is too common")]
+    Common,
+
+    #[error("Password must mix letters with digits or symbols")]
+    NotMixed,
+}
+
+impl PasswordPolicy {
+    /// Read the policy from the environment, keeping defaults for unset or
+    /// invalid values
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let min_length = match std::env::var("NIMBUS_PASSWORD_MIN_LENGTH") {
+            Ok(value) => value.parse::<usize>().unwrap_or_else(|_| {
+                warn!("Invalid NIMBUS_PASSWORD_MIN_LENGTH {:?}, using default", value);
+                defaults.min_length
+            }),
+            Err(_) => defaults.min_length,
+        };
+        let require_mixed = match std::env::var("NIMBUS_PASSWORD_REQUIRE_MIXED") {
+            Ok(value) => value.parse::<bool>().unwrap_or_else(|_| {
+                warn!("Invalid NIMBUS_PASSWORD_REQUIRE_MIXED {:?}, using default", value);
+                defaults.require_mixed
+            }),
+            Err(_) => defaults.require_mixed,
+        };
+        Self { min_length, require_mixed }
+    }
+
+    /// Check `password` against this policy
+    pub fn check(&self, password: &str) -> Result<(), PasswordPolicyError> {
+        if password.chars().count() < self.min_length {
+            return Err(PasswordPolicyError::TooShort { min_length: self.min_length });
+        }
+        if COMMON_PASSWORDS.iter().any(|common| common.eq_ignore_ascii_case(password)) {
+            return Err(PasswordPolicyError::Common);
+        }
+        if self.require_mixed {
+            let has_letter = password.chars().any(char::is_alphabetic);
+            let has_other = password.chars().any(|c| !c.is_alphabetic());
+            if !(has_letter && has_other) {
+                return Err(PasswordPolicyError::NotMixed);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AuthService {
+    /// Check a new password against the configured [`PasswordPolicy`]
+    pub fn check_password_strength(&self, password: &str) -> Result<(), PasswordPolicyError> {
+        self.password_policy.check(password)
+    }
+}
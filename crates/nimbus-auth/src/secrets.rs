@@ -0,0 +1,660 @@
+//! Audited, cached and rate-limited access to secrets
+//!
+//! Secrets live in a [`SecretStore`]: Kubernetes in production, or memory
+//! for hosts without a cluster and for tests.
+//!
+//! Every secret read goes through [`SecretReader`] so a bug that reads in a
+//! tight loop can't hammer the API server, and each read leaves a debug-level
+//! audit trace saying which secret was read and why.
+//!
+//! Backend calls are retried with backoff, so a blip of the API server
+//! doesn't fail a login. After repeated failures the reader stops calling the
+//! backend for a while and fails fast with [`SecretError::Unavailable`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::{Api, Client};
+use tracing::{debug, warn};
+
+/// Key/value data of one secret
+pub type SecretData = BTreeMap<String, ByteString>;
+
+/// Labels of one secret, matched by the selectors passed to `list`
+pub type SecretLabels = BTreeMap<String, String>;
+
+/// Opaque version of a secret, changed by every write; for [`SecretStore::put_if`]
+pub type SecretVersion = String;
+
+/// Where secrets are kept
+///
+/// Implementations report failures worth retrying as
+/// [`SecretError::Unreachable`]; [`SecretReader`] does the retrying.
+#[async_trait]
+pub trait SecretStore: Send + Sync {
+    /// Fetch one secret by name, `None` if it doesn't exist
+    async fn get(&self, name: &str) -> Result<Option<SecretData>, SecretError>;
+
+    /// Fetch all secrets matching a label selector (`key=value,...`), as
+    /// `(name, data)` pairs
+    async fn list(&self, label_selector: &str) -> Result<Vec<(String, SecretData)>, SecretError>;
+
+    /// Create a secret, failing with [`SecretError::AlreadyExists`] if it does
+    async fn create(
+        &self,
+        name: &str,
+        labels: &SecretLabels,
+        data: SecretData,
+    ) -> Result<(), SecretError>;
+
+    /// Set the keys in `set` and remove those in `remove` of an existing
+    /// secret, failing with [`SecretError::NotFound`] if there is none
+    async fn put(&self, name: &str, set: SecretData, remove: &[&str]) -> Result<(), SecretError>;
+
+    /// Fetch one secret with its current version, `None` if it doesn't exist
+    async fn get_versioned(
+        &self,
+        name: &str,
+    ) -> Result<Option<(SecretData, SecretVersion)>, SecretError>;
+
+    /// Like `put`, but only if the secret is still at `version`, failing
+    /// with [`SecretError::Conflict`] if it was written since
+    async fn put_if(
+        &self,
+        name: &str,
+        set: SecretData,
+        remove: &[&str],
+        version: &str,
+    ) -> Result<(), SecretError>;
+
+    /// Delete a secret, returning whether it existed
+    async fn delete(&self, name: &str) -> Result<bool, SecretError>;
+}
+
+/// Which [`SecretStore`] to use, from `NIMBUS_SECRET_STORE`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecretStoreKind {
+    /// Secrets in the instance's namespace (`kubernetes`)
+    #[default]
+    Kubernetes,
+    /// Secrets held by the process and lost on restart (`memory`)
+    Memory,
+}
+
+impl SecretStoreKind {
+    /// Read the store kind from the environment, defaulting to Kubernetes
+    pub fn from_env() -> Self {
+        match std::env::var("NIMBUS_SECRET_STORE") {
+            Ok(value) => value.parse().unwrap_or_else(|e| {
+                warn!("{}, using Kubernetes", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+impl std::str::FromStr for SecretStoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kubernetes" => Ok(Self::Kubernetes),
+            "memory" => Ok(Self::Memory),
+            other => Err(format!("Unknown NIMBUS_SECRET_STORE {:?}", other)),
+        }
+    }
+}
+
+/// Secrets in a Kubernetes namespace
+pub struct KubeSecretStore {
+    client: Client,
+    namespace: String,
+}
+
+impl KubeSecretStore {
+    pub fn new(client: Client, namespace: &str) -> Self {
+        Self { client, namespace: namespace.to_string() }
+    }
+
+    fn api(&self) -> Api<Secret> {
+        Api::namespaced(self.client.clone(), &self.namespace)
+    }
+
+    /// `data` of a merge patch setting `set` and removing `remove`
+    fn data_patch(
+        set: SecretData,
+        remove: &[&str],
+    ) -> Result<serde_json::Map<String, serde_json::Value>, SecretError> {
+        // In a merge patch a null value removes the key
+        let mut data = serde_json::Map::new();
+        for (key, value) in set {
+            let value = serde_json::to_value(value)
+                .map_err(|e| SecretError::Failed(format!("Failed to encode {}: {}", key, e)))?;
+            data.insert(key, value);
+        }
+        for key in remove {
+            data.insert(key.to_string(), serde_json::Value::Null);
+        }
+        Ok(data)
+    }
+
+    /// Sort an API error into conflicts, misses, rejections and blips
+    fn error(action: &str, name: &str, e: kube::Error) -> SecretError {
+        match e {
+            kube::Error::Api(response) if response.code == 409 => {
+                SecretError::AlreadyExists(name.to_string())
+            }
+            kube::Error::Api(response) if response.code == 404 => {
+                SecretError::NotFound(name.to_string())
+            }
+            kube::Error::Api(response) if response.code < 500 && response.code != 429 => {
+                SecretError::Failed(format!("Failed to {} secret {}: {}", action, name, response))
+            }
+            e => SecretError::Unreachable(format!("Failed to {} secret {}: {}", action, name, e)),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretStore for KubeSecretStore {
+    async fn get(&self, name: &str) -> Result<Option<SecretData>, SecretError> {
+        self.api()
+            .get_opt(name)
+            .await
+            .map(|secret| secret.map(|s| s.data.unwrap_or_default()))
+            .map_err(|e| Self::error("read", name, e))
+    }
+
+    async fn list(&self, label_selector: &str) -> Result<Vec<(String, SecretData)>, SecretError> {
+        let params = kube::api::ListParams::default().labels(label_selector);
+        let list =
+            self.api().list(&params).await.map_err(|e| Self::error("list", label_selector, e))?;
+
+        Ok(list
+            .items
+            .into_iter()
+            .map(|s| (s.metadata.name.unwrap_or_default(), s.data.unwrap_or_default()))
+            .collect())
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        labels: &SecretLabels,
+        data: SecretData,
+    ) -> Result<(), SecretError> {
+        let secret = Secret {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(self.namespace.clone()),
+                labels: (!labels.is_empty()).then(|| labels.clone()),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        };
+        self.api()
+            .create(&Default::default(), &secret)
+            .await
+            .map(|_| ())
+            .map_err(|e| Self::error("create", name, e))
+    }
+
+    async fn put(&self, name: &str, set: SecretData, remove: &[&str]) -> Result<(), SecretError> {
+        let patch = serde_json::json!({ "data": Self::data_patch(set, remove)? });
+        self.api()
+            .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+            .map(|_| ())
+            .map_err(|e| Self::error("update", name, e))
+    }
+
+    async fn get_versioned(
+        &self,
+        name: &str,
+    ) -> Result<Option<(SecretData, SecretVersion)>, SecretError> {
+        let secret = self.api().get_opt(name).await.map_err(|e| Self::error("read", name, e))?;
+        Ok(secret
+            .map(|s| (s.data.unwrap_or_default(), s.metadata.resource_version.unwrap_or_default())))
+    }
+
+    async fn put_if(
+        &self,
+        name: &str,
+        set: SecretData,
+        remove: &[&str],
+        version: &str,
+    ) -> Result<(), SecretError> {
+        // The API server rejects the patch with 409 if resourceVersion moved on
+        let patch = serde_json::json!({
+            "metadata": { "resourceVersion": version },
+            "data": Self::data_patch(set, remove)?,
+        });
+        match self.api().patch(name, &PatchParams::default(), &Patch::Merge(&patch)).await {
+            Ok(_) => Ok(()),
+            Err(e) => match Self::error("update", name, e) {
+                SecretError::AlreadyExists(name) => Err(SecretError::Conflict(name)),
+                e => Err(e),
+            },
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<bool, SecretError> {
+        match self.api().delete(name, &DeleteParams::default()).await {
+            Ok(_) => Ok(true),
+            Err(e) => match Self::error("delete", name, e) {
+                SecretError::NotFound(_) => Ok(false),
+                e => Err(e),
+            },
+        }
+    }
+}
+
+/// One secret in a [`MemorySecretStore`]
+struct MemorySecret {
+    labels: SecretLabels,
+    data: SecretData,
+    /// Bumped by every write
+    version: u64,
+}
+
+/// Secrets held in memory, for running without Kubernetes and for tests
+#[derive(Default)]
+pub struct MemorySecretStore {
+    secrets: Mutex<BTreeMap<String, MemorySecret>>,
+}
+
+impl MemorySecretStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemorySecret {
+    fn update(&mut self, set: SecretData, remove: &[&str]) {
+        self.data.extend(set);
+        for key in remove {
+            self.data.remove(*key);
+        }
+        self.version += 1;
+    }
+}
+
+/// Whether `labels` match a `key=value,...` selector; a bare `key` only
+/// requires the label to be present
+fn matches_selector(labels: &SecretLabels, selector: &str) -> bool {
+    selector.split(',').map(str::trim).filter(|term| !term.is_empty()).all(|term| {
+        match term.split_once('=') {
+            Some((key, value)) => labels.get(key.trim()).is_some_and(|v| v == value.trim()),
+            None => labels.contains_key(term),
+        }
+    })
+}
+
+#[async_trait]
+impl SecretStore for MemorySecretStore {
+    async fn get(&self, name: &str) -> Result<Option<SecretData>, SecretError> {
+        Ok(self.secrets.lock().unwrap().get(name).map(|secret| secret.data.clone()))
+    }
+
+    async fn list(&self, label_selector: &str) -> Result<Vec<(String, SecretData)>, SecretError> {
+        let secrets = self.secrets.lock().unwrap();
+        Ok(secrets
+            .iter()
+            .filter(|(_, secret)| matches_selector(&secret.labels, label_selector))
+            .map(|(name, secret)| (name.clone(), secret.data.clone()))
+            .collect())
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        labels: &SecretLabels,
+        data: SecretData,
+    ) -> Result<(), SecretError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        if secrets.contains_key(name) {
+            return Err(SecretError::AlreadyExists(name.to_string()));
+        }
+        secrets.insert(name.to_string(), MemorySecret { labels: labels.clone(), data, version: 1 });
+        Ok(())
+    }
+
+    async fn put(&self, name: &str, set: SecretData, remove: &[&str]) -> Result<(), SecretError> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let Some(secret) = secrets.get_mut(name) else {
+            return Err(SecretError::NotFound(name.to_string()));
+        };
+        secret.update(set, remove);
+        Ok(())
+    }
+
+    async fn get_versioned(
+        &self,
+        name: &str,
+    ) -> Result<Option<(SecretData, SecretVersion)>, SecretError> {
+        let secrets = self.secrets.lock().unwrap();
+        Ok(secrets.get(name).map(|secret| (secret.data.clone(), secret.version.to_string())))
+    }
+
+    async fn put_if(
+        &self,
+        name: &str,
+        set: SecretData,
+        remove: &[&str],
+        version: &str,
+    ) -> Result<(), SecretError> {
+        // Checked and written under one lock, so no other write can slip in
+        let mut secrets = self.secrets.lock().unwrap();
+        let Some(secret) = secrets.get_mut(name) else {
+            return Err(SecretError::NotFound(name.to_string()));
+        };
+        if secret.version.to_string() != version {
+            return Err(SecretError::Conflict(name.to_string()));
+        }
+        secret.update(set, remove);
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<bool, SecretError> {
+        Ok(self.secrets.lock().unwrap().remove(name).is_some())
+    }
+}
+
+/// Cache, rate limit and retry settings
+#[derive(Debug, Clone, Copy)]
+pub struct SecretAccessPolicy {
+    /// How long a read is served from cache
+    pub cache_ttl: Duration,
+    /// Maximum backend reads of one secret per `window`
+    pub max_reads: usize,
+    pub window: Duration,
+    /// Tries per backend call, including the first
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for each one after
+    pub retry_delay: Duration,
+    /// Consecutive failed calls after which the backend counts as unavailable
+    pub failure_threshold: u32,
+    /// How long calls fail fast once the backend counts as unavailable
+    pub cooldown: Duration,
+}
+
+impl Default for SecretAccessPolicy {
+    fn default() -> Self {
+        Self {
+            cache_ttl: Duration::from_secs(5),
+            max_reads: 10,
+            window: Duration::from_secs(1),
+            attempts: 3,
+            retry_delay: Duration::from_millis(100),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Why a secret couldn't be read or written
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SecretError {
+    /// The backend kept failing; calls fail fast until the cooldown ends
+    #[error("Secret backend unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Secret {0} already exists")]
+    AlreadyExists(String),
+
+    #[error("Secret {0} not found")]
+    NotFound(String),
+
+    /// A conditional write lost to another write of the same secret
+    #[error("Secret {0} was changed concurrently")]
+    Conflict(String),
+
+    /// The backend couldn't be reached; worth retrying
+    #[error("{0}")]
+    Unreachable(String),
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Consecutive backend failures, and until when calls fail fast
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Secrets returned by a label selector, with their names
+type NamedSecrets = Vec<(String, SecretData)>;
+
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+/// Front door for every secret read and write
+pub(crate) struct SecretReader {
+    store: Arc<dyn SecretStore>,
+    policy: SecretAccessPolicy,
+    reads: Mutex<HashMap<String, Cached<Option<SecretData>>>>,
+    lists: Mutex<HashMap<String, Cached<NamedSecrets>>>,
+    /// Recent backend fetch times per key, for rate limiting
+    fetches: Mutex<HashMap<String, Vec<Instant>>>,
+    breaker: Mutex<Breaker>,
+}
+
+impl SecretReader {
+    pub(crate) fn new(store: Arc<dyn SecretStore>, policy: SecretAccessPolicy) -> Self {
+        Self {
+            store,
+            policy,
+            reads: Mutex::new(HashMap::new()),
+            lists: Mutex::new(HashMap::new()),
+            fetches: Mutex::new(HashMap::new()),
+            breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    /// Read a secret, served from cache when fresh
+    pub(crate) async fn read(
+        &self,
+        name: &str,
+        reason: &str,
+    ) -> Result<Option<SecretData>, SecretError> {
+        if let Some(cached) = Self::fresh(&self.reads, name, self.policy.cache_ttl) {
+            debug!(target: "nimbus_auth::audit", secret = name, reason, cached = true, "secret read");
+            return Ok(cached);
+        }
+        self.read_uncached(name, reason).await
+    }
+
+    /// Read a secret bypassing the cache, for reads that must see the latest
+    /// write (e.g. single-use refresh token ids). Still audited and rate limited.
+    pub(crate) async fn read_uncached(
+        &self,
+        name: &str,
+        reason: &str,
+    ) -> Result<Option<SecretData>, SecretError> {
+        self.acquire(name)?;
+        debug!(target: "nimbus_auth::audit", secret = name, reason, cached = false, "secret read");
+
+        let value = self.call(|| self.store.get(name)).await?;
+        self.reads
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), Cached { value: value.clone(), fetched_at: Instant::now() });
+        Ok(value)
+    }
+
+    /// Read a secret and its version, bypassing the cache, for a following
+    /// [`Self::put_if`]. Audited and rate limited like other reads.
+    pub(crate) async fn read_versioned(
+        &self,
+        name: &str,
+        reason: &str,
+    ) -> Result<Option<(SecretData, SecretVersion)>, SecretError> {
+        self.acquire(name)?;
+        debug!(target: "nimbus_auth::audit", secret = name, reason, cached = false, "secret read");
+        self.call(|| self.store.get_versioned(name)).await
+    }
+
+    /// List secrets by label selector, served from cache when fresh
+    pub(crate) async fn list(
+        &self,
+        label_selector: &str,
+        reason: &str,
+    ) -> Result<Vec<(String, SecretData)>, SecretError> {
+        let key = format!("list:{}", label_selector);
+        if let Some(cached) = Self::fresh(&self.lists, &key, self.policy.cache_ttl) {
+            debug!(target: "nimbus_auth::audit", secret = %key, reason, cached = true, "secret read");
+            return Ok(cached);
+        }
+
+        self.acquire(&key)?;
+        debug!(target: "nimbus_auth::audit", secret = %key, reason, cached = false, "secret read");
+
+        let value = self.call(|| self.store.list(label_selector)).await?;
+        self.lists
+            .lock()
+            .unwrap()
+            .insert(key, Cached { value: value.clone(), fetched_at: Instant::now() });
+        Ok(value)
+    }
+
+    /// Create a secret
+    pub(crate) async fn create(
+        &self,
+        name: &str,
+        labels: &SecretLabels,
+        data: SecretData,
+        reason: &str,
+    ) -> Result<(), SecretError> {
+        debug!(target: "nimbus_auth::audit", secret = name, reason, "secret create");
+        let result = self.call(|| self.store.create(name, labels, data.clone())).await;
+        self.invalidate(name);
+        result
+    }
+
+    /// Set and remove keys of an existing secret
+    pub(crate) async fn put(
+        &self,
+        name: &str,
+        set: SecretData,
+        remove: &[&str],
+        reason: &str,
+    ) -> Result<(), SecretError> {
+        debug!(target: "nimbus_auth::audit", secret = name, reason, "secret update");
+        let result = self.call(|| self.store.put(name, set.clone(), remove)).await;
+        self.invalidate(name);
+        result
+    }
+
+    /// Set and remove keys of a secret still at `version`
+    pub(crate) async fn put_if(
+        &self,
+        name: &str,
+        set: SecretData,
+        remove: &[&str],
+        version: &str,
+        reason: &str,
+    ) -> Result<(), SecretError> {
+        debug!(target: "nimbus_auth::audit", secret = name, reason, "secret update");
+        let result = self.call(|| self.store.put_if(name, set.clone(), remove, version)).await;
+        self.invalidate(name);
+        result
+    }
+
+    /// Delete a secret, returning whether it existed
+    pub(crate) async fn delete(&self, name: &str, reason: &str) -> Result<bool, SecretError> {
+        debug!(target: "nimbus_auth::audit", secret = name, reason, "secret delete");
+        let result = self.call(|| self.store.delete(name)).await;
+        self.invalidate(name);
+        result
+    }
+
+    /// Run a backend call, retrying [`SecretError::Unreachable`] with backoff
+    ///
+    /// Fails fast with [`SecretError::Unavailable`] while the backend counts
+    /// as unavailable. Once the cooldown ends the next call goes through, and
+    /// its outcome decides whether the backend is back. Any other answer from
+    /// the backend, error or not, shows it is reachable.
+    async fn call<T, F, Fut>(&self, mut operation: F) -> Result<T, SecretError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SecretError>>,
+    {
+        if let Some(open_until) = self.breaker.lock().unwrap().open_until
+            && Instant::now() < open_until
+        {
+            return Err(SecretError::Unavailable("too many recent failures".to_string()));
+        }
+
+        let mut delay = self.policy.retry_delay;
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(SecretError::Unreachable(e)) if attempt < self.policy.attempts => {
+                    warn!("Secret backend call failed (attempt {}), retrying: {}", attempt, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(SecretError::Unreachable(e)) => {
+                    let mut breaker = self.breaker.lock().unwrap();
+                    breaker.failures += 1;
+                    if breaker.failures < self.policy.failure_threshold {
+                        return Err(SecretError::Failed(e));
+                    }
+                    warn!("Secret backend failed {} times in a row: {}", breaker.failures, e);
+                    breaker.open_until = Some(Instant::now() + self.policy.cooldown);
+                    return Err(SecretError::Unavailable(e));
+                }
+                result => {
+                    *self.breaker.lock().unwrap() = Breaker::default();
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Drop cached data after writing a secret
+    fn invalidate(&self, name: &str) {
+        self.reads.lock().unwrap().remove(name);
+        // Any list could include the written secret
+        self.lists.lock().unwrap().clear();
+    }
+
+    fn fresh<T: Clone>(
+        cache: &Mutex<HashMap<String, Cached<T>>>,
+        key: &str,
+        ttl: Duration,
+    ) -> Option<T> {
+        cache
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|entry| entry.fetched_at.elapsed() < ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Take a slot in the per-key rate limit window
+    fn acquire(&self, key: &str) -> Result<(), SecretError> {
+        let mut fetches = self.fetches.lock().unwrap();
+        let recent = fetches.entry(key.to_string()).or_default();
+        recent.retain(|at| at.elapsed() < self.policy.window);
+
+        if recent.len() >= self.policy.max_reads {
+            return Err(SecretError::Failed(format!("Rate limit exceeded reading secret {}", key)));
+        }
+        recent.push(Instant::now());
+        Ok(())
+    }
+}
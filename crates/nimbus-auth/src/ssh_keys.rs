@@ -0,0 +1,181 @@
+//! SSH key parsing and ownership index
+//!
+//! A key fingerprint must identify exactly one collaborator, otherwise the
+//! SSH server can't tell who pushed. The index is kept up to date as keys
+//! are added and removed instead of rescanning every collaborator.
+//!
+//! Fingerprints are OpenSSH's default format, `SHA256:<base64>` over the
+//! decoded key blob, so they match what `ssh-keygen -lf` prints.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD};
+use nimbus_types::{Collaborator, NimbusError, SshKey};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Key types accepted, with the number of length-prefixed fields their
+/// blobs hold after the type name
+const KEY_TYPES: &[(&str, usize)] = &[
+    ("ssh-ed25519", 1),
+    ("ssh-rsa", 2),
+    ("ecdsa-sha2-nistp256", 2),
+    ("ecdsa-sha2-nistp384", 2),
+    ("ecdsa-sha2-nistp521", 2),
+    ("sk-ssh-ed25519@openssh.com", 2),
+    ("sk-ecdsa-sha2-nistp256@openssh.com", 3),
+];
+
+/// A submitted public key that isn't a valid OpenSSH public key line
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid SSH public key: {0}")]
+pub struct InvalidSshKey(pub String);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SshKeyError {
+    #[error("SSH key {fingerprint} is already registered to collaborator {owner}")]
+    DuplicateKey { fingerprint: String, owner: Uuid },
+}
+
+/// Parse an OpenSSH public key line, `<type> <base64 blob> [comment]`
+///
+/// The comment, if any, becomes the key's name. The stored `public_key` is
+/// normalized to `<type> <base64 blob>`.
+pub fn parse_ssh_public_key(line: &str) -> Result<SshKey, InvalidSshKey> {
+    let invalid = |reason: &str| InvalidSshKey(reason.to_string());
+
+    let mut parts = line.split_whitespace();
+    let (Some(key_type), Some(encoded)) = (parts.next(), parts.next()) else {
+        return Err(invalid("expected \"<type> <base64 key> [comment]\""));
+    };
+    let comment = parts.collect::<Vec<_>>().join(" ");
+
+    let Some(&(_, fields)) = KEY_TYPES.iter().find(|(name, _)| *name == key_type) else {
+        return Err(InvalidSshKey(format!("unsupported key type {:?}", key_type)));
+    };
+    let blob = BASE64.decode(encoded).map_err(|_| invalid("key is not valid base64"))?;
+
+    // The blob repeats the type, then holds the key's own fields
+    let mut rest = blob.as_slice();
+    if read_field(&mut rest) != Some(key_type.as_bytes()) {
+        return Err(invalid("key data does not match its type"));
+    }
+    for _ in 0..fields {
+        match read_field(&mut rest) {
+            Some(field) if !field.is_empty() => {}
+            _ => return Err(invalid("key data is truncated")),
+        }
+    }
+    if !rest.is_empty() {
+        return Err(invalid("key data has trailing bytes"));
+    }
+    if key_type == "ssh-ed25519" && blob.len() != 4 + key_type.len() + 4 + 32 {
+        return Err(invalid("ed25519 keys are 32 bytes"));
+    }
+
+    Ok(SshKey {
+        id: Uuid::new_v4(),
+        name: comment,
+        public_key: format!("{} {}", key_type, encoded),
+        fingerprint: fingerprint(&blob),
+    })
+}
+
+/// Fingerprint of an OpenSSH public key line, as `ssh-keygen -lf` prints it
+pub fn ssh_fingerprint(public_key: &str) -> Result<String, NimbusError> {
+    parse_ssh_public_key(public_key)
+        .map(|key| key.fingerprint)
+        .map_err(|e| NimbusError::InvalidInput(e.to_string()))
+}
+
+/// `SHA256:<base64>` fingerprint of a decoded key blob
+pub fn fingerprint(blob: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(blob)))
+}
+
+/// Take one `u32` length-prefixed field off the front of `data`
+fn read_field<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let field = data.get(4..4 + len)?;
+    *data = &data[4 + len..];
+    Some(field)
+}
+
+/// Maps SSH key fingerprints to the collaborator that registered them
+///
+/// `AuthService` keeps one, built from the stored collaborators on first use
+/// and updated as keys come and go.
+#[derive(Debug, Default)]
+pub struct SshKeyRegistry {
+    owners: RwLock<HashMap<String, Uuid>>,
+}
+
+impl SshKeyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the index from existing collaborators
+    ///
+    /// Fails if two collaborators already share a key.
+    pub fn from_collaborators(collaborators: &[Collaborator]) -> Result<Self, SshKeyError> {
+        let registry = Self::new();
+        {
+            let mut owners = registry.owners.write().unwrap();
+            for collaborator in collaborators {
+                for key in &collaborator.ssh_keys {
+                    Self::insert_owner(&mut owners, &key.fingerprint, collaborator.id)?;
+                }
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Add a key to a collaborator, rejecting fingerprints owned by anyone
+    pub fn add_key(&self, collaborator: &mut Collaborator, key: SshKey) -> Result<(), SshKeyError> {
+        let mut owners = self.owners.write().unwrap();
+        Self::insert_owner(&mut owners, &key.fingerprint, collaborator.id)?;
+        collaborator.ssh_keys.push(key);
+        Ok(())
+    }
+
+    /// Remove a key from a collaborator and release its fingerprint
+    pub fn remove_key(&self, collaborator: &mut Collaborator, key_id: Uuid) -> Option<SshKey> {
+        let position = collaborator.ssh_keys.iter().position(|k| k.id == key_id)?;
+        let key = collaborator.ssh_keys.remove(position);
+        self.release(&key.fingerprint);
+        Some(key)
+    }
+
+    /// Reserve `fingerprint` for a collaborator before their key is saved
+    pub fn claim(&self, fingerprint: &str, collaborator_id: Uuid) -> Result<(), SshKeyError> {
+        Self::insert_owner(&mut self.owners.write().unwrap(), fingerprint, collaborator_id)
+    }
+
+    /// Free `fingerprint` for anyone to register
+    pub fn release(&self, fingerprint: &str) {
+        self.owners.write().unwrap().remove(fingerprint);
+    }
+
+    /// Look up which collaborator a key belongs to (used by SSH auth)
+    pub fn find_key_owner(&self, fingerprint: &str) -> Option<Uuid> {
+        self.owners.read().unwrap().get(fingerprint).copied()
+    }
+
+    fn insert_owner(
+        owners: &mut HashMap<String, Uuid>,
+        fingerprint: &str,
+        collaborator_id: Uuid,
+    ) -> Result<(), SshKeyError> {
+        if let Some(owner) = owners.get(fingerprint) {
+            return Err(SshKeyError::DuplicateKey {
+                fingerprint: fingerprint.to_string(),
+                owner: *owner,
+            });
+        }
+        owners.insert(fingerprint.to_string(), collaborator_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! Handlers that forward events to a channel instead of acting on them
+//!
+//! Backs [`InMemoryEventBus::subscribe_channel`](crate::InMemoryEventBus::subscribe_channel),
+//! for consumers such as live feeds that would rather pull events than
+//! implement [`EventHandler`].
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use nimbus_types::events::{EventEnvelope, EventFilter, EventHandler};
+use tracing::{debug, warn};
+
+/// Forwards matching envelopes to a channel receiver
+pub(crate) struct ChannelHandler {
+    pub(crate) name: String,
+    pub(crate) sender: async_channel::Sender<EventEnvelope>,
+    pub(crate) filter: EventFilter,
+    /// Removes this handler from the bus once every receiver is gone
+    pub(crate) unsubscribe: Arc<dyn Fn() + Send + Sync>,
+    /// Dropped with the handler, ending the task that watches for the
+    /// receivers to go away
+    pub(crate) _watcher: tokio::sync::oneshot::Sender<()>,
+}
+
+#[async_trait]
+impl EventHandler for ChannelHandler {
+    async fn handle(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        // A receiver that falls behind misses events rather than stalling dispatch
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(async_channel::TrySendError::Full(event)) => {
+                warn!("Channel subscriber {} is full, dropped event {}", self.name, event.id);
+            }
+            Err(async_channel::TrySendError::Closed(_)) => {
+                debug!("Channel subscriber {} was dropped, unsubscribing", self.name);
+                (self.unsubscribe)();
+            }
+        }
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        self.filter.clone()
+    }
+}
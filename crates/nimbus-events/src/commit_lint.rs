@@ -0,0 +1,198 @@
+//! Commit message linting
+//!
+//! [`CommitLinter`] checks the messages of pushed commits against
+//! [`CommitLintConfig`] (conventional-commit prefixes, subject length, no
+//! trailing period) and reports violations as an `AiAnalysisCompleted` event
+//! from the `commit-lint` plugin, one [`AiSuggestion`] per violation. Clean
+//! pushes publish nothing.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use nimbus_types::Commit;
+use nimbus_types::events::{
+    AiSuggestion, Event, EventBus, EventEnvelope, EventFilter, EventHandler, EventMetadata,
+    EventPriority, EventType, SuggestionSeverity,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Plugin name on the events the linter publishes
+pub const PLUGIN_NAME: &str = "commit-lint";
+
+/// Commit types accepted by default, from the conventional commits spec
+const DEFAULT_TYPES: &[&str] =
+    &["feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert"];
+
+/// Rules commit messages are checked against
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitLintConfig {
+    /// Longest accepted subject line, in characters; `None` disables the check
+    pub max_subject_length: Option<usize>,
+    /// Types a subject must start with, as `type: ...` or `type(scope): ...`;
+    /// empty disables the check
+    pub allowed_types: Vec<String>,
+    /// Reject subjects ending in a period
+    pub forbid_trailing_period: bool,
+}
+
+impl Default for CommitLintConfig {
+    fn default() -> Self {
+        Self {
+            max_subject_length: Some(72),
+            allowed_types: DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+            forbid_trailing_period: true,
+        }
+    }
+}
+
+impl CommitLintConfig {
+    /// Rules from the environment, `None` unless `NIMBUS_COMMIT_LINT=true`
+    ///
+    /// `NIMBUS_COMMIT_LINT_TYPES` (comma separated, empty to allow any) and
+    /// `NIMBUS_COMMIT_LINT_MAX_SUBJECT` (0 for no limit) override the
+    /// defaults. Invalid values are logged and the default kept.
+    pub fn from_env() -> Option<Self> {
+        if std::env::var("NIMBUS_COMMIT_LINT").ok()?.parse::<bool>().ok() != Some(true) {
+            return None;
+        }
+
+        let mut config = Self::default();
+        if let Ok(types) = std::env::var("NIMBUS_COMMIT_LINT_TYPES") {
+            config.allowed_types = types
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(value) = std::env::var("NIMBUS_COMMIT_LINT_MAX_SUBJECT") {
+            match value.parse::<usize>() {
+                Ok(0) => config.max_subject_length = None,
+                Ok(max) => config.max_subject_length = Some(max),
+                Err(_) => warn!("Ignoring invalid NIMBUS_COMMIT_LINT_MAX_SUBJECT={}", value),
+            }
+        }
+        Some(config)
+    }
+
+    /// Violations in `commit`'s message
+    ///
+    /// Suggestions carry the commit sha as their `file`, and line 1 for
+    /// subject rules. A wrong or missing type is an error; style rules are
+    /// warnings. Merge commits are skipped, as git writes their messages.
+    pub fn lint(&self, commit: &Commit) -> Vec<AiSuggestion> {
+        if commit.parent_shas.len() > 1 {
+            return Vec::new();
+        }
+
+        let subject = commit.message.lines().next().unwrap_or("").trim_end();
+        let violation = |severity, suggestion: String| AiSuggestion {
+            file: commit.sha.clone(),
+            line: Some(1),
+            suggestion,
+            severity,
+        };
+
+        let mut violations = Vec::new();
+        if !self.allowed_types.is_empty() && !self.has_allowed_type(subject) {
+            violations.push(violation(
+                SuggestionSeverity::Error,
+                format!(
+                    "Subject {:?} must start with one of {} followed by \": \"",
+                    subject,
+                    self.allowed_types.join(", ")
+                ),
+            ));
+        }
+        if let Some(max) = self.max_subject_length {
+            let length = subject.chars().count();
+            if length > max {
+                violations.push(violation(
+                    SuggestionSeverity::Warning,
+                    format!("Subject is {} characters long, more than {}", length, max),
+                ));
+            }
+        }
+        if self.forbid_trailing_period && subject.ends_with('.') {
+            violations.push(violation(
+                SuggestionSeverity::Warning,
+                "Subject should not end with a period".to_string(),
+            ));
+        }
+        violations
+    }
+
+    /// Whether `subject` is `type[(scope)][!]: description` with an allowed type
+    fn has_allowed_type(&self, subject: &str) -> bool {
+        let Some((head, description)) = subject.split_once(':') else {
+            return false;
+        };
+        if !description.starts_with(' ') || description.trim().is_empty() {
+            return false;
+        }
+
+        let head = head.strip_suffix('!').unwrap_or(head);
+        let commit_type = match head.split_once('(') {
+            Some((commit_type, scope)) => match scope.strip_suffix(')') {
+                Some(scope) if !scope.is_empty() && !scope.contains(['(', ')']) => commit_type,
+                _ => return false,
+            },
+            None => head,
+        };
+        self.allowed_types.iter().any(|allowed| allowed == commit_type)
+    }
+}
+
+/// Handler linting the commits of every push
+pub struct CommitLinter {
+    config: CommitLintConfig,
+    bus: Arc<dyn EventBus>,
+}
+
+impl CommitLinter {
+    /// Lint pushes with `config`, publishing findings on `bus`
+    pub fn new(config: CommitLintConfig, bus: Arc<dyn EventBus>) -> Self {
+        Self { config, bus }
+    }
+}
+
+#[async_trait]
+impl EventHandler for CommitLinter {
+    async fn handle(&self, envelope: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let Event::Push { repository, commits, .. } = &envelope.event else {
+            return Ok(());
+        };
+
+        let suggestions: Vec<_> =
+            commits.iter().flat_map(|commit| self.config.lint(commit)).collect();
+        if suggestions.is_empty() {
+            return Ok(());
+        }
+        debug!("{} commit message violations in push to {}", suggestions.len(), repository);
+
+        let findings = EventEnvelope {
+            id: Uuid::new_v4(),
+            timestamp: time::OffsetDateTime::now_utc(),
+            seq: 0,
+            event: Event::AiAnalysisCompleted {
+                id: Uuid::new_v4(),
+                repository: repository.clone(),
+                suggestions,
+                plugin: PLUGIN_NAME.to_string(),
+            },
+            metadata: EventMetadata {
+                target_plugins: vec![],
+                priority: EventPriority::Normal,
+                persistent: true,
+                simulated: envelope.metadata.simulated,
+            },
+        };
+        self.bus.publish(findings).await
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::for_types([EventType::Push])
+    }
+}
@@ -0,0 +1,56 @@
+//! Dead letters: events a handler could not process
+//!
+//! Once a handler has exhausted its retries (or timed out), the envelope is
+//! handed to a [`DeadLetterSink`] so operators can inspect and replay it.
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use nimbus_types::events::EventEnvelope;
+use serde::{Deserialize, Serialize};
+
+/// An event abandoned by one handler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Subscription name of the handler that gave up
+    pub handler: String,
+    pub envelope: EventEnvelope,
+    /// Last error the handler reported
+    pub error: String,
+    pub recorded_at: time::OffsetDateTime,
+}
+
+/// Where the bus puts events a handler permanently failed on
+#[async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    async fn record(&self, handler: &str, envelope: EventEnvelope, error: String);
+}
+
+/// Keeps dead letters in memory for inspection
+#[derive(Debug, Default)]
+pub struct InMemoryDeadLetterSink {
+    entries: Mutex<Vec<DeadLetter>>,
+}
+
+impl InMemoryDeadLetterSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, oldest first
+    pub fn entries(&self) -> Vec<DeadLetter> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl DeadLetterSink for InMemoryDeadLetterSink {
+    async fn record(&self, handler: &str, envelope: EventEnvelope, error: String) {
+        self.entries.lock().unwrap().push(DeadLetter {
+            handler: handler.to_string(),
+            envelope,
+            error,
+            recorded_at: time::OffsetDateTime::now_utc(),
+        });
+    }
+}
@@ -0,0 +1,55 @@
+//! Bounded memory of recently seen envelope ids
+//!
+//! Plugins republish and upstream senders retry, so the same envelope can
+//! arrive more than once. Remembering the last few thousand ids is enough to
+//! catch those repeats without the window growing with traffic.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+use uuid::Uuid;
+
+pub(crate) struct SeenIds {
+    capacity: usize,
+    inner: Mutex<Window>,
+}
+
+#[derive(Default)]
+struct Window {
+    ids: HashSet<Uuid>,
+    /// Least recently seen first
+    order: VecDeque<Uuid>,
+}
+
+impl SeenIds {
+    /// Remember up to `capacity` ids; 0 disables deduplication
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, inner: Mutex::new(Window::default()) }
+    }
+
+    /// Record `id`, returning whether it was already in the window
+    ///
+    /// A repeat counts as a fresh sighting, so an id that keeps arriving
+    /// stays remembered.
+    pub(crate) fn check_and_insert(&self, id: Uuid) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+
+        let mut window = self.inner.lock().unwrap();
+        if !window.ids.insert(id) {
+            // Repeats are rare, so the linear scan stays off the common path
+            window.order.retain(|seen| *seen != id);
+            window.order.push_back(id);
+            return true;
+        }
+
+        window.order.push_back(id);
+        if window.order.len() > self.capacity
+            && let Some(oldest) = window.order.pop_front()
+        {
+            window.ids.remove(&oldest);
+        }
+        false
+    }
+}
@@ -0,0 +1,106 @@
+//! Subscription filters compiled once at subscribe time
+//!
+//! Branch patterns use `glob` syntax with `/` as the separator: `*` and `?`
+//! stay within one path segment, `**` spans segments, and `[...]` matches a
+//! character class. So `feature/*` matches `feature/auth` but not
+//! `feature/auth/hotfix`, while `feature/**` matches both.
+//!
+//! Patterns prefixed with `re:` are regular expressions instead, for names
+//! globs can't express: `re:^release/\d+\.\d+$` matches `release/1.2` but
+//! not `release/notes`. They are unanchored unless they say otherwise.
+//!
+//! Tag patterns follow the same rules, so `v*` matches `v1.0.0`, and so do
+//! repository names: `infra-*` matches every repository starting with
+//! `infra-`, while a plain name matches only itself.
+
+use glob::{MatchOptions, Pattern};
+use nimbus_types::events::{EventFilter, EventType};
+use regex::Regex;
+
+const BRANCH_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Prefix marking a branch pattern as a regular expression
+const REGEX_PREFIX: &str = "re:";
+
+/// A branch pattern in a subscription filter that isn't valid glob or
+/// regex syntax
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid branch pattern {pattern:?}: {message}")]
+pub struct InvalidBranchPattern {
+    pub pattern: String,
+    pub message: String,
+}
+
+/// An [`EventFilter`] with its repository, branch and tag patterns parsed
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledFilter {
+    pub event_types: Vec<EventType>,
+    pub repositories: Vec<BranchPattern>,
+    pub branches: Vec<BranchPattern>,
+    pub tags: Vec<BranchPattern>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum BranchPattern {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl BranchPattern {
+    fn compile(pattern: &str) -> Result<Self, InvalidBranchPattern> {
+        let invalid =
+            |message: String| InvalidBranchPattern { pattern: pattern.to_string(), message };
+        match pattern.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => Regex::new(regex).map(Self::Regex).map_err(|e| invalid(e.to_string())),
+            None => Pattern::new(pattern).map(Self::Glob).map_err(|e| invalid(e.msg.to_string())),
+        }
+    }
+
+    fn matches(&self, branch: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => pattern.matches_with(branch, BRANCH_MATCH),
+            Self::Regex(regex) => regex.is_match(branch),
+        }
+    }
+}
+
+impl CompiledFilter {
+    pub(crate) fn compile(filter: &EventFilter) -> Result<Self, InvalidBranchPattern> {
+        let repositories = filter
+            .repositories
+            .iter()
+            .map(|pattern| BranchPattern::compile(pattern))
+            .collect::<Result<_, _>>()?;
+        let branches = filter
+            .branches
+            .iter()
+            .map(|pattern| BranchPattern::compile(pattern))
+            .collect::<Result<_, _>>()?;
+        let tags = filter
+            .tags
+            .iter()
+            .map(|pattern| BranchPattern::compile(pattern))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { event_types: filter.event_types.clone(), repositories, branches, tags })
+    }
+
+    /// Whether `repository` matches any of the repository patterns
+    pub(crate) fn matches_repository(&self, repository: &str) -> bool {
+        self.repositories.iter().any(|pattern| pattern.matches(repository))
+    }
+
+    /// Whether `branch` matches any of the patterns
+    pub(crate) fn matches_branch(&self, branch: &str) -> bool {
+        self.branches.iter().any(|pattern| pattern.matches(branch))
+    }
+
+    /// Whether `tag` matches any of the tag patterns
+    pub(crate) fn matches_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|pattern| pattern.matches(tag))
+    }
+}
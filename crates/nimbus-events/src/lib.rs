@@ -3,70 +3,731 @@
 //! This is the heart of our plugin system. Events flow through here
 //! and plugins subscribe to what they care about.
 
-use std::collections::HashSet;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use futures::future;
 use nimbus_types::events::{
-    Event, EventBus as EventBusTrait, EventEnvelope, EventFilter, EventHandler, EventType,
+    DispatchReport, Event, EventBus as EventBusTrait, EventEnvelope, EventFilter, EventHandler,
+    EventType, OrderingGuarantee, SubscriptionInfo,
 };
-use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
+mod channel;
+pub mod commit_lint;
+pub mod dead_letter;
+mod dedup;
+mod filter;
 pub mod metrics;
+#[cfg(feature = "nats")]
+pub mod nats;
+mod queue;
+pub mod quiet_hours;
+mod recent;
+pub mod signing;
+pub mod store;
+pub mod subscriptions;
+mod tasks;
+
+/// How the processor hands received events to their handlers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// Each worker finishes one event before taking the next; with a single
+    /// worker that is global ordering
+    Sequential,
+    /// Events for the same repository always go to the same worker, so they
+    /// are handled in publish order while other repositories proceed
+    PerRepository,
+    /// Process every event as soon as it arrives (no ordering)
+    Concurrent,
+}
+
+/// What `publish` does when the event queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for the processor to make room
+    #[default]
+    Block,
+    /// Evict the oldest buffered event to make room for the new one
+    DropOldest,
+    /// Discard the new event
+    DropNewest,
+    /// Fail immediately with [`QueueFull`]
+    RejectErr,
+}
+
+/// Events buffered per worker in `PerRepository` mode
+const PARTITION_BUFFER: usize = 16;
+
+/// How long a handler may take before it is flagged or aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerTimeouts {
+    /// Past this a successful run is still counted, but also reported as slow
+    pub soft: Duration,
+    /// Past this the handler is aborted and counted as failed
+    pub hard: Duration,
+}
+
+impl Default for HandlerTimeouts {
+    fn default() -> Self {
+        Self { soft: Duration::from_secs(10), hard: Duration::from_secs(30) }
+    }
+}
+
+/// How often a failing handler is retried before the event is abandoned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+    /// Factor applied to the delay after each retry
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// No retries: a failed handler is logged and the event dropped for it
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Default::default() }
+    }
+
+    /// Delay before retrying after the given (1-based) failed attempt
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1) as i32;
+        self.base_delay.mul_f64(self.multiplier.max(1.0).powi(exponent))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: 3, base_delay: Duration::from_millis(100), multiplier: 2.0 }
+    }
+}
+
+/// Tunables for [`InMemoryEventBus`]
+#[derive(Debug, Clone)]
+pub struct EventBusConfig {
+    /// Capacity of the bounded event channel
+    pub buffer_size: usize,
+    /// What `publish` does when the channel is full
+    pub overflow_policy: OverflowPolicy,
+    /// How events are dispatched once received
+    pub dispatch_mode: DispatchMode,
+    /// Events processed at once in the `Sequential` and `PerRepository` modes
+    pub workers: usize,
+    /// Timeouts for handlers without an override
+    pub handler_timeouts: HandlerTimeouts,
+    /// Per-handler timeouts, keyed by subscription name
+    pub handler_timeout_overrides: HashMap<String, HandlerTimeouts>,
+    /// Retries for handlers that return an error
+    pub retry_policy: RetryPolicy,
+    /// How many processed events `recent_events` keeps; 0 disables it
+    pub recent_events_capacity: usize,
+    /// How often `start_health_monitor` polls each handler's `health_check`
+    pub health_check_interval: Duration,
+    /// How long `shutdown` waits for running handlers before aborting them
+    pub shutdown_grace_period: Duration,
+    /// How many recent envelope ids are remembered to drop repeats; 0
+    /// disables deduplication
+    pub dedup_window: usize,
+    /// Handler runs in flight at once, across all events; dispatch waits for
+    /// a slot before starting each one
+    pub max_in_flight_handlers: usize,
+}
+
+impl EventBusConfig {
+    /// Defaults, with the buffer size from `NIMBUS_EVENT_BUFFER`, the
+    /// handler timeout from `NIMBUS_EVENT_TIMEOUT_SECS` and the handler
+    /// concurrency limit from `NIMBUS_EVENT_MAX_IN_FLIGHT`
+    ///
+    /// Invalid values are logged and the default kept.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, valid: impl Fn(&T) -> bool) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = value.parse().ok().filter(valid);
+            if parsed.is_none() {
+                warn!("Ignoring invalid {}={}", name, value);
+            }
+            parsed
+        }
+
+        let mut config = Self::default();
+        if let Some(buffer_size) = var("NIMBUS_EVENT_BUFFER", |size: &usize| *size > 0) {
+            config.buffer_size = buffer_size;
+        }
+        if let Some(secs) = var("NIMBUS_EVENT_TIMEOUT_SECS", |secs: &u64| *secs > 0) {
+            let hard = Duration::from_secs(secs);
+            let soft = config.handler_timeouts.soft.min(hard);
+            config.handler_timeouts = HandlerTimeouts { soft, hard };
+        }
+        if let Some(limit) = var("NIMBUS_EVENT_MAX_IN_FLIGHT", |limit: &usize| *limit > 0) {
+            config.max_in_flight_handlers = limit;
+        }
+        config
+    }
+
+    /// Timeouts that apply to the named handler
+    pub fn timeouts_for(&self, handler: &str) -> HandlerTimeouts {
+        self.handler_timeout_overrides.get(handler).copied().unwrap_or(self.handler_timeouts)
+    }
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 1000,
+            overflow_policy: OverflowPolicy::Block,
+            dispatch_mode: DispatchMode::Sequential,
+            workers: 1,
+            handler_timeouts: HandlerTimeouts::default(),
+            handler_timeout_overrides: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            recent_events_capacity: 200,
+            health_check_interval: Duration::from_secs(30),
+            shutdown_grace_period: Duration::from_secs(30),
+            dedup_window: 10_000,
+            max_in_flight_handlers: 64,
+        }
+    }
+}
+
+/// How one handler's run on an event ended
+enum HandlerOutcome {
+    Succeeded,
+    /// Failed on every attempt, or panicked
+    Failed,
+    /// Aborted at the hard timeout
+    TimedOut,
+}
+
+/// A subscribed handler and its compiled filter
+type RegisteredHandler = (Arc<Box<dyn EventHandler>>, Arc<filter::CompiledFilter>);
+
+/// Handlers an event is dispatched to, by name, grouped by stage
+type Stages = BTreeMap<i32, Vec<(String, Arc<Box<dyn EventHandler>>)>>;
 
 /// In-memory event bus implementation
 ///
 /// This is designed for single-instance deployments.
-/// For multi-instance, we'd use Redis Pub/Sub or NATS.
+/// For multi-instance, use `nats::NatsEventBus` (feature `nats`).
 pub struct InMemoryEventBus {
-    /// Map of handler name to handler
-    handlers: Arc<DashMap<String, Arc<Box<dyn EventHandler>>>>,
+    /// Map of handler name to handler and its compiled filter
+    handlers: Arc<DashMap<String, RegisteredHandler>>,
     /// Map of event type to interested handler names for quick lookup
-    subscriptions: Arc<RwLock<DashMap<EventType, HashSet<String>>>>,
-    /// Channel for event distribution
-    event_sender: async_channel::Sender<EventEnvelope>,
-    event_receiver: async_channel::Receiver<EventEnvelope>,
+    subscriptions: Arc<DashMap<EventType, HashSet<String>>>,
+    /// Bounded queue for event distribution, highest priority first
+    queue: Arc<queue::PriorityQueue>,
     /// Metrics collector
     metrics: Arc<metrics::EventBusMetrics>,
+    /// Handler runs still in flight, across all events
+    handler_tasks: Arc<tasks::HandlerTasks>,
+    /// One permit per handler run allowed in flight
+    handler_permits: Arc<tokio::sync::Semaphore>,
+    /// Handlers whose last health check failed; nothing is dispatched to them
+    unhealthy: Arc<DashSet<String>>,
+    /// Timeouts given at subscribe time, taking precedence over the config
+    subscribed_timeouts: DashMap<String, HandlerTimeouts>,
+    /// Stages given at subscribe time; handlers not listed run at stage 0
+    subscribed_stages: DashMap<String, i32>,
+    /// Last processed events, for the dashboard activity feed
+    recent: recent::RecentEvents,
+    /// Ids of recently processed envelopes, to drop repeats
+    seen: dedup::SeenIds,
+    config: EventBusConfig,
+    /// Where events marked `persistent` are written before dispatch
+    store: Option<Arc<dyn store::EventStore>>,
+    /// Where events abandoned by a handler are recorded
+    dead_letters: Option<Arc<dyn dead_letter::DeadLetterSink>>,
+    /// Where endpoint-backed subscriptions are recorded to survive a restart
+    subscription_store: Option<Arc<dyn subscriptions::SubscriptionStore>>,
+    /// Last `seq` handed out by [`Self::stamp`]
+    last_seq: AtomicU64,
 }
 
 impl InMemoryEventBus {
     pub fn new(buffer_size: usize) -> Self {
-        let (sender, receiver) = async_channel::bounded(buffer_size);
+        Self::with_config(EventBusConfig { buffer_size, ..Default::default() })
+    }
 
+    pub fn with_config(config: EventBusConfig) -> Self {
+        let metrics = metrics::EventBusMetrics::new();
+        metrics.channel_capacity(config.buffer_size);
         Self {
             handlers: Arc::new(DashMap::new()),
-            subscriptions: Arc::new(RwLock::new(DashMap::new())),
-            event_sender: sender,
-            event_receiver: receiver,
-            metrics: Arc::new(metrics::EventBusMetrics::new()),
+            subscriptions: Arc::new(DashMap::new()),
+            queue: Arc::new(queue::PriorityQueue::new(config.buffer_size)),
+            metrics: Arc::new(metrics),
+            handler_tasks: Arc::new(tasks::HandlerTasks::default()),
+            handler_permits: Arc::new(tokio::sync::Semaphore::new(
+                config.max_in_flight_handlers.max(1),
+            )),
+            unhealthy: Arc::new(DashSet::new()),
+            subscribed_timeouts: DashMap::new(),
+            subscribed_stages: DashMap::new(),
+            recent: recent::RecentEvents::new(config.recent_events_capacity),
+            seen: dedup::SeenIds::new(config.dedup_window),
+            config,
+            store: None,
+            dead_letters: None,
+            subscription_store: None,
+            last_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Persist events marked `persistent` to `store` before dispatching them
+    pub fn with_store(mut self, store: Arc<dyn store::EventStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Record events a handler permanently failed on in `sink`
+    pub fn with_dead_letter_sink(mut self, sink: Arc<dyn dead_letter::DeadLetterSink>) -> Self {
+        self.dead_letters = Some(sink);
+        self
+    }
+
+    /// Record subscriptions made with [`Self::subscribe_persistent`] in `store`
+    pub fn with_subscription_store(
+        mut self,
+        store: Arc<dyn subscriptions::SubscriptionStore>,
+    ) -> Self {
+        self.subscription_store = Some(store);
+        self
+    }
+
+    /// Subscribe `handler`, which delivers to `plugin`, and record the
+    /// subscription so [`Self::restore_subscriptions`] can re-create it
+    ///
+    /// Without a subscription store this is a plain `subscribe`. If the
+    /// subscription can't be recorded the handler is unsubscribed again.
+    pub async fn subscribe_persistent(
+        &self,
+        name: String,
+        plugin: nimbus_types::Plugin,
+        handler: Box<dyn EventHandler>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let filter = handler.filter();
+        self.register(name.clone(), handler)?;
+
+        let Some(store) = &self.subscription_store else {
+            return Ok(());
+        };
+        let subscription =
+            subscriptions::PersistedSubscription { name: name.clone(), filter, plugin };
+        if let Err(e) = store.save(subscription).await {
+            self.handlers.remove(&name);
+            remove_from_index(&self.subscriptions, &name);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Re-subscribe the recorded subscriptions, returning how many were
+    ///
+    /// `restore` builds the handler for each; those it returns `None` for
+    /// are skipped but stay recorded. Does nothing without a subscription
+    /// store.
+    pub async fn restore_subscriptions(
+        &self,
+        restore: impl Fn(&subscriptions::PersistedSubscription) -> Option<Box<dyn EventHandler>>,
+    ) -> Result<usize, store::StoreError> {
+        let Some(store) = &self.subscription_store else {
+            return Ok(0);
+        };
+
+        let mut restored = 0;
+        for subscription in store.load().await? {
+            let Some(handler) = restore(&subscription) else {
+                debug!("Not restoring subscription {}", subscription.name);
+                continue;
+            };
+            match self.register(subscription.name.clone(), handler) {
+                Ok(()) => restored += 1,
+                Err(e) => warn!("Failed to restore subscription {}: {}", subscription.name, e),
+            }
+        }
+        info!("Restored {} subscriptions", restored);
+        Ok(restored)
+    }
+
+    /// Subscribe `handler`, aborting each of its runs after `timeout`
+    ///
+    /// Overrides the configured timeouts for this handler only; runs past
+    /// `timeout` count as timeouts without holding up other handlers.
+    pub async fn subscribe_with_timeout(
+        &self,
+        name: String,
+        handler: Box<dyn EventHandler>,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let soft = self.config.timeouts_for(&name).soft.min(timeout);
+        // In place before the handler is, so no event runs with the old timeout
+        self.subscribed_timeouts.insert(name.clone(), HandlerTimeouts { soft, hard: timeout });
+        let subscribed = self.subscribe(name.clone(), handler).await;
+        if subscribed.is_err() {
+            self.subscribed_timeouts.remove(&name);
+        }
+        subscribed
+    }
+
+    /// Subscribe `handler` to run at `stage` of each event it matches
+    ///
+    /// The handlers matching an event run stage by stage, lowest first: a
+    /// stage starts once every handler of the one before has finished,
+    /// successfully or not. Handlers within a stage run concurrently.
+    /// Plain `subscribe` uses stage 0.
+    pub async fn subscribe_with_stage(
+        &self,
+        name: String,
+        handler: Box<dyn EventHandler>,
+        stage: i32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // In place before the handler is, so no event runs it at stage 0
+        self.subscribed_stages.insert(name.clone(), stage);
+        let subscribed = self.subscribe(name.clone(), handler).await;
+        if subscribed.is_err() {
+            self.subscribed_stages.remove(&name);
+        }
+        subscribed
+    }
+
+    /// Receive every event matching `filter` on a channel
+    ///
+    /// Registers a handler that forwards matching envelopes to the returned
+    /// receiver, holding up to `buffer_size` of them; events arriving while
+    /// it is full are dropped for this receiver only. Once every clone of
+    /// the receiver is dropped the handler unsubscribes itself, right away
+    /// when called inside a Tokio runtime and otherwise at the next matching
+    /// event. A filter with malformed patterns yields a receiver that is
+    /// already closed.
+    pub fn subscribe_channel(&self, filter: EventFilter) -> async_channel::Receiver<EventEnvelope> {
+        let (sender, receiver) = async_channel::bounded(self.config.buffer_size.max(1));
+        let name = format!("channel-{}", uuid::Uuid::new_v4().simple());
+
+        let handlers = Arc::downgrade(&self.handlers);
+        let subscriptions = Arc::downgrade(&self.subscriptions);
+        let unsubscribe_name = name.clone();
+        let unsubscribe: Arc<dyn Fn() + Send + Sync> = Arc::new(move || {
+            if let (Some(handlers), Some(subscriptions)) =
+                (Weak::upgrade(&handlers), Weak::upgrade(&subscriptions))
+            {
+                handlers.remove(&unsubscribe_name);
+                remove_from_index(&subscriptions, &unsubscribe_name);
+            }
+        });
+
+        let (watcher, handler_dropped) = tokio::sync::oneshot::channel::<()>();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let watched = sender.clone();
+                let unsubscribe = unsubscribe.clone();
+                runtime.spawn(async move {
+                    tokio::select! {
+                        _ = watched.closed() => unsubscribe(),
+                        // Unsubscribed or the bus is gone: stop holding the channel open
+                        _ = handler_dropped => {}
+                    }
+                });
+            }
+            Err(_) => debug!("No runtime to watch channel {}, unsubscribing it lazily", name),
+        }
+
+        let handler = channel::ChannelHandler {
+            name: name.clone(),
+            sender,
+            filter,
+            unsubscribe,
+            _watcher: watcher,
+        };
+        if let Err(e) = self.register(name.clone(), Box::new(handler)) {
+            // Dropping the handler drops the only sender, closing the receiver
+            error!("Failed to subscribe channel {}: {}", name, e);
+        }
+        receiver
+    }
+
+    /// Add `handler` under `name`, indexed by the event types it wants
+    fn register(
+        &self,
+        name: String,
+        handler: Box<dyn EventHandler>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Registering handler: {}", name);
+
+        // Compile branch patterns once; malformed ones reject the subscription
+        let filter = handler.filter();
+        let compiled = filter::CompiledFilter::compile(&filter)?;
+
+        // Store handler
+        self.handlers.insert(name.clone(), (Arc::new(handler), Arc::new(compiled)));
+
+        // Update subscription index for quick lookup
+        if filter.event_types.is_empty() {
+            // Subscribe to all event types
+            for event_type in EventType::ALL {
+                self.subscriptions.entry(event_type).or_default().insert(name.clone());
+            }
+        } else {
+            // Subscribe to specific event types
+            for event_type in &filter.event_types {
+                self.subscriptions.entry(*event_type).or_default().insert(name.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Timeouts that apply to the named handler
+    fn timeouts_for(&self, handler: &str) -> HandlerTimeouts {
+        match self.subscribed_timeouts.get(handler) {
+            Some(timeouts) => *timeouts,
+            None => self.config.timeouts_for(handler),
         }
     }
 
+    /// Stage the named handler runs at
+    fn stage_of(&self, handler: &str) -> i32 {
+        self.subscribed_stages.get(handler).map_or(0, |stage| *stage)
+    }
+
+    /// Up to `limit` of the most recently processed events, oldest first
+    ///
+    /// Kept in memory whether or not the events are `persistent`.
+    pub fn recent_events(&self, limit: usize) -> Vec<EventEnvelope> {
+        self.recent.latest(limit)
+    }
+
     /// Start the event bus processor
     pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
         let bus = self.clone();
+        // Workers run inside this one task, so aborting it stops them all
+        tokio::spawn(async move {
+            let workers = bus.config.workers.max(1);
+            info!("Event bus started ({:?}, {} workers)", bus.config.dispatch_mode, workers);
+            match bus.config.dispatch_mode {
+                DispatchMode::Sequential => {
+                    future::join_all((0..workers).map(|_| async {
+                        loop {
+                            let (envelope, _processing) = bus.next_event().await;
+                            bus.process_event(envelope).await;
+                        }
+                    }))
+                    .await;
+                }
+                DispatchMode::PerRepository => bus.run_partitioned(workers).await,
+                DispatchMode::Concurrent => loop {
+                    let (envelope, processing) = bus.next_event().await;
+                    let bus = bus.clone();
+                    tokio::spawn(async move {
+                        let _processing = processing;
+                        bus.process_event(envelope).await
+                    });
+                },
+            }
+        })
+    }
+
+    /// Stop taking events and finish the ones already published
+    ///
+    /// Later `publish` calls fail with [`BusClosed`]. Waits, up to
+    /// `shutdown_grace_period` in all, for the processor started with
+    /// [`Self::start`] to empty the queue and for handler runs still in
+    /// flight, then flushes the event store. Handlers still running after the
+    /// grace period are aborted; returns how many were.
+    pub async fn shutdown(&self) -> usize {
+        self.queue.close();
+        let deadline = tokio::time::Instant::now() + self.config.shutdown_grace_period;
+        info!(
+            "Event bus shutting down, draining {} events and {} handler runs",
+            self.queue.unfinished(),
+            self.handler_tasks.len()
+        );
+
+        if tokio::time::timeout_at(deadline, self.queue.join()).await.is_err() {
+            warn!(
+                "{} events were not processed within the shutdown grace period",
+                self.queue.unfinished()
+            );
+        }
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        let aborted = self.handler_tasks.drain(remaining).await;
+        if aborted > 0 {
+            warn!("Aborted {} handler runs after the shutdown grace period", aborted);
+        }
+
+        if let Some(store) = &self.store {
+            match store.flush().await {
+                Ok(()) => debug!("Flushed the event store"),
+                Err(e) => error!("Failed to flush the event store: {}", e),
+            }
+        }
+        aborted
+    }
+
+    /// Poll every handler's `health_check` each `health_check_interval`
+    ///
+    /// Handlers failing the check (or not answering within the interval) get
+    /// no events until a later check passes.
+    pub fn start_health_monitor(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
-            info!("Event bus started");
+            let mut interval = tokio::time::interval(self.config.health_check_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
             loop {
-                match bus.event_receiver.recv().await {
-                    Ok(envelope) => {
-                        bus.process_event(envelope).await;
-                    }
-                    Err(_) => {
-                        warn!("Event channel closed, shutting down event bus");
-                        break;
+                interval.tick().await;
+                self.check_handler_health().await;
+            }
+        })
+    }
+
+    async fn check_handler_health(&self) {
+        let handlers: Vec<(String, Arc<Box<dyn EventHandler>>)> = self
+            .handlers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().0.clone()))
+            .collect();
+
+        let limit = self.config.health_check_interval;
+        let results = future::join_all(handlers.into_iter().map(|(name, handler)| async move {
+            let healthy =
+                tokio::time::timeout(limit, handler.health_check()).await.unwrap_or(false);
+            (name, healthy)
+        }))
+        .await;
+
+        for (name, healthy) in results {
+            self.metrics.handler_health(&name, healthy);
+            if healthy {
+                if self.unhealthy.remove(&name).is_some() {
+                    info!("Handler {} recovered, resuming dispatch", name);
+                }
+            } else if self.unhealthy.insert(name.clone()) {
+                warn!("Handler {} is unhealthy, pausing dispatch", name);
+            }
+        }
+    }
+
+    /// Route events to `workers` queues by repository, each drained in order
+    async fn run_partitioned(&self, workers: usize) {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..workers)
+            .map(|_| {
+                tokio::sync::mpsc::channel::<(EventEnvelope, queue::Processing)>(PARTITION_BUFFER)
+            })
+            .unzip();
+
+        let route = async {
+            loop {
+                let taken = self.next_event().await;
+                let worker = Self::worker_for(&taken.0, workers);
+                // Receivers live as long as this future, so sending can't fail
+                let _ = senders[worker].send(taken).await;
+            }
+        };
+        let drain = future::join_all(receivers.into_iter().map(|mut receiver| async move {
+            while let Some((envelope, _processing)) = receiver.recv().await {
+                self.process_event(envelope).await;
+            }
+        }));
+
+        future::join(route, drain).await;
+    }
+
+    /// Publish envelopes to be dispatched in the given order
+    ///
+    /// The batch is queued at the highest priority among its envelopes, so
+    /// the priority queue can't reorder it, though it may overtake
+    /// lower-priority events published earlier. Handlers then see the
+    /// envelopes in order as far as [`Self::ordering_guarantee`] allows:
+    /// always with the default single sequential worker, never in
+    /// `Concurrent` mode. Nothing is promised about ordering across separate
+    /// calls.
+    ///
+    /// The batch is queued whole or not at all: `DropNewest` drops all of it,
+    /// `RejectErr` and a batch larger than the queue fail with [`QueueFull`],
+    /// and `Block` and `DropOldest` wait for room.
+    pub async fn publish_ordered(
+        &self,
+        mut envelopes: Vec<EventEnvelope>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(first) = envelopes.first().map(|envelope| envelope.id) else {
+            return Ok(());
+        };
+        if self.queue.is_closed() {
+            return Err(Box::new(BusClosed { id: first }));
+        }
+        let capacity = self.queue.capacity();
+        if envelopes.len() > capacity {
+            return Err(Box::new(QueueFull { capacity, id: first }));
+        }
+        for envelope in &mut envelopes {
+            self.stamp(envelope);
+        }
+        match self.config.overflow_policy {
+            OverflowPolicy::Block | OverflowPolicy::DropOldest => {
+                self.queue.push_batch(envelopes).await
+            }
+            OverflowPolicy::DropNewest => {
+                if let Err(dropped) = self.queue.try_push_batch(envelopes) {
+                    for _ in &dropped {
+                        self.metrics.event_dropped();
                     }
+                    warn!("Event queue full, dropped batch of {} events", dropped.len());
                 }
             }
-        })
+            OverflowPolicy::RejectErr => {
+                if self.queue.try_push_batch(envelopes).is_err() {
+                    return Err(Box::new(QueueFull { capacity, id: first }));
+                }
+            }
+        }
+        self.metrics.queue_depth(self.queue.len());
+        Ok(())
+    }
+
+    /// Normalize `envelope` for publishing: UTC timestamp, next `seq`
+    ///
+    /// Sequence numbers start over with each bus, so they only order events
+    /// published by the same process.
+    fn stamp(&self, envelope: &mut EventEnvelope) {
+        envelope.timestamp = envelope.timestamp.to_offset(time::UtcOffset::UTC);
+        envelope.seq = self.last_seq.fetch_add(1, Ordering::Relaxed) + 1;
+    }
+
+    /// Events published but not yet taken for processing
+    pub fn current_depth(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Take the next event off the queue, keeping the depth gauge current
+    ///
+    /// Hold on to the guard until the event has been processed, so that
+    /// [`Self::shutdown`] waits for it.
+    async fn next_event(&self) -> (EventEnvelope, queue::Processing) {
+        let taken = self.queue.pop_tracked().await;
+        self.metrics.queue_depth(self.queue.len());
+        taken
     }
 
-    /// Process a single event
-    async fn process_event(&self, envelope: EventEnvelope) {
+    /// Worker a `PerRepository` event goes to; stable for a repository
+    fn worker_for(envelope: &EventEnvelope, workers: usize) -> usize {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        Self::extract_repository(&envelope.event).hash(&mut hasher);
+        (hasher.finish() % workers as u64) as usize
+    }
+
+    /// Process a single event, reporting how its handlers fared
+    async fn process_event(&self, envelope: EventEnvelope) -> DispatchReport {
+        // A republished or retried envelope was already dispatched once
+        if self.seen.check_and_insert(envelope.id) {
+            self.metrics.event_duplicate();
+            debug!("Dropping duplicate event {}", envelope.id);
+            return DispatchReport::default();
+        }
+
         let event_type = Self::event_type(&envelope.event);
         debug!("Processing event: {:?}", event_type);
 
@@ -74,81 +735,209 @@ impl InMemoryEventBus {
         self.metrics.event_received(event_type);
         let start = std::time::Instant::now();
 
+        // Persist before dispatch so a crash mid-dispatch doesn't lose the event
+        if let Some(store) = self.store.as_ref().filter(|_| envelope.metadata.persistent) {
+            match store.append(&envelope).await {
+                Ok(()) => debug!("Persisted event {}", envelope.id),
+                Err(e) => error!("Failed to persist event {}: {}", envelope.id, e),
+            }
+        }
+
+        self.recent.push(envelope.clone());
+
         // Get handlers interested in this event
-        let handler_names = {
-            let subs = self.subscriptions.read().await;
-            subs.get(&event_type).map(|entry| entry.value().clone()).unwrap_or_default()
-        };
+        let handler_names = self
+            .subscriptions
+            .get(&event_type)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_default();
 
-        // Dispatch to all interested handlers
-        let mut tasks = Vec::new();
+        // Interested handlers, grouped by stage
+        let mut stages = Stages::new();
         for name in handler_names {
+            if self.unhealthy.contains(&name) {
+                debug!("Skipping unhealthy handler {}", name);
+                continue;
+            }
             if let Some(handler_entry) = self.handlers.get(&name) {
-                let handler = handler_entry.clone();
-                let envelope_clone = envelope.clone();
-                let metrics = self.metrics.clone();
-                let handler_name = name.clone();
-
-                // Check if event matches handler's filter
-                if Self::matches_filter(&handler.filter(), &envelope_clone) {
-                    tasks.push(tokio::spawn(async move {
-                        debug!("Dispatching to handler: {}", handler_name);
-                        let handler_start = std::time::Instant::now();
-
-                        match handler.handle(envelope_clone).await {
-                            Ok(_) => {
-                                metrics.handler_success(&handler_name);
-                                debug!(
-                                    "Handler {} completed in {:?}",
-                                    handler_name,
-                                    handler_start.elapsed()
-                                );
-                            }
-                            Err(e) => {
-                                metrics.handler_failure(&handler_name);
-                                error!("Handler {} failed: {}", handler_name, e);
-                            }
-                        }
-                    }));
+                let (handler, filter) = handler_entry.value().clone();
+                drop(handler_entry);
+
+                // Check if event is addressed to this handler and matches its filter
+                if Self::is_targeted(&name, &envelope) && Self::matches_filter(&filter, &envelope) {
+                    stages.entry(self.stage_of(&name)).or_default().push((name, handler));
                 }
             }
         }
 
-        // Wait for all handlers to complete (with timeout)
-        let timeout = std::time::Duration::from_secs(30);
-        let results = tokio::time::timeout(timeout, future::join_all(tasks)).await;
+        // Stages run one after another, whatever the outcome of the last;
+        // every handler is bounded by its hard timeout
+        let mut report = DispatchReport::default();
+        let mut timed_out = false;
+        for (stage, handlers) in stages {
+            debug!("Dispatching stage {} to {} handlers", stage, handlers.len());
+            report.matched += handlers.len();
 
-        match results {
-            Ok(_) => {
-                self.metrics.event_processed(event_type, start.elapsed());
-                debug!("Event processing completed in {:?}", start.elapsed());
+            let mut tasks = Vec::new();
+            for (handler_name, handler) in handlers {
+                // Read back if the task panics, to label the failure
+                let attempt = Arc::new(AtomicU32::new(1));
+                // Waits here while the limit is reached, holding up this event only
+                let permit = self
+                    .handler_permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("handler semaphore is never closed");
+                let run = Self::run_handler(
+                    handler,
+                    handler_name.clone(),
+                    envelope.clone(),
+                    self.metrics.clone(),
+                    self.timeouts_for(&handler_name),
+                    self.config.retry_policy,
+                    self.dead_letters.clone(),
+                    attempt.clone(),
+                );
+                let task = self.handler_tasks.spawn(async move {
+                    let _permit = permit;
+                    run.await
+                });
+                tasks.push(async move { (handler_name, attempt, task.await) });
             }
-            Err(_) => {
-                self.metrics.event_timeout(event_type);
-                error!("Event processing timed out after {:?}", timeout);
+
+            for (handler_name, attempt, result) in future::join_all(tasks).await {
+                match result {
+                    Ok(HandlerOutcome::Succeeded) => report.succeeded += 1,
+                    Ok(HandlerOutcome::Failed) => report.failed += 1,
+                    Ok(HandlerOutcome::TimedOut) => {
+                        report.failed += 1;
+                        timed_out = true;
+                    }
+                    // A panic only takes down that handler's task; siblings are unaffected
+                    Err(e) if e.is_panic() => {
+                        report.failed += 1;
+                        let message = panic_message(e.into_panic());
+                        let attempt = metrics::Attempt::from_number(attempt.load(Ordering::SeqCst));
+                        self.metrics.handler_failure(&handler_name, attempt);
+                        self.metrics.handler_dead_letter(&handler_name);
+                        error!("Handler {} panicked: {}", handler_name, message);
+                        if let Some(sink) = &self.dead_letters {
+                            let error = format!("Handler panicked: {}", message);
+                            sink.record(&handler_name, envelope.clone(), error).await;
+                        }
+                    }
+                    Err(e) => {
+                        report.failed += 1;
+                        error!("Handler {} task did not complete: {}", handler_name, e);
+                    }
+                }
             }
         }
+        report.duration = start.elapsed();
+
+        if timed_out {
+            self.metrics.event_timeout(event_type);
+            error!("Event processing hit a handler timeout after {:?}", start.elapsed());
+        } else {
+            self.metrics.event_processed(event_type, start.elapsed());
+            debug!("Event processing completed in {:?}", start.elapsed());
+        }
+        report
     }
 
-    /// Determine event type from event
-    fn event_type(event: &Event) -> EventType {
-        match event {
-            Event::Push { .. } => EventType::Push,
-            Event::PullRequestOpened { .. }
-            | Event::PullRequestMerged { .. }
-            | Event::PullRequestClosed { .. } => EventType::PullRequest,
-            Event::TagCreated { .. } => EventType::Tag,
-            Event::RepositoryCreated { .. } | Event::RepositoryDeleted { .. } => {
-                EventType::Repository
+    /// Run one handler on an event, retrying failures per the retry policy
+    ///
+    /// Timeouts are not retried: a hung handler would only hang again. Abandoned events go to
+    /// the dead-letter sink, if any. `attempt` tracks the current 1-based attempt.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_handler(
+        handler: Arc<Box<dyn EventHandler>>,
+        handler_name: String,
+        envelope: EventEnvelope,
+        metrics: Arc<metrics::EventBusMetrics>,
+        timeouts: HandlerTimeouts,
+        retry_policy: RetryPolicy,
+        dead_letters: Option<Arc<dyn dead_letter::DeadLetterSink>>,
+        attempt: Arc<AtomicU32>,
+    ) -> HandlerOutcome {
+        debug!("Dispatching to handler: {}", handler_name);
+
+        loop {
+            let attempt_number = attempt.load(Ordering::SeqCst);
+            let label = metrics::Attempt::from_number(attempt_number);
+            let handler_start = std::time::Instant::now();
+
+            // Errors aren't Send, so only their message may outlive this match
+            let (failure, timed_out) =
+                match tokio::time::timeout(timeouts.hard, handler.handle(envelope.clone())).await {
+                    Ok(Ok(_)) => (None, false),
+                    Ok(Err(e)) => (Some(e.to_string()), false),
+                    Err(_) => (Some(format!("Timed out after {:?}", timeouts.hard)), true),
+                };
+            let elapsed = handler_start.elapsed();
+            metrics.handler_duration(&handler_name, elapsed);
+
+            let Some(message) = failure else {
+                metrics.handler_success(&handler_name, label);
+                if elapsed > timeouts.soft {
+                    metrics.handler_slow(&handler_name);
+                    warn!(
+                        "Handler {} succeeded but took {:?} (soft timeout {:?})",
+                        handler_name, elapsed, timeouts.soft
+                    );
+                } else {
+                    debug!("Handler {} completed in {:?}", handler_name, elapsed);
+                }
+                return HandlerOutcome::Succeeded;
+            };
+
+            metrics.handler_failure(&handler_name, label);
+            if timed_out || attempt_number >= retry_policy.max_attempts {
+                metrics.handler_dead_letter(&handler_name);
+                let outcome = if timed_out {
+                    metrics.handler_timeout(&handler_name);
+                    error!("Handler {} aborted: {} (hard timeout)", handler_name, message);
+                    HandlerOutcome::TimedOut
+                } else {
+                    error!(
+                        "Handler {} failed after {} attempts: {}",
+                        handler_name, attempt_number, message
+                    );
+                    HandlerOutcome::Failed
+                };
+                if let Some(sink) = &dead_letters {
+                    sink.record(&handler_name, envelope, message).await;
+                }
+                return outcome;
             }
-            Event::ReviewRequested { .. } | Event::ReviewSubmitted { .. } => EventType::Review,
-            Event::CiRunStarted { .. } | Event::CiRunCompleted { .. } => EventType::CiRun,
-            _ => EventType::Push, // Default fallback
+
+            let delay = retry_policy.delay_after(attempt_number);
+            metrics.handler_retry(&handler_name);
+            warn!(
+                "Handler {} failed (attempt {}), retrying in {:?}: {}",
+                handler_name, attempt_number, delay, message
+            );
+            tokio::time::sleep(delay).await;
+            attempt.fetch_add(1, Ordering::SeqCst);
         }
     }
 
+    /// Determine event type from event
+    fn event_type(event: &Event) -> EventType {
+        event.event_type()
+    }
+
+    /// Check if an event is addressed to the named handler
+    ///
+    /// Events without `target_plugins` are broadcast to every handler.
+    fn is_targeted(handler_name: &str, envelope: &EventEnvelope) -> bool {
+        let targets = &envelope.metadata.target_plugins;
+        targets.is_empty() || targets.iter().any(|target| target == handler_name)
+    }
+
     /// Check if an event matches a handler's filter
-    fn matches_filter(filter: &EventFilter, envelope: &EventEnvelope) -> bool {
+    fn matches_filter(filter: &filter::CompiledFilter, envelope: &EventEnvelope) -> bool {
         // Check event type filter
         if !filter.event_types.is_empty() {
             let event_type = Self::event_type(&envelope.event);
@@ -160,22 +949,27 @@ impl InMemoryEventBus {
         // Check repository filter
         if !filter.repositories.is_empty() {
             let repo_name = Self::extract_repository(&envelope.event);
-            if let Some(repo) = repo_name {
-                if !filter.repositories.contains(&repo) {
-                    return false;
-                }
+            if let Some(repo) = repo_name
+                && !filter.matches_repository(&repo)
+            {
+                return false;
             }
         }
 
         // Check branch filter (glob patterns)
-        if !filter.branches.is_empty() {
-            if let Some(branch) = Self::extract_branch(&envelope.event) {
-                let matches =
-                    filter.branches.iter().any(|pattern| glob_match::glob_match(pattern, &branch));
-                if !matches {
-                    return false;
-                }
-            }
+        if !filter.branches.is_empty()
+            && let Some(branch) = Self::extract_branch(&envelope.event)
+            && !filter.matches_branch(&branch)
+        {
+            return false;
+        }
+
+        // Check tag filter (same pattern syntax as branches)
+        if !filter.tags.is_empty()
+            && let Some(tag) = Self::extract_tag(&envelope.event)
+            && !filter.matches_tag(&tag)
+        {
+            return false;
         }
 
         true
@@ -185,15 +979,18 @@ impl InMemoryEventBus {
     fn extract_repository(event: &Event) -> Option<String> {
         match event {
             Event::Push { repository, .. }
+            | Event::BranchDeleted { repository, .. }
             | Event::PullRequestOpened { repository, .. }
             | Event::PullRequestMerged { repository, .. }
             | Event::PullRequestClosed { repository, .. }
             | Event::TagCreated { repository, .. }
+            | Event::TagDeleted { repository, .. }
             | Event::RepositoryDeleted { repository, .. }
             | Event::CiRunStarted { repository, .. }
             | Event::CiRunCompleted { repository, .. }
             | Event::ReviewRequested { repository, .. }
             | Event::ReviewSubmitted { repository, .. }
+            | Event::ReviewCommentAdded { repository, .. }
             | Event::AiAnalysisRequested { repository, .. }
             | Event::AiAnalysisCompleted { repository, .. } => Some(repository.clone()),
             Event::RepositoryCreated { repository } => Some(repository.name.clone()),
@@ -203,17 +1000,72 @@ impl InMemoryEventBus {
     /// Extract branch from event
     fn extract_branch(event: &Event) -> Option<String> {
         match event {
-            Event::Push { branch, .. } | Event::CiRunStarted { branch, .. } => Some(branch.clone()),
+            Event::Push { branch, .. }
+            | Event::BranchDeleted { branch, .. }
+            | Event::CiRunStarted { branch, .. } => Some(branch.clone()),
             Event::PullRequestOpened { from_branch, .. } => Some(from_branch.clone()),
             _ => None,
         }
     }
+
+    /// Extract tag name from event
+    fn extract_tag(event: &Event) -> Option<String> {
+        match event {
+            Event::TagCreated { tag, .. } | Event::TagDeleted { tag, .. } => Some(tag.clone()),
+            _ => None,
+        }
+    }
 }
 
 #[async_trait]
 impl EventBusTrait for InMemoryEventBus {
-    async fn publish(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
-        self.event_sender.send(event).await.map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
+    async fn publish(&self, mut event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        if self.queue.is_closed() {
+            return Err(Box::new(BusClosed { id: event.id }));
+        }
+        self.stamp(&mut event);
+        match self.config.overflow_policy {
+            OverflowPolicy::Block => self.queue.push(event).await,
+            OverflowPolicy::DropOldest => {
+                if let Some(evicted) = self.queue.push_evicting_oldest(event) {
+                    self.metrics.event_dropped();
+                    warn!("Event queue full, dropped oldest event {}", evicted.id);
+                }
+            }
+            OverflowPolicy::DropNewest => {
+                if let Err(dropped) = self.queue.try_push(event) {
+                    self.metrics.event_dropped();
+                    warn!("Event queue full, dropped new event {}", dropped.id);
+                }
+            }
+            OverflowPolicy::RejectErr => {
+                if let Err(rejected) = self.queue.try_push(event) {
+                    let capacity = self.queue.capacity();
+                    return Err(Box::new(QueueFull { capacity, id: rejected.id }));
+                }
+            }
+        }
+        self.metrics.queue_depth(self.queue.len());
+        Ok(())
+    }
+
+    /// Dispatch inline, skipping the queue, so the caller learns the outcome
+    ///
+    /// On timeout the handlers keep running in the background; only the
+    /// report is lost.
+    async fn publish_and_wait(
+        &self,
+        mut event: EventEnvelope,
+        timeout: Duration,
+    ) -> Result<DispatchReport, Box<dyn std::error::Error>> {
+        let id = event.id;
+        if self.queue.is_closed() {
+            return Err(Box::new(BusClosed { id }));
+        }
+        self.stamp(&mut event);
+        tokio::time::timeout(timeout, self.process_event(event))
+            .await
+            .map_err(|_| format!("Event {} was not handled within {:?}", id, timeout).into())
     }
 
     async fn subscribe(
@@ -221,36 +1073,7 @@ impl EventBusTrait for InMemoryEventBus {
         name: String,
         handler: Box<dyn EventHandler>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Registering handler: {}", name);
-
-        // Store handler
-        let handler = Arc::new(handler);
-        self.handlers.insert(name.clone(), handler.clone());
-
-        // Update subscription index for quick lookup
-        let filter = handler.filter();
-        let subs = self.subscriptions.write().await;
-
-        if filter.event_types.is_empty() {
-            // Subscribe to all event types
-            for event_type in [
-                EventType::Push,
-                EventType::PullRequest,
-                EventType::Tag,
-                EventType::Repository,
-                EventType::Review,
-                EventType::CiRun,
-            ] {
-                subs.entry(event_type).or_insert_with(HashSet::new).insert(name.clone());
-            }
-        } else {
-            // Subscribe to specific event types
-            for event_type in &filter.event_types {
-                subs.entry(*event_type).or_insert_with(HashSet::new).insert(name.clone());
-            }
-        }
-
-        Ok(())
+        self.register(name, handler)
     }
 
     async fn unsubscribe(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
@@ -258,44 +1081,75 @@ impl EventBusTrait for InMemoryEventBus {
 
         // Remove handler
         self.handlers.remove(name);
+        self.unhealthy.remove(name);
+        self.subscribed_timeouts.remove(name);
+        self.subscribed_stages.remove(name);
 
         // Remove from subscription index
-        let subs = self.subscriptions.write().await;
-        for mut entry in subs.iter_mut() {
-            entry.value_mut().remove(name);
-        }
+        remove_from_index(&self.subscriptions, name);
 
+        if let Some(store) = &self.subscription_store {
+            store.remove(name).await?;
+        }
         Ok(())
     }
 
     async fn subscriber_count(&self) -> usize {
         self.handlers.len()
     }
-}
 
-// Re-export for convenience
-pub use nimbus_types::events::{EventMetadata, EventPriority};
+    async fn subscriptions(&self) -> Vec<SubscriptionInfo> {
+        // Collect first so no map guard is held while awaiting health checks
+        let mut handlers: Vec<(String, Arc<Box<dyn EventHandler>>)> = self
+            .handlers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().0.clone()))
+            .collect();
+        handlers.sort_by(|a, b| a.0.cmp(&b.0));
 
-// Add glob matching support
-mod glob_match {
-    pub fn glob_match(pattern: &str, text: &str) -> bool {
-        // Simple glob implementation - in production use `glob` crate
-        if pattern == "*" {
-            return true;
-        }
-        if pattern.starts_with('*') && pattern.ends_with('*') {
-            let inner = &pattern[1..pattern.len() - 1];
-            return text.contains(inner);
-        }
-        if let Some(stripped) = pattern.strip_prefix('*') {
-            return text.ends_with(stripped);
-        }
-        if let Some(stripped) = pattern.strip_suffix('*') {
-            return text.starts_with(stripped);
+        future::join_all(handlers.into_iter().map(|(name, handler)| async move {
+            SubscriptionInfo {
+                filter: handler.filter(),
+                healthy: handler.health_check().await,
+                stage: self.stage_of(&name),
+                name,
+            }
+        }))
+        .await
+    }
+
+    fn ordering_guarantee(&self) -> OrderingGuarantee {
+        match (self.config.dispatch_mode, self.config.workers) {
+            (DispatchMode::Concurrent, _) => OrderingGuarantee::None,
+            (_, 0 | 1) => OrderingGuarantee::Global,
+            (DispatchMode::Sequential, _) => OrderingGuarantee::None,
+            (DispatchMode::PerRepository, _) => OrderingGuarantee::PerRepository,
         }
-        pattern == text
     }
 }
 
+/// Drop `name` from the event type index
+fn remove_from_index(subscriptions: &DashMap<EventType, HashSet<String>>, name: &str) {
+    for mut entry in subscriptions.iter_mut() {
+        entry.value_mut().remove(name);
+    }
+}
+
+/// Best-effort text of a panic payload
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+// Re-export for convenience
+pub use filter::InvalidBranchPattern;
+#[cfg(feature = "nats")]
+pub use nats::NatsEventBus;
+pub use nimbus_types::events::{EventMetadata, EventPriority};
+pub use queue::{BusClosed, QueueFull};
+
 #[cfg(test)]
 mod tests;
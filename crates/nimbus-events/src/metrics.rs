@@ -4,21 +4,55 @@
 
 use std::time::Duration;
 
-use prometheus::{CounterVec, HistogramVec, register_counter_vec, register_histogram_vec};
+use prometheus::{
+    CounterVec, HistogramVec, IntCounter, IntGauge, IntGaugeVec, register_counter_vec,
+    register_histogram_vec, register_int_counter, register_int_gauge, register_int_gauge_vec,
+};
 
 use nimbus_types::events::EventType;
 
+/// Whether a handler execution was the first try at an event or a retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attempt {
+    First,
+    Retry,
+}
+
+impl Attempt {
+    /// Label for the given 1-based attempt number
+    pub fn from_number(attempt: u32) -> Self {
+        if attempt <= 1 { Attempt::First } else { Attempt::Retry }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Attempt::First => "first",
+            Attempt::Retry => "retry",
+        }
+    }
+}
+
 pub struct EventBusMetrics {
     events_received: CounterVec,
     events_processed: HistogramVec,
     events_timeout: CounterVec,
+    events_duplicate: IntCounter,
+    events_dropped: IntCounter,
     handler_success: CounterVec,
     handler_failure: CounterVec,
+    handler_duration: HistogramVec,
+    handler_slow: CounterVec,
+    handler_timeout: CounterVec,
+    handler_retry: CounterVec,
+    handler_dead_letter: CounterVec,
+    handler_unhealthy: IntGaugeVec,
+    queue_depth: IntGauge,
+    channel_capacity: IntGauge,
 }
 
 impl EventBusMetrics {
     pub fn new() -> Self {
-        Self {
+        let metrics = Self {
             events_received: register_counter_vec!(
                 "nimbus_events_received_total",
                 "Total number of events received",
@@ -68,38 +102,191 @@ impl EventBusMetrics {
                 .unwrap()
             }),
 
+            events_duplicate: register_int_counter!(
+                "nimbus_events_duplicate_total",
+                "Total number of events dropped as repeats of a recently seen envelope id"
+            )
+            .unwrap_or_else(|_| {
+                IntCounter::new(
+                    "nimbus_events_duplicate_total",
+                    "Total number of events dropped as repeats of a recently seen envelope id",
+                )
+                .unwrap()
+            }),
+
+            events_dropped: register_int_counter!(
+                "nimbus_events_dropped_total",
+                "Total number of events discarded because the queue was full"
+            )
+            .unwrap_or_else(|_| {
+                IntCounter::new(
+                    "nimbus_events_dropped_total",
+                    "Total number of events discarded because the queue was full",
+                )
+                .unwrap()
+            }),
+
             handler_success: register_counter_vec!(
                 "nimbus_handler_success_total",
-                "Total number of successful handler executions",
-                &["handler"]
+                "Total number of successful handler executions, by first attempt or retry",
+                &["handler", "attempt"]
             )
             .unwrap_or_else(|_| {
                 CounterVec::new(
                     prometheus::Opts::new(
                         "nimbus_handler_success_total",
-                        "Total number of successful handler executions",
+                        "Total number of successful handler executions, by first attempt or retry",
                     ),
-                    &["handler"],
+                    &["handler", "attempt"],
                 )
                 .unwrap()
             }),
 
             handler_failure: register_counter_vec!(
                 "nimbus_handler_failure_total",
-                "Total number of failed handler executions",
-                &["handler"]
+                "Total number of failed handler executions, by first attempt or retry",
+                &["handler", "attempt"]
             )
             .unwrap_or_else(|_| {
                 CounterVec::new(
                     prometheus::Opts::new(
                         "nimbus_handler_failure_total",
-                        "Total number of failed handler executions",
+                        "Total number of failed handler executions, by first attempt or retry",
+                    ),
+                    &["handler", "attempt"],
+                )
+                .unwrap()
+            }),
+
+            handler_duration: register_histogram_vec!(
+                "nimbus_handler_duration_seconds",
+                "Time taken by each handler execution, retries counted separately",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                HistogramVec::new(
+                    prometheus::HistogramOpts::new(
+                        "nimbus_handler_duration_seconds",
+                        "Time taken by each handler execution, retries counted separately",
+                    ),
+                    &["handler"],
+                )
+                .unwrap()
+            }),
+
+            handler_slow: register_counter_vec!(
+                "nimbus_handler_slow_total",
+                "Total number of handler executions that succeeded past their soft timeout",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                CounterVec::new(
+                    prometheus::Opts::new(
+                        "nimbus_handler_slow_total",
+                        "Total number of handler executions that succeeded past their soft timeout",
+                    ),
+                    &["handler"],
+                )
+                .unwrap()
+            }),
+
+            handler_timeout: register_counter_vec!(
+                "nimbus_handler_timeout_total",
+                "Total number of handler executions aborted at their hard timeout",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                CounterVec::new(
+                    prometheus::Opts::new(
+                        "nimbus_handler_timeout_total",
+                        "Total number of handler executions aborted at their hard timeout",
+                    ),
+                    &["handler"],
+                )
+                .unwrap()
+            }),
+
+            handler_retry: register_counter_vec!(
+                "nimbus_handler_retry_total",
+                "Total number of handler executions retried after a failure",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                CounterVec::new(
+                    prometheus::Opts::new(
+                        "nimbus_handler_retry_total",
+                        "Total number of handler executions retried after a failure",
+                    ),
+                    &["handler"],
+                )
+                .unwrap()
+            }),
+
+            handler_dead_letter: register_counter_vec!(
+                "nimbus_handler_dead_letter_total",
+                "Total number of events a handler gave up on after its last attempt",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                CounterVec::new(
+                    prometheus::Opts::new(
+                        "nimbus_handler_dead_letter_total",
+                        "Total number of events a handler gave up on after its last attempt",
                     ),
                     &["handler"],
                 )
                 .unwrap()
             }),
+
+            handler_unhealthy: register_int_gauge_vec!(
+                "nimbus_handler_unhealthy",
+                "1 while a handler's health check fails and dispatch to it is paused",
+                &["handler"]
+            )
+            .unwrap_or_else(|_| {
+                IntGaugeVec::new(
+                    prometheus::Opts::new(
+                        "nimbus_handler_unhealthy",
+                        "1 while a handler's health check fails and dispatch to it is paused",
+                    ),
+                    &["handler"],
+                )
+                .unwrap()
+            }),
+
+            queue_depth: register_int_gauge!(
+                "nimbus_event_queue_depth",
+                "Events published but not yet taken for processing"
+            )
+            .unwrap_or_else(|_| {
+                IntGauge::new(
+                    "nimbus_event_queue_depth",
+                    "Events published but not yet taken for processing",
+                )
+                .unwrap()
+            }),
+
+            channel_capacity: register_int_gauge!(
+                "nimbus_event_channel_capacity",
+                "Events the queue holds before publishers wait"
+            )
+            .unwrap_or_else(|_| {
+                IntGauge::new(
+                    "nimbus_event_channel_capacity",
+                    "Events the queue holds before publishers wait",
+                )
+                .unwrap()
+            }),
+        };
+
+        // Export zeros for every event type so the series exist before the
+        // first event and rates start from a known value
+        for event_type in EventType::ALL {
+            let label = format!("{:?}", event_type);
+            metrics.events_received.with_label_values(&[&label]);
+            metrics.events_timeout.with_label_values(&[&label]);
         }
+        metrics
     }
 
     pub fn event_received(&self, event_type: EventType) {
@@ -116,12 +303,118 @@ impl EventBusMetrics {
         self.events_timeout.with_label_values(&[&format!("{:?}", event_type)]).inc();
     }
 
-    pub fn handler_success(&self, handler: &str) {
-        self.handler_success.with_label_values(&[handler]).inc();
+    pub fn event_duplicate(&self) {
+        self.events_duplicate.inc();
+    }
+
+    pub fn event_dropped(&self) {
+        self.events_dropped.inc();
+    }
+
+    pub fn handler_success(&self, handler: &str, attempt: Attempt) {
+        self.handler_success.with_label_values(&[handler, attempt.as_str()]).inc();
+    }
+
+    pub fn handler_failure(&self, handler: &str, attempt: Attempt) {
+        self.handler_failure.with_label_values(&[handler, attempt.as_str()]).inc();
+    }
+
+    pub fn handler_duration(&self, handler: &str, duration: Duration) {
+        self.handler_duration.with_label_values(&[handler]).observe(duration.as_secs_f64());
+    }
+
+    pub fn handler_slow(&self, handler: &str) {
+        self.handler_slow.with_label_values(&[handler]).inc();
+    }
+
+    pub fn handler_timeout(&self, handler: &str) {
+        self.handler_timeout.with_label_values(&[handler]).inc();
+    }
+
+    pub fn handler_retry(&self, handler: &str) {
+        self.handler_retry.with_label_values(&[handler]).inc();
+    }
+
+    pub fn handler_dead_letter(&self, handler: &str) {
+        self.handler_dead_letter.with_label_values(&[handler]).inc();
+    }
+
+    pub fn handler_health(&self, handler: &str, healthy: bool) {
+        self.handler_unhealthy.with_label_values(&[handler]).set(i64::from(!healthy));
+    }
+
+    pub fn queue_depth(&self, depth: usize) {
+        self.queue_depth.set(depth as i64);
+    }
+
+    pub fn channel_capacity(&self, capacity: usize) {
+        self.channel_capacity.set(capacity as i64);
+    }
+
+    /// Successful executions, first attempts and retries together
+    pub fn handler_success_count(&self, handler: &str) -> u64 {
+        [Attempt::First, Attempt::Retry]
+            .into_iter()
+            .map(|attempt| self.handler_attempt_success_count(handler, attempt))
+            .sum()
+    }
+
+    pub fn handler_attempt_success_count(&self, handler: &str, attempt: Attempt) -> u64 {
+        self.handler_success.with_label_values(&[handler, attempt.as_str()]).get() as u64
+    }
+
+    /// Number of executions timed and their total seconds
+    pub fn handler_duration_samples(&self, handler: &str) -> (u64, f64) {
+        let histogram = self.handler_duration.with_label_values(&[handler]);
+        (histogram.get_sample_count(), histogram.get_sample_sum())
+    }
+
+    pub fn handler_slow_count(&self, handler: &str) -> u64 {
+        self.handler_slow.with_label_values(&[handler]).get() as u64
+    }
+
+    /// Failed executions, first attempts and retries together
+    pub fn handler_failure_count(&self, handler: &str) -> u64 {
+        [Attempt::First, Attempt::Retry]
+            .into_iter()
+            .map(|attempt| self.handler_attempt_failure_count(handler, attempt))
+            .sum()
+    }
+
+    pub fn handler_attempt_failure_count(&self, handler: &str, attempt: Attempt) -> u64 {
+        self.handler_failure.with_label_values(&[handler, attempt.as_str()]).get() as u64
+    }
+
+    pub fn handler_timeout_count(&self, handler: &str) -> u64 {
+        self.handler_timeout.with_label_values(&[handler]).get() as u64
+    }
+
+    pub fn handler_retry_count(&self, handler: &str) -> u64 {
+        self.handler_retry.with_label_values(&[handler]).get() as u64
+    }
+
+    pub fn handler_dead_letter_count(&self, handler: &str) -> u64 {
+        self.handler_dead_letter.with_label_values(&[handler]).get() as u64
+    }
+
+    pub fn duplicate_count(&self) -> u64 {
+        self.events_duplicate.get()
+    }
+
+    pub fn dropped_count(&self) -> u64 {
+        self.events_dropped.get()
+    }
+
+    pub fn queue_depth_value(&self) -> i64 {
+        self.queue_depth.get()
+    }
+
+    pub fn channel_capacity_value(&self) -> i64 {
+        self.channel_capacity.get()
     }
 
-    pub fn handler_failure(&self, handler: &str) {
-        self.handler_failure.with_label_values(&[handler]).inc();
+    pub fn is_handler_unhealthy(&self, handler: &str) -> bool {
+        self.handler_unhealthy.with_label_values(&[handler]).get() != 0
     }
 }
 
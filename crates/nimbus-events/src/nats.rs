@@ -0,0 +1,200 @@
+//! NATS-backed event bus, for deployments with more than one instance
+//!
+//! Envelopes are published as JSON to `<prefix>.<event type>`, e.g.
+//! `nimbus.events.pull_request`. Each handler subscribes to the subjects of
+//! the event types its filter names (all of them for an empty list) in a
+//! queue group named after the handler, so every handler sees each event
+//! once however many replicas register it. Repository, branch and target
+//! filtering happen client-side, the same way [`InMemoryEventBus`] does it.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::StreamExt;
+use nimbus_types::events::{
+    DispatchReport, EventBus as EventBusTrait, EventEnvelope, EventHandler, EventType,
+    SubscriptionInfo,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::InMemoryEventBus;
+use crate::filter::CompiledFilter;
+
+/// Subject prefix used unless configured otherwise
+pub const DEFAULT_SUBJECT_PREFIX: &str = "nimbus.events";
+
+/// A registered handler and the task feeding it
+struct NatsSubscription {
+    handler: Arc<Box<dyn EventHandler>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+pub struct NatsEventBus {
+    client: async_nats::Client,
+    subject_prefix: String,
+    handlers: DashMap<String, NatsSubscription>,
+}
+
+impl NatsEventBus {
+    /// Connect to the NATS server at `url`
+    pub async fn connect(url: &str) -> Result<Self, async_nats::ConnectError> {
+        let client = async_nats::connect(url).await?;
+        info!("Event bus connected to NATS at {}", url);
+        Ok(Self {
+            client,
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+            handlers: DashMap::new(),
+        })
+    }
+
+    /// Connect to `NATS_URL` (default `nats://localhost:4222`)
+    pub async fn from_env() -> Result<Self, async_nats::ConnectError> {
+        let url = std::env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".into());
+        Self::connect(&url).await
+    }
+
+    /// Publish and subscribe under `prefix` instead of `nimbus.events`
+    pub fn with_subject_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.subject_prefix = prefix.into();
+        self
+    }
+
+    /// Subject events of `event_type` are published to
+    pub fn subject_for(&self, event_type: EventType) -> String {
+        format!("{}.{}", self.subject_prefix, event_type.as_str())
+    }
+
+    /// Feed envelopes from `messages` to `handler`, one at a time
+    async fn run_handler(
+        name: String,
+        handler: Arc<Box<dyn EventHandler>>,
+        filter: CompiledFilter,
+        mut messages: futures::stream::SelectAll<async_nats::Subscriber>,
+    ) {
+        while let Some(message) = messages.next().await {
+            let envelope: EventEnvelope = match serde_json::from_slice(&message.payload) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    warn!("Ignoring malformed event on {}: {}", message.subject, e);
+                    continue;
+                }
+            };
+            if !InMemoryEventBus::is_targeted(&name, &envelope)
+                || !InMemoryEventBus::matches_filter(&filter, &envelope)
+            {
+                continue;
+            }
+
+            debug!("Dispatching event {} to handler {}", envelope.id, name);
+            let id = envelope.id;
+            if let Err(e) = handler.handle(envelope).await {
+                error!("Handler {} failed on event {}: {}", name, id, e);
+            }
+        }
+        debug!("Subscription for handler {} closed", name);
+    }
+}
+
+#[async_trait]
+impl EventBusTrait for NatsEventBus {
+    async fn publish(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let subject = self.subject_for(InMemoryEventBus::event_type(&event.event));
+        let payload = serde_json::to_vec(&event)?;
+        self.client.publish(subject, payload.into()).await?;
+        Ok(())
+    }
+
+    /// Not supported: handlers may run on any instance, so their outcome
+    /// can't be observed here
+    async fn publish_and_wait(
+        &self,
+        event: EventEnvelope,
+        _timeout: Duration,
+    ) -> Result<DispatchReport, Box<dyn std::error::Error>> {
+        Err(format!(
+            "Event {}: NatsEventBus can't wait for handlers running on other instances",
+            event.id
+        )
+        .into())
+    }
+
+    async fn subscribe(
+        &self,
+        name: String,
+        handler: Box<dyn EventHandler>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Registering handler: {}", name);
+
+        let filter = handler.filter();
+        let compiled = CompiledFilter::compile(&filter)?;
+        let event_types = if filter.event_types.is_empty() {
+            EventType::ALL.to_vec()
+        } else {
+            filter.event_types
+        };
+
+        // Subscribe before returning so nothing published afterwards is missed
+        let mut subscribers = Vec::with_capacity(event_types.len());
+        for event_type in event_types {
+            let subject = self.subject_for(event_type);
+            subscribers.push(self.client.queue_subscribe(subject, name.clone()).await?);
+        }
+        self.client.flush().await?;
+
+        let handler = Arc::new(handler);
+        let task = tokio::spawn(Self::run_handler(
+            name.clone(),
+            handler.clone(),
+            compiled,
+            futures::stream::select_all(subscribers),
+        ));
+        if let Some(previous) = self.handlers.insert(name, NatsSubscription { handler, task }) {
+            previous.task.abort();
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Unregistering handler: {}", name);
+
+        // Dropping the subscribers with the task unsubscribes from NATS
+        if let Some((_, subscription)) = self.handlers.remove(name) {
+            subscription.task.abort();
+        }
+        Ok(())
+    }
+
+    async fn subscriber_count(&self) -> usize {
+        self.handlers.len()
+    }
+
+    async fn subscriptions(&self) -> Vec<SubscriptionInfo> {
+        let mut handlers: Vec<(String, Arc<Box<dyn EventHandler>>)> = self
+            .handlers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().handler.clone()))
+            .collect();
+        handlers.sort_by(|a, b| a.0.cmp(&b.0));
+
+        futures::future::join_all(handlers.into_iter().map(|(name, handler)| async move {
+            SubscriptionInfo {
+                filter: handler.filter(),
+                healthy: handler.health_check().await,
+                // Each subscription consumes on its own; stages aren't supported
+                stage: 0,
+                name,
+            }
+        }))
+        .await
+    }
+}
+
+impl Drop for NatsEventBus {
+    fn drop(&mut self) {
+        for entry in self.handlers.iter() {
+            entry.value().task.abort();
+        }
+    }
+}
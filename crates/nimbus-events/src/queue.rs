@@ -0,0 +1,260 @@
+//! Bounded priority queue feeding the event processor
+//!
+//! Higher `EventPriority` envelopes are taken first; within a priority,
+//! envelopes keep their publish order. A batch from
+//! [`PriorityQueue::push_batch`] is queued at its highest priority, so it is
+//! taken in the given order. What happens when it is full is up to
+//! the caller: wait ([`PriorityQueue::push`]), give up
+//! ([`PriorityQueue::try_push`]) or make room
+//! ([`PriorityQueue::push_evicting_oldest`]).
+//!
+//! For shutdown, the queue also counts envelopes not yet finished with:
+//! queued, or popped with [`PriorityQueue::pop_tracked`] and still being
+//! processed. [`PriorityQueue::join`] waits for that count to reach zero.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+use nimbus_types::events::{EventEnvelope, EventPriority};
+use tokio::sync::{Notify, Semaphore};
+
+struct Queued {
+    /// Publish order, used to keep FIFO within a priority
+    seq: u64,
+    /// The envelope's priority, or its batch's highest
+    priority: EventPriority,
+    envelope: EventEnvelope,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap: highest priority, then lowest seq, first
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for Queued {}
+
+/// Returned by `publish` under `OverflowPolicy::RejectErr` when the queue is full
+#[derive(Debug, thiserror::Error)]
+#[error("Event queue is full ({capacity} events buffered), event {id} rejected")]
+pub struct QueueFull {
+    pub capacity: usize,
+    pub id: uuid::Uuid,
+}
+
+/// Returned by `publish` once the bus has been shut down
+#[derive(Debug, thiserror::Error)]
+#[error("Event bus is shutting down, event {id} rejected")]
+pub struct BusClosed {
+    pub id: uuid::Uuid,
+}
+
+pub(crate) struct PriorityQueue {
+    capacity: usize,
+    heap: Mutex<(BinaryHeap<Queued>, u64)>,
+    /// Free slots; `push` waits on this when the queue is full
+    free: Semaphore,
+    /// Queued envelopes; `pop` waits on this when the queue is empty
+    ready: Semaphore,
+    /// Set by `close`; the bus stops publishing once it is
+    closed: AtomicBool,
+    /// Envelopes queued or popped by `pop_tracked` and not yet finished
+    unfinished: AtomicUsize,
+    /// Signalled whenever `unfinished` drops to zero
+    drained: Notify,
+}
+
+/// Marks an envelope from [`PriorityQueue::pop_tracked`] finished when dropped
+pub(crate) struct Processing(Arc<PriorityQueue>);
+
+impl Drop for Processing {
+    fn drop(&mut self) {
+        self.0.finish_one();
+    }
+}
+
+impl PriorityQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: Mutex::new((BinaryHeap::with_capacity(capacity), 0)),
+            free: Semaphore::new(capacity),
+            ready: Semaphore::new(0),
+            closed: AtomicBool::new(false),
+            unfinished: AtomicUsize::new(0),
+            drained: Notify::new(),
+        }
+    }
+
+    /// Mark the queue closed; callers check [`Self::is_closed`] before pushing
+    pub(crate) fn close(&self) {
+        self.closed.store(true, AtomicOrdering::SeqCst);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Queue an envelope, waiting for space if the queue is full
+    pub(crate) async fn push(&self, envelope: EventEnvelope) {
+        // The semaphores are never closed, so acquiring can't fail
+        self.free.acquire().await.expect("queue semaphore closed").forget();
+        self.insert(envelope);
+    }
+
+    /// Queue envelopes to be taken in this order, waiting for space for all
+    ///
+    /// Never completes for a batch larger than the capacity; callers check.
+    pub(crate) async fn push_batch(&self, envelopes: Vec<EventEnvelope>) {
+        let permits = u32::try_from(envelopes.len()).expect("batch fits the queue");
+        self.free.acquire_many(permits).await.expect("queue semaphore closed").forget();
+        self.insert_batch(envelopes);
+    }
+
+    /// Queue envelopes in this order if there is space for all, handing them back otherwise
+    pub(crate) fn try_push_batch(
+        &self,
+        envelopes: Vec<EventEnvelope>,
+    ) -> Result<(), Vec<EventEnvelope>> {
+        let Ok(permits) = u32::try_from(envelopes.len()) else {
+            return Err(envelopes);
+        };
+        match self.free.try_acquire_many(permits) {
+            Ok(permit) => {
+                permit.forget();
+                self.insert_batch(envelopes);
+                Ok(())
+            }
+            Err(_) => Err(envelopes),
+        }
+    }
+
+    /// Queue an envelope if there is space, handing it back otherwise
+    pub(crate) fn try_push(&self, envelope: EventEnvelope) -> Result<(), Box<EventEnvelope>> {
+        match self.free.try_acquire() {
+            Ok(permit) => {
+                permit.forget();
+                self.insert(envelope);
+                Ok(())
+            }
+            Err(_) => Err(Box::new(envelope)),
+        }
+    }
+
+    /// Queue an envelope, evicting the oldest queued one if full
+    ///
+    /// Returns the evicted envelope; that is `envelope` itself if there was
+    /// nothing to evict (a zero capacity queue, or every slot claimed by a
+    /// push that hasn't inserted yet).
+    pub(crate) fn push_evicting_oldest(&self, envelope: EventEnvelope) -> Option<EventEnvelope> {
+        let envelope = match self.try_push(envelope) {
+            Ok(()) => return None,
+            Err(envelope) => *envelope,
+        };
+
+        let mut guard = self.heap.lock().unwrap();
+        let (heap, next_seq) = &mut *guard;
+        let mut queued = std::mem::take(heap).into_vec();
+        let Some(oldest) = queued.iter().enumerate().min_by_key(|(_, q)| q.seq).map(|(i, _)| i)
+        else {
+            return Some(envelope);
+        };
+        // Swap in place: the number of queued envelopes, and so the permits, is unchanged
+        let priority = envelope.metadata.priority;
+        let evicted =
+            std::mem::replace(&mut queued[oldest], Queued { seq: *next_seq, priority, envelope });
+        *next_seq += 1;
+        *heap = BinaryHeap::from(queued);
+        Some(evicted.envelope)
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Add an envelope a free slot has already been claimed for
+    fn insert(&self, envelope: EventEnvelope) {
+        self.insert_batch(vec![envelope]);
+    }
+
+    /// Add envelopes free slots have already been claimed for
+    ///
+    /// They share the highest priority among them and take consecutive
+    /// sequence numbers under one lock, so nothing sorts between them.
+    fn insert_batch(&self, envelopes: Vec<EventEnvelope>) {
+        let count = envelopes.len();
+        let Some(priority) = envelopes.iter().map(|e| e.metadata.priority).max() else {
+            return;
+        };
+        {
+            let mut guard = self.heap.lock().unwrap();
+            let (heap, next_seq) = &mut *guard;
+            for envelope in envelopes {
+                heap.push(Queued { seq: *next_seq, priority, envelope });
+                *next_seq += 1;
+            }
+        }
+        self.unfinished.fetch_add(count, AtomicOrdering::SeqCst);
+        self.ready.add_permits(count);
+    }
+
+    /// Envelopes waiting to be taken
+    pub(crate) fn len(&self) -> usize {
+        self.heap.lock().unwrap().0.len()
+    }
+
+    /// Take the highest priority envelope, waiting for one if empty
+    #[cfg(test)]
+    pub(crate) async fn pop(self: &Arc<Self>) -> EventEnvelope {
+        self.pop_tracked().await.0
+    }
+
+    /// Take the highest priority envelope, waiting for one if empty
+    ///
+    /// The envelope only counts as finished for [`Self::join`] once the
+    /// returned guard is dropped.
+    pub(crate) async fn pop_tracked(self: &Arc<Self>) -> (EventEnvelope, Processing) {
+        self.ready.acquire().await.expect("queue semaphore closed").forget();
+        let queued =
+            self.heap.lock().unwrap().0.pop().expect("a ready permit implies a queued envelope");
+        self.free.add_permits(1);
+        (queued.envelope, Processing(self.clone()))
+    }
+
+    /// Envelopes queued or still being processed
+    pub(crate) fn unfinished(&self) -> usize {
+        self.unfinished.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Wait until every queued envelope has been popped and finished with
+    pub(crate) async fn join(&self) {
+        loop {
+            let drained = self.drained.notified();
+            if self.unfinished() == 0 {
+                return;
+            }
+            drained.await;
+        }
+    }
+
+    fn finish_one(&self) {
+        if self.unfinished.fetch_sub(1, AtomicOrdering::SeqCst) == 1 {
+            self.drained.notify_waiters();
+        }
+    }
+}
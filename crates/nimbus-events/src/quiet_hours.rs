@@ -0,0 +1,268 @@
+//! Quiet hours for notification handlers
+//!
+//! Wraps a notifying handler (Slack, email, ...) so that non-critical events
+//! arriving inside the quiet window are held back and delivered once the
+//! window ends. `EventPriority::Critical` events always go out immediately.
+//!
+//! Deferred events have already been acknowledged to the bus, so the wrapper
+//! retries failed deliveries itself, with the bus's [`RetryPolicy`], and
+//! hands the ones it gives up on to a [`DeadLetterSink`].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use nimbus_types::events::{EventEnvelope, EventFilter, EventHandler, EventPriority};
+use time::{OffsetDateTime, Time};
+use time_tz::{OffsetDateTimeExt, TimeZone, Tz};
+use tracing::{debug, error, warn};
+
+use crate::RetryPolicy;
+use crate::dead_letter::DeadLetterSink;
+
+/// Daily window during which notifications are deferred
+#[derive(Debug, Clone, Copy)]
+pub struct QuietHours {
+    /// Local time the window opens
+    pub start: Time,
+    /// Local time the window closes (may be earlier than `start` to span midnight)
+    pub end: Time,
+    /// Time zone the window is expressed in, so it follows daylight saving
+    zone: &'static Tz,
+}
+
+/// A time zone name missing from the IANA database
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown time zone {0:?}")]
+pub struct UnknownTimeZone(pub String);
+
+impl QuietHours {
+    /// Window from `start` to `end` local time in the IANA zone `zone`,
+    /// e.g. `Europe/Berlin`
+    pub fn new(start: Time, end: Time, zone: &str) -> Result<Self, UnknownTimeZone> {
+        let zone = time_tz::timezones::get_by_name(zone)
+            .ok_or_else(|| UnknownTimeZone(zone.to_string()))?;
+        Ok(Self { start, end, zone })
+    }
+
+    /// IANA name of the window's time zone
+    pub fn zone(&self) -> &str {
+        self.zone.name()
+    }
+
+    /// Whether `at` falls inside the quiet window
+    pub fn contains(&self, at: OffsetDateTime) -> bool {
+        let local = at.to_timezone(self.zone).time();
+        if self.start <= self.end {
+            self.start <= local && local < self.end
+        } else {
+            // Window spans midnight, e.g. 22:00-07:00
+            local >= self.start || local < self.end
+        }
+    }
+}
+
+/// What happens to an event deferred while the queue is already full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeferOverflow {
+    /// Drop the oldest deferred event to make room for the new one
+    #[default]
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Deliver the new event right away, quiet hours or not
+    DeliverNow,
+}
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+/// Handler wrapper deferring non-critical events during quiet hours
+#[derive(Clone)]
+pub struct QuietHoursHandler {
+    inner: Arc<dyn EventHandler>,
+    window: QuietHours,
+    deferred: Arc<Mutex<VecDeque<EventEnvelope>>>,
+    /// Most events held back at once
+    max_deferred: usize,
+    overflow: DeferOverflow,
+    retry_policy: RetryPolicy,
+    /// Subscription name and sink for deferred events given up on
+    dead_letters: Option<(String, Arc<dyn DeadLetterSink>)>,
+    clock: Clock,
+}
+
+impl QuietHoursHandler {
+    pub fn new(inner: impl EventHandler + 'static, window: QuietHours) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            window,
+            deferred: Arc::new(Mutex::new(VecDeque::new())),
+            max_deferred: 1000,
+            overflow: DeferOverflow::default(),
+            retry_policy: RetryPolicy::default(),
+            dead_letters: None,
+            clock: Arc::new(OffsetDateTime::now_utc),
+        }
+    }
+
+    /// Hold back at most `max` events, handling the rest per `overflow`
+    pub fn with_max_deferred(mut self, max: usize, overflow: DeferOverflow) -> Self {
+        self.max_deferred = max;
+        self.overflow = overflow;
+        self
+    }
+
+    /// Retry failed deliveries of deferred events per `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Record deferred events that are dropped or keep failing in `sink`,
+    /// under the subscription name `handler`
+    pub fn with_dead_letter_sink(
+        mut self,
+        handler: impl Into<String>,
+        sink: Arc<dyn DeadLetterSink>,
+    ) -> Self {
+        self.dead_letters = Some((handler.into(), sink));
+        self
+    }
+
+    /// Replace the wall clock (for tests)
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Number of events waiting for the quiet window to end
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.lock().unwrap().len()
+    }
+
+    /// Deliver deferred events if the quiet window has ended
+    ///
+    /// Returns how many events were delivered; those that still fail after
+    /// the retries go to the dead-letter sink.
+    pub async fn flush_deferred(&self) -> usize {
+        if self.window.contains((self.clock)()) {
+            return 0;
+        }
+
+        let mut delivered = 0;
+        loop {
+            // Don't hold the lock across the handler call
+            let next = self.deferred.lock().unwrap().pop_front();
+            let Some(envelope) = next else { break };
+
+            match self.deliver(envelope.clone()).await {
+                Ok(()) => delivered += 1,
+                Err(message) => self.dead_letter(envelope, message).await,
+            }
+        }
+        delivered
+    }
+
+    /// Hand `envelope` to the inner handler, retrying per the retry policy
+    async fn deliver(&self, envelope: EventEnvelope) -> Result<(), String> {
+        let mut attempt = 1;
+        loop {
+            // Errors aren't Send, so only their message is kept
+            let failure = self.inner.handle(envelope.clone()).await.err().map(|e| e.to_string());
+            let Some(message) = failure else {
+                return Ok(());
+            };
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(message);
+            }
+            let delay = self.retry_policy.delay_after(attempt);
+            warn!(
+                "Deferred notification {} failed (attempt {}), retrying in {:?}: {}",
+                envelope.id, attempt, delay, message
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn dead_letter(&self, envelope: EventEnvelope, message: String) {
+        error!("Deferred notification {} abandoned: {}", envelope.id, message);
+        if let Some((handler, sink)) = &self.dead_letters {
+            sink.record(handler, envelope, message).await;
+        }
+    }
+
+    /// Periodically flush deferred events so they go out when the window ends
+    pub fn spawn_flusher(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let handler = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let delivered = handler.flush_deferred().await;
+                if delivered > 0 {
+                    debug!("Delivered {} deferred notifications", delivered);
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl EventHandler for QuietHoursHandler {
+    async fn handle(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let quiet = self.window.contains((self.clock)());
+
+        if quiet && event.metadata.priority != EventPriority::Critical {
+            // Decided under the lock, acted on after it is released
+            let overflowing = {
+                let mut deferred = self.deferred.lock().unwrap();
+                if deferred.len() < self.max_deferred {
+                    debug!("Quiet hours: deferring event {}", event.id);
+                    deferred.push_back(event);
+                    return Ok(());
+                }
+                match self.overflow {
+                    DeferOverflow::DropOldest => {
+                        let oldest = deferred.pop_front();
+                        deferred.push_back(event);
+                        oldest.map(Err)
+                    }
+                    DeferOverflow::DropNewest => Some(Err(event)),
+                    DeferOverflow::DeliverNow => Some(Ok(event)),
+                }
+            };
+            match overflowing {
+                Some(Ok(event)) => {
+                    warn!("Quiet hours queue full, delivering event {} now", event.id);
+                    return self.inner.handle(event).await;
+                }
+                Some(Err(dropped)) => {
+                    let message = format!("Dropped: {} events already deferred", self.max_deferred);
+                    self.dead_letter(dropped, message).await;
+                }
+                None => {}
+            }
+            return Ok(());
+        }
+
+        if !quiet {
+            // Anything held back from the last quiet window goes out first
+            self.flush_deferred().await;
+        }
+
+        self.inner.handle(event).await
+    }
+
+    fn filter(&self) -> EventFilter {
+        self.inner.filter()
+    }
+
+    async fn health_check(&self) -> bool {
+        self.inner.health_check().await
+    }
+}
@@ -0,0 +1,61 @@
+//! Lock-free ring of the most recently processed events
+//!
+//! Lets the dashboard activity feed work without configuring an event store.
+//! Writers claim a slot with an atomic counter and swap the envelope in, so
+//! recording never blocks event processing.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use arc_swap::ArcSwapOption;
+use nimbus_types::events::EventEnvelope;
+
+/// An envelope tagged with the sequence number of the write that stored it
+struct Slot {
+    seq: u64,
+    envelope: EventEnvelope,
+}
+
+pub(crate) struct RecentEvents {
+    slots: Box<[ArcSwapOption<Slot>]>,
+    /// Sequence number the next write will use
+    next: AtomicU64,
+}
+
+impl RecentEvents {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity).map(|_| ArcSwapOption::empty()).collect(),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Record an envelope, overwriting the oldest once full
+    pub(crate) fn push(&self, envelope: EventEnvelope) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let seq = self.next.fetch_add(1, Ordering::AcqRel);
+        self.slot(seq).store(Some(Arc::new(Slot { seq, envelope })));
+    }
+
+    /// Up to `limit` of the newest envelopes, oldest first
+    ///
+    /// Slots overwritten or not yet filled while reading are skipped rather
+    /// than returned out of order.
+    pub(crate) fn latest(&self, limit: usize) -> Vec<EventEnvelope> {
+        let end = self.next.load(Ordering::Acquire);
+        let count = limit.min(self.slots.len()) as u64;
+
+        (end.saturating_sub(count)..end)
+            .filter_map(|seq| {
+                let slot = self.slot(seq).load_full()?;
+                (slot.seq == seq).then(|| slot.envelope.clone())
+            })
+            .collect()
+    }
+
+    fn slot(&self, seq: u64) -> &ArcSwapOption<Slot> {
+        &self.slots[(seq % self.slots.len() as u64) as usize]
+    }
+}
@@ -0,0 +1,92 @@
+//! Canonical JSON and HMAC signatures for payloads leaving the instance
+//!
+//! Signatures are computed over bytes, so producer and consumer must agree on
+//! the exact serialization. Canonical JSON sorts object keys and drops all
+//! insignificant whitespace, independent of struct field order or map type.
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Prefix of the signature header value, as in `sha256=<hex>`
+pub const SIGNATURE_PREFIX: &str = "sha256=";
+
+/// Serialize `value` as canonical JSON: sorted keys, no whitespace
+pub fn to_canonical_json<T: Serialize>(value: &T) -> Result<Vec<u8>, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut out = Vec::new();
+    write_canonical(&value, &mut out)?;
+    Ok(out)
+}
+
+fn write_canonical(value: &Value, out: &mut Vec<u8>) -> Result<(), serde_json::Error> {
+    match value {
+        Value::Object(map) => {
+            // Sort explicitly: `serde_json::Map` keeps insertion order when
+            // the `preserve_order` feature is enabled anywhere in the build
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            out.push(b'{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serde_json::to_writer(&mut *out, key)?;
+                out.push(b':');
+                write_canonical(value, out)?;
+            }
+            out.push(b'}');
+        }
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical(item, out)?;
+            }
+            out.push(b']');
+        }
+        scalar => serde_json::to_writer(&mut *out, scalar)?,
+    }
+    Ok(())
+}
+
+/// HMAC-SHA256 of `body`, formatted as `sha256=<hex>`
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("{}{}", SIGNATURE_PREFIX, hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Check a `sha256=<hex>` signature over `body` in constant time
+pub fn verify(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some(expected) =
+        signature.strip_prefix(SIGNATURE_PREFIX).and_then(|hex| hex::decode(hex).ok())
+    else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Canonical body for `value` and its signature, for the sending side
+pub fn sign_json<T: Serialize>(
+    secret: &[u8],
+    value: &T,
+) -> Result<(Vec<u8>, String), serde_json::Error> {
+    let body = to_canonical_json(value)?;
+    let signature = sign(secret, &body);
+    Ok((body, signature))
+}
+
+/// Verify a signature against the canonical form of `value`, for the
+/// receiving side after it has parsed the payload
+pub fn verify_json<T: Serialize>(secret: &[u8], value: &T, signature: &str) -> bool {
+    to_canonical_json(value).is_ok_and(|body| verify(secret, &body, signature))
+}
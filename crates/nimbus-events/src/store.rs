@@ -0,0 +1,286 @@
+//! Persistent, tamper-evident event storage
+//!
+//! Each stored record carries the hash of the record before it, so editing,
+//! reordering or removing a record breaks the chain at a detectable offset.
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use nimbus_types::events::{EventEnvelope, EventFilter};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::InMemoryEventBus;
+use crate::filter::{CompiledFilter, InvalidBranchPattern};
+
+/// `prev_hash` of the first record in a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// An event as written to the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvent {
+    /// Position in the log, starting at 0
+    pub offset: u64,
+    /// `hash` of the previous record, or [`GENESIS_HASH`]
+    pub prev_hash: String,
+    /// SHA-256 over the offset, `prev_hash` and envelope
+    pub hash: String,
+    pub envelope: EventEnvelope,
+}
+
+impl StoredEvent {
+    /// Chain `envelope` onto the record with hash `prev_hash`
+    pub fn new(offset: u64, prev_hash: &str, envelope: EventEnvelope) -> Self {
+        let hash = Self::compute_hash(offset, prev_hash, &envelope);
+        Self { offset, prev_hash: prev_hash.to_string(), hash, envelope }
+    }
+
+    fn compute_hash(offset: u64, prev_hash: &str, envelope: &EventEnvelope) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(offset.to_be_bytes());
+        hasher.update(prev_hash.as_bytes());
+        // Envelopes always serialize; the fields are plain data
+        hasher.update(serde_json::to_vec(envelope).expect("envelope serializes"));
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Where and how a log's hash chain is broken
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IntegrityError {
+    #[error("Record at offset {offset} has been modified")]
+    Modified { offset: u64 },
+
+    #[error("Record at offset {offset} does not follow the previous record")]
+    BrokenLink { offset: u64 },
+
+    #[error("Expected offset {expected}, found {found}: records are missing or reordered")]
+    OffsetMismatch { expected: u64, found: u64 },
+}
+
+impl IntegrityError {
+    /// Log offset at which the chain stops being trustworthy
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::Modified { offset } | Self::BrokenLink { offset } => *offset,
+            Self::OffsetMismatch { expected, .. } => *expected,
+        }
+    }
+}
+
+/// Walk `records` from the start, confirming every link in the chain
+///
+/// Truncating the end of a log can't be detected from the records alone;
+/// compare the last hash against a separately kept copy for that.
+pub fn verify_chain(records: &[StoredEvent]) -> Result<(), IntegrityError> {
+    let mut prev_hash = GENESIS_HASH;
+    for (expected, record) in (0u64..).zip(records) {
+        if record.offset != expected {
+            return Err(IntegrityError::OffsetMismatch { expected, found: record.offset });
+        }
+        if record.prev_hash != prev_hash {
+            return Err(IntegrityError::BrokenLink { offset: record.offset });
+        }
+        if StoredEvent::compute_hash(record.offset, &record.prev_hash, &record.envelope)
+            != record.hash
+        {
+            return Err(IntegrityError::Modified { offset: record.offset });
+        }
+        prev_hash = &record.hash;
+    }
+    Ok(())
+}
+
+/// Append-only, hash-chained event log held in memory
+#[derive(Debug, Default)]
+pub struct EventLog {
+    records: Vec<StoredEvent>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild a log from previously stored records, without verifying them
+    pub fn from_records(records: Vec<StoredEvent>) -> Self {
+        Self { records }
+    }
+
+    pub fn append(&mut self, envelope: EventEnvelope) -> &StoredEvent {
+        let offset = self.records.len() as u64;
+        let record = StoredEvent::new(offset, self.head(), envelope);
+        self.records.push(record);
+        &self.records[self.records.len() - 1]
+    }
+
+    /// Add a record already chained onto [`EventLog::head`]
+    fn push(&mut self, record: StoredEvent) {
+        debug_assert_eq!(record.prev_hash, self.head());
+        self.records.push(record);
+    }
+
+    /// Hash of the newest record, or [`GENESIS_HASH`] when empty
+    pub fn head(&self) -> &str {
+        self.records.last().map(|record| record.hash.as_str()).unwrap_or(GENESIS_HASH)
+    }
+
+    pub fn records(&self) -> &[StoredEvent] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Confirm no record has been modified, removed or reordered
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        verify_chain(&self.records)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Event store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Malformed event record: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    InvalidFilter(#[from] InvalidBranchPattern),
+}
+
+/// Durable storage for events marked `persistent`
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    /// Durably record an event
+    async fn append(&self, envelope: &EventEnvelope) -> Result<(), StoreError>;
+
+    /// Events with a timestamp at or after `since`, oldest first
+    async fn load_since(&self, since: OffsetDateTime) -> Result<Vec<EventEnvelope>, StoreError>;
+
+    /// Up to `limit` events matching `filter` from strictly before `before`,
+    /// newest first
+    ///
+    /// `filter` matches exactly as it would for a bus subscriber, so
+    /// repository, branch and tag patterns work here too. Page back through
+    /// history by passing the timestamp of the last event as `before`.
+    async fn query_events(
+        &self,
+        filter: EventFilter,
+        before: Option<OffsetDateTime>,
+        limit: usize,
+    ) -> Result<Vec<EventEnvelope>, StoreError>;
+
+    /// Make sure everything appended so far is on disk; called at shutdown
+    async fn flush(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+/// [`EventStore`] writing the hash-chained log as newline-delimited JSON
+pub struct FileEventStore {
+    path: PathBuf,
+    inner: Mutex<FileLog>,
+}
+
+struct FileLog {
+    log: EventLog,
+    file: tokio::fs::File,
+}
+
+impl FileEventStore {
+    /// Open the log at `path`, creating it if needed and loading existing records
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+
+        let records = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<StoredEvent>, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?;
+
+        Ok(Self { path, inner: Mutex::new(FileLog { log: EventLog::from_records(records), file }) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Confirm the records on disk still form an unbroken chain
+    pub async fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        self.inner.lock().await.log.verify_integrity()
+    }
+}
+
+#[async_trait]
+impl EventStore for FileEventStore {
+    async fn append(&self, envelope: &EventEnvelope) -> Result<(), StoreError> {
+        let mut inner = self.inner.lock().await;
+
+        let offset = inner.log.len() as u64;
+        let record = StoredEvent::new(offset, inner.log.head(), envelope.clone());
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        // Only extend the in-memory chain once the record is on disk
+        inner.file.write_all(&line).await?;
+        inner.file.sync_data().await?;
+        inner.log.push(record);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), StoreError> {
+        // Appends already sync their data; this also syncs file metadata
+        self.inner.lock().await.file.sync_all().await?;
+        Ok(())
+    }
+
+    async fn load_since(&self, since: OffsetDateTime) -> Result<Vec<EventEnvelope>, StoreError> {
+        let inner = self.inner.lock().await;
+        Ok(inner
+            .log
+            .records()
+            .iter()
+            .filter(|record| record.envelope.timestamp >= since)
+            .map(|record| record.envelope.clone())
+            .collect())
+    }
+
+    async fn query_events(
+        &self,
+        filter: EventFilter,
+        before: Option<OffsetDateTime>,
+        limit: usize,
+    ) -> Result<Vec<EventEnvelope>, StoreError> {
+        let filter = CompiledFilter::compile(&filter)?;
+        let inner = self.inner.lock().await;
+        // Append order is close to, but not exactly, publish order; equal
+        // timestamps fall back to the bus's sequence, then to append order
+        let mut matching: Vec<&EventEnvelope> = inner
+            .log
+            .records()
+            .iter()
+            .rev()
+            .map(|record| &record.envelope)
+            .filter(|envelope| before.is_none_or(|before| envelope.timestamp < before))
+            .filter(|envelope| InMemoryEventBus::matches_filter(&filter, envelope))
+            .collect();
+        matching.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.seq.cmp(&a.seq)));
+        Ok(matching.into_iter().take(limit).cloned().collect())
+    }
+}
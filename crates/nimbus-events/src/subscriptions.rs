@@ -0,0 +1,128 @@
+//! Subscriptions that survive a restart
+//!
+//! In-process handlers are code and subscribe again at startup, but
+//! endpoint-backed ones are data. With a [`SubscriptionStore`] the bus
+//! records those subscribed through `subscribe_persistent`, forgets them on
+//! `unsubscribe`, and `restore_subscriptions` re-creates them on startup.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use nimbus_types::Plugin;
+use nimbus_types::events::EventFilter;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::store::StoreError;
+
+/// An endpoint-backed subscription as recorded by the bus
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedSubscription {
+    /// Subscription name on the bus
+    pub name: String,
+    /// Filter the handler was subscribed with
+    pub filter: EventFilter,
+    /// Plugin whose endpoint the handler delivers to
+    pub plugin: Plugin,
+}
+
+/// Where the bus records endpoint-backed subscriptions
+#[async_trait]
+pub trait SubscriptionStore: Send + Sync {
+    /// Record `subscription`, replacing any with the same name
+    async fn save(&self, subscription: PersistedSubscription) -> Result<(), StoreError>;
+
+    /// Forget the subscription called `name`, if recorded
+    async fn remove(&self, name: &str) -> Result<(), StoreError>;
+
+    /// Every recorded subscription, by name
+    async fn load(&self) -> Result<Vec<PersistedSubscription>, StoreError>;
+}
+
+/// Keeps subscriptions in memory, e.g. to share between bus instances in tests
+#[derive(Debug, Default)]
+pub struct InMemorySubscriptionStore {
+    subscriptions: Mutex<BTreeMap<String, PersistedSubscription>>,
+}
+
+impl InMemorySubscriptionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SubscriptionStore for InMemorySubscriptionStore {
+    async fn save(&self, subscription: PersistedSubscription) -> Result<(), StoreError> {
+        self.subscriptions.lock().await.insert(subscription.name.clone(), subscription);
+        Ok(())
+    }
+
+    async fn remove(&self, name: &str) -> Result<(), StoreError> {
+        self.subscriptions.lock().await.remove(name);
+        Ok(())
+    }
+
+    async fn load(&self) -> Result<Vec<PersistedSubscription>, StoreError> {
+        Ok(self.subscriptions.lock().await.values().cloned().collect())
+    }
+}
+
+/// [`SubscriptionStore`] keeping every subscription in one JSON file
+///
+/// The file is rewritten on each change, through a temporary file renamed
+/// into place, so a crash never leaves it half written.
+pub struct FileSubscriptionStore {
+    path: PathBuf,
+    subscriptions: Mutex<BTreeMap<String, PersistedSubscription>>,
+}
+
+impl FileSubscriptionStore {
+    /// Open the store at `path`, loading it if it exists
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let subscriptions = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice::<Vec<PersistedSubscription>>(&bytes)?
+                .into_iter()
+                .map(|subscription| (subscription.name.clone(), subscription))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, subscriptions: Mutex::new(subscriptions) })
+    }
+
+    async fn write(
+        &self,
+        subscriptions: &BTreeMap<String, PersistedSubscription>,
+    ) -> Result<(), StoreError> {
+        let json = serde_json::to_vec_pretty(&subscriptions.values().collect::<Vec<_>>())?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SubscriptionStore for FileSubscriptionStore {
+    async fn save(&self, subscription: PersistedSubscription) -> Result<(), StoreError> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.insert(subscription.name.clone(), subscription);
+        self.write(&subscriptions).await
+    }
+
+    async fn remove(&self, name: &str) -> Result<(), StoreError> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        if subscriptions.remove(name).is_some() {
+            self.write(&subscriptions).await?;
+        }
+        Ok(())
+    }
+
+    async fn load(&self) -> Result<Vec<PersistedSubscription>, StoreError> {
+        Ok(self.subscriptions.lock().await.values().cloned().collect())
+    }
+}
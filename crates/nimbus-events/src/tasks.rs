@@ -0,0 +1,79 @@
+//! Registry of handler tasks still running, so shutdown can wait for them
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+#[derive(Default)]
+pub(crate) struct HandlerTasks {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, AbortHandle>>,
+    /// Signalled whenever a task finishes
+    finished: Notify,
+}
+
+/// Removes its task from the registry when dropped, including on panic
+pub(crate) struct TaskGuard {
+    tasks: Arc<HandlerTasks>,
+    id: u64,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.tasks.running.lock().unwrap().remove(&self.id);
+        self.tasks.finished.notify_waiters();
+    }
+}
+
+impl HandlerTasks {
+    /// Spawn `future`, tracking it until it completes
+    pub(crate) fn spawn<F>(self: &Arc<Self>, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let guard = TaskGuard { tasks: self.clone(), id };
+
+        // Hold the lock across the spawn so the guard can't remove the entry
+        // before it was inserted
+        let mut running = self.running.lock().unwrap();
+        let task = tokio::spawn(async move {
+            let _guard = guard;
+            future.await
+        });
+        running.insert(id, task.abort_handle());
+        task
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+
+    /// Wait up to `grace` for every tracked task, then abort the rest
+    ///
+    /// Returns how many tasks had to be aborted.
+    pub(crate) async fn drain(&self, grace: Duration) -> usize {
+        let wait = async {
+            loop {
+                let finished = self.finished.notified();
+                if self.len() == 0 {
+                    return;
+                }
+                finished.await;
+            }
+        };
+        if tokio::time::timeout(grace, wait).await.is_ok() {
+            return 0;
+        }
+
+        let running: Vec<AbortHandle> =
+            self.running.lock().unwrap().drain().map(|(_, handle)| handle).collect();
+        running.iter().for_each(AbortHandle::abort);
+        running.len()
+    }
+}
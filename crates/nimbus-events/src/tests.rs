@@ -3,6 +3,8 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use super::*;
+use metrics::Attempt;
+use nimbus_types::events::EventFilter;
 use uuid::Uuid;
 
 /// Test handler that counts events
@@ -44,7 +46,7 @@ impl EventHandler for FailingHandler {
     }
 
     fn filter(&self) -> EventFilter {
-        EventFilter { event_types: vec![], repositories: vec![], branches: vec![] }
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
     }
 }
 
@@ -58,6 +60,7 @@ async fn test_basic_publish_subscribe() {
         event_types: vec![EventType::Push],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let counter = handler.count.clone();
 
@@ -68,6 +71,7 @@ async fn test_basic_publish_subscribe() {
     let event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "test-repo".to_string(),
             branch: "main".to_string(),
@@ -78,6 +82,7 @@ async fn test_basic_publish_subscribe() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -100,6 +105,7 @@ async fn test_multiple_subscribers() {
         event_types: vec![EventType::Push],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let counter1 = handler1.count.clone();
 
@@ -107,6 +113,7 @@ async fn test_multiple_subscribers() {
         event_types: vec![EventType::Push],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let counter2 = handler2.count.clone();
 
@@ -118,6 +125,7 @@ async fn test_multiple_subscribers() {
     let event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "test-repo".to_string(),
             branch: "main".to_string(),
@@ -128,6 +136,7 @@ async fn test_multiple_subscribers() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -151,6 +160,7 @@ async fn test_event_filtering() {
         event_types: vec![EventType::Push],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let push_counter = push_handler.count.clone();
 
@@ -159,6 +169,7 @@ async fn test_event_filtering() {
         event_types: vec![EventType::PullRequest],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let pr_counter = pr_handler.count.clone();
 
@@ -170,6 +181,7 @@ async fn test_event_filtering() {
     let push_event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "test-repo".to_string(),
             branch: "main".to_string(),
@@ -180,6 +192,7 @@ async fn test_event_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -187,6 +200,7 @@ async fn test_event_filtering() {
     let pr_event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::PullRequestOpened {
             id: Uuid::new_v4(),
             repository: "test-repo".to_string(),
@@ -199,6 +213,7 @@ async fn test_event_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -223,6 +238,7 @@ async fn test_repository_filtering() {
         event_types: vec![],
         repositories: vec!["important-repo".to_string()],
         branches: vec![],
+        tags: vec![],
     });
     let counter = handler.count.clone();
 
@@ -232,6 +248,7 @@ async fn test_repository_filtering() {
     let event1 = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "important-repo".to_string(),
             branch: "main".to_string(),
@@ -242,6 +259,7 @@ async fn test_repository_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -249,6 +267,7 @@ async fn test_repository_filtering() {
     let event2 = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "other-repo".to_string(),
             branch: "main".to_string(),
@@ -259,6 +278,7 @@ async fn test_repository_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -281,6 +301,7 @@ async fn test_branch_filtering() {
         event_types: vec![],
         repositories: vec![],
         branches: vec!["main".to_string()],
+        tags: vec![],
     });
     let counter = handler.count.clone();
 
@@ -290,6 +311,7 @@ async fn test_branch_filtering() {
     let main_event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "main".to_string(),
@@ -300,6 +322,7 @@ async fn test_branch_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -307,6 +330,7 @@ async fn test_branch_filtering() {
     let feature_event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "feature".to_string(),
@@ -317,6 +341,7 @@ async fn test_branch_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -339,6 +364,7 @@ async fn test_glob_branch_filtering() {
         event_types: vec![],
         repositories: vec![],
         branches: vec!["feature/*".to_string()],
+        tags: vec![],
     });
     let counter = handler.count.clone();
 
@@ -349,6 +375,7 @@ async fn test_glob_branch_filtering() {
         let event = EventEnvelope {
             id: Uuid::new_v4(),
             timestamp: time::OffsetDateTime::now_utc(),
+            seq: 0,
             event: Event::Push {
                 repository: "repo".to_string(),
                 branch: branch.to_string(),
@@ -359,6 +386,7 @@ async fn test_glob_branch_filtering() {
                 target_plugins: vec![],
                 priority: EventPriority::Normal,
                 persistent: false,
+                simulated: false,
             },
         };
         bus.publish(event).await.unwrap();
@@ -368,6 +396,7 @@ async fn test_glob_branch_filtering() {
     let main_event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "main".to_string(),
@@ -378,6 +407,7 @@ async fn test_glob_branch_filtering() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
     bus.publish(main_event).await.unwrap();
@@ -398,6 +428,7 @@ async fn test_handler_failure_doesnt_affect_others() {
         event_types: vec![],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let counter = good_handler.count.clone();
 
@@ -409,6 +440,7 @@ async fn test_handler_failure_doesnt_affect_others() {
     let event = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "main".to_string(),
@@ -419,6 +451,7 @@ async fn test_handler_failure_doesnt_affect_others() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
 
@@ -438,6 +471,7 @@ async fn test_unsubscribe() {
         event_types: vec![],
         repositories: vec![],
         branches: vec![],
+        tags: vec![],
     });
     let counter = handler.count.clone();
 
@@ -448,6 +482,7 @@ async fn test_unsubscribe() {
     let event1 = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "main".to_string(),
@@ -458,6 +493,7 @@ async fn test_unsubscribe() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
     bus.publish(event1).await.unwrap();
@@ -471,6 +507,7 @@ async fn test_unsubscribe() {
     let event2 = EventEnvelope {
         id: Uuid::new_v4(),
         timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
         event: Event::Push {
             repository: "repo".to_string(),
             branch: "main".to_string(),
@@ -481,6 +518,7 @@ async fn test_unsubscribe() {
             target_plugins: vec![],
             priority: EventPriority::Normal,
             persistent: false,
+            simulated: false,
         },
     };
     bus.publish(event2).await.unwrap();
@@ -489,3 +527,1885 @@ async fn test_unsubscribe() {
     // Count should still be 1
     assert_eq!(counter.load(Ordering::SeqCst), 1);
 }
+
+#[tokio::test]
+async fn test_ordering_guarantee_matches_dispatch_mode() {
+    let sequential = InMemoryEventBus::with_config(EventBusConfig {
+        dispatch_mode: DispatchMode::Sequential,
+        ..Default::default()
+    });
+    assert_eq!(sequential.ordering_guarantee(), OrderingGuarantee::Global);
+
+    let concurrent = InMemoryEventBus::with_config(EventBusConfig {
+        dispatch_mode: DispatchMode::Concurrent,
+        ..Default::default()
+    });
+    assert_eq!(concurrent.ordering_guarantee(), OrderingGuarantee::None);
+
+    // The default bus keeps the original one-event-at-a-time behaviour
+    assert_eq!(InMemoryEventBus::new(10).ordering_guarantee(), OrderingGuarantee::Global);
+}
+
+fn push_envelope(priority: EventPriority) -> EventEnvelope {
+    EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event: Event::Push {
+            repository: "repo".to_string(),
+            branch: "main".to_string(),
+            commits: vec![],
+            pusher: "user".to_string(),
+        },
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority,
+            persistent: false,
+            simulated: false,
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_quiet_hours_defer_normal_but_not_critical() {
+    use quiet_hours::{QuietHours, QuietHoursHandler};
+    use std::sync::Mutex;
+
+    // 22:00-07:00 in Berlin, with the clock controlled by the test
+    let window = QuietHours::new(
+        time::Time::from_hms(22, 0, 0).unwrap(),
+        time::Time::from_hms(7, 0, 0).unwrap(),
+        "Europe/Berlin",
+    )
+    .unwrap();
+    let night =
+        time::OffsetDateTime::UNIX_EPOCH.replace_time(time::Time::from_hms(23, 0, 0).unwrap());
+    let now = Arc::new(Mutex::new(night));
+
+    let counting = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = counting.count.clone();
+    let clock = now.clone();
+    let handler =
+        QuietHoursHandler::new(counting, window).with_clock(move || *clock.lock().unwrap());
+
+    // 23:00 UTC is 00:00 local: inside quiet hours
+    handler.handle(push_envelope(EventPriority::Normal)).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 0);
+    assert_eq!(handler.deferred_len(), 1);
+
+    handler.handle(push_envelope(EventPriority::Critical)).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+    // 08:00 UTC is 09:00 local: the deferred event is delivered
+    *now.lock().unwrap() =
+        time::OffsetDateTime::UNIX_EPOCH.replace_time(time::Time::from_hms(8, 0, 0).unwrap());
+    assert_eq!(handler.flush_deferred().await, 1);
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+    assert_eq!(handler.deferred_len(), 0);
+}
+
+#[test]
+fn test_quiet_hours_follow_daylight_saving() {
+    use quiet_hours::QuietHours;
+
+    let window = QuietHours::new(
+        time::Time::from_hms(22, 0, 0).unwrap(),
+        time::Time::from_hms(7, 0, 0).unwrap(),
+        "Europe/Berlin",
+    )
+    .unwrap();
+    assert_eq!(window.zone(), "Europe/Berlin");
+
+    // 20:30 UTC is 21:30 in winter (CET) but 22:30 in summer (CEST)
+    let evening = |month| {
+        time::Date::from_calendar_date(2026, month, 15)
+            .unwrap()
+            .with_hms(20, 30, 0)
+            .unwrap()
+            .assume_utc()
+    };
+    let (winter, summer) = (evening(time::Month::January), evening(time::Month::July));
+    assert!(!window.contains(winter));
+    assert!(window.contains(summer));
+
+    assert!(QuietHours::new(window.start, window.end, "Mars/Olympus_Mons").is_err());
+}
+
+#[tokio::test]
+async fn test_quiet_hours_queue_is_bounded() {
+    use dead_letter::InMemoryDeadLetterSink;
+    use quiet_hours::{DeferOverflow, QuietHours, QuietHoursHandler};
+
+    let always = QuietHours::new(
+        time::Time::from_hms(0, 0, 0).unwrap(),
+        time::Time::from_hms(23, 59, 59).unwrap(),
+        "UTC",
+    )
+    .unwrap();
+    let midday =
+        time::OffsetDateTime::UNIX_EPOCH.replace_time(time::Time::from_hms(12, 0, 0).unwrap());
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+
+    let handler = QuietHoursHandler::new(CountingHandler::new(EventFilter::default()), always)
+        .with_max_deferred(2, DeferOverflow::DropOldest)
+        .with_dead_letter_sink("notify", sink.clone())
+        .with_clock(move || midday);
+    let first = push_envelope(EventPriority::Normal);
+    let first_id = first.id;
+    handler.handle(first).await.unwrap();
+    for _ in 0..2 {
+        handler.handle(push_envelope(EventPriority::Normal)).await.unwrap();
+    }
+    assert_eq!(handler.deferred_len(), 2);
+    let dropped = sink.entries();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].handler, "notify");
+    assert_eq!(dropped[0].envelope.id, first_id);
+
+    // Delivering now bypasses the full queue instead of dropping anything
+    let counting = CountingHandler::new(EventFilter::default());
+    let counter = counting.count.clone();
+    let handler = QuietHoursHandler::new(counting, always)
+        .with_max_deferred(0, DeferOverflow::DeliverNow)
+        .with_clock(move || midday);
+    handler.handle(push_envelope(EventPriority::Normal)).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    assert_eq!(handler.deferred_len(), 0);
+}
+
+#[tokio::test]
+async fn test_failed_deferred_delivery_is_retried_then_dead_lettered() {
+    use dead_letter::InMemoryDeadLetterSink;
+    use quiet_hours::{QuietHours, QuietHoursHandler};
+    use std::sync::Mutex;
+
+    let window = QuietHours::new(
+        time::Time::from_hms(22, 0, 0).unwrap(),
+        time::Time::from_hms(7, 0, 0).unwrap(),
+        "UTC",
+    )
+    .unwrap();
+    let at = |hour| {
+        time::OffsetDateTime::UNIX_EPOCH.replace_time(time::Time::from_hms(hour, 0, 0).unwrap())
+    };
+    let now = Arc::new(Mutex::new(at(23)));
+    let clock = now.clone();
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+    let retry_policy =
+        RetryPolicy { max_attempts: 2, base_delay: Duration::from_millis(1), multiplier: 1.0 };
+
+    let handler = QuietHoursHandler::new(FailingHandler, window)
+        .with_retry_policy(retry_policy)
+        .with_dead_letter_sink("notify", sink.clone())
+        .with_clock(move || *clock.lock().unwrap());
+    let deferred = push_envelope(EventPriority::Normal);
+    let id = deferred.id;
+    handler.handle(deferred).await.unwrap();
+
+    *now.lock().unwrap() = at(8);
+    assert_eq!(handler.flush_deferred().await, 0);
+    assert_eq!(handler.deferred_len(), 0);
+    let dead = sink.entries();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].envelope.id, id);
+    assert_eq!(dead[0].error, "Test failure");
+}
+
+/// Test handler that takes a fixed time to succeed
+struct SlowHandler {
+    delay: Duration,
+}
+
+#[async_trait]
+impl EventHandler for SlowHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        tokio::time::sleep(self.delay).await;
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
+    }
+}
+
+#[tokio::test]
+async fn test_slow_handler_counts_as_success_and_slow() {
+    let mut config = EventBusConfig::default();
+    config.handler_timeout_overrides.insert(
+        "slow".to_string(),
+        HandlerTimeouts { soft: Duration::from_millis(50), hard: Duration::from_secs(5) },
+    );
+    let bus = Arc::new(InMemoryEventBus::with_config(config));
+    let _handle = bus.clone().start();
+
+    bus.subscribe("slow".to_string(), Box::new(SlowHandler { delay: Duration::from_millis(150) }))
+        .await
+        .unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    assert_eq!(bus.metrics.handler_success_count("slow"), 1);
+    assert_eq!(bus.metrics.handler_slow_count("slow"), 1);
+}
+
+#[tokio::test]
+async fn test_configured_timeout_aborts_slow_handler() {
+    let config = EventBusConfig {
+        buffer_size: 5,
+        handler_timeouts: HandlerTimeouts {
+            soft: Duration::from_millis(50),
+            hard: Duration::from_millis(200),
+        },
+        ..Default::default()
+    };
+    let bus = InMemoryEventBus::with_config(config);
+    assert_eq!(bus.queue.capacity(), 5);
+
+    bus.subscribe("slow".to_string(), Box::new(SlowHandler { delay: Duration::from_secs(5) }))
+        .await
+        .unwrap();
+    let report = bus
+        .publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    assert_eq!(report.failed, 1);
+    assert!(report.duration < Duration::from_secs(1), "took {:?}", report.duration);
+    assert_eq!(bus.metrics.handler_timeout_count("slow"), 1);
+}
+
+#[tokio::test]
+async fn test_fast_handler_is_not_slow() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    bus.subscribe("fast".to_string(), Box::new(SlowHandler { delay: Duration::ZERO }))
+        .await
+        .unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(bus.metrics.handler_success_count("fast"), 1);
+    assert_eq!(bus.metrics.handler_slow_count("fast"), 0);
+}
+
+#[tokio::test]
+async fn test_handler_duration_is_recorded() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    bus.subscribe("timed".to_string(), Box::new(SlowHandler { delay: Duration::from_millis(100) }))
+        .await
+        .unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let (count, seconds) = bus.metrics.handler_duration_samples("timed");
+    assert_eq!(count, 1);
+    assert!((0.1..1.0).contains(&seconds), "recorded {}s", seconds);
+}
+
+fn event_log(len: usize) -> store::EventLog {
+    let mut log = store::EventLog::new();
+    for _ in 0..len {
+        log.append(push_envelope(EventPriority::Normal));
+    }
+    log
+}
+
+#[test]
+fn test_intact_event_log_verifies() {
+    let log = event_log(3);
+
+    assert_eq!(log.records()[0].prev_hash, store::GENESIS_HASH);
+    assert_eq!(log.records()[2].prev_hash, log.records()[1].hash);
+    assert_eq!(log.head(), log.records()[2].hash);
+    assert_eq!(log.verify_integrity(), Ok(()));
+}
+
+#[test]
+fn test_tampered_event_log_reports_offset() {
+    let log = event_log(3);
+
+    // Rewrite the pusher of the middle record
+    let mut records = log.records().to_vec();
+    records[1].envelope.event = Event::Push {
+        repository: "repo".to_string(),
+        branch: "main".to_string(),
+        commits: vec![],
+        pusher: "mallory".to_string(),
+    };
+    let err = store::verify_chain(&records).unwrap_err();
+    assert_eq!(err, store::IntegrityError::Modified { offset: 1 });
+
+    // Recomputing its hash just moves the break to the next record
+    records[1] = store::StoredEvent::new(1, &records[0].hash, records[1].envelope.clone());
+    assert_eq!(store::verify_chain(&records).unwrap_err().offset(), 2);
+
+    // Deleting a record is caught where the gap starts
+    let mut records = log.records().to_vec();
+    records.remove(1);
+    assert_eq!(store::verify_chain(&records).unwrap_err().offset(), 1);
+}
+
+#[tokio::test]
+async fn test_file_event_store_survives_reopen() {
+    use store::{EventStore, FileEventStore};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let before = time::OffsetDateTime::now_utc() - time::Duration::seconds(1);
+
+    let store = Arc::new(FileEventStore::open(&path).await.unwrap());
+    let bus = Arc::new(InMemoryEventBus::new(10).with_store(store.clone()));
+    let _handle = bus.clone().start();
+
+    let mut persistent = push_envelope(EventPriority::Normal);
+    persistent.metadata.persistent = true;
+    let id = persistent.id;
+    bus.publish(persistent).await.unwrap();
+    // Events not marked persistent never reach the store
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drop(bus);
+    drop(store);
+
+    let reopened = FileEventStore::open(&path).await.unwrap();
+    let events = reopened.load_since(before).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id, id);
+    assert_eq!(reopened.verify_integrity().await, Ok(()));
+
+    // Appends after reopening continue the same chain
+    reopened.append(&push_envelope(EventPriority::Low)).await.unwrap();
+    assert_eq!(reopened.verify_integrity().await, Ok(()));
+    assert!(reopened.load_since(time::OffsetDateTime::now_utc()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_file_event_store_verifies_logs_written_before_newer_fields() {
+    use sha2::{Digest, Sha256};
+    use store::{EventStore, FileEventStore, GENESIS_HASH};
+
+    let mut envelope = push_envelope(EventPriority::Normal);
+    envelope.event = Event::Push {
+        repository: "repo".to_string(),
+        branch: "main".to_string(),
+        commits: vec![nimbus_types::Commit {
+            sha: "abc123".to_string(),
+            message: "Initial commit".to_string(),
+            author: "user".to_string(),
+            timestamp: time::OffsetDateTime::now_utc(),
+            parent_shas: vec![],
+            files_changed: vec![],
+            verification: Default::default(),
+        }],
+        pusher: "user".to_string(),
+    };
+
+    // The envelope as it was serialized before commits carried file changes
+    // and signature status
+    let current = serde_json::to_string(&envelope).unwrap();
+    let legacy = current
+        .replace(r#","files_changed":[]"#, "")
+        .replace(r#","verification":{"status":"unsigned"}"#, "");
+    let mut hasher = Sha256::new();
+    hasher.update(0u64.to_be_bytes());
+    hasher.update(GENESIS_HASH.as_bytes());
+    hasher.update(legacy.as_bytes());
+    let hash = hex::encode(hasher.finalize());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.ndjson");
+    let line = format!(
+        r#"{{"offset":0,"prev_hash":"{}","hash":"{}","envelope":{}}}"#,
+        GENESIS_HASH, hash, legacy
+    );
+    tokio::fs::write(&path, line + "\n").await.unwrap();
+
+    let reopened = FileEventStore::open(&path).await.unwrap();
+    assert_eq!(reopened.verify_integrity().await, Ok(()));
+    assert_eq!(reopened.load_since(time::OffsetDateTime::UNIX_EPOCH).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_equal_timestamps_are_ordered_by_sequence() {
+    use store::{EventStore, FileEventStore};
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(FileEventStore::open(dir.path().join("events.ndjson")).await.unwrap());
+    let bus = InMemoryEventBus::new(10).with_store(store.clone());
+
+    // One instant, given in a non-UTC offset
+    let timestamp =
+        time::OffsetDateTime::now_utc().to_offset(time::UtcOffset::from_hms(2, 0, 0).unwrap());
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut envelope = push_envelope(EventPriority::Normal);
+        envelope.timestamp = timestamp;
+        envelope.metadata.persistent = true;
+        ids.push(envelope.id);
+        bus.publish_and_wait(envelope, Duration::from_secs(5)).await.unwrap();
+    }
+
+    let events = store.query_events(EventFilter::default(), None, 10).await.unwrap();
+    assert_eq!(events.iter().map(|e| e.id).collect::<Vec<_>>(), [ids[1], ids[0]]);
+    assert!(events[0].seq > events[1].seq);
+    assert!(events.iter().all(|e| e.timestamp.offset().is_utc() && e.timestamp == timestamp));
+}
+
+/// A store holding `repositories` pushes in order, one second apart
+async fn store_with_history(
+    dir: &tempfile::TempDir,
+    repositories: &[&str],
+) -> (store::FileEventStore, Vec<EventEnvelope>) {
+    use store::{EventStore, FileEventStore};
+
+    let store = FileEventStore::open(dir.path().join("events.ndjson")).await.unwrap();
+    let start = time::OffsetDateTime::now_utc() - time::Duration::hours(1);
+    let mut appended = Vec::new();
+    for (i, repository) in repositories.iter().enumerate() {
+        let mut envelope = push_to_repository(repository);
+        envelope.timestamp = start + time::Duration::seconds(i as i64);
+        store.append(&envelope).await.unwrap();
+        appended.push(envelope);
+    }
+    (store, appended)
+}
+
+#[tokio::test]
+async fn test_query_events_pages_newest_first() {
+    use store::EventStore;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (store, appended) = store_with_history(&dir, &["a", "b", "c", "d", "e"]).await;
+    let ids = |envelopes: &[EventEnvelope]| envelopes.iter().map(|e| e.id).collect::<Vec<_>>();
+
+    let page = store.query_events(EventFilter::default(), None, 2).await.unwrap();
+    assert_eq!(ids(&page), vec![appended[4].id, appended[3].id]);
+
+    let before = Some(page[1].timestamp);
+    let next = store.query_events(EventFilter::default(), before, 2).await.unwrap();
+    assert_eq!(ids(&next), vec![appended[2].id, appended[1].id]);
+
+    let all = store.query_events(EventFilter::default(), None, 100).await.unwrap();
+    assert_eq!(all.len(), 5);
+}
+
+#[tokio::test]
+async fn test_query_events_matches_repository_patterns() {
+    use store::{EventStore, StoreError};
+
+    let dir = tempfile::tempdir().unwrap();
+    let (store, appended) =
+        store_with_history(&dir, &["infra-dns", "website", "infra-ci", "infra"]).await;
+
+    let filter = |repository: &str| EventFilter {
+        repositories: vec![repository.to_string()],
+        ..Default::default()
+    };
+    let infra = store.query_events(filter("infra-*"), None, 10).await.unwrap();
+    assert_eq!(
+        infra.iter().map(|e| e.id).collect::<Vec<_>>(),
+        vec![appended[2].id, appended[0].id]
+    );
+
+    // Plain names only match themselves
+    let exact = store.query_events(filter("infra"), None, 10).await.unwrap();
+    assert_eq!(exact.len(), 1);
+    assert_eq!(exact[0].id, appended[3].id);
+
+    let error = store.query_events(filter("infra-[a"), None, 10).await.unwrap_err();
+    assert!(matches!(error, StoreError::InvalidFilter(_)));
+}
+
+#[tokio::test]
+async fn test_repository_patterns_filter_subscriptions() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let handler = CountingHandler::new(EventFilter {
+        repositories: vec!["infra-*".to_string()],
+        ..Default::default()
+    });
+    let count = handler.count.clone();
+    bus.subscribe("infra".to_string(), Box::new(handler)).await.unwrap();
+
+    for repository in ["infra-dns", "website", "infra"] {
+        bus.publish(push_to_repository(repository)).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+/// Test handler that records the order events arrive in
+struct RecordingHandler {
+    seen: Arc<std::sync::Mutex<Vec<EventPriority>>>,
+}
+
+#[async_trait]
+impl EventHandler for RecordingHandler {
+    async fn handle(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.seen.lock().unwrap().push(event.metadata.priority);
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
+    }
+}
+
+#[tokio::test]
+async fn test_buffered_events_dispatch_by_priority() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    bus.subscribe("recorder".to_string(), Box::new(RecordingHandler { seen: seen.clone() }))
+        .await
+        .unwrap();
+
+    // Processor isn't running yet, so these stay buffered
+    bus.publish(push_envelope(EventPriority::Low)).await.unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    bus.publish(push_envelope(EventPriority::Critical)).await.unwrap();
+    bus.publish(push_envelope(EventPriority::Low)).await.unwrap();
+
+    let _handle = bus.clone().start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            EventPriority::Critical,
+            EventPriority::Normal,
+            EventPriority::Low,
+            EventPriority::Low
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_ordered_batch_keeps_its_order_despite_priorities() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    bus.subscribe("recorder".to_string(), Box::new(RecordingHandler { seen: seen.clone() }))
+        .await
+        .unwrap();
+
+    // Buffered until the processor starts, where priority alone would reorder them
+    bus.publish_ordered(vec![
+        push_envelope(EventPriority::Low),
+        push_envelope(EventPriority::Critical),
+        push_envelope(EventPriority::Normal),
+    ])
+    .await
+    .unwrap();
+
+    let _handle = bus.clone().start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![EventPriority::Low, EventPriority::Critical, EventPriority::Normal]
+    );
+}
+
+#[tokio::test]
+async fn test_publish_waits_when_queue_is_full() {
+    let bus = Arc::new(InMemoryEventBus::new(1));
+    bus.publish(push_envelope(EventPriority::Low)).await.unwrap();
+
+    let blocked = tokio::time::timeout(
+        Duration::from_millis(50),
+        bus.publish(push_envelope(EventPriority::Critical)),
+    )
+    .await;
+    assert!(blocked.is_err(), "publish should wait for space in a full queue");
+}
+
+#[tokio::test]
+async fn test_target_plugins_limits_delivery() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let all_events = || EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    };
+    let reviewer = CountingHandler::new(all_events());
+    let reviewer_count = reviewer.count.clone();
+    let bystander = CountingHandler::new(all_events());
+    let bystander_count = bystander.count.clone();
+    bus.subscribe("claude-reviewer".to_string(), Box::new(reviewer)).await.unwrap();
+    bus.subscribe("notifier".to_string(), Box::new(bystander)).await.unwrap();
+
+    let mut targeted = push_envelope(EventPriority::Normal);
+    targeted.metadata.target_plugins = vec!["claude-reviewer".to_string()];
+    bus.publish(targeted).await.unwrap();
+    // Untargeted events still go to everyone
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(reviewer_count.load(Ordering::SeqCst), 2);
+    assert_eq!(bystander_count.load(Ordering::SeqCst), 1);
+}
+
+/// Test handler that fails a fixed number of times before succeeding
+struct FlakyHandler {
+    failures_left: AtomicUsize,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl EventHandler for FlakyHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let left = self.failures_left.load(Ordering::SeqCst);
+        if left > 0 {
+            self.failures_left.store(left - 1, Ordering::SeqCst);
+            return Err("transient failure".into());
+        }
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
+    }
+}
+
+#[tokio::test]
+async fn test_failed_handler_is_retried_until_success() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        retry_policy: RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            multiplier: 2.0,
+        },
+        ..Default::default()
+    }));
+    let _handle = bus.clone().start();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let flaky = FlakyHandler { failures_left: AtomicUsize::new(2), calls: calls.clone() };
+    let steady = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let steady_count = steady.count.clone();
+    bus.subscribe("flaky".to_string(), Box::new(flaky)).await.unwrap();
+    bus.subscribe("steady".to_string(), Box::new(steady)).await.unwrap();
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(bus.metrics.handler_retry_count("flaky"), 2);
+    // One failed first attempt, one failed retry, then a retry that succeeded
+    assert_eq!(bus.metrics.handler_attempt_success_count("flaky", Attempt::Retry), 1);
+    assert_eq!(bus.metrics.handler_attempt_success_count("flaky", Attempt::First), 0);
+    assert_eq!(bus.metrics.handler_attempt_failure_count("flaky", Attempt::First), 1);
+    assert_eq!(bus.metrics.handler_attempt_failure_count("flaky", Attempt::Retry), 1);
+    assert_eq!(bus.metrics.handler_dead_letter_count("flaky"), 0);
+    // Retries are per handler; the one that succeeded isn't run again
+    assert_eq!(steady_count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_retry_delays_grow_exponentially() {
+    let policy =
+        RetryPolicy { max_attempts: 4, base_delay: Duration::from_millis(100), multiplier: 2.0 };
+
+    assert_eq!(policy.delay_after(1), Duration::from_millis(100));
+    assert_eq!(policy.delay_after(2), Duration::from_millis(200));
+    assert_eq!(policy.delay_after(3), Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn test_failing_handler_produces_one_dead_letter() {
+    use dead_letter::InMemoryDeadLetterSink;
+
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+    let bus = Arc::new(
+        InMemoryEventBus::with_config(EventBusConfig {
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(10),
+                multiplier: 2.0,
+            },
+            ..Default::default()
+        })
+        .with_dead_letter_sink(sink.clone()),
+    );
+    let _handle = bus.clone().start();
+
+    let healthy = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    bus.subscribe("good".to_string(), Box::new(healthy)).await.unwrap();
+    bus.subscribe("bad".to_string(), Box::new(FailingHandler)).await.unwrap();
+
+    let envelope = push_envelope(EventPriority::Normal);
+    let id = envelope.id;
+    bus.publish(envelope).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Retried three times, dead-lettered once
+    let entries = sink.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].handler, "bad");
+    assert_eq!(entries[0].envelope.id, id);
+    assert_eq!(entries[0].error, "Test failure");
+    assert_eq!(bus.metrics.handler_dead_letter_count("bad"), 1);
+    assert_eq!(bus.metrics.handler_attempt_failure_count("bad", Attempt::Retry), 2);
+    assert_eq!(bus.metrics.handler_dead_letter_count("good"), 0);
+}
+
+#[test]
+fn test_canonical_json_is_stable_and_signs_identically() {
+    let envelope = push_envelope(EventPriority::High);
+
+    // The same event, once from the struct and once from a map built in
+    // reverse key order, as a consumer re-serializing a parsed payload might
+    let direct = signing::to_canonical_json(&envelope).unwrap();
+    let serde_json::Value::Object(fields) = serde_json::to_value(&envelope).unwrap() else {
+        panic!("envelope serializes to an object");
+    };
+    let reversed: serde_json::Map<String, serde_json::Value> = fields.into_iter().rev().collect();
+    let rebuilt = signing::to_canonical_json(&reversed).unwrap();
+
+    assert_eq!(direct, rebuilt);
+    assert!(!direct.contains(&b' '));
+
+    let secret = b"webhook-secret";
+    let (body, signature) = signing::sign_json(secret, &envelope).unwrap();
+    assert_eq!(body, direct);
+    assert_eq!(signature, signing::sign(secret, &rebuilt));
+    assert!(signature.starts_with(signing::SIGNATURE_PREFIX));
+
+    // Receiving side: parse, then verify against the canonical form
+    let parsed: EventEnvelope = serde_json::from_slice(&body).unwrap();
+    assert!(signing::verify_json(secret, &parsed, &signature));
+    assert!(!signing::verify_json(b"other-secret", &parsed, &signature));
+    assert!(!signing::verify(secret, b"{}", &signature));
+}
+
+/// Test handler that panics
+struct PanickingHandler;
+
+#[async_trait]
+impl EventHandler for PanickingHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        panic!("handler bug");
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
+    }
+}
+
+#[tokio::test]
+async fn test_panicking_handler_is_isolated_and_counted() {
+    use dead_letter::InMemoryDeadLetterSink;
+
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+    let bus = Arc::new(InMemoryEventBus::new(10).with_dead_letter_sink(sink.clone()));
+    let _handle = bus.clone().start();
+
+    let good = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = good.count.clone();
+    bus.subscribe("good".to_string(), Box::new(good)).await.unwrap();
+    bus.subscribe("panicky".to_string(), Box::new(PanickingHandler)).await.unwrap();
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The sibling keeps working and the bus keeps processing later events
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+    assert_eq!(bus.metrics.handler_success_count("good"), 2);
+    assert_eq!(bus.metrics.handler_failure_count("panicky"), 2);
+    assert_eq!(bus.metrics.handler_dead_letter_count("panicky"), 2);
+
+    let entries = sink.entries();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|entry| entry.handler == "panicky"));
+    assert!(entries[0].error.contains("handler bug"));
+}
+
+#[tokio::test]
+async fn test_recent_events_keep_only_the_newest() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        recent_events_capacity: 5,
+        ..Default::default()
+    }));
+    let _handle = bus.clone().start();
+
+    let published: Vec<EventEnvelope> =
+        (0..8).map(|_| push_envelope(EventPriority::Normal)).collect();
+    for envelope in &published {
+        bus.publish(envelope.clone()).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let ids = |envelopes: &[EventEnvelope]| envelopes.iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids(&bus.recent_events(100)), ids(&published[3..]));
+    assert_eq!(ids(&bus.recent_events(2)), ids(&published[6..]));
+}
+
+#[tokio::test]
+async fn test_ai_events_reach_ai_subscribers_only() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let ai = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Ai],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let ai_count = ai.count.clone();
+    let push = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let push_count = push.count.clone();
+    bus.subscribe("ai".to_string(), Box::new(ai)).await.unwrap();
+    bus.subscribe("push".to_string(), Box::new(push)).await.unwrap();
+
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event: Event::AiAnalysisRequested {
+            id: Uuid::new_v4(),
+            repository: "repo".to_string(),
+            context: nimbus_types::events::AnalysisContext::Repository,
+            plugin: "reviewer".to_string(),
+        },
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: false,
+            simulated: false,
+        },
+    };
+    bus.publish(envelope).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(ai_count.load(Ordering::SeqCst), 1);
+    assert_eq!(push_count.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_review_comments_reach_review_subscribers() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let review = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Review],
+        repositories: vec!["repo".to_string()],
+        ..Default::default()
+    });
+    let review_count = review.count.clone();
+    let pull_requests = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::PullRequest],
+        ..Default::default()
+    });
+    let pull_request_count = pull_requests.count.clone();
+    bus.subscribe("review".to_string(), Box::new(review)).await.unwrap();
+    bus.subscribe("pull-requests".to_string(), Box::new(pull_requests)).await.unwrap();
+
+    let event = Event::ReviewCommentAdded {
+        pull_request_id: Uuid::new_v4(),
+        repository: "repo".to_string(),
+        reviewer: "ai-reviewer".to_string(),
+        file: "src/lib.rs".to_string(),
+        line: Some(42),
+        body: "This unwrap can panic".to_string(),
+        plugin: "reviewer".to_string(),
+    };
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["type"], "review_comment_added");
+    assert_eq!(json["line"], 42);
+
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: false,
+            simulated: false,
+        },
+    };
+    bus.publish(envelope).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(review_count.load(Ordering::SeqCst), 1);
+    assert_eq!(pull_request_count.load(Ordering::SeqCst), 0);
+}
+
+fn envelope_for(event: Event) -> EventEnvelope {
+    EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: false,
+            simulated: false,
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_branch_deletions_reach_push_subscribers() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let cleanup = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        repositories: vec!["repo".to_string()],
+        branches: vec!["feature/*".to_string()],
+        ..Default::default()
+    });
+    let cleanup_count = cleanup.count.clone();
+    let tags = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Tag],
+        ..Default::default()
+    });
+    let tag_count = tags.count.clone();
+    bus.subscribe("cleanup".to_string(), Box::new(cleanup)).await.unwrap();
+    bus.subscribe("tags".to_string(), Box::new(tags)).await.unwrap();
+
+    for branch in ["feature/done", "main"] {
+        let event = Event::BranchDeleted {
+            repository: "repo".to_string(),
+            branch: branch.to_string(),
+            deleter: "alice".to_string(),
+        };
+        bus.publish(envelope_for(event)).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(cleanup_count.load(Ordering::SeqCst), 1);
+    assert_eq!(tag_count.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_tag_deletions_reach_tag_subscribers() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let _handle = bus.clone().start();
+
+    let releases = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Tag],
+        repositories: vec!["repo".to_string()],
+        tags: vec!["v*".to_string()],
+        ..Default::default()
+    });
+    let release_count = releases.count.clone();
+    let pushes = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        ..Default::default()
+    });
+    let push_count = pushes.count.clone();
+    bus.subscribe("releases".to_string(), Box::new(releases)).await.unwrap();
+    bus.subscribe("pushes".to_string(), Box::new(pushes)).await.unwrap();
+
+    for tag in ["v1.0", "nightly"] {
+        let event = Event::TagDeleted { repository: "repo".to_string(), tag: tag.to_string() };
+        bus.publish(envelope_for(event)).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(release_count.load(Ordering::SeqCst), 1);
+    assert_eq!(push_count.load(Ordering::SeqCst), 0);
+}
+
+fn push_to(branch: &str) -> EventEnvelope {
+    let mut envelope = push_envelope(EventPriority::Normal);
+    if let Event::Push { branch: target, .. } = &mut envelope.event {
+        *target = branch.to_string();
+    }
+    envelope
+}
+
+async fn count_matching(pattern: &str, branches: &[&str]) -> usize {
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _handle = bus.clone().start();
+
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![pattern.to_string()],
+        tags: vec![],
+    });
+    let counter = handler.count.clone();
+    bus.subscribe("glob".to_string(), Box::new(handler)).await.unwrap();
+
+    for branch in branches {
+        bus.publish(push_to(branch)).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    counter.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn test_recursive_branch_glob() {
+    let branches = ["feature/auth", "feature/auth/hotfix", "features/auth", "main"];
+    assert_eq!(count_matching("feature/**", &branches).await, 2);
+    // A single `*` stays within one segment
+    assert_eq!(count_matching("feature/*", &branches).await, 1);
+    assert_eq!(count_matching("feature/*/hotfix", &branches).await, 1);
+}
+
+#[tokio::test]
+async fn test_single_character_branch_glob() {
+    let branches = ["v1.2.3", "v1.22.3", "v1.2", "release-1.0"];
+    assert_eq!(count_matching("v?.?.?", &branches).await, 1);
+    assert_eq!(count_matching("release-?.?", &branches).await, 1);
+}
+
+#[tokio::test]
+async fn test_character_class_branch_glob() {
+    let branches = ["2024-release", "9", "release-2024", "hotfix"];
+    assert_eq!(count_matching("[0-9]*", &branches).await, 2);
+}
+
+#[tokio::test]
+async fn test_malformed_branch_pattern_rejected_at_subscribe() {
+    let bus = InMemoryEventBus::new(10);
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec!["release/[".to_string()],
+        tags: vec![],
+    });
+
+    let error = bus.subscribe("broken".to_string(), Box::new(handler)).await.unwrap_err();
+    assert!(error.downcast_ref::<InvalidBranchPattern>().is_some());
+    assert_eq!(bus.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn test_publish_and_wait_reports_handler_outcomes() {
+    let bus = InMemoryEventBus::with_config(EventBusConfig {
+        retry_policy: RetryPolicy::none(),
+        ..Default::default()
+    });
+    // Not started: the event is dispatched inline
+
+    let good = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = good.count.clone();
+    bus.subscribe("good".to_string(), Box::new(good)).await.unwrap();
+    bus.subscribe("failing".to_string(), Box::new(FailingHandler)).await.unwrap();
+
+    let report = bus
+        .publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert_eq!(report.matched, 2);
+    assert_eq!(report.succeeded, 1);
+    assert_eq!(report.failed, 1);
+    assert!(report.duration > Duration::ZERO);
+    // Both handlers are done by the time the call returns
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_publish_and_wait_times_out() {
+    let bus = InMemoryEventBus::new(10);
+    let slow = SlowHandler { delay: Duration::from_millis(500) };
+    bus.subscribe("slow".to_string(), Box::new(slow)).await.unwrap();
+
+    let result =
+        bus.publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_millis(50)).await;
+    assert!(result.is_err());
+}
+
+/// Test handler whose health check fails
+struct UnhealthyHandler;
+
+#[async_trait]
+impl EventHandler for UnhealthyHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter {
+            event_types: vec![EventType::PullRequest],
+            repositories: vec!["repo".to_string()],
+            branches: vec![],
+            tags: vec![],
+        }
+    }
+
+    async fn health_check(&self) -> bool {
+        false
+    }
+}
+
+#[tokio::test]
+async fn test_subscriptions_report_filters_and_health() {
+    let bus = InMemoryEventBus::new(10);
+    let counting = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        repositories: vec![],
+        branches: vec!["main".to_string()],
+        tags: vec![],
+    });
+    bus.subscribe("notifier".to_string(), Box::new(counting)).await.unwrap();
+    bus.subscribe("reviewer".to_string(), Box::new(UnhealthyHandler)).await.unwrap();
+
+    let subscriptions = bus.subscriptions().await;
+    assert_eq!(subscriptions.len(), 2);
+
+    assert_eq!(subscriptions[0].name, "notifier");
+    assert_eq!(subscriptions[0].filter.event_types, vec![EventType::Push]);
+    assert_eq!(subscriptions[0].filter.branches, vec!["main".to_string()]);
+    assert!(subscriptions[0].healthy);
+
+    assert_eq!(subscriptions[1].name, "reviewer");
+    assert_eq!(subscriptions[1].filter.repositories, vec!["repo".to_string()]);
+    assert!(!subscriptions[1].healthy);
+}
+
+fn push_to_repository(repository: &str) -> EventEnvelope {
+    let mut envelope = push_envelope(EventPriority::Normal);
+    if let Event::Push { repository: target, .. } = &mut envelope.event {
+        *target = repository.to_string();
+    }
+    envelope
+}
+
+#[tokio::test]
+async fn test_worker_pool_processes_events_concurrently() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        workers: 2,
+        ..Default::default()
+    }));
+    let slow = SlowHandler { delay: Duration::from_millis(300) };
+    bus.subscribe("slow".to_string(), Box::new(slow)).await.unwrap();
+    let _handle = bus.clone().start();
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    // One worker would still be on the first event
+    tokio::time::sleep(Duration::from_millis(450)).await;
+    assert_eq!(bus.metrics.handler_success_count("slow"), 2);
+    assert_eq!(bus.ordering_guarantee(), OrderingGuarantee::None);
+}
+
+#[tokio::test]
+async fn test_per_repository_workers_keep_repository_order() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        dispatch_mode: DispatchMode::PerRepository,
+        workers: 4,
+        ..Default::default()
+    }));
+    let slow = SlowHandler { delay: Duration::from_millis(300) };
+    bus.subscribe("slow".to_string(), Box::new(slow)).await.unwrap();
+    let _handle = bus.clone().start();
+    assert_eq!(bus.ordering_guarantee(), OrderingGuarantee::PerRepository);
+
+    // A repository handled by a different worker than "alpha"
+    let other = (0..)
+        .map(|i| format!("repo-{}", i))
+        .find(|name| {
+            InMemoryEventBus::worker_for(&push_to_repository(name), 4)
+                != InMemoryEventBus::worker_for(&push_to_repository("alpha"), 4)
+        })
+        .unwrap();
+
+    bus.publish(push_to_repository("alpha")).await.unwrap();
+    bus.publish(push_to_repository("alpha")).await.unwrap();
+    bus.publish(push_to_repository(&other)).await.unwrap();
+
+    // The second "alpha" event waits for the first; the other repository doesn't
+    tokio::time::sleep(Duration::from_millis(450)).await;
+    assert_eq!(bus.metrics.handler_success_count("slow"), 2);
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(bus.metrics.handler_success_count("slow"), 3);
+}
+
+/// Test handler that counts events and reports a switchable health
+struct FlappingHandler {
+    count: Arc<AtomicUsize>,
+    healthy: Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[async_trait]
+impl EventHandler for FlappingHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] }
+    }
+
+    async fn health_check(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn test_unhealthy_handler_receives_no_events_until_recovered() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        health_check_interval: Duration::from_millis(50),
+        ..Default::default()
+    }));
+    let count = Arc::new(AtomicUsize::new(0));
+    let healthy = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let handler = FlappingHandler { count: count.clone(), healthy: healthy.clone() };
+    bus.subscribe("flapping".to_string(), Box::new(handler)).await.unwrap();
+    let _handle = bus.clone().start();
+    let _monitor = bus.clone().start_health_monitor();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert!(bus.metrics.is_handler_unhealthy("flapping"));
+
+    healthy.store(true, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!bus.metrics.is_handler_unhealthy("flapping"));
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_shutdown_waits_for_running_handlers() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    let slow = SlowHandler { delay: Duration::from_millis(300) };
+    bus.subscribe("slow".to_string(), Box::new(slow)).await.unwrap();
+    let handle = bus.clone().start();
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Stopping intake doesn't take the running handler down with it
+    handle.abort();
+    assert_eq!(bus.shutdown().await, 0);
+    assert_eq!(bus.metrics.handler_success_count("slow"), 1);
+}
+
+#[tokio::test]
+async fn test_shutdown_aborts_handlers_past_grace_period() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        shutdown_grace_period: Duration::from_millis(50),
+        ..Default::default()
+    }));
+    let slow = SlowHandler { delay: Duration::from_secs(5) };
+    bus.subscribe("slow".to_string(), Box::new(slow)).await.unwrap();
+    let handle = bus.clone().start();
+
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    handle.abort();
+    assert_eq!(bus.shutdown().await, 1);
+    assert_eq!(bus.metrics.handler_success_count("slow"), 0);
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_published_events() {
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = handler.count.clone();
+    bus.subscribe("counter".to_string(), Box::new(handler)).await.unwrap();
+
+    // Queued before the processor starts, so none can have been handled yet
+    for _ in 0..50 {
+        bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    }
+    let _handle = bus.clone().start();
+
+    assert_eq!(bus.shutdown().await, 0);
+    assert_eq!(counter.load(Ordering::SeqCst), 50);
+    assert_eq!(bus.current_depth(), 0);
+
+    // Nothing more is taken once shutdown has begun
+    let error = bus.publish(push_envelope(EventPriority::Normal)).await.unwrap_err();
+    assert!(error.downcast_ref::<BusClosed>().is_some());
+}
+
+#[tokio::test]
+async fn test_queue_depth_gauge_tracks_backlog() {
+    let bus = Arc::new(InMemoryEventBus::new(10));
+    assert_eq!(bus.metrics.channel_capacity_value(), 10);
+
+    // Nothing drains the queue until the processor starts
+    for _ in 0..4 {
+        bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    }
+    assert_eq!(bus.current_depth(), 4);
+    assert_eq!(bus.metrics.queue_depth_value(), 4);
+
+    let _handle = bus.clone().start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(bus.current_depth(), 0);
+    assert_eq!(bus.metrics.queue_depth_value(), 0);
+}
+
+#[tokio::test]
+async fn test_duplicate_envelope_id_is_handled_once() {
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _handle = bus.clone().start();
+
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = handler.count.clone();
+    bus.subscribe("counter".to_string(), Box::new(handler)).await.unwrap();
+
+    let envelope = push_envelope(EventPriority::Normal);
+    bus.publish(envelope.clone()).await.unwrap();
+    bus.publish(envelope).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    assert_eq!(bus.metrics.duplicate_count(), 1);
+}
+
+#[tokio::test]
+async fn test_dedup_window_is_bounded() {
+    let bus =
+        InMemoryEventBus::with_config(EventBusConfig { dedup_window: 2, ..Default::default() });
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = handler.count.clone();
+    bus.subscribe("counter".to_string(), Box::new(handler)).await.unwrap();
+
+    let envelopes: Vec<EventEnvelope> =
+        (0..3).map(|_| push_envelope(EventPriority::Normal)).collect();
+    for envelope in &envelopes {
+        bus.publish_and_wait(envelope.clone(), Duration::from_secs(5)).await.unwrap();
+    }
+
+    // The oldest id has left the window, the newest is still in it
+    let report = bus.publish_and_wait(envelopes[0].clone(), Duration::from_secs(5)).await.unwrap();
+    assert_eq!(report.matched, 1);
+    let report = bus.publish_and_wait(envelopes[2].clone(), Duration::from_secs(5)).await.unwrap();
+    assert_eq!(report.matched, 0);
+    assert_eq!(counter.load(Ordering::SeqCst), 4);
+}
+
+/// Round-trips events between two bus instances through a real NATS server
+#[cfg(feature = "nats-integration-tests")]
+#[tokio::test]
+async fn test_nats_bus_delivers_across_instances() {
+    use crate::nats::NatsEventBus;
+
+    // A fresh prefix keeps concurrent runs against one server apart
+    let prefix = format!("nimbus-test.{}", Uuid::new_v4());
+    let publisher = NatsEventBus::from_env().await.unwrap().with_subject_prefix(&prefix);
+    let subscriber = NatsEventBus::from_env().await.unwrap().with_subject_prefix(&prefix);
+
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![EventType::Push],
+        repositories: vec!["repo".to_string()],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = handler.count.clone();
+    subscriber.subscribe("counter".to_string(), Box::new(handler)).await.unwrap();
+
+    let mut other_repository = push_envelope(EventPriority::Normal);
+    if let Event::Push { repository, .. } = &mut other_repository.event {
+        *repository = "other-repo".to_string();
+    }
+    publisher.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+    publisher.publish(other_repository).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    assert_eq!(subscriber.subscriber_count().await, 1);
+    subscriber.unsubscribe("counter").await.unwrap();
+    assert_eq!(subscriber.subscriber_count().await, 0);
+}
+
+fn bus_with_overflow(overflow_policy: OverflowPolicy) -> InMemoryEventBus {
+    // The processor is never started, so the single slot stays taken
+    InMemoryEventBus::with_config(EventBusConfig {
+        buffer_size: 1,
+        overflow_policy,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn test_overflow_block_waits_for_space() {
+    let bus = bus_with_overflow(OverflowPolicy::Block);
+    bus.publish(push_envelope(EventPriority::Normal)).await.unwrap();
+
+    let blocked = tokio::time::timeout(
+        Duration::from_millis(50),
+        bus.publish(push_envelope(EventPriority::Normal)),
+    )
+    .await;
+    assert!(blocked.is_err());
+    assert_eq!(bus.current_depth(), 1);
+}
+
+#[tokio::test]
+async fn test_overflow_drop_oldest_keeps_newest() {
+    let bus = bus_with_overflow(OverflowPolicy::DropOldest);
+    let (first, second) =
+        (push_envelope(EventPriority::Normal), push_envelope(EventPriority::Normal));
+    bus.publish(first).await.unwrap();
+    bus.publish(second.clone()).await.unwrap();
+
+    assert_eq!(bus.current_depth(), 1);
+    assert_eq!(bus.queue.pop().await.id, second.id);
+    assert_eq!(bus.metrics.dropped_count(), 1);
+}
+
+#[tokio::test]
+async fn test_overflow_drop_newest_keeps_oldest() {
+    let bus = bus_with_overflow(OverflowPolicy::DropNewest);
+    let (first, second) =
+        (push_envelope(EventPriority::Normal), push_envelope(EventPriority::Normal));
+    bus.publish(first.clone()).await.unwrap();
+    bus.publish(second).await.unwrap();
+
+    assert_eq!(bus.current_depth(), 1);
+    assert_eq!(bus.queue.pop().await.id, first.id);
+    assert_eq!(bus.metrics.dropped_count(), 1);
+}
+
+#[tokio::test]
+async fn test_overflow_reject_err_fails_immediately() {
+    let bus = bus_with_overflow(OverflowPolicy::RejectErr);
+    let (first, second) =
+        (push_envelope(EventPriority::Normal), push_envelope(EventPriority::Normal));
+    bus.publish(first.clone()).await.unwrap();
+
+    let error = bus.publish(second.clone()).await.unwrap_err();
+    let full = error.downcast_ref::<QueueFull>().expect("a QueueFull error");
+    assert_eq!(full.capacity, 1);
+    assert_eq!(full.id, second.id);
+
+    assert_eq!(bus.current_depth(), 1);
+    assert_eq!(bus.queue.pop().await.id, first.id);
+    assert_eq!(bus.metrics.dropped_count(), 0);
+}
+
+#[tokio::test]
+async fn test_per_handler_timeout_does_not_block_siblings() {
+    let bus = InMemoryEventBus::new(10);
+    let slow = SlowHandler { delay: Duration::from_secs(5) };
+    bus.subscribe_with_timeout("slow".to_string(), Box::new(slow), Duration::from_secs(1))
+        .await
+        .unwrap();
+    let instant = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec![],
+    });
+    let counter = instant.count.clone();
+    bus.subscribe_with_timeout("instant".to_string(), Box::new(instant), Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    let report = bus
+        .publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(10))
+        .await
+        .unwrap();
+
+    assert_eq!(report.matched, 2);
+    assert_eq!(report.succeeded, 1);
+    assert_eq!(report.failed, 1);
+    // Bounded by the slow handler's own timeout, not its 5s run
+    assert!(report.duration < Duration::from_secs(2), "took {:?}", report.duration);
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    assert_eq!(bus.metrics.handler_timeout_count("slow"), 1);
+    assert_eq!(bus.metrics.handler_timeout_count("instant"), 0);
+}
+
+#[tokio::test]
+async fn test_regex_branch_pattern() {
+    let pattern = r"re:^release/\d+\.\d+$";
+    assert_eq!(count_matching(pattern, &["release/1.2"]).await, 1);
+    assert_eq!(count_matching(pattern, &["release/notes", "release/1.2.3", "main"]).await, 0);
+}
+
+#[tokio::test]
+async fn test_malformed_regex_branch_pattern_rejected_at_subscribe() {
+    let bus = InMemoryEventBus::new(10);
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec!["re:release/(".to_string()],
+        tags: vec![],
+    });
+
+    let error = bus.subscribe("broken".to_string(), Box::new(handler)).await.unwrap_err();
+    assert!(error.downcast_ref::<InvalidBranchPattern>().is_some());
+    assert_eq!(bus.subscriber_count().await, 0);
+}
+
+fn tag_created(tag: &str) -> EventEnvelope {
+    let mut envelope = push_envelope(EventPriority::Normal);
+    envelope.event = Event::TagCreated {
+        repository: "repo".to_string(),
+        tag: tag.to_string(),
+        target: "abc123".to_string(),
+        tagger: "user".to_string(),
+    };
+    envelope
+}
+
+#[tokio::test]
+async fn test_tag_filter_matches_glob() {
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _handle = bus.clone().start();
+
+    let handler = CountingHandler::new(EventFilter {
+        event_types: vec![],
+        repositories: vec![],
+        branches: vec![],
+        tags: vec!["v*".to_string()],
+    });
+    let counter = handler.count.clone();
+    bus.subscribe("releases".to_string(), Box::new(handler)).await.unwrap();
+
+    bus.publish(tag_created("v1.0.0")).await.unwrap();
+    bus.publish(tag_created("nightly")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_event_filter_tags_default_to_empty() {
+    let filter: EventFilter =
+        serde_json::from_str(r#"{"event_types":[],"repositories":[],"branches":[]}"#).unwrap();
+    assert!(filter.tags.is_empty());
+}
+
+#[tokio::test]
+async fn test_channel_subscriber_receives_matching_events() {
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _handle = bus.clone().start();
+
+    let receiver =
+        bus.subscribe_channel(EventFilter { tags: vec!["v*".to_string()], ..Default::default() });
+    bus.publish(tag_created("v1.0.0")).await.unwrap();
+    bus.publish(tag_created("nightly")).await.unwrap();
+    bus.publish(tag_created("v1.1.0")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let tags: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok())
+        .map(|envelope| match envelope.event {
+            Event::TagCreated { tag, .. } => tag,
+            other => panic!("unexpected event {:?}", other),
+        })
+        .collect();
+    assert_eq!(tags, vec!["v1.0.0".to_string(), "v1.1.0".to_string()]);
+
+    // Dropping the receiver unsubscribes it without waiting for another event
+    assert_eq!(bus.subscriber_count().await, 1);
+    drop(receiver);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(bus.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn test_channel_receiver_closes_with_the_bus() {
+    let bus = InMemoryEventBus::new(10);
+    let receiver = bus.subscribe_channel(EventFilter::default());
+
+    drop(bus);
+    let closed = tokio::time::timeout(Duration::from_secs(1), receiver.recv()).await;
+    assert!(matches!(closed, Ok(Err(async_channel::RecvError))));
+}
+
+fn commit_with_message(message: &str) -> nimbus_types::Commit {
+    nimbus_types::Commit {
+        sha: "0123456789abcdef".to_string(),
+        message: message.to_string(),
+        author: "alice".to_string(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        parent_shas: vec!["fedcba9876543210".to_string()],
+        files_changed: vec![],
+        verification: Default::default(),
+    }
+}
+
+#[test]
+fn test_commit_lint_flags_non_conventional_message() {
+    use commit_lint::CommitLintConfig;
+    use nimbus_types::events::SuggestionSeverity;
+
+    let config = CommitLintConfig::default();
+    let suggestions = config.lint(&commit_with_message("Updated the readme."));
+
+    assert!(suggestions.iter().any(|s| matches!(s.severity, SuggestionSeverity::Error)));
+    assert!(suggestions.iter().any(|s| matches!(s.severity, SuggestionSeverity::Warning)));
+    assert!(suggestions.iter().all(|s| s.file == "0123456789abcdef" && s.line == Some(1)));
+
+    let long = format!("fix: {}", "x".repeat(80));
+    let suggestions = config.lint(&commit_with_message(&long));
+    assert_eq!(suggestions.len(), 1);
+    assert!(matches!(suggestions[0].severity, SuggestionSeverity::Warning));
+
+    for bad in ["feature: add login", "fix:no space", "fix(): empty scope", "fix: "] {
+        assert!(!config.lint(&commit_with_message(bad)).is_empty(), "{:?} passed", bad);
+    }
+}
+
+#[test]
+fn test_commit_lint_accepts_conforming_messages() {
+    use commit_lint::CommitLintConfig;
+
+    let config = CommitLintConfig::default();
+    for good in [
+        "feat: add login",
+        "fix(auth): reject expired tokens\n\nLonger body text that may end with a period.",
+        "refactor!: drop the v1 API",
+    ] {
+        assert!(config.lint(&commit_with_message(good)).is_empty(), "{:?} failed", good);
+    }
+
+    // Merge commits are written by git
+    let mut merge = commit_with_message("Merge branch 'main' into feature");
+    merge.parent_shas.push("aaaa".to_string());
+    assert!(config.lint(&merge).is_empty());
+
+    // Without allowed types any prefix goes
+    let relaxed = CommitLintConfig { allowed_types: vec![], ..Default::default() };
+    assert!(relaxed.lint(&commit_with_message("Update readme")).is_empty());
+}
+
+#[tokio::test]
+async fn test_commit_linter_publishes_violations_for_push() {
+    use commit_lint::{CommitLintConfig, CommitLinter, PLUGIN_NAME};
+
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _handle = bus.clone().start();
+    let linter = CommitLinter::new(CommitLintConfig::default(), bus.clone());
+    bus.subscribe("commit-lint".to_string(), Box::new(linter)).await.unwrap();
+
+    let push = |message: &str| {
+        envelope_for(Event::Push {
+            repository: "app".to_string(),
+            branch: "main".to_string(),
+            commits: vec![commit_with_message(message)],
+            pusher: "alice".to_string(),
+        })
+    };
+    bus.publish(push("feat: add login")).await.unwrap();
+    bus.publish(push("wip")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let findings: Vec<_> = bus
+        .recent_events(10)
+        .into_iter()
+        .filter_map(|envelope| match envelope.event {
+            Event::AiAnalysisCompleted { repository, suggestions, plugin, .. } => {
+                Some((repository, suggestions, plugin))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(findings.len(), 1);
+    let (repository, suggestions, plugin) = &findings[0];
+    assert_eq!(repository, "app");
+    assert_eq!(plugin, PLUGIN_NAME);
+    assert_eq!(suggestions.len(), 1);
+}
+
+#[tokio::test]
+async fn test_unrestorable_subscriptions_stay_recorded() {
+    use nimbus_types::{Plugin, PluginType};
+    use subscriptions::{InMemorySubscriptionStore, SubscriptionStore};
+
+    let store = Arc::new(InMemorySubscriptionStore::new());
+    let plugin = Plugin {
+        id: Uuid::new_v4(),
+        name: "reviewer".to_string(),
+        plugin_type: PluginType::ReviewSystem,
+        endpoint: "http://reviewer/events".to_string(),
+        health_check: "http://reviewer/health".to_string(),
+        interests: Some(EventFilter::for_types([EventType::PullRequest])),
+    };
+    let bus = InMemoryEventBus::new(10).with_subscription_store(store.clone());
+    let handler = CountingHandler::new(EventFilter::for_types([EventType::PullRequest]));
+    bus.subscribe_persistent("reviewer".to_string(), plugin, Box::new(handler)).await.unwrap();
+
+    let bus = InMemoryEventBus::new(10).with_subscription_store(store.clone());
+    assert_eq!(bus.restore_subscriptions(|_| None).await.unwrap(), 0);
+    assert_eq!(bus.subscriber_count().await, 0);
+    let recorded = store.load().await.unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].filter, EventFilter::for_types([EventType::PullRequest]));
+
+    let restored = bus
+        .restore_subscriptions(|subscription| {
+            Some(Box::new(CountingHandler::new(subscription.filter.clone()))
+                as Box<dyn EventHandler>)
+        })
+        .await
+        .unwrap();
+    assert_eq!(restored, 1);
+    assert_eq!(bus.subscriber_count().await, 1);
+}
+
+/// Handler tracking how many runs overlap, pairing up on a barrier
+struct BarrierHandler {
+    barrier: Arc<tokio::sync::Barrier>,
+    running: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl EventHandler for BarrierHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        self.barrier.wait().await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::all()
+    }
+}
+
+#[tokio::test]
+async fn test_in_flight_handlers_are_limited() {
+    let bus = Arc::new(InMemoryEventBus::with_config(EventBusConfig {
+        max_in_flight_handlers: 2,
+        dispatch_mode: DispatchMode::Concurrent,
+        ..Default::default()
+    }));
+    let _handle = bus.clone().start();
+
+    let barrier = Arc::new(tokio::sync::Barrier::new(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    for i in 0..4 {
+        let handler = BarrierHandler {
+            barrier: barrier.clone(),
+            running: running.clone(),
+            peak: peak.clone(),
+        };
+        bus.subscribe(format!("barrier-{}", i), Box::new(handler)).await.unwrap();
+    }
+
+    // Two events at once, eight handler runs between them
+    let (first, second) = tokio::join!(
+        bus.publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(5)),
+        bus.publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(5)),
+    );
+    assert_eq!(first.unwrap().succeeded, 4);
+    assert_eq!(second.unwrap().succeeded, 4);
+
+    // Pairs meet at the barrier, so two did run together, and never more
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+/// Handler logging when it starts and finishes
+struct StagedHandler {
+    label: &'static str,
+    delay: Duration,
+    log: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl EventHandler for StagedHandler {
+    async fn handle(&self, _event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.log.lock().unwrap().push(format!("{} started", self.label));
+        tokio::time::sleep(self.delay).await;
+        self.log.lock().unwrap().push(format!("{} finished", self.label));
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::all()
+    }
+}
+
+#[tokio::test]
+async fn test_handler_stages_run_in_order() {
+    let bus = InMemoryEventBus::new(10);
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let handler = |label, delay| Box::new(StagedHandler { label, delay, log: log.clone() });
+
+    // Subscribed first, but runs only once the CI stage is done
+    bus.subscribe_with_stage("notifier".to_string(), handler("notifier", Duration::ZERO), 1)
+        .await
+        .unwrap();
+    bus.subscribe("ci".to_string(), handler("ci", Duration::from_millis(50))).await.unwrap();
+
+    let report = bus
+        .publish_and_wait(push_envelope(EventPriority::Normal), Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(report.succeeded, 2);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["ci started", "ci finished", "notifier started", "notifier finished"]
+    );
+
+    let stages: Vec<_> =
+        bus.subscriptions().await.into_iter().map(|info| (info.name, info.stage)).collect();
+    assert_eq!(stages, [("ci".to_string(), 0), ("notifier".to_string(), 1)]);
+}
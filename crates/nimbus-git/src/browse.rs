@@ -0,0 +1,271 @@
+//! Reading repository contents at a commit: trees, history and the README
+//!
+//! Paths from callers go through [`clean_path`] first: they only ever name
+//! entries inside the commit's tree, never anything on disk.
+
+use base64::Engine;
+use git2::{ObjectType, Oid, Repository, Sort};
+use nimbus_types::{Commit, NimbusError};
+use serde::{Deserialize, Serialize};
+
+use crate::diff::files_changed;
+use crate::git_error;
+use crate::signing::Keyring;
+
+/// README file names, in order of preference
+const README_NAMES: &[&str] = &["README.md", "README", "README.txt", "readme.md"];
+
+/// Largest README returned inline
+const MAX_README_BYTES: usize = 512 * 1024;
+
+/// Most bytes of a file [`read_blob`] returns
+const MAX_BLOB_BYTES: usize = 1024 * 1024;
+
+/// Type of the object a tree entry points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    /// A submodule, pointing at a commit of another repository
+    Commit,
+}
+
+/// One entry of a tree, as git records it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub name: String,
+    /// Path from the repository root
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: ObjectKind,
+    /// Size in bytes, for blobs
+    pub size: Option<u64>,
+    /// File mode in octal, e.g. `100644`
+    pub mode: String,
+    pub sha: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobEncoding {
+    Utf8,
+    Base64,
+}
+
+/// Contents of a file; binary files are base64 encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blob {
+    pub path: String,
+    pub sha: String,
+    /// Size of the whole file in bytes
+    pub size: u64,
+    pub binary: bool,
+    /// Whether `content` stops short of the end of the file
+    pub truncated: bool,
+    pub encoding: BlobEncoding,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitSummary {
+    pub sha: String,
+    pub summary: String,
+    pub author: String,
+    pub author_email: String,
+    /// Unix timestamp of the author date
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readme {
+    pub path: String,
+    pub content: String,
+}
+
+/// Whether the repository has no commits yet
+///
+/// A freshly created repository has an unborn `HEAD` and no refs, so there
+/// is nothing to resolve a ref against; read endpoints report this state
+/// instead of failing to find `HEAD`.
+pub fn is_empty(repo: &Repository) -> Result<bool, NimbusError> {
+    repo.is_empty().map_err(git_error)
+}
+
+/// Entries of the tree at `path` (empty for the root) in `commit`, with
+/// their git types, modes and blob sizes
+///
+/// Trees are listed first, then everything else, each sorted by name.
+pub fn list_tree(
+    repo: &Repository,
+    commit: Oid,
+    path: &str,
+) -> Result<Vec<TreeEntry>, NimbusError> {
+    let path = clean_path(path)?;
+    let tree = tree_at(repo, commit, &path)?;
+
+    let odb = repo.odb().map_err(git_error)?;
+    let mut entries = Vec::new();
+    for entry in tree.iter() {
+        let kind = match entry.kind() {
+            Some(ObjectType::Tree) => ObjectKind::Tree,
+            Some(ObjectType::Commit) => ObjectKind::Commit,
+            _ => ObjectKind::Blob,
+        };
+        let size = match kind {
+            ObjectKind::Blob => {
+                let (size, _) = odb.read_header(entry.id()).map_err(git_error)?;
+                Some(size as u64)
+            }
+            _ => None,
+        };
+        let name = entry.name().unwrap_or_default().to_string();
+        entries.push(TreeEntry {
+            path: if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) },
+            name,
+            kind,
+            size,
+            mode: format!("{:06o}", entry.filemode()),
+            sha: entry.id().to_string(),
+        });
+    }
+    entries.sort_by(|a, b| {
+        (a.kind != ObjectKind::Tree, &a.name).cmp(&(b.kind != ObjectKind::Tree, &b.name))
+    });
+    Ok(entries)
+}
+
+/// Contents of the file at `path` in `commit`
+///
+/// Only the first megabyte is returned, with `truncated` set for anything
+/// longer. Text is returned as is, binary files base64 encoded.
+pub fn read_blob(repo: &Repository, commit: Oid, path: &str) -> Result<Blob, NimbusError> {
+    let path = clean_path(path)?;
+    let root = repo.find_commit(commit).map_err(git_error)?.tree().map_err(git_error)?;
+    let blob = root
+        .get_path(std::path::Path::new(&path))
+        .map_err(|_| NimbusError::PathNotFound(path.clone()))?
+        .to_object(repo)
+        .and_then(|object| object.peel_to_blob())
+        .map_err(|_| NimbusError::InvalidGitOperation(format!("{} is not a file", path)))?;
+
+    let bytes = blob.content();
+    let shown = &bytes[..bytes.len().min(MAX_BLOB_BYTES)];
+    let binary = blob.is_binary() || std::str::from_utf8(shown).is_err();
+    let (encoding, content) = if binary {
+        (BlobEncoding::Base64, base64::engine::general_purpose::STANDARD.encode(shown))
+    } else {
+        (BlobEncoding::Utf8, String::from_utf8_lossy(shown).into_owned())
+    };
+    Ok(Blob {
+        path,
+        sha: blob.id().to_string(),
+        size: bytes.len() as u64,
+        binary,
+        truncated: shown.len() < bytes.len(),
+        encoding,
+        content,
+    })
+}
+
+/// `path` with empty segments dropped, rejecting `.` and `..` segments
+///
+/// Trees can't reach outside the repository anyway; this keeps paths
+/// unambiguous so `a/../b` is refused rather than resolved.
+pub fn clean_path(path: &str) -> Result<String, NimbusError> {
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    if segments.iter().any(|segment| matches!(*segment, "." | "..") || segment.contains('\0')) {
+        return Err(NimbusError::InvalidGitOperation(format!("Invalid path {:?}", path)));
+    }
+    Ok(segments.join("/"))
+}
+
+/// The tree at cleaned `path` (empty for the root) in `commit`
+fn tree_at<'r>(
+    repo: &'r Repository,
+    commit: Oid,
+    path: &str,
+) -> Result<git2::Tree<'r>, NimbusError> {
+    let root = repo.find_commit(commit).map_err(git_error)?.tree().map_err(git_error)?;
+    if path.is_empty() {
+        return Ok(root);
+    }
+    let entry = root
+        .get_path(std::path::Path::new(path))
+        .map_err(|_| NimbusError::PathNotFound(path.to_string()))?;
+    entry
+        .to_object(repo)
+        .and_then(|object| object.peel_to_tree())
+        .map_err(|_| NimbusError::InvalidGitOperation(format!("{} is not a directory", path)))
+}
+
+/// Up to `limit` commits reachable from `commit`, newest first
+pub fn list_commits(
+    repo: &Repository,
+    commit: Oid,
+    limit: usize,
+) -> Result<Vec<CommitSummary>, NimbusError> {
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).map_err(git_error)?;
+    walk.push(commit).map_err(git_error)?;
+
+    walk.take(limit)
+        .map(|oid| {
+            let commit = repo.find_commit(oid.map_err(git_error)?).map_err(git_error)?;
+            let author = commit.author();
+            Ok(CommitSummary {
+                sha: commit.id().to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                author_email: author.email().unwrap_or_default().to_string(),
+                timestamp: author.when().seconds(),
+            })
+        })
+        .collect()
+}
+
+/// Full record of `commit`, including the files it changed and its
+/// signature verified against `keyring`
+///
+/// This is the form pushed commits take in `Event::Push`.
+pub fn commit_details(
+    repo: &Repository,
+    commit: Oid,
+    keyring: &Keyring,
+) -> Result<Commit, NimbusError> {
+    let verification = keyring.verify(repo, commit)?;
+    let commit = repo.find_commit(commit).map_err(git_error)?;
+    let author = commit.author();
+    let timestamp = time::OffsetDateTime::from_unix_timestamp(author.when().seconds())
+        .map_err(|e| NimbusError::Internal(format!("Invalid commit time: {}", e)))?;
+
+    Ok(Commit {
+        sha: commit.id().to_string(),
+        message: commit.message().unwrap_or_default().to_string(),
+        author: author.name().unwrap_or_default().to_string(),
+        timestamp,
+        parent_shas: commit.parent_ids().map(|id| id.to_string()).collect(),
+        files_changed: files_changed(repo, &commit)?,
+        verification,
+    })
+}
+
+/// The README at the root of `commit`, if there is a readable one
+pub fn readme(repo: &Repository, commit: Oid) -> Result<Option<Readme>, NimbusError> {
+    let tree = repo.find_commit(commit).map_err(git_error)?.tree().map_err(git_error)?;
+
+    for name in README_NAMES {
+        let Some(entry) = tree.get_name(name) else {
+            continue;
+        };
+        let Ok(blob) = entry.to_object(repo).and_then(|object| object.peel_to_blob()) else {
+            continue;
+        };
+        if blob.is_binary() || blob.size() > MAX_README_BYTES {
+            continue;
+        }
+        let content = String::from_utf8_lossy(blob.content()).into_owned();
+        return Ok(Some(Readme { path: name.to_string(), content }));
+    }
+    Ok(None)
+}
@@ -0,0 +1,316 @@
+//! Structured diffs with stable line positions
+//!
+//! Every diff line carries a `position` derived from its file, side, line
+//! number and content. Review comments anchor to that id, so they survive
+//! re-renders and can be detected as outdated once the line itself changes.
+//!
+//! [`compare`] is the bounded variant served to browsers: past
+//! [`DiffLimits::max_lines`] the diff is cut off and flagged `truncated`.
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use nimbus_types::{ChangeStatus, FileChange, NimbusError};
+
+use crate::git_error;
+use crate::refs::{ResolvedRef, resolve_ref};
+
+/// Diff lines returned by [`compare`] unless configured otherwise
+const DEFAULT_MAX_LINES: usize = 5_000;
+
+/// Which version of the file a line belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// The base version (removed lines)
+    Old,
+    /// The head version (added and context lines)
+    New,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    /// Stable identifier for anchoring comments to this line
+    pub position: String,
+    pub kind: LineKind,
+    pub side: Side,
+    pub old_line: Option<u32>,
+    pub new_line: Option<u32>,
+    pub content: String,
+}
+
+impl DiffLine {
+    /// Line number on the side this line belongs to
+    pub fn line(&self) -> u32 {
+        match self.side {
+            Side::Old => self.old_line.unwrap_or_default(),
+            Side::New => self.new_line.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileDiff {
+    pub fn lines(&self) -> impl Iterator<Item = &DiffLine> {
+        self.hunks.iter().flat_map(|hunk| hunk.lines.iter())
+    }
+}
+
+/// Find the line a position refers to, together with its file path
+pub fn find_position<'a>(files: &'a [FileDiff], position: &str) -> Option<(&'a str, &'a DiffLine)> {
+    files.iter().find_map(|file| {
+        file.lines().find(|line| line.position == position).map(|line| (file.path.as_str(), line))
+    })
+}
+
+/// Stable id of a diff line
+pub fn line_position(path: &str, side: Side, line: u32, content: &str) -> String {
+    let side = match side {
+        Side::Old => "old",
+        Side::New => "new",
+    };
+    let key = format!("{}\0{}\0{}\0{}", path, side, line, content);
+    // Content addressed with git's own hashing so ids never depend on the toolchain
+    let oid = git2::Oid::hash_object(git2::ObjectType::Blob, key.as_bytes())
+        .expect("hashing an in-memory buffer cannot fail");
+    oid.to_string()[..16].to_string()
+}
+
+/// Diff `head` against its merge base with `base`
+///
+/// Both arguments are revisions (`main`, a tag, a sha, ...).
+pub fn diff_revisions(
+    repo: &git2::Repository,
+    base: &str,
+    head: &str,
+) -> Result<Vec<FileDiff>, NimbusError> {
+    let base_commit =
+        repo.revparse_single(base).and_then(|o| o.peel_to_commit()).map_err(git_error)?;
+    let head_commit =
+        repo.revparse_single(head).and_then(|o| o.peel_to_commit()).map_err(git_error)?;
+
+    diff_from_merge_base(repo, base_commit, &head_commit).map(|(_, files)| files)
+}
+
+/// Diff `head` against its merge base with `base`, returning that merge base
+fn diff_from_merge_base<'r>(
+    repo: &'r git2::Repository,
+    base: git2::Commit<'r>,
+    head: &git2::Commit<'r>,
+) -> Result<(git2::Oid, Vec<FileDiff>), NimbusError> {
+    // Only show what `head` introduces, not what `base` gained since
+    let merge_base =
+        repo.merge_base(base.id(), head.id()).and_then(|oid| repo.find_commit(oid)).unwrap_or(base);
+
+    let old_tree = merge_base.tree().map_err(git_error)?;
+    let new_tree = head.tree().map_err(git_error)?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None).map_err(git_error)?;
+
+    Ok((merge_base.id(), collect_diff(&diff)?))
+}
+
+/// How much of a diff [`compare`] returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLimits {
+    /// Most diff lines returned, across all files
+    pub max_lines: usize,
+}
+
+impl Default for DiffLimits {
+    fn default() -> Self {
+        Self { max_lines: DEFAULT_MAX_LINES }
+    }
+}
+
+impl DiffLimits {
+    /// Limits from `NIMBUS_DIFF_MAX_LINES`, keeping the default if unset or invalid
+    pub fn from_env() -> Self {
+        let mut limits = Self::default();
+        if let Ok(value) = std::env::var("NIMBUS_DIFF_MAX_LINES") {
+            match value.parse() {
+                Ok(max_lines) => limits.max_lines = max_lines,
+                Err(_) => warn!("Ignoring invalid NIMBUS_DIFF_MAX_LINES={}", value),
+            }
+        }
+        limits
+    }
+}
+
+/// Two revisions and the diff between them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparison {
+    pub base: ResolvedRef,
+    pub head: ResolvedRef,
+    /// Commit the diff was taken from
+    pub merge_base: String,
+    pub files: Vec<FileDiff>,
+    /// Whether lines past [`DiffLimits::max_lines`] were left out
+    pub truncated: bool,
+}
+
+/// Compare `head` with `base` as `git diff base...head` does
+///
+/// Both are resolved with [`resolve_ref`], so unknown revisions are
+/// `RefNotFound`. The diff is cut to `limits`, see [`truncate`].
+pub fn compare(
+    repo: &git2::Repository,
+    base: &str,
+    head: &str,
+    limits: &DiffLimits,
+) -> Result<Comparison, NimbusError> {
+    let base = resolve_ref(repo, base)?;
+    let head = resolve_ref(repo, head)?;
+    let base_commit = repo.find_commit(base.oid()).map_err(git_error)?;
+    let head_commit = repo.find_commit(head.oid()).map_err(git_error)?;
+
+    let (merge_base, mut files) = diff_from_merge_base(repo, base_commit, &head_commit)?;
+    let truncated = truncate(&mut files, limits.max_lines);
+    Ok(Comparison { base, head, merge_base: merge_base.to_string(), files, truncated })
+}
+
+/// Keep the first `max_lines` lines of `files`, returning whether any were dropped
+///
+/// The hunk reaching the limit is cut short and everything after it dropped,
+/// along with files left without lines.
+pub fn truncate(files: &mut Vec<FileDiff>, max_lines: usize) -> bool {
+    let mut remaining = max_lines;
+    let mut truncated = false;
+    files.retain_mut(|file| {
+        if truncated {
+            return false;
+        }
+        file.hunks.retain_mut(|hunk| {
+            if truncated {
+                return false;
+            }
+            if hunk.lines.len() > remaining {
+                hunk.lines.truncate(remaining);
+                truncated = true;
+            }
+            remaining -= hunk.lines.len();
+            !hunk.lines.is_empty()
+        });
+        !(truncated && file.hunks.is_empty())
+    });
+    truncated
+}
+
+/// Convert a libgit2 diff into files, hunks and positioned lines
+pub fn collect_diff(diff: &git2::Diff<'_>) -> Result<Vec<FileDiff>, NimbusError> {
+    let mut files: Vec<FileDiff> = Vec::new();
+
+    diff.print(git2::DiffFormat::Patch, |delta, hunk, line| {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if files.last().is_none_or(|file| file.path != path) {
+            files.push(FileDiff { path: path.clone(), hunks: Vec::new() });
+        }
+        let file = files.last_mut().expect("file was just pushed");
+
+        let (kind, side) = match line.origin() {
+            ' ' => (LineKind::Context, Side::New),
+            '+' => (LineKind::Added, Side::New),
+            '-' => (LineKind::Removed, Side::Old),
+            // File headers, hunk headers and end-of-file markers
+            _ => return true,
+        };
+
+        if let Some(hunk) = hunk {
+            let header = String::from_utf8_lossy(hunk.header()).trim_end().to_string();
+            if file.hunks.last().is_none_or(|h| h.header != header) {
+                file.hunks.push(DiffHunk { header, lines: Vec::new() });
+            }
+        }
+        let Some(current) = file.hunks.last_mut() else {
+            return true;
+        };
+
+        let content = String::from_utf8_lossy(line.content()).trim_end_matches('\n').to_string();
+        let number = match side {
+            Side::Old => line.old_lineno(),
+            Side::New => line.new_lineno(),
+        }
+        .unwrap_or_default();
+
+        current.lines.push(DiffLine {
+            position: line_position(&path, side, number, &content),
+            kind,
+            side,
+            old_line: line.old_lineno(),
+            new_line: line.new_lineno(),
+            content,
+        });
+        true
+    })
+    .map_err(git_error)?;
+
+    Ok(files)
+}
+
+/// Files `commit` changed relative to its first parent, with line counts
+///
+/// Renames are detected as `git diff -M` does. Binary files count no lines.
+pub fn files_changed(
+    repo: &git2::Repository,
+    commit: &git2::Commit<'_>,
+) -> Result<Vec<FileChange>, NimbusError> {
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree().map_err(git_error)?),
+        None => None,
+    };
+    let tree = commit.tree().map_err(git_error)?;
+    let mut diff =
+        repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None).map_err(git_error)?;
+    diff.find_similar(Some(git2::DiffFindOptions::new().renames(true))).map_err(git_error)?;
+
+    let mut changes = Vec::new();
+    for (index, delta) in diff.deltas().enumerate() {
+        let status = match delta.status() {
+            git2::Delta::Added | git2::Delta::Copied => ChangeStatus::Added,
+            git2::Delta::Deleted => ChangeStatus::Deleted,
+            git2::Delta::Renamed => ChangeStatus::Renamed,
+            git2::Delta::Modified | git2::Delta::Typechange => ChangeStatus::Modified,
+            _ => continue,
+        };
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (additions, deletions) =
+            match git2::Patch::from_diff(&diff, index).map_err(git_error)? {
+                Some(patch) => {
+                    let (_, additions, deletions) = patch.line_stats().map_err(git_error)?;
+                    (additions as u32, deletions as u32)
+                }
+                None => (0, 0),
+            };
+        changes.push(FileChange { path, additions, deletions, status });
+    }
+    Ok(changes)
+}
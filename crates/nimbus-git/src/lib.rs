@@ -2,6 +2,26 @@
 //!
 //! This crate handles all git operations using libgit2
 
-pub fn placeholder() {
-    // Placeholder until we implement git operations
+use nimbus_types::NimbusError;
+
+pub mod browse;
+pub mod diff;
+pub mod policy;
+pub mod pulls;
+pub mod push;
+pub mod refs;
+pub mod signing;
+pub mod smart_http;
+pub mod storage;
+pub mod store;
+
+pub use storage::GitStorage;
+pub use store::{FsRepositoryStore, RepositoryStore};
+
+/// Convert a libgit2 error into the shared error type
+pub(crate) fn git_error(e: git2::Error) -> NimbusError {
+    NimbusError::InvalidGitOperation(e.message().to_string())
 }
+
+#[cfg(test)]
+mod tests;
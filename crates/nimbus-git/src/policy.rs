@@ -0,0 +1,185 @@
+//! Push policy: branch protection, commit messages, push size and signing
+//!
+//! Every ref update is described as a [`ProposedPush`] and checked by
+//! [`PushPolicy::check`]. Pushes over smart HTTP are checked before git
+//! accepts them (see [`crate::push`]), and the push-check API runs the same
+//! check as a dry run on a proposal the client sends.
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Rules every push must satisfy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushPolicy {
+    /// Branches that can't be force-pushed or deleted
+    pub protected_branches: Vec<String>,
+    /// Longest allowed commit subject, if limited
+    pub max_subject_length: Option<usize>,
+    /// Every pushed commit must carry a signature
+    pub require_signed_commits: bool,
+    /// Largest total size of new blobs in one push
+    pub max_push_bytes: u64,
+}
+
+impl Default for PushPolicy {
+    fn default() -> Self {
+        Self {
+            protected_branches: vec!["main".to_string(), "master".to_string()],
+            max_subject_length: None,
+            require_signed_commits: false,
+            max_push_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+impl PushPolicy {
+    /// Policy from `NIMBUS_PROTECTED_BRANCHES` (comma separated),
+    /// `NIMBUS_MAX_SUBJECT_LENGTH`, `NIMBUS_REQUIRE_SIGNED_COMMITS` and
+    /// `NIMBUS_MAX_PUSH_BYTES`, keeping the defaults for unset values
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring invalid {}={}", name, value);
+            }
+            parsed
+        }
+
+        let defaults = Self::default();
+        Self {
+            protected_branches: std::env::var("NIMBUS_PROTECTED_BRANCHES")
+                .map(|branches| {
+                    branches
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|branch| !branch.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or(defaults.protected_branches),
+            max_subject_length: var("NIMBUS_MAX_SUBJECT_LENGTH").or(defaults.max_subject_length),
+            require_signed_commits: var("NIMBUS_REQUIRE_SIGNED_COMMITS")
+                .unwrap_or(defaults.require_signed_commits),
+            max_push_bytes: var("NIMBUS_MAX_PUSH_BYTES").unwrap_or(defaults.max_push_bytes),
+        }
+    }
+
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.protected_branches.iter().any(|protected| protected == branch)
+    }
+
+    /// Every rule `push` breaks; empty if it may go ahead
+    pub fn check(&self, push: &ProposedPush) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.is_protected(&push.branch) {
+            if push.delete {
+                violations.push(Violation::branch(format!(
+                    "{} is protected and can't be deleted",
+                    push.branch
+                )));
+            } else if push.force {
+                violations.push(Violation::branch(format!(
+                    "{} is protected and can't be force-pushed",
+                    push.branch
+                )));
+            }
+        }
+
+        for commit in &push.commits {
+            let subject = commit.message.lines().next().unwrap_or_default().trim();
+            if subject.is_empty() {
+                violations.push(Violation::commit(
+                    PolicyRule::CommitMessage,
+                    commit,
+                    "Commit message is empty".to_string(),
+                ));
+            } else if let Some(max) =
+                self.max_subject_length.filter(|max| subject.chars().count() > *max)
+            {
+                violations.push(Violation::commit(
+                    PolicyRule::CommitMessage,
+                    commit,
+                    format!("Commit subject is longer than {} characters", max),
+                ));
+            }
+            if self.require_signed_commits && !commit.signed {
+                violations.push(Violation::commit(
+                    PolicyRule::Signing,
+                    commit,
+                    "Commit is not signed".to_string(),
+                ));
+            }
+        }
+
+        if push.size_bytes > self.max_push_bytes {
+            violations.push(Violation {
+                rule: PolicyRule::Size,
+                commit: None,
+                message: format!(
+                    "Push adds {} bytes, more than the {} allowed",
+                    push.size_bytes, self.max_push_bytes
+                ),
+            });
+        }
+
+        violations
+    }
+}
+
+/// A ref update, as far as policy is concerned
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposedPush {
+    pub branch: String,
+    /// The update isn't a fast-forward of the current branch
+    #[serde(default)]
+    pub force: bool,
+    /// The update deletes the branch
+    #[serde(default)]
+    pub delete: bool,
+    /// Commits the push introduces
+    #[serde(default)]
+    pub commits: Vec<ProposedCommit>,
+    /// Total size of the blobs the push introduces
+    #[serde(default)]
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposedCommit {
+    #[serde(default)]
+    pub sha: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub signed: bool,
+}
+
+/// Which rule a violation is of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    BranchProtection,
+    CommitMessage,
+    Size,
+    Signing,
+}
+
+/// One reason a push would be rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    pub rule: PolicyRule,
+    /// Sha of the offending commit, for per-commit rules
+    pub commit: Option<String>,
+    pub message: String,
+}
+
+impl Violation {
+    fn branch(message: String) -> Self {
+        Self { rule: PolicyRule::BranchProtection, commit: None, message }
+    }
+
+    fn commit(rule: PolicyRule, commit: &ProposedCommit, message: String) -> Self {
+        Self { rule, commit: commit.sha.clone(), message }
+    }
+}
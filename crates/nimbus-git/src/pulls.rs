@@ -0,0 +1,135 @@
+//! Pull requests and their inline review comments
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use nimbus_types::NimbusError;
+
+use crate::diff::{FileDiff, Side, find_position};
+
+/// A pull request between two branches of one repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub id: Uuid,
+    pub repository: String,
+    pub from_branch: String,
+    pub to_branch: String,
+    pub title: String,
+    pub author: String,
+}
+
+/// Inline comment anchored to a diff line position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub id: Uuid,
+    pub pull_request_id: Uuid,
+    pub position: String,
+    pub file: String,
+    pub side: Side,
+    pub line: u32,
+    pub body: String,
+    pub author: String,
+    pub created_at: time::OffsetDateTime,
+    /// The anchored line no longer exists in the current diff
+    #[serde(default)]
+    pub outdated: bool,
+}
+
+/// In-memory pull request and review comment store
+#[derive(Debug, Default)]
+pub struct PullRequests {
+    pulls: RwLock<HashMap<Uuid, PullRequest>>,
+    comments: RwLock<HashMap<Uuid, Vec<ReviewComment>>>,
+}
+
+impl PullRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(
+        &self,
+        repository: &str,
+        from_branch: &str,
+        to_branch: &str,
+        title: &str,
+        author: &str,
+    ) -> PullRequest {
+        let pull = PullRequest {
+            id: Uuid::new_v4(),
+            repository: repository.to_string(),
+            from_branch: from_branch.to_string(),
+            to_branch: to_branch.to_string(),
+            title: title.to_string(),
+            author: author.to_string(),
+        };
+        self.pulls.write().unwrap().insert(pull.id, pull.clone());
+        pull
+    }
+
+    /// Look up a pull request, scoped to its repository
+    pub fn get(&self, repository: &str, id: Uuid) -> Option<PullRequest> {
+        self.pulls.read().unwrap().get(&id).filter(|pull| pull.repository == repository).cloned()
+    }
+
+    pub fn list(&self, repository: &str) -> Vec<PullRequest> {
+        self.pulls
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pull| pull.repository == repository)
+            .cloned()
+            .collect()
+    }
+
+    /// Anchor a comment to a position in the pull request's current diff
+    pub fn add_comment(
+        &self,
+        pull_request_id: Uuid,
+        diff: &[FileDiff],
+        position: &str,
+        body: &str,
+        author: &str,
+    ) -> Result<ReviewComment, NimbusError> {
+        let (file, line) = find_position(diff, position).ok_or_else(|| {
+            NimbusError::InvalidGitOperation(format!("Unknown diff position: {}", position))
+        })?;
+
+        let comment = ReviewComment {
+            id: Uuid::new_v4(),
+            pull_request_id,
+            position: position.to_string(),
+            file: file.to_string(),
+            side: line.side,
+            line: line.line(),
+            body: body.to_string(),
+            author: author.to_string(),
+            created_at: time::OffsetDateTime::now_utc(),
+            outdated: false,
+        };
+
+        self.comments.write().unwrap().entry(pull_request_id).or_default().push(comment.clone());
+        Ok(comment)
+    }
+
+    /// Comments on a pull request, flagged outdated against the current diff
+    pub fn comments(&self, pull_request_id: Uuid, diff: &[FileDiff]) -> Vec<ReviewComment> {
+        let comments = self.comments.read().unwrap();
+        comments
+            .get(&pull_request_id)
+            .map(|comments| {
+                comments
+                    .iter()
+                    .cloned()
+                    .map(|mut comment| {
+                        comment.outdated = find_position(diff, &comment.position).is_none();
+                        comment
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
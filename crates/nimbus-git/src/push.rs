@@ -0,0 +1,394 @@
+//! Checking pushes before git accepts them
+//!
+//! A receive-pack request is a list of ref updates followed by a pack. The
+//! pack is first indexed into a quarantine object directory inside the
+//! repository, so every update can be described as a [`ProposedPush`] and
+//! checked with [`PushPolicy::check`] while nothing the push brings is
+//! reachable from the repository. The quarantine is removed afterwards;
+//! an allowed push is handed to `git receive-pack` as sent.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use git2::{ErrorCode, ObjectType, Odb, Oid, Repository, Sort};
+use nimbus_types::NimbusError;
+use nimbus_types::events::Event;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::browse::commit_details;
+use crate::policy::{ProposedCommit, ProposedPush, PushPolicy, Violation};
+use crate::refs::deletion_event;
+use crate::signing::Keyring;
+use crate::smart_http::pkt_line;
+use crate::{GitStorage, git_error};
+
+/// Largest side-band packet payload, after the band byte
+const SIDE_BAND_PAYLOAD: usize = 65515;
+
+/// One `<old> <new> <ref>` command of a push
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub old: Oid,
+    pub new: Oid,
+    pub refname: String,
+}
+
+impl RefUpdate {
+    /// receive-pack deletes refs pushed to the zero oid
+    pub fn is_delete(&self) -> bool {
+        self.new.is_zero()
+    }
+}
+
+/// The commands of a receive-pack request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveRequest {
+    pub updates: Vec<RefUpdate>,
+    /// Capabilities the client asked for on its first command
+    pub capabilities: Vec<String>,
+    /// Where the pack starts in the request body
+    pack_offset: usize,
+}
+
+impl ReceiveRequest {
+    /// Parse the pkt-line commands at the start of a receive-pack request body
+    pub fn parse(body: &[u8]) -> Result<Self, NimbusError> {
+        let invalid = |message: &str| NimbusError::InvalidGitOperation(message.to_string());
+        let mut updates = Vec::new();
+        let mut capabilities = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let length = body
+                .get(offset..offset + 4)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid("Malformed push request"))?;
+            if length == 0 {
+                offset += 4;
+                break;
+            }
+            let line = body
+                .get(offset + 4..offset + length)
+                .filter(|_| length > 4)
+                .ok_or_else(|| invalid("Malformed push request"))?;
+            offset += length;
+
+            let line = String::from_utf8_lossy(line);
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            // Shallow clones announce their boundary before the commands
+            if line.starts_with("shallow ") {
+                continue;
+            }
+            let command = match line.split_once('\0') {
+                Some((command, requested)) => {
+                    capabilities = requested.split_whitespace().map(str::to_string).collect();
+                    command
+                }
+                None => line,
+            };
+            let mut parts = command.splitn(3, ' ');
+            let (Some(old), Some(new), Some(refname)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(invalid("Malformed ref update in push request"));
+            };
+            let oid = |hex: &str| Oid::from_str(hex).map_err(|_| invalid("Malformed object id"));
+            updates.push(RefUpdate {
+                old: oid(old)?,
+                new: oid(new)?,
+                refname: refname.to_string(),
+            });
+        }
+
+        if updates.is_empty() {
+            return Err(invalid("Push request has no ref updates"));
+        }
+        Ok(Self { updates, capabilities, pack_offset: offset })
+    }
+
+    /// The pack following the commands; empty for pushes that only delete
+    pub fn pack<'a>(&self, body: &'a [u8]) -> &'a [u8] {
+        &body[self.pack_offset..]
+    }
+
+    pub fn wants(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|requested| requested == capability)
+    }
+}
+
+/// A ref update with what the policy found and the commits it introduces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckedUpdate {
+    pub update: RefUpdate,
+    /// Commits reachable from the new value but not from any existing ref,
+    /// newest first
+    pub commits: Vec<Oid>,
+    pub violations: Vec<Violation>,
+}
+
+/// Check every update of a receive-pack request against `policy`
+///
+/// `body` is the whole request, whose pack is indexed into a quarantine
+/// and removed again before this returns.
+pub fn check_push(
+    storage: &GitStorage,
+    name: &str,
+    request: &ReceiveRequest,
+    body: &[u8],
+    policy: &PushPolicy,
+) -> Result<Vec<CheckedUpdate>, NimbusError> {
+    let repo = storage.open(name)?;
+    let quarantine = Quarantine::receive(repo.path(), request.pack(body))?;
+    repo.odb()
+        .map_err(git_error)?
+        .add_disk_alternate(&quarantine.path_str()?)
+        .map_err(git_error)?;
+    let size_bytes = quarantine.blob_bytes()?;
+
+    request
+        .updates
+        .iter()
+        .map(|update| {
+            let commits = if update.is_delete() {
+                Vec::new()
+            } else {
+                introduced_commits(&repo, update.new)?
+            };
+            let proposed = ProposedPush {
+                branch: update
+                    .refname
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(&update.refname)
+                    .to_string(),
+                force: is_force(&repo, update),
+                delete: update.is_delete(),
+                commits: commits
+                    .iter()
+                    .map(|oid| proposed_commit(&repo, *oid))
+                    .collect::<Result<_, _>>()?,
+                size_bytes,
+            };
+            Ok(CheckedUpdate {
+                update: update.clone(),
+                commits,
+                violations: policy.check(&proposed),
+            })
+        })
+        .collect()
+}
+
+/// Whether `update` moves a branch to a commit that doesn't contain its old one
+fn is_force(repo: &Repository, update: &RefUpdate) -> bool {
+    if !update.refname.starts_with("refs/heads/") || update.old.is_zero() || update.is_delete() {
+        return false;
+    }
+    // An old value git can't find is treated as a rewrite; git rejects the
+    // update anyway if the branch doesn't point there
+    update.new != update.old && !repo.graph_descendant_of(update.new, update.old).unwrap_or(false)
+}
+
+/// Commits reachable from `new` that no existing ref reaches, newest first
+fn introduced_commits(repo: &Repository, new: Oid) -> Result<Vec<Oid>, NimbusError> {
+    // Tags may point at trees or blobs, which introduce no commits
+    let Ok(commit) = repo.find_object(new, None).and_then(|object| object.peel_to_commit()) else {
+        return Ok(Vec::new());
+    };
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).map_err(git_error)?;
+    walk.push(commit.id()).map_err(git_error)?;
+    for reference in repo.references().map_err(git_error)?.flatten() {
+        if let Ok(existing) = reference.peel_to_commit() {
+            walk.hide(existing.id()).map_err(git_error)?;
+        }
+    }
+    walk.map(|oid| oid.map_err(git_error)).collect()
+}
+
+fn proposed_commit(repo: &Repository, oid: Oid) -> Result<ProposedCommit, NimbusError> {
+    let commit = repo.find_commit(oid).map_err(git_error)?;
+    let signed = match repo.extract_signature(&oid, None) {
+        Ok(_) => true,
+        Err(e) if e.code() == ErrorCode::NotFound => false,
+        Err(e) => return Err(git_error(e)),
+    };
+    Ok(ProposedCommit {
+        sha: Some(oid.to_string()),
+        message: commit.message().unwrap_or_default().to_string(),
+        signed,
+    })
+}
+
+/// Objects of a pushed pack, kept apart from the repository's own
+struct Quarantine {
+    path: PathBuf,
+}
+
+impl Quarantine {
+    /// Index `pack` into a fresh object directory under `git_dir`
+    fn receive(git_dir: &Path, pack: &[u8]) -> Result<Self, NimbusError> {
+        let objects = git_dir.join("objects");
+        let quarantine =
+            Self { path: objects.join(format!("incoming-{}", Uuid::new_v4().simple())) };
+        let io_error =
+            |e: std::io::Error| NimbusError::Internal(format!("Failed to quarantine push: {}", e));
+        std::fs::create_dir_all(quarantine.path.join("pack")).map_err(io_error)?;
+        if pack.is_empty() {
+            return Ok(quarantine);
+        }
+
+        // Thin packs are completed from the repository's own objects
+        let mut child = Command::new("git")
+            .arg("--git-dir")
+            .arg(git_dir)
+            .args(["index-pack", "--stdin", "--fix-thin"])
+            .env("GIT_OBJECT_DIRECTORY", &quarantine.path)
+            .env("GIT_ALTERNATE_OBJECT_DIRECTORIES", &objects)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(io_error)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let pack = pack.to_vec();
+        let writer = std::thread::spawn(move || {
+            let _ = stdin.write_all(&pack);
+        });
+        let mut stderr = String::new();
+        let _ = child.stderr.take().expect("stderr is piped").read_to_string(&mut stderr);
+        let status = child.wait().map_err(io_error)?;
+        let _ = writer.join();
+        if !status.success() {
+            return Err(NimbusError::InvalidGitOperation(format!(
+                "Pushed pack is invalid: {}",
+                stderr.trim()
+            )));
+        }
+        Ok(quarantine)
+    }
+
+    fn path_str(&self) -> Result<String, NimbusError> {
+        self.path
+            .to_str()
+            .map(str::to_string)
+            .ok_or_else(|| NimbusError::Internal("Repository path is not UTF-8".to_string()))
+    }
+
+    /// Total size of the blobs in the quarantine
+    fn blob_bytes(&self) -> Result<u64, NimbusError> {
+        let odb = Odb::new().map_err(git_error)?;
+        odb.add_disk_alternate(&self.path_str()?).map_err(git_error)?;
+        let mut oids = Vec::new();
+        odb.foreach(|oid| {
+            oids.push(*oid);
+            true
+        })
+        .map_err(git_error)?;
+
+        let mut total = 0;
+        for oid in oids {
+            if let (size, ObjectType::Blob) = odb.read_header(oid).map_err(git_error)? {
+                total += size as u64;
+            }
+        }
+        Ok(total)
+    }
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Failed to remove push quarantine {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// receive-pack's report for a push refused before git saw it
+///
+/// Every update is rejected, with the first violation found for it or, for
+/// updates that were fine, the reason the rest of the push was refused.
+/// Wrapped in side-band packets when the client asked for them.
+pub fn rejection_report(request: &ReceiveRequest, checked: &[CheckedUpdate]) -> Vec<u8> {
+    let mut report = pkt_line("unpack ok\n");
+    for update in checked {
+        let reason = match update.violations.first() {
+            Some(violation) => violation.message.clone(),
+            None => "rejected with the rest of the push".to_string(),
+        };
+        report.push_str(&pkt_line(&format!("ng {} {}\n", update.update.refname, reason)));
+    }
+    report.push_str("0000");
+
+    let payload = if request.wants("side-band-64k") {
+        SIDE_BAND_PAYLOAD
+    } else if request.wants("side-band") {
+        995
+    } else {
+        return report.into_bytes();
+    };
+    let mut banded = Vec::new();
+    for chunk in report.as_bytes().chunks(payload) {
+        banded.extend_from_slice(format!("{:04x}\x01", chunk.len() + 5).as_bytes());
+        banded.extend_from_slice(chunk);
+    }
+    banded.extend_from_slice(b"0000");
+    banded
+}
+
+/// Events for the updates of a push that git applied
+///
+/// Updates receive-pack refused leave the ref where it was and produce no
+/// event. Branch updates are `Push` events carrying the commits they
+/// introduced, with signatures verified against `keyring`; new tags are
+/// `TagCreated`. Deleted branches and tags are reported by
+/// [`deletion_event`].
+pub fn push_events(
+    storage: &GitStorage,
+    name: &str,
+    checked: &[CheckedUpdate],
+    pusher: &str,
+    keyring: &Keyring,
+) -> Result<Vec<Event>, NimbusError> {
+    let repo = storage.open(name)?;
+    let mut events = Vec::new();
+    for CheckedUpdate { update, commits, .. } in checked {
+        if !applied(&repo, update) {
+            continue;
+        }
+        if update.is_delete() {
+            events.extend(deletion_event(name, &update.refname, update.new, pusher));
+            continue;
+        }
+        if let Some(branch) = update.refname.strip_prefix("refs/heads/") {
+            events.push(Event::Push {
+                repository: name.to_string(),
+                branch: branch.to_string(),
+                commits: commits
+                    .iter()
+                    .map(|oid| commit_details(&repo, *oid, keyring))
+                    .collect::<Result<_, _>>()?,
+                pusher: pusher.to_string(),
+            });
+        } else if let Some(tag) = update.refname.strip_prefix("refs/tags/") {
+            if !update.old.is_zero() {
+                continue;
+            }
+            events.push(Event::TagCreated {
+                repository: name.to_string(),
+                tag: tag.to_string(),
+                target: update.new.to_string(),
+                tagger: pusher.to_string(),
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// Whether the ref now holds the value `update` pushed
+fn applied(repo: &Repository, update: &RefUpdate) -> bool {
+    match repo.refname_to_id(&update.refname) {
+        Ok(current) => current == update.new,
+        Err(e) => e.code() == ErrorCode::NotFound && update.is_delete(),
+    }
+}
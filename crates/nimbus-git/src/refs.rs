@@ -0,0 +1,134 @@
+//! Resolving the refs users pass around (`?ref=`) to concrete commits
+//!
+//! Every endpoint taking a ref goes through [`resolve_ref`], so `HEAD`,
+//! branch and tag names, and full or abbreviated shas mean the same thing
+//! everywhere.
+
+use git2::{ErrorCode, Oid, Reference, Repository};
+use nimbus_types::NimbusError;
+use nimbus_types::events::Event;
+use serde::{Deserialize, Serialize};
+
+/// Shortest sha prefix accepted, matching git's own minimum
+const MIN_SHA_PREFIX: usize = 4;
+
+/// What a resolved ref named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefKind {
+    Branch,
+    Tag,
+    /// A full or abbreviated commit sha
+    Commit,
+    /// A symbolic ref such as `HEAD`
+    Symbolic,
+}
+
+/// A ref resolved to the commit it currently points at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedRef {
+    pub kind: RefKind,
+    pub target_sha: String,
+    pub short_sha: String,
+}
+
+impl ResolvedRef {
+    pub fn oid(&self) -> Oid {
+        Oid::from_str(&self.target_sha).expect("resolved shas are valid")
+    }
+}
+
+/// Resolve `spec` to a commit
+///
+/// Tried in git's order: symbolic refs (`HEAD`), full ref names, branches,
+/// tags, then sha prefixes. Annotated tags are peeled to their commit.
+/// Unknown refs, and refs that don't lead to a commit (including `HEAD` in
+/// an empty repository), are `RefNotFound`.
+pub fn resolve_ref(repo: &Repository, spec: &str) -> Result<ResolvedRef, NimbusError> {
+    let not_found = || NimbusError::RefNotFound(spec.to_string());
+    if spec.is_empty() {
+        return Err(not_found());
+    }
+
+    let candidates = [
+        (spec.to_string(), None),
+        (format!("refs/heads/{}", spec), Some(RefKind::Branch)),
+        (format!("refs/tags/{}", spec), Some(RefKind::Tag)),
+    ];
+    for (name, kind) in candidates {
+        let reference = match repo.find_reference(&name) {
+            Ok(reference) => reference,
+            Err(e) if e.code() == ErrorCode::NotFound || e.code() == ErrorCode::InvalidSpec => {
+                continue;
+            }
+            Err(e) => return Err(crate::git_error(e)),
+        };
+        let kind = kind.unwrap_or_else(|| reference_kind(&reference));
+        return peel(repo, &reference, kind).ok_or_else(not_found);
+    }
+
+    let is_sha_prefix = spec.len() >= MIN_SHA_PREFIX
+        && spec.len() <= 40
+        && spec.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_sha_prefix {
+        return Err(not_found());
+    }
+    match repo.revparse_single(spec) {
+        Ok(object) => {
+            let commit = object.peel_to_commit().map_err(|_| not_found())?;
+            Ok(resolved(repo, RefKind::Commit, commit.id()))
+        }
+        Err(e) if e.code() == ErrorCode::NotFound => Err(not_found()),
+        Err(e) if e.code() == ErrorCode::Ambiguous => {
+            Err(NimbusError::InvalidGitOperation(format!("Short sha {} is ambiguous", spec)))
+        }
+        Err(e) => Err(crate::git_error(e)),
+    }
+}
+
+fn reference_kind(reference: &Reference<'_>) -> RefKind {
+    if reference.is_branch() {
+        RefKind::Branch
+    } else if reference.is_tag() {
+        RefKind::Tag
+    } else if reference.symbolic_target().is_some() {
+        RefKind::Symbolic
+    } else {
+        RefKind::Commit
+    }
+}
+
+/// The commit behind `reference`, following symbolic refs and annotated tags
+fn peel(repo: &Repository, reference: &Reference<'_>, kind: RefKind) -> Option<ResolvedRef> {
+    let commit = reference.resolve().ok()?.peel_to_commit().ok()?;
+    Some(resolved(repo, kind, commit.id()))
+}
+
+fn resolved(repo: &Repository, kind: RefKind, oid: Oid) -> ResolvedRef {
+    let short_sha = repo
+        .find_object(oid, None)
+        .ok()
+        .and_then(|object| object.short_id().ok())
+        .and_then(|short| short.as_str().map(str::to_string))
+        .unwrap_or_else(|| oid.to_string()[..7].to_string());
+    ResolvedRef { kind, target_sha: oid.to_string(), short_sha }
+}
+
+/// Event for a ref deleted by a push, `None` if the update isn't a deletion
+///
+/// receive-pack reports deletions as updates to the zero oid. Refs other
+/// than branches and tags produce no event.
+pub fn deletion_event(repository: &str, refname: &str, new: Oid, pusher: &str) -> Option<Event> {
+    if !new.is_zero() {
+        return None;
+    }
+    if let Some(branch) = refname.strip_prefix("refs/heads/") {
+        return Some(Event::BranchDeleted {
+            repository: repository.to_string(),
+            branch: branch.to_string(),
+            deleter: pusher.to_string(),
+        });
+    }
+    let tag = refname.strip_prefix("refs/tags/")?;
+    Some(Event::TagDeleted { repository: repository.to_string(), tag: tag.to_string() })
+}
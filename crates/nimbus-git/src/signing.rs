@@ -0,0 +1,124 @@
+//! Commit signature verification
+//!
+//! Signatures are checked the way git checks them, by handing them to
+//! GnuPG: `gpgv` against a [`Keyring`] exported with `gpg --export`. Only
+//! keys in that keyring are trusted, so a commit signed with any other key
+//! is `Invalid`, as is every signed commit when no keyring is configured.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use git2::{ErrorCode, Oid, Repository};
+use nimbus_types::{CommitVerification, NimbusError};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::git_error;
+
+/// First line of an ASCII-armored OpenPGP signature
+const PGP_SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// Public keys commit signatures are verified against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyring {
+    /// OpenPGP keyring file; `None` trusts no keys
+    path: Option<PathBuf>,
+}
+
+impl Keyring {
+    /// Trust the keys in the keyring file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: Some(path.into()) }
+    }
+
+    /// Keyring from `NIMBUS_GPG_KEYRING`, trusting no keys if unset
+    pub fn from_env() -> Self {
+        match std::env::var("NIMBUS_GPG_KEYRING") {
+            Ok(path) if !path.is_empty() => Self::new(path),
+            _ => Self::default(),
+        }
+    }
+
+    /// Verify the signature of `commit`, if it has one
+    pub fn verify(
+        &self,
+        repo: &Repository,
+        commit: Oid,
+    ) -> Result<CommitVerification, NimbusError> {
+        let (signature, signed_data) = match repo.extract_signature(&commit, None) {
+            Ok(extracted) => extracted,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(CommitVerification::Unsigned),
+            Err(e) => return Err(git_error(e)),
+        };
+        let invalid = |reason: &str| CommitVerification::Invalid { reason: reason.to_string() };
+
+        let signature = String::from_utf8_lossy(&signature);
+        if !signature.trim_start().starts_with(PGP_SIGNATURE_HEADER) {
+            return Ok(invalid("Only OpenPGP signatures are supported"));
+        }
+        let Some(keyring) = &self.path else {
+            return Ok(invalid("No keyring is configured"));
+        };
+
+        let status = run_gpgv(keyring, signature.as_bytes(), &signed_data)?;
+        Ok(parse_status(&status))
+    }
+}
+
+/// Run `gpgv` on a detached signature, returning its `--status-fd` output
+fn run_gpgv(keyring: &Path, signature: &[u8], data: &[u8]) -> Result<String, NimbusError> {
+    // gpgv reads the signature from a file and the signed data from stdin
+    let signature_path = std::env::temp_dir().join(format!("nimbus-{}.sig", Uuid::new_v4()));
+    let io_error = |e: std::io::Error| NimbusError::Internal(format!("Failed to run gpgv: {}", e));
+    std::fs::write(&signature_path, signature).map_err(io_error)?;
+
+    let keyring = std::path::absolute(keyring).map_err(io_error)?;
+    let child = Command::new("gpgv")
+        .arg("--status-fd")
+        .arg("1")
+        .arg("--keyring")
+        .arg(&keyring)
+        .arg(&signature_path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let output = child.and_then(|mut child| {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // gpgv may stop reading early, e.g. on a malformed signature
+        let _ = stdin.write_all(data);
+        drop(stdin);
+        child.wait_with_output()
+    });
+    if let Err(e) = std::fs::remove_file(&signature_path) {
+        warn!("Failed to remove {}: {}", signature_path.display(), e);
+    }
+
+    // A failed verification exits non-zero; the status lines say why
+    Ok(String::from_utf8_lossy(&output.map_err(io_error)?.stdout).into_owned())
+}
+
+/// Verification outcome from `gpgv --status-fd` lines
+fn parse_status(status: &str) -> CommitVerification {
+    let invalid = |reason: String| CommitVerification::Invalid { reason };
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, args) = line.split_once(' ').unwrap_or((line, ""));
+        // Arguments are the key id, then the user id where there is one
+        let (key_id, user_id) = args.split_once(' ').unwrap_or((args, ""));
+        match keyword {
+            "GOODSIG" => return CommitVerification::Valid { signer: user_id.to_string() },
+            "BADSIG" => return invalid(format!("Bad signature from {}", user_id)),
+            "EXPSIG" => return invalid(format!("Expired signature from {}", user_id)),
+            "EXPKEYSIG" => return invalid(format!("Signed with expired key of {}", user_id)),
+            "REVKEYSIG" => return invalid(format!("Signed with revoked key of {}", user_id)),
+            "NO_PUBKEY" => return invalid(format!("Signed with unknown key {}", key_id)),
+            _ => {}
+        }
+    }
+    invalid("Signature could not be verified".to_string())
+}
@@ -0,0 +1,246 @@
+//! Git smart HTTP transport
+//!
+//! Runs `git upload-pack` / `git receive-pack` in stateless RPC mode, the
+//! same way `git http-backend` does. The wire protocol version requested via
+//! the `Git-Protocol` header is passed through as `GIT_PROTOCOL`, so clients
+//! asking for `version=2` get v2 (`ls-refs`, `fetch`) and everyone else v0/v1.
+//!
+//! RPC responses are read from git as it writes them, see [`ServiceOutput`],
+//! so serving a pack never holds all of it in memory.
+
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::JoinHandle;
+
+use nimbus_types::NimbusError;
+
+use crate::GitStorage;
+
+/// Wire protocol version negotiated with the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    V0,
+    V1,
+    V2,
+}
+
+impl ProtocolVersion {
+    /// Parse a `Git-Protocol` header, e.g. `version=2:object-format=sha1`
+    ///
+    /// Missing or unknown versions fall back to v0, like git itself does.
+    pub fn from_header(header: Option<&str>) -> Self {
+        header
+            .into_iter()
+            .flat_map(|value| value.split(':'))
+            .filter_map(|param| param.trim().strip_prefix("version="))
+            .map(|version| match version {
+                "2" => Self::V2,
+                "1" => Self::V1,
+                _ => Self::V0,
+            })
+            .max()
+            .unwrap_or(Self::V0)
+    }
+
+    /// Value for the `GIT_PROTOCOL` environment variable, if any
+    fn env_value(&self) -> Option<&'static str> {
+        match self {
+            Self::V0 => None,
+            Self::V1 => Some("version=1"),
+            Self::V2 => Some("version=2"),
+        }
+    }
+}
+
+/// Git service a smart HTTP request is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    UploadPack,
+    ReceivePack,
+}
+
+impl Service {
+    /// Parse the `service` query parameter / endpoint name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "git-upload-pack" => Some(Self::UploadPack),
+            "git-receive-pack" => Some(Self::ReceivePack),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UploadPack => "git-upload-pack",
+            Self::ReceivePack => "git-receive-pack",
+        }
+    }
+
+    pub fn advertisement_content_type(&self) -> String {
+        format!("application/x-{}-advertisement", self.name())
+    }
+
+    pub fn result_content_type(&self) -> String {
+        format!("application/x-{}-result", self.name())
+    }
+
+    /// Protocol actually spoken: only fetches support v2
+    pub fn negotiate(&self, requested: ProtocolVersion) -> ProtocolVersion {
+        match (self, requested) {
+            (Self::ReceivePack, ProtocolVersion::V2) => ProtocolVersion::V1,
+            (_, requested) => requested,
+        }
+    }
+
+    fn subcommand(&self) -> &'static str {
+        self.name().trim_start_matches("git-")
+    }
+}
+
+/// Encode one pkt-line
+pub fn pkt_line(data: &str) -> String {
+    format!("{:04x}{}", data.len() + 4, data)
+}
+
+/// Body for `GET /<repo>/info/refs?service=<service>`
+///
+/// v0/v1 advertisements start with the `# service=` banner; v2 starts
+/// directly with the capability advertisement.
+pub fn advertise_refs(
+    storage: &GitStorage,
+    name: &str,
+    service: Service,
+    version: ProtocolVersion,
+) -> Result<Vec<u8>, NimbusError> {
+    let version = service.negotiate(version);
+
+    let mut body = Vec::new();
+    if version != ProtocolVersion::V2 {
+        body.extend_from_slice(pkt_line(&format!("# service={}\n", service.name())).as_bytes());
+        body.extend_from_slice(b"0000");
+    }
+    let mut output = run_service(storage, name, service, version, true, &[])?;
+    output.read_to_end(&mut body).map_err(|e| NimbusError::InvalidGitOperation(e.to_string()))?;
+    Ok(body)
+}
+
+/// Body for `POST /<repo>/<service>`: the client's request piped through git
+///
+/// With v2 the request itself carries the command (`ls-refs` or `fetch`).
+pub fn stateless_rpc(
+    storage: &GitStorage,
+    name: &str,
+    service: Service,
+    version: ProtocolVersion,
+    request: &[u8],
+) -> Result<ServiceOutput, NimbusError> {
+    run_service(storage, name, service, service.negotiate(version), false, request)
+}
+
+/// Standard output of a running git service
+///
+/// Reading returns git's output as it is produced. Once it ends, git's
+/// exit status is checked, and a failure is reported as an error carrying
+/// what git wrote to stderr. Dropping the output early stops git.
+pub struct ServiceOutput {
+    service: Service,
+    child: Child,
+    stdout: ChildStdout,
+    stdin_writer: Option<JoinHandle<()>>,
+    stderr_reader: Option<JoinHandle<Vec<u8>>>,
+    finished: bool,
+}
+
+impl ServiceOutput {
+    /// Wait for git to exit, failing if it didn't succeed
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.finished = true;
+        let status = self.child.wait()?;
+        // git may exit before reading everything (e.g. a rejected request)
+        if let Some(writer) = self.stdin_writer.take() {
+            let _ = writer.join();
+        }
+        let stderr = self.stderr_reader.take().and_then(|reader| reader.join().ok());
+        if status.success() {
+            return Ok(());
+        }
+        Err(std::io::Error::other(format!(
+            "{} failed: {}",
+            self.service.name(),
+            String::from_utf8_lossy(&stderr.unwrap_or_default()).trim()
+        )))
+    }
+}
+
+impl Read for ServiceOutput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for ServiceOutput {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn run_service(
+    storage: &GitStorage,
+    name: &str,
+    service: Service,
+    version: ProtocolVersion,
+    advertise_refs: bool,
+    input: &[u8],
+) -> Result<ServiceOutput, NimbusError> {
+    let path = storage.path_for(name);
+    if !path.is_dir() {
+        return Err(NimbusError::RepositoryNotFound(name.to_string()));
+    }
+
+    let mut command = Command::new("git");
+    command.arg(service.subcommand()).arg("--stateless-rpc");
+    if advertise_refs {
+        command.arg("--advertise-refs");
+    }
+    command.arg(&path).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if let Some(protocol) = version.env_value() {
+        command.env("GIT_PROTOCOL", protocol);
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| NimbusError::Internal(format!("Failed to run {}: {}", service.name(), e)))?;
+
+    // Feed stdin and drain stderr from threads so neither pipe can stall git
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let stdin_writer = std::thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_reader = std::thread::spawn(move || {
+        let mut message = Vec::new();
+        let _ = stderr.read_to_end(&mut message);
+        message
+    });
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok(ServiceOutput {
+        service,
+        child,
+        stdout,
+        stdin_writer: Some(stdin_writer),
+        stderr_reader: Some(stderr_reader),
+        finished: false,
+    })
+}
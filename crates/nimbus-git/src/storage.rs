@@ -0,0 +1,42 @@
+//! On-disk layout of the bare repositories served by this instance
+
+use std::path::PathBuf;
+
+use nimbus_types::NimbusError;
+
+/// Root directory holding one bare repository per `<name>.git`
+#[derive(Debug, Clone)]
+pub struct GitStorage {
+    root: PathBuf,
+}
+
+impl GitStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Storage rooted at `NIMBUS_REPO_ROOT` (default `/var/lib/nimbus/repos`)
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("NIMBUS_REPO_ROOT").unwrap_or_else(|_| "/var/lib/nimbus/repos".into()),
+        )
+    }
+
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    /// Path of the bare repository for `name`
+    pub fn path_for(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{}.git", name))
+    }
+
+    /// Open the bare repository for `name`
+    pub fn open(&self, name: &str) -> Result<git2::Repository, NimbusError> {
+        let path = self.path_for(name);
+        if !path.is_dir() {
+            return Err(NimbusError::RepositoryNotFound(name.to_string()));
+        }
+        git2::Repository::open_bare(&path).map_err(crate::git_error)
+    }
+}
@@ -0,0 +1,255 @@
+//! Repository records and their lifecycle
+//!
+//! A [`RepositoryStore`] owns the set of repositories on an instance: it
+//! creates them, lists them and deletes them, and keeps the metadata behind
+//! the [`Repository`] type. [`FsRepositoryStore`] keeps everything in the
+//! bare repositories of a [`GitStorage`]: the description in git's own
+//! `description` file and the rest under a `[nimbus]` section of the
+//! repository config, so nothing outside the repository directory needs to
+//! stay in sync with it.
+
+use std::path::Path;
+
+use git2::Repository as GitRepository;
+use nimbus_types::{NimbusError, Repository, Visibility};
+use serde::Deserialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{GitStorage, git_error};
+
+/// Branch `HEAD` points at in a new repository unless one is requested
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// Description git writes into every new repository
+const GIT_DEFAULT_DESCRIPTION: &str = "Unnamed repository;";
+
+/// What to create a repository with
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NewRepository {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Private unless said otherwise; also accepted as the legacy
+    /// `is_private` flag
+    #[serde(default, alias = "is_private")]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub default_branch: Option<String>,
+}
+
+/// Creates, lists and deletes the repositories of an instance
+pub trait RepositoryStore: Send + Sync {
+    /// Every repository, sorted by name
+    fn list(&self) -> Result<Vec<Repository>, NimbusError>;
+
+    /// The repository called `name`
+    fn get(&self, name: &str) -> Result<Repository, NimbusError>;
+
+    /// Create an empty repository; fails if the name is taken or invalid
+    fn create(&self, new: NewRepository) -> Result<Repository, NimbusError>;
+
+    /// Delete `name` and everything in it, returning what was deleted
+    fn delete(&self, name: &str) -> Result<Repository, NimbusError>;
+}
+
+/// Check that `name` can be used as a repository name
+///
+/// Names become directory names and URL segments, so they are limited to
+/// ASCII letters, digits, `-`, `_` and `.`, must not start with `.`, contain
+/// `..` or end in `.git`. Uniqueness is the store's concern.
+pub fn validate_repo_name(name: &str) -> Result<(), NimbusError> {
+    let invalid = |reason: &str| {
+        Err(NimbusError::InvalidGitOperation(format!(
+            "Invalid repository name {:?}: {}",
+            name, reason
+        )))
+    };
+    if name.is_empty() || name.len() > 100 {
+        return invalid("must be 1 to 100 characters");
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return invalid("only letters, digits, '-', '_' and '.' are allowed");
+    }
+    if name.starts_with('.') {
+        return invalid("must not start with '.'");
+    }
+    if name.contains("..") {
+        return invalid("must not contain '..'");
+    }
+    if name.ends_with(".git") {
+        return invalid("must not end with '.git'");
+    }
+    Ok(())
+}
+
+/// [`RepositoryStore`] over the bare repositories in a [`GitStorage`]
+#[derive(Debug, Clone)]
+pub struct FsRepositoryStore {
+    storage: GitStorage,
+}
+
+impl FsRepositoryStore {
+    pub fn new(storage: GitStorage) -> Self {
+        Self { storage }
+    }
+
+    pub fn storage(&self) -> &GitStorage {
+        &self.storage
+    }
+
+    /// Name of the repository on disk matching `name` up to ASCII case
+    fn find_ignoring_case(&self, name: &str) -> Result<Option<String>, NimbusError> {
+        let entries = match std::fs::read_dir(self.storage.root()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error("list repositories", e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|e| io_error("list repositories", e))?;
+            let file_name = entry.file_name();
+            if let Some(existing) = file_name.to_str().and_then(|n| n.strip_suffix(".git"))
+                && existing.eq_ignore_ascii_case(name)
+            {
+                return Ok(Some(existing.to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The record of `repo`, stored as `name`
+    ///
+    /// Repositories put on disk by other means have no id yet; one is
+    /// assigned and saved the first time they are read.
+    fn read(&self, name: &str, repo: &GitRepository) -> Result<Repository, NimbusError> {
+        let mut config = repo.config().map_err(git_error)?;
+        let id = match config.get_string("nimbus.id").ok().and_then(|id| id.parse().ok()) {
+            Some(id) => id,
+            None => {
+                let id = Uuid::new_v4();
+                config.set_str("nimbus.id", &id.to_string()).map_err(git_error)?;
+                id
+            }
+        };
+        // Repositories from before `nimbus.visibility` only have the flag
+        let visibility = match config.get_string("nimbus.visibility") {
+            Ok(value) => value.parse().map_err(NimbusError::Internal)?,
+            Err(_) => match config.get_bool("nimbus.private") {
+                Ok(false) => Visibility::Public,
+                _ => Visibility::Private,
+            },
+        };
+
+        let default_branch = repo
+            .find_reference("HEAD")
+            .ok()
+            .and_then(|head| head.symbolic_target().map(str::to_string))
+            .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string))
+            .unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+
+        Ok(Repository {
+            id,
+            name: name.to_string(),
+            description: read_description(repo.path()),
+            visibility,
+            default_branch,
+            collaborator_permissions: vec![],
+        })
+    }
+}
+
+/// The repository's `description`, unless it is missing or git's placeholder
+fn read_description(git_dir: &Path) -> Option<String> {
+    let description = std::fs::read_to_string(git_dir.join("description")).ok()?;
+    let description = description.trim();
+    (!description.is_empty() && !description.starts_with(GIT_DEFAULT_DESCRIPTION))
+        .then(|| description.to_string())
+}
+
+fn io_error(action: &str, e: std::io::Error) -> NimbusError {
+    NimbusError::Internal(format!("Failed to {}: {}", action, e))
+}
+
+impl RepositoryStore for FsRepositoryStore {
+    fn list(&self) -> Result<Vec<Repository>, NimbusError> {
+        let entries = match std::fs::read_dir(self.storage.root()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(io_error("list repositories", e)),
+        };
+
+        let mut repositories = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| io_error("list repositories", e))?;
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(|name| name.strip_suffix(".git")) else {
+                continue;
+            };
+            if !entry.path().is_dir() {
+                continue;
+            }
+            match self.get(name) {
+                Ok(repository) => repositories.push(repository),
+                Err(e) => warn!("Skipping unreadable repository {}: {}", name, e),
+            }
+        }
+        repositories.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(repositories)
+    }
+
+    fn get(&self, name: &str) -> Result<Repository, NimbusError> {
+        if validate_repo_name(name).is_err() {
+            return Err(NimbusError::RepositoryNotFound(name.to_string()));
+        }
+        let repo = self.storage.open(name)?;
+        self.read(name, &repo)
+    }
+
+    fn create(&self, new: NewRepository) -> Result<Repository, NimbusError> {
+        validate_repo_name(&new.name)?;
+        let default_branch = new.default_branch.unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+        if !git2::Branch::name_is_valid(&default_branch).map_err(git_error)? {
+            return Err(NimbusError::InvalidGitOperation(format!(
+                "Invalid default branch {:?}",
+                default_branch
+            )));
+        }
+
+        // Names differing only in case would collide on case-insensitive
+        // filesystems and confuse people everywhere else
+        if let Some(existing) = self.find_ignoring_case(&new.name)? {
+            return Err(NimbusError::InvalidGitOperation(format!(
+                "Repository {} already exists",
+                existing
+            )));
+        }
+        let path = self.storage.path_for(&new.name);
+        std::fs::create_dir_all(self.storage.root())
+            .map_err(|e| io_error("create the repository root", e))?;
+
+        let mut options = git2::RepositoryInitOptions::new();
+        options.bare(true).no_reinit(true).mkdir(true).initial_head(&default_branch);
+        let repo = GitRepository::init_opts(&path, &options).map_err(git_error)?;
+        // libgit2 only writes `description` when it has a template to copy
+        if let Some(description) = &new.description {
+            std::fs::write(repo.path().join("description"), format!("{}\n", description))
+                .map_err(|e| io_error("write the repository description", e))?;
+        }
+
+        let id = Uuid::new_v4();
+        let mut config = repo.config().map_err(git_error)?;
+        config.set_str("nimbus.id", &id.to_string()).map_err(git_error)?;
+        config.set_str("nimbus.visibility", new.visibility.as_str()).map_err(git_error)?;
+
+        info!("Created repository {} at {}", new.name, path.display());
+        self.read(&new.name, &repo)
+    }
+
+    fn delete(&self, name: &str) -> Result<Repository, NimbusError> {
+        let repository = self.get(name)?;
+        std::fs::remove_dir_all(self.storage.path_for(name))
+            .map_err(|e| io_error("delete repository", e))?;
+        info!("Deleted repository {}", name);
+        Ok(repository)
+    }
+}
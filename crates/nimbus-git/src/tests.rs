@@ -0,0 +1,650 @@
+//! Tests for git operations against fixture repositories
+
+use git2::{Oid, Repository, Signature};
+use nimbus_types::{NimbusError, Visibility};
+use tempfile::TempDir;
+
+use crate::GitStorage;
+use crate::diff::{DiffLimits, LineKind, compare, diff_revisions};
+use crate::pulls::PullRequests;
+
+/// A temporary storage root with one bare repository
+struct Fixture {
+    _dir: TempDir,
+    storage: GitStorage,
+    name: String,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let dir = TempDir::new().unwrap();
+        let storage = GitStorage::new(dir.path());
+        Repository::init_bare(storage.path_for(name)).unwrap();
+        Self { _dir: dir, storage, name: name.to_string() }
+    }
+
+    fn repo(&self) -> Repository {
+        self.storage.open(&self.name).unwrap()
+    }
+
+    /// Commit a flat set of files onto `branch`, returning the new commit id
+    fn commit(&self, branch: &str, files: &[(&str, &str)], message: &str) -> Oid {
+        let repo = self.repo();
+        let reference = format!("refs/heads/{}", branch);
+        let parent = repo.refname_to_id(&reference).ok().map(|id| repo.find_commit(id).unwrap());
+
+        let mut builder = repo.treebuilder(None).unwrap();
+        for (path, content) in files {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some(&reference), &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    /// Create `branch` pointing at `target`
+    fn branch(&self, branch: &str, target: Oid) {
+        let repo = self.repo();
+        let commit = repo.find_commit(target).unwrap();
+        repo.branch(branch, &commit, true).unwrap();
+    }
+}
+
+#[test]
+fn test_diff_lines_have_stable_positions() {
+    let fixture = Fixture::new("project");
+    let base = fixture.commit("main", &[("lib.rs", "fn a() {}\nfn b() {}\n")], "initial");
+    fixture.branch("feature", base);
+    fixture.commit("feature", &[("lib.rs", "fn a() {}\nfn c() {}\n")], "swap b for c");
+
+    let repo = fixture.repo();
+    let first = diff_revisions(&repo, "main", "feature").unwrap();
+    let second = diff_revisions(&repo, "main", "feature").unwrap();
+
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].path, "lib.rs");
+    let added = first[0].lines().find(|l| l.kind == LineKind::Added).unwrap();
+    assert_eq!(added.content, "fn c() {}");
+
+    let positions: Vec<_> = first[0].lines().map(|l| l.position.clone()).collect();
+    let again: Vec<_> = second[0].lines().map(|l| l.position.clone()).collect();
+    assert_eq!(positions, again);
+}
+
+#[test]
+fn test_compare_truncates_past_line_limit() {
+    let fixture = Fixture::new("project");
+    let base = fixture.commit("main", &[("a.txt", "one\n"), ("b.txt", "two\n")], "initial");
+    fixture.branch("feature", base);
+    fixture.commit("feature", &[("a.txt", "uno\n"), ("b.txt", "dos\n")], "translate");
+
+    let repo = fixture.repo();
+    let full = compare(&repo, "main", "feature", &DiffLimits::default()).unwrap();
+    assert!(!full.truncated);
+    assert_eq!(full.merge_base, base.to_string());
+    assert_eq!(full.files.len(), 2);
+    let kinds: Vec<_> = full.files[0].lines().map(|l| l.kind).collect();
+    assert_eq!(kinds, [LineKind::Removed, LineKind::Added]);
+
+    let cut = compare(&repo, "main", "feature", &DiffLimits { max_lines: 3 }).unwrap();
+    assert!(cut.truncated);
+    assert_eq!(cut.files.len(), 2);
+    assert_eq!(cut.files.iter().flat_map(|f| f.lines()).count(), 3);
+
+    let error = compare(&repo, "main", "missing", &DiffLimits::default()).unwrap_err();
+    assert!(matches!(error, NimbusError::RefNotFound(_)));
+}
+
+#[test]
+fn test_review_comment_anchors_to_position() {
+    let fixture = Fixture::new("project");
+    let base = fixture.commit("main", &[("lib.rs", "fn a() {}\n")], "initial");
+    fixture.branch("feature", base);
+    fixture.commit("feature", &[("lib.rs", "fn a() {}\nfn b() {}\n")], "add b");
+
+    let pulls = PullRequests::new();
+    let pull = pulls.open("project", "feature", "main", "Add b", "alice");
+
+    let repo = fixture.repo();
+    let diff = diff_revisions(&repo, &pull.to_branch, &pull.from_branch).unwrap();
+    let target = diff[0].lines().find(|l| l.kind == LineKind::Added).unwrap().clone();
+
+    let comment =
+        pulls.add_comment(pull.id, &diff, &target.position, "Needs a doc comment", "bob").unwrap();
+    assert_eq!(comment.file, "lib.rs");
+    assert_eq!(comment.line, 2);
+
+    // Re-fetching the diff keeps the comment attached to the same line
+    let refetched = diff_revisions(&repo, &pull.to_branch, &pull.from_branch).unwrap();
+    let comments = pulls.comments(pull.id, &refetched);
+    assert_eq!(comments.len(), 1);
+    assert!(!comments[0].outdated);
+    assert!(refetched[0].lines().any(|l| l.position == comments[0].position));
+}
+
+#[test]
+fn test_review_comment_outdated_when_line_changes() {
+    let fixture = Fixture::new("project");
+    let base = fixture.commit("main", &[("lib.rs", "fn a() {}\n")], "initial");
+    fixture.branch("feature", base);
+    fixture.commit("feature", &[("lib.rs", "fn a() {}\nfn b() {}\n")], "add b");
+
+    let pulls = PullRequests::new();
+    let pull = pulls.open("project", "feature", "main", "Add b", "alice");
+    let repo = fixture.repo();
+    let diff = diff_revisions(&repo, "main", "feature").unwrap();
+    let target = diff[0].lines().find(|l| l.kind == LineKind::Added).unwrap().clone();
+    pulls.add_comment(pull.id, &diff, &target.position, "Rename this", "bob").unwrap();
+
+    // The commented line is rewritten by a follow-up commit
+    fixture.commit("feature", &[("lib.rs", "fn a() {}\nfn renamed() {}\n")], "rename b");
+
+    let diff = diff_revisions(&repo, "main", "feature").unwrap();
+    let comments = pulls.comments(pull.id, &diff);
+    assert!(comments[0].outdated);
+}
+
+#[test]
+fn test_review_comment_rejects_unknown_position() {
+    let pulls = PullRequests::new();
+    let pull = pulls.open("project", "feature", "main", "Add b", "alice");
+
+    assert!(pulls.add_comment(pull.id, &[], "deadbeef", "Hello", "bob").is_err());
+}
+
+/// Split a pkt-line stream into its payloads, with `None` for flush/delim packets
+fn pkt_lines(mut data: &[u8]) -> Vec<Option<String>> {
+    let mut lines = Vec::new();
+    while data.len() >= 4 {
+        let len = usize::from_str_radix(std::str::from_utf8(&data[..4]).unwrap(), 16).unwrap();
+        if len < 4 {
+            lines.push(None);
+            data = &data[4..];
+        } else {
+            lines.push(Some(String::from_utf8_lossy(&data[4..len]).into_owned()));
+            data = &data[len..];
+        }
+    }
+    lines
+}
+
+#[test]
+fn test_v2_ls_refs() {
+    use std::io::Read;
+
+    use crate::smart_http::{ProtocolVersion, Service, advertise_refs, pkt_line, stateless_rpc};
+
+    let fixture = Fixture::new("project");
+    let head = fixture.commit("main", &[("README.md", "hello\n")], "initial");
+    let version = ProtocolVersion::from_header(Some("version=2"));
+    assert_eq!(version, ProtocolVersion::V2);
+
+    // v2 advertises capabilities, not refs, and has no service banner
+    let advert = advertise_refs(&fixture.storage, "project", Service::UploadPack, version).unwrap();
+    let lines = pkt_lines(&advert);
+    assert_eq!(lines[0].as_deref(), Some("version 2\n"));
+    assert!(lines.iter().flatten().any(|line| line.starts_with("ls-refs")));
+    assert!(lines.iter().flatten().any(|line| line.starts_with("fetch")));
+
+    let request = format!("{}0001{}0000", pkt_line("command=ls-refs\n"), pkt_line("symrefs\n"));
+    let mut output = stateless_rpc(
+        &fixture.storage,
+        "project",
+        Service::UploadPack,
+        version,
+        request.as_bytes(),
+    )
+    .unwrap();
+    let mut response = Vec::new();
+    output.read_to_end(&mut response).unwrap();
+    let lines = pkt_lines(&response);
+    assert!(lines.contains(&Some(format!("{} refs/heads/main\n", head))));
+    assert_eq!(lines.last(), Some(&None));
+
+    // git failing is reported once its output ends
+    let mut output =
+        stateless_rpc(&fixture.storage, "project", Service::UploadPack, version, b"garbage")
+            .unwrap();
+    let err = output.read_to_end(&mut Vec::new()).unwrap_err();
+    assert!(err.to_string().starts_with("git-upload-pack failed"), "{}", err);
+}
+
+#[test]
+fn test_v1_fallback_advertises_refs() {
+    use crate::smart_http::{ProtocolVersion, Service, advertise_refs};
+
+    let fixture = Fixture::new("project");
+    let head = fixture.commit("main", &[("README.md", "hello\n")], "initial");
+
+    for header in [None, Some("version=1"), Some("version=9")] {
+        let version = ProtocolVersion::from_header(header);
+        assert_ne!(version, ProtocolVersion::V2);
+
+        let advert =
+            advertise_refs(&fixture.storage, "project", Service::UploadPack, version).unwrap();
+        let lines = pkt_lines(&advert);
+        assert_eq!(lines[0].as_deref(), Some("# service=git-upload-pack\n"));
+        assert_eq!(lines[1], None);
+        assert!(
+            lines
+                .iter()
+                .flatten()
+                .any(|line| line.starts_with(&format!("{} refs/heads/main", head)))
+        );
+    }
+
+    // Pushes never speak v2, even when asked
+    assert_eq!(Service::ReceivePack.negotiate(ProtocolVersion::V2), ProtocolVersion::V1);
+}
+
+#[test]
+fn test_resolve_branch_tag_and_head() {
+    use crate::refs::{RefKind, resolve_ref};
+
+    let fixture = Fixture::new("project");
+    let first = fixture.commit("main", &[("README.md", "hello\n")], "initial");
+    let second = fixture.commit("main", &[("README.md", "hello again\n")], "update");
+    let repo = fixture.repo();
+    repo.set_head("refs/heads/main").unwrap();
+
+    // Annotated tags peel to the commit they point at
+    let signature = Signature::now("Test User", "test@example.com").unwrap();
+    let target = repo.find_object(first, None).unwrap();
+    repo.tag("v1.0", &target, &signature, "release", false).unwrap();
+
+    let branch = resolve_ref(&repo, "main").unwrap();
+    assert_eq!(branch.kind, RefKind::Branch);
+    assert_eq!(branch.target_sha, second.to_string());
+    assert!(second.to_string().starts_with(&branch.short_sha));
+
+    let tag = resolve_ref(&repo, "v1.0").unwrap();
+    assert_eq!(tag.kind, RefKind::Tag);
+    assert_eq!(tag.target_sha, first.to_string());
+
+    let head = resolve_ref(&repo, "HEAD").unwrap();
+    assert_eq!(head.kind, RefKind::Symbolic);
+    assert_eq!(head.target_sha, second.to_string());
+
+    assert_eq!(resolve_ref(&repo, "refs/heads/main").unwrap().kind, RefKind::Branch);
+}
+
+#[test]
+fn test_resolve_full_and_abbreviated_shas() {
+    use crate::refs::{RefKind, resolve_ref};
+
+    let fixture = Fixture::new("project");
+    let commit = fixture.commit("main", &[("README.md", "hello\n")], "initial");
+    let repo = fixture.repo();
+    let sha = commit.to_string();
+
+    let full = resolve_ref(&repo, &sha).unwrap();
+    assert_eq!(full.kind, RefKind::Commit);
+    assert_eq!(full.target_sha, sha);
+
+    let short = resolve_ref(&repo, &sha[..7]).unwrap();
+    assert_eq!(short.kind, RefKind::Commit);
+    assert_eq!(short.target_sha, sha);
+}
+
+#[test]
+fn test_resolve_unknown_ref() {
+    use crate::refs::resolve_ref;
+
+    let fixture = Fixture::new("project");
+    fixture.commit("main", &[("README.md", "hello\n")], "initial");
+    let repo = fixture.repo();
+
+    for spec in ["no-such-branch", "deadbeefdeadbeef", "", "main~999"] {
+        let err = resolve_ref(&repo, spec).unwrap_err();
+        assert!(matches!(err, NimbusError::RefNotFound(_)), "{:?} -> {:?}", spec, err);
+    }
+}
+
+#[test]
+fn test_browse_tree_commits_and_readme() {
+    use crate::browse::{ObjectKind, is_empty, list_commits, list_tree, readme};
+
+    let fixture = Fixture::new("project");
+    assert!(is_empty(&fixture.repo()).unwrap());
+
+    fixture.commit("main", &[("README.md", "# Project\n")], "initial");
+    let head = fixture.commit("main", &[("README.md", "# Project\n"), ("lib.rs", "")], "add lib");
+    let repo = fixture.repo();
+    assert!(!is_empty(&repo).unwrap());
+
+    let entries = list_tree(&repo, head, "").unwrap();
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["README.md", "lib.rs"]);
+    assert!(entries.iter().all(|entry| entry.kind == ObjectKind::Blob));
+
+    let commits = list_commits(&repo, head, 10).unwrap();
+    let summaries: Vec<_> = commits.iter().map(|commit| commit.summary.as_str()).collect();
+    assert_eq!(summaries, ["add lib", "initial"]);
+    assert_eq!(list_commits(&repo, head, 1).unwrap().len(), 1);
+
+    let readme = readme(&repo, head).unwrap().unwrap();
+    assert_eq!(readme.path, "README.md");
+    assert_eq!(readme.content, "# Project\n");
+}
+
+#[test]
+fn test_read_blob_encodes_binary_and_rejects_traversal() {
+    use crate::browse::{BlobEncoding, ObjectKind, clean_path, list_tree, read_blob};
+
+    let fixture = Fixture::new("project");
+    let head = fixture.commit("main", &[("notes.txt", "hello\n"), ("logo.png", "\0PNG")], "add");
+    let repo = fixture.repo();
+
+    let entries = list_tree(&repo, head, "").unwrap();
+    assert!(entries.iter().all(|entry| entry.kind == ObjectKind::Blob && entry.mode == "100644"));
+    assert_eq!(entries.iter().find(|e| e.name == "notes.txt").unwrap().size, Some(6));
+
+    let text = read_blob(&repo, head, "/notes.txt").unwrap();
+    assert_eq!(
+        (text.binary, text.encoding, text.content.as_str()),
+        (false, BlobEncoding::Utf8, "hello\n")
+    );
+
+    let binary = read_blob(&repo, head, "logo.png").unwrap();
+    assert!(binary.binary);
+    assert_eq!(binary.encoding, BlobEncoding::Base64);
+    assert_eq!(binary.content, "AFBORw==");
+
+    assert_eq!(clean_path("//src//lib.rs/").unwrap(), "src/lib.rs");
+    for path in ["../secret", "src/../../config", "./notes.txt"] {
+        let err = read_blob(&repo, head, path).unwrap_err();
+        assert!(matches!(err, NimbusError::InvalidGitOperation(_)), "{:?} -> {:?}", path, err);
+    }
+    let err = read_blob(&repo, head, "missing.txt").unwrap_err();
+    assert!(matches!(err, NimbusError::PathNotFound(_)));
+}
+
+#[test]
+fn test_push_policy_reports_every_broken_rule() {
+    use crate::policy::{PolicyRule, ProposedCommit, ProposedPush, PushPolicy};
+
+    let policy = PushPolicy {
+        protected_branches: vec!["main".to_string()],
+        max_subject_length: Some(20),
+        require_signed_commits: true,
+        max_push_bytes: 16,
+    };
+    let commit = ProposedCommit {
+        sha: Some("abc123".to_string()),
+        message: "A subject far longer than policy allows".to_string(),
+        signed: false,
+    };
+
+    // Force-pushing unrelated history over main breaks every rule
+    let push = ProposedPush {
+        branch: "main".to_string(),
+        force: true,
+        delete: false,
+        commits: vec![commit.clone()],
+        size_bytes: 64,
+    };
+    let violations = policy.check(&push);
+    let rules: Vec<_> = violations.iter().map(|violation| violation.rule).collect();
+    assert_eq!(
+        rules,
+        [
+            PolicyRule::BranchProtection,
+            PolicyRule::CommitMessage,
+            PolicyRule::Signing,
+            PolicyRule::Size
+        ]
+    );
+    assert_eq!(violations[1].commit.as_deref(), Some("abc123"));
+
+    // Fast-forwards and other branches are fine; deleting main is not
+    let relaxed = PushPolicy {
+        require_signed_commits: false,
+        max_subject_length: None,
+        max_push_bytes: 1024,
+        ..policy
+    };
+    assert!(relaxed.check(&ProposedPush { force: false, ..push.clone() }).is_empty());
+    assert!(
+        relaxed.check(&ProposedPush { branch: "topic".to_string(), ..push.clone() }).is_empty()
+    );
+    let deleted = relaxed.check(&ProposedPush {
+        delete: true,
+        force: false,
+        commits: vec![],
+        size_bytes: 0,
+        ..push
+    });
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].rule, PolicyRule::BranchProtection);
+}
+
+#[test]
+fn test_repository_store_round_trip() {
+    use crate::store::{FsRepositoryStore, NewRepository, RepositoryStore};
+
+    // Repositories already on disk are adopted with a stable id
+    let fixture = Fixture::new("existing");
+    let store = FsRepositoryStore::new(fixture.storage.clone());
+    let existing = store.get("existing").unwrap();
+    assert_eq!(store.get("existing").unwrap().id, existing.id);
+    assert_eq!(existing.visibility, Visibility::Private);
+
+    let created = store
+        .create(NewRepository {
+            name: "docs".to_string(),
+            description: Some("Documentation".to_string()),
+            visibility: Visibility::Public,
+            default_branch: Some("trunk".to_string()),
+        })
+        .unwrap();
+    assert_eq!(created.default_branch, "trunk");
+    assert_eq!(created.description.as_deref(), Some("Documentation"));
+    assert_eq!(created.visibility, Visibility::Public);
+    assert_eq!(store.get("docs").unwrap().id, created.id);
+
+    let names: Vec<String> = store.list().unwrap().into_iter().map(|repo| repo.name).collect();
+    assert_eq!(names, ["docs", "existing"]);
+
+    for bad in ["", ".hidden", "a/b", "../escape", "name.git"] {
+        let new = NewRepository {
+            name: bad.to_string(),
+            description: None,
+            visibility: Visibility::Private,
+            default_branch: None,
+        };
+        assert!(matches!(store.create(new), Err(NimbusError::InvalidGitOperation(_))), "{}", bad);
+    }
+
+    store.delete("docs").unwrap();
+    assert!(matches!(store.get("docs"), Err(NimbusError::RepositoryNotFound(_))));
+    assert!(matches!(store.delete("docs"), Err(NimbusError::RepositoryNotFound(_))));
+}
+
+#[test]
+fn test_repository_names_are_validated() {
+    use crate::store::validate_repo_name;
+
+    for good in ["website", "my-repo_2", "v1.2", "A", &"a".repeat(100)] {
+        assert!(validate_repo_name(good).is_ok(), "{}", good);
+    }
+    let too_long = "a".repeat(101);
+    for bad in ["", &too_long, "../escape", "a..b", "..", ".git", "with space", "a/b", "a\\b"] {
+        assert!(
+            matches!(validate_repo_name(bad), Err(NimbusError::InvalidGitOperation(_))),
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn test_repository_names_are_unique_ignoring_case() {
+    use crate::store::{FsRepositoryStore, NewRepository, RepositoryStore};
+
+    let fixture = Fixture::new("Website");
+    let store = FsRepositoryStore::new(fixture.storage.clone());
+    let new = |name: &str| NewRepository {
+        name: name.to_string(),
+        description: None,
+        visibility: Visibility::Private,
+        default_branch: None,
+    };
+
+    for taken in ["Website", "website", "WEBSITE"] {
+        let err = store.create(new(taken)).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid git operation: Repository Website already exists");
+    }
+    store.create(new("website-2")).unwrap();
+}
+
+#[test]
+fn test_pushes_to_the_zero_oid_are_deletions() {
+    use nimbus_types::events::Event;
+
+    use crate::refs::deletion_event;
+
+    let event = deletion_event("project", "refs/heads/feature/x", Oid::zero(), "alice").unwrap();
+    assert!(matches!(
+        event,
+        Event::BranchDeleted { repository, branch, deleter }
+            if repository == "project" && branch == "feature/x" && deleter == "alice"
+    ));
+
+    let event = deletion_event("project", "refs/tags/v1.0", Oid::zero(), "alice").unwrap();
+    assert!(matches!(event, Event::TagDeleted { tag, .. } if tag == "v1.0"));
+
+    let updated = Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+    assert!(deletion_event("project", "refs/heads/main", updated, "alice").is_none());
+    assert!(deletion_event("project", "refs/notes/commits", Oid::zero(), "alice").is_none());
+}
+
+#[test]
+fn test_commit_details_report_renamed_files() {
+    use nimbus_types::{ChangeStatus, Commit, FileChange};
+
+    use crate::browse::commit_details;
+
+    let fixture = Fixture::new("project");
+    let body = "line one\nline two\nline three\nline four\n";
+    fixture.commit("main", &[("old.txt", body), ("notes.txt", "a\n")], "initial");
+    let renamed = fixture.commit(
+        "main",
+        &[("new.txt", body), ("notes.txt", "a\nb\n"), ("added.txt", "x\n")],
+        "rename old.txt",
+    );
+
+    let commit = commit_details(&fixture.repo(), renamed, &Default::default()).unwrap();
+    assert_eq!(commit.message, "rename old.txt");
+    assert_eq!(commit.parent_shas.len(), 1);
+
+    let mut files = commit.files_changed.clone();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let change = |path: &str, additions, deletions, status| FileChange {
+        path: path.to_string(),
+        additions,
+        deletions,
+        status,
+    };
+    assert_eq!(
+        files,
+        vec![
+            change("added.txt", 1, 0, ChangeStatus::Added),
+            change("new.txt", 0, 0, ChangeStatus::Renamed),
+            change("notes.txt", 1, 0, ChangeStatus::Modified),
+        ]
+    );
+
+    let json = serde_json::to_value(&commit).unwrap();
+    let round_tripped: Commit = serde_json::from_value(json).unwrap();
+    assert_eq!(round_tripped.files_changed, commit.files_changed);
+}
+
+/// Run gpg against the keys in `home`, feeding it `input`
+fn gpg(home: &std::path::Path, args: &[&str], input: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("gpg")
+        .env("GNUPGHOME", home)
+        .args(["--batch", "--quiet", "--passphrase", ""])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "gpg {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn test_commit_signatures_are_verified_against_keyring() {
+    use nimbus_types::CommitVerification;
+
+    use crate::browse::commit_details;
+    use crate::signing::Keyring;
+
+    let home = TempDir::new().unwrap();
+    gpg(home.path(), &["--quick-gen-key", "Test Signer <signer@example.com>", "ed25519"], b"");
+    let keyring = home.path().join("trusted.gpg");
+    gpg(home.path(), &["--output", keyring.to_str().unwrap(), "--export"], b"");
+
+    let fixture = Fixture::new("project");
+    let unsigned = fixture.commit("main", &[("lib.rs", "fn a() {}\n")], "unsigned");
+    let repo = fixture.repo();
+    let parent = repo.find_commit(unsigned).unwrap();
+    let signature = Signature::now("Test User", "test@example.com").unwrap();
+    let buffer = repo
+        .commit_create_buffer(&signature, &signature, "signed", &parent.tree().unwrap(), &[&parent])
+        .unwrap();
+    let buffer = buffer.as_str().unwrap();
+    let armored = gpg(home.path(), &["--armor", "--detach-sign"], buffer.as_bytes());
+    let signed = repo.commit_signed(buffer, std::str::from_utf8(&armored).unwrap(), None).unwrap();
+
+    let trusted = Keyring::new(&keyring);
+    let details = commit_details(&repo, signed, &trusted).unwrap();
+    assert_eq!(
+        details.verification,
+        CommitVerification::Valid { signer: "Test Signer <signer@example.com>".to_string() }
+    );
+    assert_eq!(trusted.verify(&repo, unsigned).unwrap(), CommitVerification::Unsigned);
+    // Without the signer's key the signature can't be trusted
+    assert!(matches!(
+        Keyring::default().verify(&repo, signed).unwrap(),
+        CommitVerification::Invalid { .. }
+    ));
+
+    let _ = std::process::Command::new("gpgconf")
+        .env("GNUPGHOME", home.path())
+        .args(["--kill", "gpg-agent"])
+        .status();
+}
+
+#[test]
+fn test_receive_request_parses_commands_and_pack() {
+    use crate::push::ReceiveRequest;
+    use crate::smart_http::pkt_line;
+
+    let old = "0".repeat(40);
+    let new = "0123456789abcdef0123456789abcdef01234567";
+    let mut body = pkt_line(&format!("{} {} refs/heads/main\0report-status ofs-delta\n", old, new));
+    body.push_str(&pkt_line(&format!("{} {} refs/tags/v1\n", new, old)));
+    body.push_str("0000PACK");
+
+    let request = ReceiveRequest::parse(body.as_bytes()).unwrap();
+    assert_eq!(request.updates.len(), 2);
+    assert_eq!(request.updates[0].refname, "refs/heads/main");
+    assert!(!request.updates[0].is_delete());
+    assert!(request.updates[1].is_delete());
+    assert!(request.wants("report-status"));
+    assert!(!request.wants("side-band-64k"));
+    assert_eq!(request.pack(body.as_bytes()), b"PACK");
+
+    assert!(ReceiveRequest::parse(b"0000").is_err());
+    assert!(ReceiveRequest::parse(b"00zzgarbage").is_err());
+}
@@ -0,0 +1,106 @@
+//! Access policy for repositories
+//!
+//! Owner sees everything, collaborators see repos they've been granted plus
+//! internal and public ones, and anonymous callers only see public repos.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{NimbusError, Permission, Repository, Visibility};
+
+/// Who is making a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Actor {
+    Owner,
+    Collaborator { id: Uuid },
+    Anonymous,
+}
+
+impl Actor {
+    /// Permission this actor holds on `repo`, `None` if it can't see it at all
+    pub fn permission_on(&self, repo: &Repository) -> Option<Permission> {
+        match self {
+            Actor::Owner => Some(Permission::Admin),
+            Actor::Collaborator { id } => repo
+                .collaborator_permissions
+                .iter()
+                .find(|grant| grant.collaborator_id == *id)
+                .map(|grant| grant.permission)
+                .or_else(|| (repo.visibility != Visibility::Private).then_some(Permission::Read)),
+            Actor::Anonymous => (repo.visibility == Visibility::Public).then_some(Permission::Read),
+        }
+    }
+
+    /// Whether this actor may know the repository exists
+    pub fn can_see(&self, repo: &Repository) -> bool {
+        self.permission_on(repo).is_some()
+    }
+}
+
+/// Check that `actor` holds `required` on `repo`
+///
+/// Repositories the actor can't see at all yield `RepositoryNotFound`, the
+/// same error as a missing repository, so private repos never leak. Visible
+/// repositories with too little access yield `Forbidden`.
+pub fn resolve_repo_access(
+    actor: &Actor,
+    repo: &Repository,
+    required: Permission,
+) -> Result<Repository, NimbusError> {
+    match actor.permission_on(repo) {
+        None => Err(NimbusError::RepositoryNotFound(repo.name.clone())),
+        Some(held) if held < required => {
+            Err(NimbusError::Forbidden(format!("{:?} access to {} required", required, repo.name)))
+        }
+        Some(_) => Ok(repo.clone()),
+    }
+}
+
+/// Id [`authorize`] takes for the owner, who has no collaborator record
+pub const OWNER_ID: Uuid = Uuid::nil();
+
+/// Check that collaborator `collaborator_id` holds `required` on `repo`
+///
+/// The owner, passed as [`OWNER_ID`], implicitly holds `Admin`. Errors are
+/// those of [`resolve_repo_access`].
+pub fn authorize(
+    repo: &Repository,
+    collaborator_id: Uuid,
+    required: Permission,
+) -> Result<(), NimbusError> {
+    let actor = if collaborator_id == OWNER_ID {
+        Actor::Owner
+    } else {
+        Actor::Collaborator { id: collaborator_id }
+    };
+    resolve_repo_access(&actor, repo, required).map(|_| ())
+}
+
+/// One page of repositories visible to an actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryListing {
+    pub repositories: Vec<Repository>,
+    /// Number of repositories visible to the actor, not the instance total
+    pub total: usize,
+}
+
+/// List the repositories `actor` can see, sorted by name and paginated
+///
+/// Filtering happens before pagination so page boundaries and `total` are
+/// computed over visible repositories only and never hint at private ones.
+pub fn list_visible_repositories(
+    actor: &Actor,
+    repositories: &[Repository],
+    offset: usize,
+    limit: usize,
+) -> RepositoryListing {
+    let mut visible: Vec<&Repository> =
+        repositories.iter().filter(|repo| actor.can_see(repo)).collect();
+    visible.sort_by(|a, b| a.name.cmp(&b.name));
+
+    RepositoryListing {
+        total: visible.len(),
+        repositories: visible.into_iter().skip(offset).take(limit).cloned().collect(),
+    }
+}
@@ -12,15 +12,87 @@ use async_trait::async_trait;
 
 use crate::{Commit, Repository};
 
-/// Event subscription filter
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Event subscription filter; the default matches every event
+///
+/// Build one with [`EventFilter::builder`] or a preset such as
+/// [`EventFilter::for_repo`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EventFilter {
     /// Event types to receive (empty = all)
     pub event_types: Vec<EventType>,
-    /// Repository names to filter (empty = all)
+    /// Repository names, or patterns with the same syntax as branches (empty = all)
     pub repositories: Vec<String>,
-    /// Branch patterns to match (glob patterns)
+    /// Branch patterns to match: globs, or regexes prefixed with `re:`
     pub branches: Vec<String>,
+    /// Tag name patterns to match, with the same syntax as branches (empty = all)
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl EventFilter {
+    /// Start building a filter; with nothing added it matches every event
+    pub fn builder() -> EventFilterBuilder {
+        EventFilterBuilder::default()
+    }
+
+    /// Filter matching every event
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Filter matching every event of one repository
+    pub fn for_repo(name: impl Into<String>) -> Self {
+        Self::builder().repository(name).build()
+    }
+
+    /// Filter matching events of the given types in any repository
+    pub fn for_types(event_types: impl IntoIterator<Item = EventType>) -> Self {
+        Self::builder().event_types(event_types).build()
+    }
+}
+
+/// Builder for [`EventFilter`]
+///
+/// Each call narrows one dimension; values given for the same dimension are
+/// alternatives, e.g. two `branch` patterns match either branch.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct EventFilterBuilder {
+    filter: EventFilter,
+}
+
+impl EventFilterBuilder {
+    pub fn event_type(mut self, event_type: EventType) -> Self {
+        self.filter.event_types.push(event_type);
+        self
+    }
+
+    pub fn event_types(mut self, event_types: impl IntoIterator<Item = EventType>) -> Self {
+        self.filter.event_types.extend(event_types);
+        self
+    }
+
+    /// Repository name, or a pattern with the same syntax as branches
+    pub fn repository(mut self, repository: impl Into<String>) -> Self {
+        self.filter.repositories.push(repository.into());
+        self
+    }
+
+    /// Branch glob, or regex prefixed with `re:`
+    pub fn branch(mut self, pattern: impl Into<String>) -> Self {
+        self.filter.branches.push(pattern.into());
+        self
+    }
+
+    /// Tag name pattern, with the same syntax as branches
+    pub fn tag(mut self, pattern: impl Into<String>) -> Self {
+        self.filter.tags.push(pattern.into());
+        self
+    }
+
+    pub fn build(self) -> EventFilter {
+        self.filter
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -31,17 +103,59 @@ pub enum EventType {
     Repository,
     Review,
     CiRun,
+    Ai,
+}
+
+impl EventType {
+    /// Every event type, e.g. for subscribing to all of them
+    pub const ALL: [EventType; 7] = [
+        EventType::Push,
+        EventType::PullRequest,
+        EventType::Tag,
+        EventType::Repository,
+        EventType::Review,
+        EventType::CiRun,
+        EventType::Ai,
+    ];
+
+    /// Short `snake_case` name, as used in NATS subjects and query strings
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Push => "push",
+            EventType::PullRequest => "pull_request",
+            EventType::Tag => "tag",
+            EventType::Repository => "repository",
+            EventType::Review => "review",
+            EventType::CiRun => "ci_run",
+            EventType::Ai => "ai",
+        }
+    }
+
+    /// The event type named `name` (see [`EventType::as_str`])
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event_type| event_type.as_str() == name)
+    }
 }
 
 /// Extended event with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventEnvelope {
     pub id: Uuid,
+    /// Always UTC once published
     pub timestamp: time::OffsetDateTime,
+    /// Assigned by the bus on publish, increasing in publish order; breaks
+    /// ties between equal timestamps. 0 until then, and left out of the
+    /// JSON so envelopes stored before it existed keep their hashes
+    #[serde(default, skip_serializing_if = "is_unsequenced")]
+    pub seq: u64,
     pub event: Event,
     pub metadata: EventMetadata,
 }
 
+fn is_unsequenced(seq: &u64) -> bool {
+    *seq == 0
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventMetadata {
     /// Which plugin should handle this (if specific)
@@ -50,6 +164,9 @@ pub struct EventMetadata {
     pub priority: EventPriority,
     /// Should this event be persisted?
     pub persistent: bool,
+    /// Injected by an operator rather than produced by git; handlers may skip these
+    #[serde(default)]
+    pub simulated: bool,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Ord, PartialOrd, Eq)]
@@ -72,6 +189,12 @@ pub enum Event {
         pusher: String,
     },
 
+    BranchDeleted {
+        repository: String,
+        branch: String,
+        deleter: String,
+    },
+
     PullRequestOpened {
         id: Uuid,
         repository: String,
@@ -99,6 +222,11 @@ pub enum Event {
         tagger: String,
     },
 
+    TagDeleted {
+        repository: String,
+        tag: String,
+    },
+
     // Repository Events
     RepositoryCreated {
         repository: Repository,
@@ -139,6 +267,17 @@ pub enum Event {
         plugin: String,
     },
 
+    /// A comment on a pull request, anchored to a line when `line` is set
+    ReviewCommentAdded {
+        pull_request_id: Uuid,
+        repository: String,
+        reviewer: String,
+        file: String,
+        line: Option<u32>,
+        body: String,
+        plugin: String,
+    },
+
     // AI Events (from plugins)
     AiAnalysisRequested {
         id: Uuid,
@@ -155,6 +294,41 @@ pub enum Event {
     },
 }
 
+impl Event {
+    /// The type subscribers filter this event by
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::Push { .. } | Event::BranchDeleted { .. } => EventType::Push,
+            Event::PullRequestOpened { .. }
+            | Event::PullRequestMerged { .. }
+            | Event::PullRequestClosed { .. } => EventType::PullRequest,
+            Event::TagCreated { .. } | Event::TagDeleted { .. } => EventType::Tag,
+            Event::RepositoryCreated { .. } | Event::RepositoryDeleted { .. } => {
+                EventType::Repository
+            }
+            Event::ReviewRequested { .. }
+            | Event::ReviewSubmitted { .. }
+            | Event::ReviewCommentAdded { .. } => EventType::Review,
+            Event::CiRunStarted { .. } | Event::CiRunCompleted { .. } => EventType::CiRun,
+            Event::AiAnalysisRequested { .. } | Event::AiAnalysisCompleted { .. } => EventType::Ai,
+        }
+    }
+
+    /// The plugin that produced this event, for events plugins emit
+    pub fn plugin(&self) -> Option<&str> {
+        match self {
+            Event::CiRunStarted { plugin, .. }
+            | Event::CiRunCompleted { plugin, .. }
+            | Event::ReviewRequested { plugin, .. }
+            | Event::ReviewSubmitted { plugin, .. }
+            | Event::ReviewCommentAdded { plugin, .. }
+            | Event::AiAnalysisRequested { plugin, .. }
+            | Event::AiAnalysisCompleted { plugin, .. } => Some(plugin),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CiStatus {
     Success,
@@ -207,12 +381,57 @@ pub trait EventHandler: Send + Sync {
     }
 }
 
+/// Ordering a bus promises between events it delivers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderingGuarantee {
+    /// Events may reach handlers in any order
+    None,
+    /// Events for the same repository are delivered in publish order
+    PerRepository,
+    /// All events are delivered in publish order
+    Global,
+}
+
+/// A registered handler, as reported by [`EventBus::subscriptions`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionInfo {
+    pub name: String,
+    pub filter: EventFilter,
+    /// Result of the handler's `health_check`
+    pub healthy: bool,
+    /// Stage the handler runs at; lower stages finish before higher ones start
+    #[serde(default)]
+    pub stage: i32,
+}
+
+/// What happened when one event was dispatched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DispatchReport {
+    /// Handlers the event was delivered to
+    pub matched: usize,
+    pub succeeded: usize,
+    /// Handlers that gave up, timed out, or panicked
+    pub failed: usize,
+    /// Time until the last handler finished
+    pub duration: std::time::Duration,
+}
+
 /// Trait for the event bus itself
 #[async_trait]
 pub trait EventBus: Send + Sync {
     /// Publish an event to all interested subscribers
     async fn publish(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>>;
 
+    /// Publish an event and wait until every interested handler is done
+    ///
+    /// Fails if that takes longer than `timeout`.
+    async fn publish_and_wait(
+        &self,
+        event: EventEnvelope,
+        timeout: std::time::Duration,
+    ) -> Result<DispatchReport, Box<dyn std::error::Error>>;
+
     /// Subscribe a handler to events
     async fn subscribe(
         &self,
@@ -225,4 +444,14 @@ pub trait EventBus: Send + Sync {
 
     /// Get subscriber count
     async fn subscriber_count(&self) -> usize;
+
+    /// Registered handlers with their filters and current health
+    async fn subscriptions(&self) -> Vec<SubscriptionInfo> {
+        Vec::new()
+    }
+
+    /// Ordering handlers can rely on, so they can adapt (e.g. tolerate reordering)
+    fn ordering_guarantee(&self) -> OrderingGuarantee {
+        OrderingGuarantee::None
+    }
 }
@@ -3,10 +3,12 @@
 //! Single-owner model: Each instance has one owner and multiple collaborators.
 //! This is NOT a GitHub clone - it's a personal git platform.
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 
+pub mod access;
 pub mod events;
+pub mod pull_state;
 
 /// The instance owner - there's only one per deployment
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +18,36 @@ pub struct Owner {
     pub instance_domain: String, // e.g., "code.navicore.tech"
 }
 
+/// Instance configuration the owner can edit from the settings page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceSettings {
+    /// Display name shown in the UI
+    pub instance_name: String,
+    pub instance_domain: String,
+    pub owner_email: String,
+}
+
+impl InstanceSettings {
+    /// Why these settings can't be saved, if they can't
+    pub fn validate(&self) -> Result<(), String> {
+        if self.instance_name.trim().is_empty() {
+            return Err("Instance name must not be empty".to_string());
+        }
+        let domain = &self.instance_domain;
+        if domain.is_empty()
+            || !domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':')
+        {
+            return Err(format!("Invalid instance domain {:?}", domain));
+        }
+        if !self.owner_email.contains('@') {
+            return Err(format!("Invalid owner email {:?}", self.owner_email));
+        }
+        Ok(())
+    }
+}
+
 /// Collaborators can contribute but not create repos
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Collaborator {
@@ -27,7 +59,9 @@ pub struct Collaborator {
 }
 
 /// Simple permission model - no complex RBAC needed
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+///
+/// Ordered so that `Read < Write < Admin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Permission {
     Read,
     Write,
@@ -40,11 +74,75 @@ pub struct Repository {
     pub id: Uuid,
     pub name: String,
     pub description: Option<String>,
-    pub is_private: bool,
+    /// Accepted as the legacy `is_private` flag too
+    #[serde(alias = "is_private")]
+    pub visibility: Visibility,
     pub default_branch: String,
     pub collaborator_permissions: Vec<CollaboratorPermission>,
 }
 
+/// Who can see a repository besides the owner and granted collaborators
+///
+/// Deserializes from the legacy `is_private` boolean as well: `true` is
+/// `Private` and `false` is `Public`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Only the owner and granted collaborators
+    #[default]
+    Private,
+    /// Every collaborator, but not anonymous callers
+    Internal,
+    /// Anyone, including anonymous clones
+    Public,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Internal => "internal",
+            Visibility::Public => "public",
+        }
+    }
+}
+
+impl std::fmt::Display for Visibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" => Ok(Visibility::Private),
+            "internal" => Ok(Visibility::Internal),
+            "public" => Ok(Visibility::Public),
+            other => Err(format!("Unknown visibility: {}", other)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Visibility {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Named(String),
+            LegacyPrivate(bool),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Named(name) => name.parse().map_err(serde::de::Error::custom),
+            Repr::LegacyPrivate(true) => Ok(Visibility::Private),
+            Repr::LegacyPrivate(false) => Ok(Visibility::Public),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CollaboratorPermission {
     pub collaborator_id: Uuid,
@@ -111,10 +209,59 @@ pub struct Commit {
     pub author: String,
     pub timestamp: time::OffsetDateTime,
     pub parent_shas: Vec<String>,
+    /// Files the commit changed relative to its first parent; left out of
+    /// the JSON when empty so events stored before it existed keep their hashes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_changed: Vec<FileChange>,
+    /// Whether the commit is signed, and by whom; left out of the JSON for
+    /// unsigned commits, like `files_changed`
+    #[serde(default, skip_serializing_if = "CommitVerification::is_unsigned")]
+    pub verification: CommitVerification,
+}
+
+/// Outcome of checking a commit's signature
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CommitVerification {
+    #[default]
+    Unsigned,
+    /// Signed by a key in the configured keyring
+    Valid {
+        /// User id of the signing key
+        signer: String,
+    },
+    /// Signed, but the signature couldn't be verified
+    Invalid { reason: String },
+}
+
+impl CommitVerification {
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Self::Unsigned)
+    }
+}
+
+/// One file changed by a commit, with line counts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Path after the change; the new path for renames
+    pub path: String,
+    pub additions: u32,
+    pub deletions: u32,
+    pub status: ChangeStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeStatus {
+    Added,
+    Modified,
+    Deleted,
+    /// Moved, possibly with edits; `additions`/`deletions` count the edits
+    Renamed,
 }
 
 /// Plugin types for the extension system
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PluginType {
     CiRunner,
     ReviewSystem,
@@ -124,11 +271,16 @@ pub enum PluginType {
 /// Plugin registration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Plugin {
+    /// Generated when a registration leaves it out
+    #[serde(default = "Uuid::new_v4")]
     pub id: Uuid,
     pub name: String,
     pub plugin_type: PluginType,
     pub endpoint: String, // gRPC or HTTP endpoint
     pub health_check: String,
+    /// Events to deliver to `endpoint`; none are delivered if unset
+    #[serde(default)]
+    pub interests: Option<events::EventFilter>,
 }
 
 // Error types
@@ -137,15 +289,49 @@ pub enum NimbusError {
     #[error("Repository not found: {0}")]
     RepositoryNotFound(String),
 
+    #[error("Ref not found: {0}")]
+    RefNotFound(String),
+
+    #[error("Path not found: {0}")]
+    PathNotFound(String),
+
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Invalid git operation: {0}")]
     InvalidGitOperation(String),
 
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("Plugin error: {0}")]
     PluginError(String),
 
     #[error("Internal error: {0}")]
     Internal(String),
 }
+
+impl NimbusError {
+    /// HTTP status code for this error
+    ///
+    /// Hidden repositories are reported as missing, so the 404/403 split
+    /// follows `access::resolve_repo_access`.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            NimbusError::RepositoryNotFound(_)
+            | NimbusError::RefNotFound(_)
+            | NimbusError::PathNotFound(_) => 404,
+            NimbusError::Unauthorized(_) => 401,
+            NimbusError::Forbidden(_) => 403,
+            NimbusError::InvalidGitOperation(_) | NimbusError::InvalidInput(_) => 400,
+            NimbusError::PluginError(_) => 502,
+            NimbusError::Internal(_) => 500,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
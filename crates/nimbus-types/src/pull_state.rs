@@ -0,0 +1,89 @@
+//! Pull request lifecycle
+//!
+//! A pull request is opened once, then either merged or closed, and stays
+//! that way. [`PullRequestStateMachine`] holds each pull request's state so
+//! whatever emits pull request events can check a transition first.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::NimbusError;
+use crate::events::Event;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullRequestState {
+    Open,
+    Merged,
+    Closed,
+}
+
+/// State of every pull request seen so far, by id
+#[derive(Debug, Clone, Default)]
+pub struct PullRequestStateMachine {
+    states: HashMap<Uuid, PullRequestState>,
+}
+
+impl PullRequestStateMachine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current state, `None` if the pull request was never opened
+    pub fn state(&self, id: Uuid) -> Option<PullRequestState> {
+        self.states.get(&id).copied()
+    }
+
+    /// Record a new pull request; fails if the id is already in use
+    pub fn open(&mut self, id: Uuid) -> Result<(), NimbusError> {
+        if let Some(state) = self.state(id) {
+            return Err(NimbusError::InvalidGitOperation(format!(
+                "Pull request {} was already opened and is {:?}",
+                id, state
+            )));
+        }
+        self.states.insert(id, PullRequestState::Open);
+        Ok(())
+    }
+
+    /// Mark an open pull request merged
+    pub fn merge(&mut self, id: Uuid) -> Result<(), NimbusError> {
+        self.finish(id, PullRequestState::Merged)
+    }
+
+    /// Mark an open pull request closed without merging
+    pub fn close(&mut self, id: Uuid) -> Result<(), NimbusError> {
+        self.finish(id, PullRequestState::Closed)
+    }
+
+    /// Apply the transition a pull request event describes
+    ///
+    /// Other events are accepted and ignored.
+    pub fn apply(&mut self, event: &Event) -> Result<(), NimbusError> {
+        match event {
+            Event::PullRequestOpened { id, .. } => self.open(*id),
+            Event::PullRequestMerged { id, .. } => self.merge(*id),
+            Event::PullRequestClosed { id, .. } => self.close(*id),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, id: Uuid, to: PullRequestState) -> Result<(), NimbusError> {
+        match self.states.get_mut(&id) {
+            Some(state @ PullRequestState::Open) => {
+                *state = to;
+                Ok(())
+            }
+            Some(state) => Err(NimbusError::InvalidGitOperation(format!(
+                "Pull request {} is already {:?}",
+                id, state
+            ))),
+            None => Err(NimbusError::InvalidGitOperation(format!(
+                "Pull request {} was never opened",
+                id
+            ))),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Tests for shared types and policies
+
+use uuid::Uuid;
+
+use crate::access::{Actor, OWNER_ID, authorize, list_visible_repositories, resolve_repo_access};
+use crate::{
+    CollaboratorPermission, InstanceSettings, NimbusError, Permission, Repository, Visibility,
+};
+
+fn repository(name: &str, visibility: Visibility) -> Repository {
+    Repository {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        description: None,
+        visibility,
+        default_branch: "main".to_string(),
+        collaborator_permissions: vec![],
+    }
+}
+
+fn grant(repo: &mut Repository, collaborator_id: Uuid, permission: Permission) {
+    repo.collaborator_permissions.push(CollaboratorPermission {
+        collaborator_id,
+        repository_id: repo.id,
+        permission,
+    });
+}
+
+fn names(listing: &crate::access::RepositoryListing) -> Vec<&str> {
+    listing.repositories.iter().map(|r| r.name.as_str()).collect()
+}
+
+#[test]
+fn test_collaborator_sees_granted_private_and_public_repos() {
+    let alice = Uuid::new_v4();
+    let mut shared = repository("shared-private", Visibility::Private);
+    grant(&mut shared, alice, Permission::Read);
+    let repos = vec![
+        repository("public", Visibility::Public),
+        shared,
+        repository("secret", Visibility::Private),
+    ];
+
+    let listing = list_visible_repositories(&Actor::Collaborator { id: alice }, &repos, 0, 50);
+
+    assert_eq!(names(&listing), vec!["public", "shared-private"]);
+    assert_eq!(listing.total, 2);
+}
+
+#[test]
+fn test_anonymous_sees_only_public_repos() {
+    let repos = vec![
+        repository("public-a", Visibility::Public),
+        repository("secret", Visibility::Private),
+        repository("public-b", Visibility::Public),
+    ];
+
+    let listing = list_visible_repositories(&Actor::Anonymous, &repos, 0, 50);
+
+    assert_eq!(names(&listing), vec!["public-a", "public-b"]);
+    assert_eq!(listing.total, 2);
+}
+
+#[test]
+fn test_owner_sees_all_repos() {
+    let repos =
+        vec![repository("public", Visibility::Public), repository("secret", Visibility::Private)];
+
+    let listing = list_visible_repositories(&Actor::Owner, &repos, 0, 50);
+
+    assert_eq!(listing.total, 2);
+}
+
+#[test]
+fn test_pagination_counts_only_visible_repos() {
+    let repos = vec![
+        repository("a", Visibility::Public),
+        repository("b", Visibility::Private),
+        repository("c", Visibility::Public),
+        repository("d", Visibility::Private),
+        repository("e", Visibility::Public),
+    ];
+
+    let first = list_visible_repositories(&Actor::Anonymous, &repos, 0, 2);
+    let second = list_visible_repositories(&Actor::Anonymous, &repos, 2, 2);
+
+    assert_eq!(names(&first), vec!["a", "c"]);
+    assert_eq!(names(&second), vec!["e"]);
+    assert_eq!(first.total, 3);
+}
+
+#[test]
+fn test_internal_repos_are_visible_to_collaborators_only() {
+    let repo = repository("team", Visibility::Internal);
+
+    assert_eq!(
+        Actor::Collaborator { id: Uuid::new_v4() }.permission_on(&repo),
+        Some(Permission::Read)
+    );
+    assert_eq!(Actor::Anonymous.permission_on(&repo), None);
+}
+
+#[test]
+fn test_repository_visibility_read_from_legacy_is_private() {
+    let legacy = |is_private: bool| {
+        serde_json::json!({
+            "id": Uuid::new_v4(),
+            "name": "legacy",
+            "description": null,
+            "is_private": is_private,
+            "default_branch": "main",
+            "collaborator_permissions": []
+        })
+    };
+
+    let private: Repository = serde_json::from_value(legacy(true)).unwrap();
+    assert_eq!(private.visibility, Visibility::Private);
+    let public: Repository = serde_json::from_value(legacy(false)).unwrap();
+    assert_eq!(public.visibility, Visibility::Public);
+
+    // New records round-trip through the named form
+    let internal = repository("team", Visibility::Internal);
+    let json = serde_json::to_value(&internal).unwrap();
+    assert_eq!(json["visibility"], "internal");
+    let back: Repository = serde_json::from_value(json).unwrap();
+    assert_eq!(back.visibility, Visibility::Internal);
+    assert!(serde_json::from_value::<Visibility>(serde_json::json!("secret")).is_err());
+}
+
+#[test]
+fn test_anonymous_gets_not_found_for_private_repo() {
+    let secret = repository("secret", Visibility::Private);
+
+    let err = resolve_repo_access(&Actor::Anonymous, &secret, Permission::Read).unwrap_err();
+
+    // Indistinguishable from a repository that doesn't exist
+    assert!(matches!(err, NimbusError::RepositoryNotFound(name) if name == "secret"));
+}
+
+#[test]
+fn test_read_only_collaborator_is_forbidden_to_write() {
+    let alice = Uuid::new_v4();
+    let mut shared = repository("shared", Visibility::Private);
+    grant(&mut shared, alice, Permission::Read);
+    let actor = Actor::Collaborator { id: alice };
+
+    assert!(resolve_repo_access(&actor, &shared, Permission::Read).is_ok());
+    let err = resolve_repo_access(&actor, &shared, Permission::Write).unwrap_err();
+    assert!(matches!(err, NimbusError::Forbidden(_)));
+
+    // Public repos are readable but still not writable without a grant
+    let public = repository("public", Visibility::Public);
+    let err = resolve_repo_access(&actor, &public, Permission::Write).unwrap_err();
+    assert!(matches!(err, NimbusError::Forbidden(_)));
+}
+
+#[test]
+fn test_owner_has_full_access() {
+    let secret = repository("secret", Visibility::Private);
+
+    let repo = resolve_repo_access(&Actor::Owner, &secret, Permission::Admin).unwrap();
+
+    assert_eq!(repo.name, "secret");
+}
+
+#[test]
+fn test_authorize_read_grant() {
+    let alice = Uuid::new_v4();
+    let mut repo = repository("shared", Visibility::Private);
+    grant(&mut repo, alice, Permission::Read);
+
+    assert!(authorize(&repo, alice, Permission::Read).is_ok());
+    assert!(matches!(authorize(&repo, alice, Permission::Write), Err(NimbusError::Forbidden(_))));
+    assert!(matches!(authorize(&repo, alice, Permission::Admin), Err(NimbusError::Forbidden(_))));
+}
+
+#[test]
+fn test_authorize_write_grant() {
+    let alice = Uuid::new_v4();
+    let mut repo = repository("shared", Visibility::Private);
+    grant(&mut repo, alice, Permission::Write);
+
+    assert!(authorize(&repo, alice, Permission::Read).is_ok());
+    assert!(authorize(&repo, alice, Permission::Write).is_ok());
+    assert!(matches!(authorize(&repo, alice, Permission::Admin), Err(NimbusError::Forbidden(_))));
+}
+
+#[test]
+fn test_authorize_admin_grant() {
+    let alice = Uuid::new_v4();
+    let mut repo = repository("shared", Visibility::Private);
+    grant(&mut repo, alice, Permission::Admin);
+
+    for required in [Permission::Read, Permission::Write, Permission::Admin] {
+        assert!(authorize(&repo, alice, required).is_ok());
+    }
+}
+
+#[test]
+fn test_authorize_without_grant() {
+    let alice = Uuid::new_v4();
+    let mut repo = repository("secret", Visibility::Private);
+    grant(&mut repo, Uuid::new_v4(), Permission::Admin);
+
+    // Another collaborator's grant gives alice nothing, not even a hint the repo exists
+    let err = authorize(&repo, alice, Permission::Read).unwrap_err();
+    assert!(matches!(err, NimbusError::RepositoryNotFound(name) if name == "secret"));
+}
+
+#[test]
+fn test_authorize_owner_bypass() {
+    let repo = repository("secret", Visibility::Private);
+
+    for required in [Permission::Read, Permission::Write, Permission::Admin] {
+        assert!(authorize(&repo, OWNER_ID, required).is_ok());
+    }
+}
+
+#[test]
+fn test_error_status_codes() {
+    let cases = [
+        (NimbusError::RepositoryNotFound("r".into()), 404),
+        (NimbusError::RefNotFound("main".into()), 404),
+        (NimbusError::PathNotFound("src".into()), 404),
+        (NimbusError::Unauthorized("who".into()), 401),
+        (NimbusError::Forbidden("no".into()), 403),
+        (NimbusError::InvalidGitOperation("bad".into()), 400),
+        (NimbusError::InvalidInput("bad".into()), 400),
+        (NimbusError::PluginError("boom".into()), 502),
+        (NimbusError::Internal("boom".into()), 500),
+    ];
+    for (error, status) in cases {
+        assert_eq!(error.status_code(), status, "{:?}", error);
+    }
+}
+
+#[test]
+fn test_commits_without_file_changes_still_deserialize() {
+    let commit = crate::Commit {
+        sha: "0123456789abcdef0123456789abcdef01234567".to_string(),
+        message: "initial".to_string(),
+        author: "alice".to_string(),
+        timestamp: time::OffsetDateTime::UNIX_EPOCH,
+        parent_shas: vec![],
+        files_changed: vec![],
+        verification: crate::CommitVerification::Unsigned,
+    };
+    // As serialized before commits carried file changes or verification
+    let mut json = serde_json::to_value(&commit).unwrap();
+    json.as_object_mut().unwrap().remove("files_changed");
+    json.as_object_mut().unwrap().remove("verification");
+
+    let commit: crate::Commit = serde_json::from_value(json).unwrap();
+
+    assert!(commit.files_changed.is_empty());
+    assert_eq!(commit.verification, crate::CommitVerification::Unsigned);
+}
+
+#[test]
+fn test_commit_verification_serialization() {
+    use crate::CommitVerification;
+
+    let valid = CommitVerification::Valid { signer: "Alice <alice@example.com>".to_string() };
+    assert_eq!(
+        serde_json::to_value(&valid).unwrap(),
+        serde_json::json!({ "status": "valid", "signer": "Alice <alice@example.com>" })
+    );
+    let unsigned: CommitVerification =
+        serde_json::from_value(serde_json::json!({ "status": "unsigned" })).unwrap();
+    assert_eq!(unsigned, CommitVerification::Unsigned);
+}
+
+#[test]
+fn test_instance_settings_validation() {
+    let valid = InstanceSettings {
+        instance_name: "Home".to_string(),
+        instance_domain: "code.example.com".to_string(),
+        owner_email: "me@example.com".to_string(),
+    };
+    assert!(valid.validate().is_ok());
+    assert!(
+        InstanceSettings { instance_name: " ".to_string(), ..valid.clone() }.validate().is_err()
+    );
+    assert!(
+        InstanceSettings { instance_domain: "code example".to_string(), ..valid.clone() }
+            .validate()
+            .is_err()
+    );
+    assert!(InstanceSettings { owner_email: "me".to_string(), ..valid }.validate().is_err());
+}
+
+#[test]
+fn test_pull_request_transitions() {
+    use crate::events::Event;
+    use crate::pull_state::{PullRequestState, PullRequestStateMachine};
+
+    let mut pulls = PullRequestStateMachine::new();
+    let id = Uuid::new_v4();
+    let opened = Event::PullRequestOpened {
+        id,
+        repository: "website".to_string(),
+        from_branch: "feature".to_string(),
+        to_branch: "main".to_string(),
+        title: "Add a page".to_string(),
+        author: "alice".to_string(),
+    };
+
+    // open -> merge is fine
+    pulls.apply(&opened).unwrap();
+    assert_eq!(pulls.state(id), Some(PullRequestState::Open));
+    let merged = Event::PullRequestMerged {
+        id,
+        repository: "website".to_string(),
+        merge_commit: "abc".into(),
+    };
+    pulls.apply(&merged).unwrap();
+    assert_eq!(pulls.state(id), Some(PullRequestState::Merged));
+
+    // merge -> close, a second merge and a second open are not
+    let closed = Event::PullRequestClosed { id, repository: "website".to_string() };
+    assert!(matches!(pulls.apply(&closed), Err(NimbusError::InvalidGitOperation(_))));
+    assert!(matches!(pulls.apply(&merged), Err(NimbusError::InvalidGitOperation(_))));
+    assert!(matches!(pulls.apply(&opened), Err(NimbusError::InvalidGitOperation(_))));
+    assert_eq!(pulls.state(id), Some(PullRequestState::Merged));
+
+    // Nothing happens to a pull request before it is opened
+    let unknown = Uuid::new_v4();
+    assert!(pulls.close(unknown).is_err());
+    assert_eq!(pulls.state(unknown), None);
+}
+
+#[test]
+fn test_event_filter_builder_matches_manual_construction() {
+    use crate::events::{EventFilter, EventType};
+
+    let built = EventFilter::builder()
+        .event_type(EventType::Push)
+        .event_types([EventType::Tag, EventType::Repository])
+        .repository("website")
+        .branch("main")
+        .branch("release/*")
+        .tag("v*")
+        .build();
+    let manual = EventFilter {
+        event_types: vec![EventType::Push, EventType::Tag, EventType::Repository],
+        repositories: vec!["website".to_string()],
+        branches: vec!["main".to_string(), "release/*".to_string()],
+        tags: vec!["v*".to_string()],
+    };
+    assert_eq!(built, manual);
+
+    let empty =
+        EventFilter { event_types: vec![], repositories: vec![], branches: vec![], tags: vec![] };
+    assert_eq!(EventFilter::builder().build(), empty);
+    assert_eq!(EventFilter::all(), empty);
+}
+
+#[test]
+fn test_event_filter_presets() {
+    use crate::events::{EventFilter, EventType};
+
+    assert_eq!(
+        EventFilter::for_repo("website"),
+        EventFilter { repositories: vec!["website".to_string()], ..Default::default() }
+    );
+    assert_eq!(
+        EventFilter::for_types([EventType::CiRun, EventType::Ai]),
+        EventFilter { event_types: vec![EventType::CiRun, EventType::Ai], ..Default::default() }
+    );
+}
@@ -0,0 +1,212 @@
+//! Calls to the Nimbus REST API shared by the pages
+
+use std::fmt;
+
+use gloo_net::http::{Request, RequestBuilder, Response};
+use nimbus_types::access::RepositoryListing;
+use nimbus_types::{InstanceSettings, PluginType, Repository};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::auth::Session;
+
+/// Why an API call failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// No response at all, e.g. the server is unreachable
+    Network(String),
+    /// The server answered with an error status
+    Status { status: u16, message: String },
+    /// The response body wasn't in the expected shape
+    Parse(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(e) => write!(f, "Could not reach the server: {}", e),
+            ApiError::Status { message, .. } => f.write_str(message),
+            ApiError::Parse(e) => write!(f, "Unexpected response from the server: {}", e),
+        }
+    }
+}
+
+/// Attach `token`, if any, as a bearer credential
+pub fn authorized(request: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+    match token {
+        Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+/// Exchange a username and password for a session
+pub async fn login(username: &str, password: &str) -> Result<Session, ApiError> {
+    let body = serde_json::json!({ "username": username, "password": password });
+    let response = Request::post("/api/auth/login")
+        .json(&body)
+        .map_err(|e| ApiError::Parse(e.to_string()))?
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    parse(response).await
+}
+
+/// Repositories the caller can see, sorted by name
+pub async fn list_repos(token: Option<String>) -> Result<Vec<Repository>, ApiError> {
+    let response = authorized(Request::get("/api/repos?limit=100"), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    let listing: RepositoryListing = parse(response).await?;
+    Ok(listing.repositories)
+}
+
+/// A directory entry as listed by `/api/repos/:name/tree/:ref/*path`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TreeObject {
+    pub name: String,
+    pub path: String,
+    /// `blob`, `tree`, or `commit` for submodules
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TreeListing {
+    /// Missing for repositories without commits
+    #[serde(default)]
+    entries: Vec<TreeObject>,
+}
+
+/// Entries of the directory at `path` in `reference`, directories first
+pub async fn list_tree(
+    token: Option<String>,
+    name: String,
+    reference: String,
+    path: String,
+) -> Result<Vec<TreeObject>, ApiError> {
+    let url = format!("/api/repos/{}/tree/{}/{}", name, reference, path);
+    let response = authorized(Request::get(&url), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    let listing: TreeListing = parse(response).await?;
+    Ok(listing.entries)
+}
+
+/// Instance configuration; owner only
+pub async fn get_settings(token: Option<String>) -> Result<InstanceSettings, ApiError> {
+    let response = authorized(Request::get("/api/settings"), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    parse(response).await
+}
+
+/// Replace the instance configuration, returning what was saved
+pub async fn save_settings(
+    token: Option<String>,
+    settings: InstanceSettings,
+) -> Result<InstanceSettings, ApiError> {
+    let response = authorized(Request::put("/api/settings"), token.as_deref())
+        .json(&settings)
+        .map_err(|e| ApiError::Parse(e.to_string()))?
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    parse(response).await
+}
+
+/// A plugin as listed by `/api/plugins`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PluginSummary {
+    pub name: String,
+    pub plugin_type: PluginType,
+    /// `up`, `down`, or `unknown` before the first health check
+    pub health: String,
+}
+
+#[derive(Deserialize)]
+struct PluginList {
+    plugins: Vec<PluginSummary>,
+}
+
+/// Registered plugins with their last known health, sorted by name; owner only
+pub async fn list_plugins(token: Option<String>) -> Result<Vec<PluginSummary>, ApiError> {
+    let response = authorized(Request::get("/api/plugins"), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    let list: PluginList = parse(response).await?;
+    Ok(list.plugins)
+}
+
+/// A collaborator as listed by `/api/collaborators`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CollaboratorSummary {
+    pub username: String,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+struct CollaboratorList {
+    collaborators: Vec<CollaboratorSummary>,
+}
+
+/// Every collaborator, sorted by username; owner only
+pub async fn list_collaborators(
+    token: Option<String>,
+) -> Result<Vec<CollaboratorSummary>, ApiError> {
+    let response = authorized(Request::get("/api/collaborators"), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    let list: CollaboratorList = parse(response).await?;
+    Ok(list.collaborators)
+}
+
+/// Register a collaborator who logs in with `password`
+pub async fn add_collaborator(
+    token: Option<String>,
+    username: String,
+    email: String,
+    password: String,
+) -> Result<(), ApiError> {
+    let body = serde_json::json!({ "username": username, "email": email, "password": password });
+    let response = authorized(Request::post("/api/auth/register"), token.as_deref())
+        .json(&body)
+        .map_err(|e| ApiError::Parse(e.to_string()))?
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    parse::<serde_json::Value>(response).await.map(|_| ())
+}
+
+pub async fn remove_collaborator(token: Option<String>, username: String) -> Result<(), ApiError> {
+    let path = format!("/api/collaborators/{}", username);
+    let response = authorized(Request::delete(&path), token.as_deref())
+        .send()
+        .await
+        .map_err(|e| ApiError::Network(e.to_string()))?;
+    parse::<serde_json::Value>(response).await.map(|_| ())
+}
+
+/// The JSON body of a successful response, or the API error it carries
+///
+/// Some endpoints report failures as `{ "success": false, ... }` with a 200
+/// status, so the body is checked as well as the status.
+async fn parse<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
+    let body = response.json::<serde_json::Value>().await;
+    let failed = !response.ok() || body.as_ref().is_ok_and(|body| body["success"] == false);
+    if failed {
+        // Error bodies look like `{ "error": { "code", "message" } }`
+        let message = body
+            .ok()
+            .and_then(|body| body["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| response.status_text());
+        return Err(ApiError::Status { status: response.status(), message });
+    }
+    let body = body.map_err(|e| ApiError::Parse(e.to_string()))?;
+    serde_json::from_value(body).map_err(|e| ApiError::Parse(e.to_string()))
+}
@@ -0,0 +1,65 @@
+//! Login state shared by every page
+//!
+//! The session from `/api/auth/login` is kept in `localStorage`, so a
+//! reload doesn't log the user out. [`AuthContext`] is provided by `App`;
+//! pages get it with [`use_auth`] and pass [`AuthContext::token`] to the
+//! `api` helpers, which send it as a bearer token.
+
+use gloo_storage::{LocalStorage, Storage};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+/// `localStorage` key the session is kept under
+const SESSION_KEY: &str = "nimbus.session";
+
+/// A logged-in user, as returned by the login endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub token: String,
+    pub user: String,
+    /// `owner` or `collaborator`
+    pub role: String,
+}
+
+/// The current session, if any
+#[derive(Debug, Clone, Copy)]
+pub struct AuthContext {
+    session: RwSignal<Option<Session>>,
+}
+
+impl AuthContext {
+    /// Context holding the session saved by a previous login, if any
+    pub fn load() -> Self {
+        Self { session: create_rw_signal(LocalStorage::get(SESSION_KEY).ok()) }
+    }
+
+    pub fn session(&self) -> Option<Session> {
+        self.session.get()
+    }
+
+    /// Access token to send with API requests
+    pub fn token(&self) -> Option<String> {
+        self.session.with(|session| session.as_ref().map(|session| session.token.clone()))
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.session.with(Option::is_some)
+    }
+
+    pub fn log_in(&self, session: Session) {
+        if let Err(e) = LocalStorage::set(SESSION_KEY, &session) {
+            log::warn!("Failed to save the session: {}", e);
+        }
+        self.session.set(Some(session));
+    }
+
+    pub fn log_out(&self) {
+        LocalStorage::delete(SESSION_KEY);
+        self.session.set(None);
+    }
+}
+
+/// The [`AuthContext`] provided by `App`
+pub fn use_auth() -> AuthContext {
+    expect_context::<AuthContext>()
+}
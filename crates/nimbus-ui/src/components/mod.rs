@@ -1,2 +1,12 @@
-// Components module - will hold reusable UI components
-// For now, keeping components inline in pages for simplicity
+// Components module - reusable UI components shared by the pages
+use leptos::*;
+
+/// Red banner reporting a failed request
+#[component]
+pub fn ErrorBanner(message: String) -> impl IntoView {
+    view! {
+        <div class="bg-red-50 border border-red-200 text-red-700 px-4 py-3 rounded" role="alert">
+            {message}
+        </div>
+    }
+}
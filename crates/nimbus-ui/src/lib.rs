@@ -2,14 +2,20 @@ use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
 
+mod api;
+mod auth;
 mod components;
 mod pages;
 
-use pages::{Dashboard, RepoDetail, RepoList, Settings};
+use auth::{AuthContext, use_auth};
+use pages::{Dashboard, Login, RepoDetail, RepoList, Settings};
 
 #[component]
 pub fn App() -> impl IntoView {
     provide_meta_context();
+    let auth = AuthContext::load();
+    provide_context(auth);
+    let logged_in = move || auth.is_authenticated();
 
     view! {
         <Stylesheet id="leptos" href="/pkg/nimbus-ui.css"/>
@@ -21,10 +27,16 @@ pub fn App() -> impl IntoView {
             <Nav/>
             <main class="container mx-auto px-4 py-8">
                 <Routes>
-                    <Route path="/" view=Dashboard/>
+                    <Route path="/login" view=Login/>
+                    <ProtectedRoute path="/" redirect_path="/login" condition=logged_in view=Dashboard/>
                     <Route path="/repos" view=RepoList/>
                     <Route path="/repos/:owner/:name" view=RepoDetail/>
-                    <Route path="/settings" view=Settings/>
+                    <ProtectedRoute
+                        path="/settings"
+                        redirect_path="/login"
+                        condition=logged_in
+                        view=Settings
+                    />
                 </Routes>
             </main>
         </Router>
@@ -33,6 +45,28 @@ pub fn App() -> impl IntoView {
 
 #[component]
 fn Nav() -> impl IntoView {
+    let auth = use_auth();
+    let navigate = use_navigate();
+    let log_out = move |_| {
+        auth.log_out();
+        navigate("/login", Default::default());
+    };
+    let account = move || match auth.session() {
+        Some(session) => view! {
+            <span class="text-sm text-gray-400">{session.user}</span>
+            <button class="text-sm hover:text-gray-300" on:click=log_out.clone()>
+                "Log out"
+            </button>
+        }
+        .into_view(),
+        None => view! {
+            <A href="/login" class="text-sm hover:text-gray-300">
+                "Log in"
+            </A>
+        }
+        .into_view(),
+    };
+
     view! {
         <nav class="bg-gray-900 text-white p-4">
             <div class="container mx-auto flex items-center justify-between">
@@ -53,6 +87,7 @@ fn Nav() -> impl IntoView {
                     <span class="text-sm text-gray-400">
                         "Single Owner Instance"
                     </span>
+                    {account}
                 </div>
             </div>
         </nav>
@@ -0,0 +1,70 @@
+use leptos::*;
+use leptos_router::*;
+
+use crate::api;
+use crate::auth::use_auth;
+use crate::components::ErrorBanner;
+
+#[component]
+pub fn Login() -> impl IntoView {
+    let auth = use_auth();
+    let navigate = use_navigate();
+    let (username, set_username) = create_signal(String::new());
+    let (password, set_password) = create_signal(String::new());
+
+    let login = create_action(|(username, password): &(String, String)| {
+        let (username, password) = (username.clone(), password.clone());
+        async move { api::login(&username, &password).await }
+    });
+
+    create_effect(move |_| {
+        if let Some(Ok(session)) = login.value().get() {
+            auth.log_in(session);
+            navigate("/", Default::default());
+        }
+    });
+
+    let on_submit = move |ev: ev::SubmitEvent| {
+        ev.prevent_default();
+        login.dispatch((username.get(), password.get()));
+    };
+    let error = move || {
+        login
+            .value()
+            .get()
+            .and_then(Result::err)
+            .map(|e| view! { <ErrorBanner message=e.to_string()/> })
+    };
+
+    view! {
+        <div class="max-w-sm mx-auto mt-16">
+            <h1 class="text-3xl font-bold mb-6">"Log in"</h1>
+            <form class="bg-white rounded-lg shadow p-6 space-y-4" on:submit=on_submit>
+                {error}
+                <input
+                    type="text"
+                    placeholder="Username"
+                    autocomplete="username"
+                    class="w-full px-3 py-2 border rounded focus:outline-none focus:ring-2 focus:ring-blue-500"
+                    prop:value=username
+                    on:input=move |ev| set_username.set(event_target_value(&ev))
+                />
+                <input
+                    type="password"
+                    placeholder="Password"
+                    autocomplete="current-password"
+                    class="w-full px-3 py-2 border rounded focus:outline-none focus:ring-2 focus:ring-blue-500"
+                    prop:value=password
+                    on:input=move |ev| set_password.set(event_target_value(&ev))
+                />
+                <button
+                    type="submit"
+                    class="w-full bg-blue-600 text-white px-4 py-2 rounded hover:bg-blue-700 disabled:opacity-50"
+                    disabled=move || login.pending().get()
+                >
+                    {move || if login.pending().get() { "Logging in..." } else { "Log in" }}
+                </button>
+            </form>
+        </div>
+    }
+}
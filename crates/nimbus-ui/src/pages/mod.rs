@@ -1,9 +1,11 @@
 mod dashboard;
+mod login;
 mod repo_detail;
 mod repo_list;
 mod settings;
 
 pub use dashboard::Dashboard;
+pub use login::Login;
 pub use repo_detail::RepoDetail;
 pub use repo_list::RepoList;
 pub use settings::Settings;
@@ -1,10 +1,31 @@
 use leptos::*;
 use leptos_router::*;
+use serde::Deserialize;
+
+use crate::api::{self, TreeObject};
+use crate::auth::use_auth;
+use crate::components::ErrorBanner;
+
+/// The part of `GET /api/repos/:name/tree/HEAD/` this page needs
+#[derive(Debug, Clone, Deserialize)]
+struct TreeState {
+    empty: bool,
+}
+
+async fn fetch_tree(name: String) -> Option<TreeState> {
+    let url = format!("/api/repos/{}/tree/HEAD/", name);
+    let response = gloo_net::http::Request::get(&url).send().await.ok()?;
+    if !response.ok() {
+        return None;
+    }
+    response.json().await.ok()
+}
 
 #[component]
 pub fn RepoDetail() -> impl IntoView {
     let params = use_params_map();
     let name = move || params.with(|p| p.get("name").cloned().unwrap_or_default());
+    let tree = create_local_resource(name, fetch_tree);
 
     view! {
         <div>
@@ -22,7 +43,18 @@ pub fn RepoDetail() -> impl IntoView {
 
             <div class="grid grid-cols-1 lg:grid-cols-4 gap-6">
                 <div class="lg:col-span-3">
-                    <FileExplorer/>
+                    <Suspense fallback=|| view! { <p class="text-gray-500">"Loading..."</p> }>
+                        {move || {
+                            tree.get()
+                                .map(|state| {
+                                    if state.is_some_and(|state| state.empty) {
+                                        view! { <EmptyRepository name=name()/> }.into_view()
+                                    } else {
+                                        view! { <FileExplorer name=name()/> }.into_view()
+                                    }
+                                })
+                        }}
+                    </Suspense>
                 </div>
                 <div>
                     <RepoSidebar/>
@@ -46,7 +78,39 @@ fn TabButton(label: &'static str, active: bool) -> impl IntoView {
 }
 
 #[component]
-fn FileExplorer() -> impl IntoView {
+fn EmptyRepository(name: String) -> impl IntoView {
+    let origin = window().location().origin().unwrap_or_default();
+    let remote = format!("git remote add origin {}/{}.git", origin, name);
+
+    view! {
+        <div class="bg-white rounded-lg shadow p-6">
+            <h2 class="text-xl font-semibold mb-2">"This repository is empty"</h2>
+            <p class="text-gray-600 mb-4">"Push to get started:"</p>
+            <pre class="bg-gray-100 rounded p-4 font-mono text-sm overflow-x-auto">
+                {remote}
+                "\ngit push -u origin main"
+            </pre>
+        </div>
+    }
+}
+
+#[component]
+fn FileExplorer(name: String) -> impl IntoView {
+    let auth = use_auth();
+    let entries = create_local_resource(
+        move || (auth.token(), name.clone()),
+        |(token, name)| api::list_tree(token, name, "HEAD".to_string(), String::new()),
+    );
+
+    let rows = move || {
+        entries.get().map(|result| match result {
+            Err(e) => view! { <ErrorBanner message=e.to_string()/> }.into_view(),
+            Ok(entries) => {
+                entries.into_iter().map(|entry| view! { <FileRow entry=entry/> }).collect_view()
+            }
+        })
+    };
+
     view! {
         <div class="bg-white rounded-lg shadow">
             <div class="p-4 border-b bg-gray-50">
@@ -67,10 +131,9 @@ fn FileExplorer() -> impl IntoView {
 
             <div class="p-4">
                 <div class="space-y-2">
-                    <FileRow name="src/" is_dir=true/>
-                    <FileRow name="Cargo.toml" is_dir=false/>
-                    <FileRow name="README.md" is_dir=false/>
-                    <FileRow name=".gitignore" is_dir=false/>
+                    <Suspense fallback=|| view! { <p class="text-gray-500">"Loading..."</p> }>
+                        {rows}
+                    </Suspense>
                 </div>
             </div>
         </div>
@@ -78,8 +141,11 @@ fn FileExplorer() -> impl IntoView {
 }
 
 #[component]
-fn FileRow(name: &'static str, is_dir: bool) -> impl IntoView {
-    let icon = if is_dir { "📁" } else { "📄" };
+fn FileRow(entry: TreeObject) -> impl IntoView {
+    let (icon, name) = match entry.kind.as_str() {
+        "tree" => ("📁", format!("{}/", entry.name)),
+        _ => ("📄", entry.name),
+    };
 
     view! {
         <div class="flex items-center py-2 px-2 hover:bg-gray-50 rounded cursor-pointer">
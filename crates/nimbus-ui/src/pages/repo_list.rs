@@ -1,41 +1,54 @@
 use leptos::*;
 use leptos_router::*;
+use nimbus_types::{Repository, Visibility};
 
-#[derive(Clone, Debug)]
-struct Repo {
-    name: String,
-    description: String,
-    language: String,
-    stars: u32,
-    updated: String,
+use crate::api;
+use crate::auth::use_auth;
+use crate::components::ErrorBanner;
+
+/// Whether `repo` matches the search box, by name or description
+fn matches_search(repo: &Repository, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    query.is_empty()
+        || repo.name.to_lowercase().contains(&query)
+        || repo.description.as_deref().is_some_and(|d| d.to_lowercase().contains(&query))
 }
 
 #[component]
 pub fn RepoList() -> impl IntoView {
-    // Mock data - will be replaced with API calls
-    let repos = vec![
-        Repo {
-            name: "nimbus-git".to_string(),
-            description: "Cloud-native git platform with plugin architecture".to_string(),
-            language: "Rust".to_string(),
-            stars: 42,
-            updated: "2 hours ago".to_string(),
-        },
-        Repo {
-            name: "awesome-project".to_string(),
-            description: "Something awesome built with Rust".to_string(),
-            language: "Rust".to_string(),
-            stars: 128,
-            updated: "1 day ago".to_string(),
-        },
-        Repo {
-            name: "web-experiments".to_string(),
-            description: "WASM and Leptos experiments".to_string(),
-            language: "Rust".to_string(),
-            stars: 15,
-            updated: "3 days ago".to_string(),
-        },
-    ];
+    let auth = use_auth();
+    // Refetched when the user logs in or out, since that changes what's visible
+    let repos = create_local_resource(move || auth.token(), api::list_repos);
+    let (query, set_query) = create_signal(String::new());
+
+    let listing = move || {
+        repos.get().map(|result| match result {
+            Err(e) => {
+                view! { <div class="p-4"><ErrorBanner message=e.to_string()/></div> }.into_view()
+            }
+            Ok(repos) if repos.is_empty() => view! {
+                <p class="p-8 text-center text-gray-500">
+                    "No repositories yet. Create one to get started."
+                </p>
+            }
+            .into_view(),
+            Ok(repos) => {
+                let query = query.get();
+                let matching: Vec<Repository> =
+                    repos.into_iter().filter(|repo| matches_search(repo, &query)).collect();
+                if matching.is_empty() {
+                    view! {
+                        <p class="p-8 text-center text-gray-500">
+                            "No repositories match your search."
+                        </p>
+                    }
+                    .into_view()
+                } else {
+                    matching.into_iter().map(|repo| view! { <RepoItem repo=repo/> }).collect_view()
+                }
+            }
+        })
+    };
 
     view! {
         <div>
@@ -52,18 +65,17 @@ pub fn RepoList() -> impl IntoView {
                         type="text"
                         placeholder="Search repositories..."
                         class="w-full px-3 py-2 border rounded focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        prop:value=query
+                        on:input=move |ev| set_query.set(event_target_value(&ev))
                     />
                 </div>
 
                 <div class="divide-y">
-                    {repos
-                        .into_iter()
-                        .map(|repo| {
-                            view! {
-                                <RepoItem repo=repo/>
-                            }
-                        })
-                        .collect_view()}
+                    <Suspense fallback=move || {
+                        view! { <p class="p-8 text-center text-gray-500">"Loading repositories..."</p> }
+                    }>
+                        {listing}
+                    </Suspense>
                 </div>
             </div>
         </div>
@@ -71,7 +83,7 @@ pub fn RepoList() -> impl IntoView {
 }
 
 #[component]
-fn RepoItem(repo: Repo) -> impl IntoView {
+fn RepoItem(repo: Repository) -> impl IntoView {
     let owner = "owner"; // In single-owner model, this is always the instance owner
 
     view! {
@@ -84,14 +96,21 @@ fn RepoItem(repo: Repo) -> impl IntoView {
                     >
                         {repo.name.clone()}
                     </A>
-                    <p class="text-gray-600 mt-1">{repo.description}</p>
+                    {match repo.visibility {
+                        Visibility::Private => Some("Private"),
+                        Visibility::Internal => Some("Internal"),
+                        Visibility::Public => None,
+                    }
+                        .map(|label| view! {
+                            <span class="ml-2 text-xs border rounded-full px-2 py-0.5 text-gray-600">
+                                {label}
+                            </span>
+                        })}
+                    <p class="text-gray-600 mt-1">
+                        {repo.description.unwrap_or_else(|| "No description".to_string())}
+                    </p>
                     <div class="flex items-center space-x-4 mt-3 text-sm text-gray-500">
-                        <span class="flex items-center">
-                            <span class="w-3 h-3 bg-orange-400 rounded-full mr-1"></span>
-                            {repo.language}
-                        </span>
-                        <span>"⭐ " {repo.stars}</span>
-                        <span>"Updated " {repo.updated}</span>
+                        <span>"Default branch: " {repo.default_branch}</span>
                     </div>
                 </div>
                 <div class="flex space-x-2 ml-4">
@@ -1,56 +1,37 @@
 use leptos::*;
+use nimbus_types::{InstanceSettings, PluginType};
+
+use crate::api::{self, CollaboratorSummary, PluginSummary};
+use crate::auth::use_auth;
+use crate::components::ErrorBanner;
 
 #[component]
 pub fn Settings() -> impl IntoView {
+    let auth = use_auth();
+    let settings = create_local_resource(move || auth.token(), api::get_settings);
+
     view! {
         <div>
             <h1 class="text-3xl font-bold mb-8">"Settings"</h1>
 
             <div class="space-y-6">
                 <SettingsSection title="Instance Configuration">
-                    <SettingRow
-                        label="Instance Domain"
-                        value="code.example.com"
-                        editable=true
-                    />
-                    <SettingRow
-                        label="Owner Email"
-                        value="owner@example.com"
-                        editable=true
-                    />
-                    <SettingRow
-                        label="Instance Name"
-                        value="My Nimbus Instance"
-                        editable=true
-                    />
+                    <Suspense fallback=move || view! { <p class="text-gray-500">"Loading settings..."</p> }>
+                        {move || {
+                            settings.get().map(|result| match result {
+                                Ok(settings) => view! { <InstanceSettingsRows initial=settings/> }.into_view(),
+                                Err(e) => view! { <ErrorBanner message=e.to_string()/> }.into_view(),
+                            })
+                        }}
+                    </Suspense>
                 </SettingsSection>
 
                 <SettingsSection title="Collaborators">
-                    <div class="space-y-3">
-                        <CollaboratorRow name="Alice Developer" email="alice@example.com"/>
-                        <CollaboratorRow name="Bob Reviewer" email="bob@example.com"/>
-                        <button class="text-blue-600 hover:text-blue-800">
-                            "+ Add Collaborator"
-                        </button>
-                    </div>
+                    <Collaborators/>
                 </SettingsSection>
 
                 <SettingsSection title="Plugins">
-                    <PluginRow
-                        name="GitHub Actions Runner"
-                        status="Active"
-                        description="Run GitHub Actions workflows"
-                    />
-                    <PluginRow
-                        name="Claude AI Assistant"
-                        status="Active"
-                        description="AI-powered code review and assistance"
-                    />
-                    <PluginRow
-                        name="Slack Notifications"
-                        status="Inactive"
-                        description="Send notifications to Slack"
-                    />
+                    <Plugins/>
                 </SettingsSection>
 
                 <SettingsSection title="API Keys">
@@ -79,32 +60,191 @@ fn SettingsSection(title: &'static str, children: Children) -> impl IntoView {
     }
 }
 
+/// The editable instance settings, saved as soon as a row is confirmed
+///
+/// Edits show immediately and are rolled back if the server refuses them.
 #[component]
-fn SettingRow(label: &'static str, value: &'static str, editable: bool) -> impl IntoView {
+fn InstanceSettingsRows(initial: InstanceSettings) -> impl IntoView {
+    let auth = use_auth();
+    let settings = create_rw_signal(initial);
+    let error = create_rw_signal(None::<String>);
+
+    let saver = move |apply: fn(&mut InstanceSettings, String)| {
+        Callback::new(move |value: String| {
+            let previous = settings.get_untracked();
+            let mut updated = previous.clone();
+            apply(&mut updated, value);
+            settings.set(updated.clone());
+            error.set(None);
+            let token = auth.token();
+            spawn_local(async move {
+                match api::save_settings(token, updated).await {
+                    Ok(saved) => settings.set(saved),
+                    Err(e) => {
+                        settings.set(previous);
+                        error.set(Some(e.to_string()));
+                    }
+                }
+            });
+        })
+    };
+    let field = move |get: fn(&InstanceSettings) -> &String| {
+        Signal::derive(move || settings.with(|settings| get(settings).clone()))
+    };
+
+    view! {
+        {move || error.get().map(|message| view! { <ErrorBanner message=message/> })}
+        <SettingRow
+            label="Instance Domain"
+            value=field(|s| &s.instance_domain)
+            on_save=saver(|s, value| s.instance_domain = value)
+        />
+        <SettingRow
+            label="Owner Email"
+            value=field(|s| &s.owner_email)
+            on_save=saver(|s, value| s.owner_email = value)
+        />
+        <SettingRow
+            label="Instance Name"
+            value=field(|s| &s.instance_name)
+            on_save=saver(|s, value| s.instance_name = value)
+        />
+    }
+}
+
+/// A setting shown as text until its edit button is pressed
+#[component]
+fn SettingRow(
+    label: &'static str,
+    value: Signal<String>,
+    on_save: Callback<String>,
+) -> impl IntoView {
+    let editing = create_rw_signal(false);
+    let draft = create_rw_signal(String::new());
+
+    let edit = move |_| {
+        draft.set(value.get_untracked());
+        editing.set(true);
+    };
+    let save = move |_| {
+        editing.set(false);
+        let draft = draft.get_untracked();
+        if draft != value.get_untracked() {
+            on_save.call(draft);
+        }
+    };
+    let cancel = move |_| editing.set(false);
+
     view! {
         <div class="flex items-center justify-between py-2">
             <span class="text-gray-700">{label}</span>
             <div class="flex items-center space-x-2">
-                <span class="text-gray-900">{value}</span>
-                {editable.then(|| view! {
-                    <button class="text-gray-500 hover:text-gray-700">
-                        "✏️"
+                <Show
+                    when=move || editing.get()
+                    fallback=move || view! {
+                        <span class="text-gray-900">{value}</span>
+                        <button class="text-gray-500 hover:text-gray-700" on:click=edit>
+                            "✏️"
+                        </button>
+                    }
+                >
+                    <input
+                        type="text"
+                        class="px-2 py-1 border rounded focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        prop:value=draft
+                        on:input=move |ev| draft.set(event_target_value(&ev))
+                    />
+                    <button class="text-blue-600 hover:text-blue-800" on:click=save>
+                        "Save"
+                    </button>
+                    <button class="text-gray-500 hover:text-gray-700" on:click=cancel>
+                        "Cancel"
                     </button>
-                })}
+                </Show>
             </div>
         </div>
     }
 }
 
+/// Registered collaborators, with controls to add and remove them
+#[component]
+fn Collaborators() -> impl IntoView {
+    let auth = use_auth();
+    let collaborators = create_local_resource(move || auth.token(), api::list_collaborators);
+    let error = create_rw_signal(None::<String>);
+    let adding = create_rw_signal(false);
+
+    // Hidden at once; the list is reloaded if the server refuses
+    let remove = Callback::new(move |username: String| {
+        collaborators.update(|list| {
+            if let Some(Ok(list)) = list {
+                list.retain(|collaborator| collaborator.username != username);
+            }
+        });
+        error.set(None);
+        let token = auth.token();
+        spawn_local(async move {
+            if let Err(e) = api::remove_collaborator(token, username).await {
+                error.set(Some(e.to_string()));
+                collaborators.refetch();
+            }
+        });
+    });
+    let added = Callback::new(move |()| {
+        adding.set(false);
+        collaborators.refetch();
+    });
+
+    view! {
+        <div class="space-y-3">
+            {move || error.get().map(|message| view! { <ErrorBanner message=message/> })}
+            <Suspense fallback=move || view! { <p class="text-gray-500">"Loading collaborators..."</p> }>
+                {move || {
+                    collaborators.get().map(|result| match result {
+                        Err(e) => view! { <ErrorBanner message=e.to_string()/> }.into_view(),
+                        Ok(list) if list.is_empty() => {
+                            view! { <p class="text-gray-500">"No collaborators yet."</p> }.into_view()
+                        }
+                        Ok(list) => list
+                            .into_iter()
+                            .map(|collaborator| {
+                                view! { <CollaboratorRow collaborator=collaborator on_remove=remove/> }
+                            })
+                            .collect_view(),
+                    })
+                }}
+            </Suspense>
+            <Show
+                when=move || adding.get()
+                fallback=move || view! {
+                    <button class="text-blue-600 hover:text-blue-800" on:click=move |_| adding.set(true)>
+                        "+ Add Collaborator"
+                    </button>
+                }
+            >
+                <AddCollaboratorForm on_added=added on_cancel=Callback::new(move |()| adding.set(false))/>
+            </Show>
+        </div>
+    }
+}
+
 #[component]
-fn CollaboratorRow(name: &'static str, email: &'static str) -> impl IntoView {
+fn CollaboratorRow(
+    collaborator: CollaboratorSummary,
+    on_remove: Callback<String>,
+) -> impl IntoView {
+    let username = collaborator.username.clone();
+
     view! {
         <div class="flex items-center justify-between py-2 border-b">
             <div>
-                <div class="font-medium">{name}</div>
-                <div class="text-sm text-gray-600">{email}</div>
+                <div class="font-medium">{collaborator.username}</div>
+                <div class="text-sm text-gray-600">{collaborator.email}</div>
             </div>
-            <button class="text-red-600 hover:text-red-800">
+            <button
+                class="text-red-600 hover:text-red-800"
+                on:click=move |_| on_remove.call(username.clone())
+            >
                 "Remove"
             </button>
         </div>
@@ -112,21 +252,114 @@ fn CollaboratorRow(name: &'static str, email: &'static str) -> impl IntoView {
 }
 
 #[component]
-fn PluginRow(name: &'static str, status: &'static str, description: &'static str) -> impl IntoView {
-    let status_class = if status == "Active" { "text-green-600" } else { "text-gray-500" };
+fn AddCollaboratorForm(on_added: Callback<()>, on_cancel: Callback<()>) -> impl IntoView {
+    let auth = use_auth();
+    let username = create_rw_signal(String::new());
+    let email = create_rw_signal(String::new());
+    let password = create_rw_signal(String::new());
+
+    let register = create_action(move |(username, email, password): &(String, String, String)| {
+        let (username, email, password) = (username.clone(), email.clone(), password.clone());
+        let token = auth.token();
+        async move { api::add_collaborator(token, username, email, password).await }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(())) = register.value().get() {
+            on_added.call(());
+        }
+    });
+
+    let on_submit = move |ev: ev::SubmitEvent| {
+        ev.prevent_default();
+        register.dispatch((username.get(), email.get(), password.get()));
+    };
+    let input = |kind: &'static str, placeholder: &'static str, value: RwSignal<String>| {
+        view! {
+            <input
+                type=kind
+                placeholder=placeholder
+                class="w-full px-3 py-2 border rounded focus:outline-none focus:ring-2 focus:ring-blue-500"
+                prop:value=value
+                on:input=move |ev| value.set(event_target_value(&ev))
+            />
+        }
+    };
+
+    view! {
+        <form class="space-y-2 pt-2" on:submit=on_submit>
+            {move || {
+                register
+                    .value()
+                    .get()
+                    .and_then(Result::err)
+                    .map(|e| view! { <ErrorBanner message=e.to_string()/> })
+            }}
+            {input("text", "Username", username)}
+            {input("email", "Email", email)}
+            {input("password", "Initial password", password)}
+            <div class="flex space-x-2">
+                <button
+                    type="submit"
+                    class="bg-blue-600 text-white px-4 py-2 rounded hover:bg-blue-700 disabled:opacity-50"
+                    disabled=move || register.pending().get()
+                >
+                    "Add"
+                </button>
+                <button
+                    type="button"
+                    class="text-gray-500 hover:text-gray-700"
+                    on:click=move |_| on_cancel.call(())
+                >
+                    "Cancel"
+                </button>
+            </div>
+        </form>
+    }
+}
+
+#[component]
+fn Plugins() -> impl IntoView {
+    let auth = use_auth();
+    let plugins = create_local_resource(move || auth.token(), api::list_plugins);
+
+    view! {
+        <Suspense fallback=move || view! { <p class="text-gray-500">"Loading plugins..."</p> }>
+            {move || {
+                plugins.get().map(|result| match result {
+                    Err(e) => view! { <ErrorBanner message=e.to_string()/> }.into_view(),
+                    Ok(list) if list.is_empty() => {
+                        view! { <p class="text-gray-500">"No plugins registered."</p> }.into_view()
+                    }
+                    Ok(list) => list
+                        .into_iter()
+                        .map(|plugin| view! { <PluginRow plugin=plugin/> })
+                        .collect_view(),
+                })
+            }}
+        </Suspense>
+    }
+}
+
+#[component]
+fn PluginRow(plugin: PluginSummary) -> impl IntoView {
+    let (status, status_class) = match plugin.health.as_str() {
+        "up" => ("Up", "text-green-600"),
+        "down" => ("Down", "text-red-600"),
+        _ => ("Not checked yet", "text-gray-500"),
+    };
+    let description = match plugin.plugin_type {
+        PluginType::CiRunner => "CI runner",
+        PluginType::ReviewSystem => "Review system",
+        PluginType::AiReviewer => "AI reviewer",
+    };
 
     view! {
         <div class="flex items-center justify-between py-3 border-b last:border-0">
             <div>
-                <div class="font-medium">{name}</div>
+                <div class="font-medium">{plugin.name}</div>
                 <div class="text-sm text-gray-600">{description}</div>
             </div>
-            <div class="flex items-center space-x-3">
-                <span class=format!("text-sm {}", status_class)>{status}</span>
-                <button class="text-blue-600 hover:text-blue-800">
-                    "Configure"
-                </button>
-            </div>
+            <span class=format!("text-sm {}", status_class)>{status}</span>
         </div>
     }
 }
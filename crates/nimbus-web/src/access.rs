@@ -0,0 +1,98 @@
+//! Repository access checks shared by the API and git transport routes
+//!
+//! Callers send a session JWT or API token as `Bearer <token>`, or as the
+//! password of `Basic` credentials, which is how git clients send it.
+//! Signed-in callers are checked with [`authorize`] and anonymous ones with
+//! [`resolve_repo_access`], so repositories a caller can't see look missing,
+//! and API tokens are further limited to their scopes.
+
+use std::sync::Arc;
+
+use base64::Engine;
+use nimbus_auth::AuthService;
+use nimbus_git::RepositoryStore;
+use nimbus_types::access::{Actor, authorize, resolve_repo_access};
+use nimbus_types::{NimbusError, Permission, Repository};
+
+use crate::{AuthVia, AuthenticatedActor, authenticate};
+
+/// A repository the caller holds the required permission on
+#[derive(Debug, Clone)]
+pub struct RepoAccess {
+    pub repository: Repository,
+    /// `None` for anonymous callers
+    pub caller: Option<AuthenticatedActor>,
+}
+
+/// Token from `Bearer <token>` or the password of `Basic` credentials
+pub fn credential(header: &str) -> Option<String> {
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        return Some(token.trim().to_string());
+    }
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+    Some(password.to_string())
+}
+
+/// The caller behind `auth_header`, `None` if it carries no credential
+///
+/// Credentials that don't verify are `Unauthorized`, not anonymous.
+pub async fn caller(
+    auth_service: &AuthService,
+    auth_header: Option<&str>,
+) -> Result<Option<AuthenticatedActor>, NimbusError> {
+    let Some(token) = auth_header.and_then(credential) else {
+        return Ok(None);
+    };
+    match authenticate(auth_service, &token).await? {
+        Some(actor) => Ok(Some(actor)),
+        None => Err(NimbusError::Unauthorized("Invalid credentials".to_string())),
+    }
+}
+
+/// Check that the caller holds `required` on repository `name`
+pub async fn authorize_repo(
+    store: &Arc<dyn RepositoryStore>,
+    auth_service: &AuthService,
+    auth_header: Option<&str>,
+    name: &str,
+    required: Permission,
+) -> Result<RepoAccess, NimbusError> {
+    let caller = caller(auth_service, auth_header).await?;
+    let repository = authorize_actor(store, caller.as_ref(), name, required).await?;
+    Ok(RepoAccess { repository, caller })
+}
+
+/// Check that `caller` (`None` for anonymous) holds `required` on
+/// repository `name`, returning the repository
+pub async fn authorize_actor(
+    store: &Arc<dyn RepositoryStore>,
+    caller: Option<&AuthenticatedActor>,
+    name: &str,
+    required: Permission,
+) -> Result<Repository, NimbusError> {
+    let store = store.clone();
+    let lookup = name.to_string();
+    let repository = tokio::task::spawn_blocking(move || store.get(&lookup))
+        .await
+        .map_err(|e| NimbusError::Internal(format!("Repository store task failed: {}", e)))??;
+
+    match caller {
+        // The owner's id is `OWNER_ID`, which `authorize` treats as `Admin`
+        Some(caller) => authorize(&repository, caller.id, required)?,
+        None => {
+            resolve_repo_access(&Actor::Anonymous, &repository, required)?;
+        }
+    }
+    if let Some(AuthVia::ApiToken(identity)) = caller.map(|caller| &caller.via)
+        && identity.permission_on(&repository).is_none_or(|held| held < required)
+    {
+        return Err(NimbusError::Forbidden(format!(
+            "Token is not scoped for {:?} access to {}",
+            required, name
+        )));
+    }
+    Ok(repository)
+}
@@ -0,0 +1,107 @@
+//! Owner-only operational routes
+
+use std::sync::Arc;
+
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthService, Role};
+use nimbus_events::{EventMetadata, EventPriority};
+use nimbus_types::events::{Event, EventBus, EventEnvelope};
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::cache::ReadCache;
+use crate::{AuthenticatedActor, json_error, with_scope};
+
+/// Everything the admin routes need
+#[derive(Clone)]
+pub struct AdminContext {
+    pub auth_service: Arc<AuthService>,
+    pub event_bus: Arc<dyn EventBus>,
+    pub cache: Arc<ReadCache>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: AdminContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    simulate_event_route(context.clone()).or(cache_stats_route(context))
+}
+
+fn with_context(
+    context: AdminContext,
+) -> impl Filter<Extract = (AdminContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+fn simulate_event_route(
+    context: AdminContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "admin" / "events" / "simulate")
+        .and(warp::post())
+        .and(with_scope(context.auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(warp::body::json())
+        .and(with_context(context))
+        .and_then(handle_simulate_event)
+}
+
+fn cache_stats_route(
+    context: AdminContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "admin" / "cache")
+        .and(warp::get())
+        .and(with_scope(context.auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(with_context(context))
+        .and_then(handle_cache_stats)
+}
+
+/// Publish an operator-supplied event to the real bus, flagged as simulated
+async fn handle_simulate_event(
+    owner: AuthenticatedActor,
+    event: Event,
+    context: AdminContext,
+) -> Result<Reply, warp::Rejection> {
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: false,
+            simulated: true,
+        },
+    };
+    let id = envelope.id;
+
+    match context.event_bus.publish(envelope).await {
+        Ok(()) => {
+            info!("{} published simulated event {}", owner.name, id);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "success": true, "id": id })),
+                StatusCode::ACCEPTED,
+            ))
+        }
+        Err(e) => {
+            warn!("Failed to publish simulated event: {}", e);
+            Ok(json_error(StatusCode::SERVICE_UNAVAILABLE, "Failed to publish event"))
+        }
+    }
+}
+
+/// Size, hit and miss counts of the git read caches
+async fn handle_cache_stats(
+    _owner: AuthenticatedActor,
+    context: AdminContext,
+) -> Result<Reply, warp::Rejection> {
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "success": true,
+            "caches": context.cache.stats()
+        })),
+        StatusCode::OK,
+    ))
+}
@@ -0,0 +1,276 @@
+//! Session endpoints under `/api/auth`: login, token refresh, logout, token
+//! introspection and API tokens
+//!
+//! Request bodies here carry passwords and tokens, so handlers log who is
+//! asking and never the bodies themselves.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthError, AuthService, Claims, Role};
+use tracing::info;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::errors::{ErrorCode, api_error, error_body};
+use crate::{AuthVia, AuthenticatedActor, json_error, with_authenticated, with_scope};
+
+pub fn routes(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("api").and(warp::path("auth")).and(
+        login_route(auth_service.clone())
+            .or(refresh_route(auth_service.clone()))
+            .or(logout_route(auth_service.clone()))
+            .or(whoami_route(auth_service.clone()))
+            .or(create_token_route(auth_service.clone()))
+            .or(list_tokens_route(auth_service)),
+    )
+}
+
+fn login_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("login")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_auth_service(auth_service))
+        .and_then(handle_login)
+}
+
+fn refresh_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("refresh")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_auth_service(auth_service))
+        .and_then(handle_refresh)
+}
+
+fn logout_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("logout")
+        .and(warp::post())
+        .and(with_authenticated(auth_service.clone()))
+        .and(with_auth_service(auth_service))
+        .and_then(handle_logout)
+}
+
+fn whoami_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("whoami")
+        .and(warp::get())
+        .and(with_authenticated(auth_service))
+        .and_then(handle_whoami)
+}
+
+fn with_auth_service(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = (Arc<AuthService>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || auth_service.clone())
+}
+
+async fn handle_login(
+    body: serde_json::Value,
+    auth_service: Arc<AuthService>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let username =
+        body.get("username").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
+    // Never the body itself: it holds the password
+    info!("Login request for {}", username);
+
+    let password =
+        body.get("password").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
+
+    // Validate login against the owner and collaborators
+    match auth_service.validate_login(username, password).await {
+        Ok(Some(role)) => {
+            // Generate JWT access and refresh tokens
+            match auth_service.generate_token_pair(username, role).await {
+                Ok((token, refresh_token)) => Ok(warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "success": true,
+                        "token": token,
+                        "refresh_token": refresh_token,
+                        "user": username,
+                        "role": role
+                    })),
+                    StatusCode::OK,
+                )),
+                Err(e) => {
+                    info!("Failed to generate token: {}", e);
+                    Ok(api_error(ErrorCode::Internal, "Failed to generate token"))
+                }
+            }
+        }
+        Ok(None) => Ok(api_error(ErrorCode::Unauthorized, "Invalid credentials")),
+        // A weak first owner password is the caller's to fix
+        Err(e @ AuthError::WeakPassword(_)) => Ok(api_error(ErrorCode::from(&e), &e.to_string())),
+        Err(e) => {
+            info!("Login error: {}", e);
+            Ok(api_error(ErrorCode::from(&e), "Authentication service error"))
+        }
+    }
+}
+
+async fn handle_refresh(
+    body: serde_json::Value,
+    auth_service: Arc<AuthService>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refresh_token =
+        body.get("refresh_token").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
+
+    match auth_service.refresh(refresh_token).await {
+        Ok((token, refresh_token)) => Ok(warp::reply::json(&serde_json::json!({
+            "success": true,
+            "token": token,
+            "refresh_token": refresh_token
+        }))),
+        Err(e) => {
+            info!("Token refresh rejected: {}", e);
+            Ok(warp::reply::json(&error_body(ErrorCode::from(&e), "Invalid refresh token")))
+        }
+    }
+}
+
+async fn handle_logout(
+    actor: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // API tokens are revoked by deleting them, not by logging out
+    let AuthVia::Jwt(Claims { jti: Some(jti), exp, .. }) = actor.via else {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Token cannot be revoked"));
+    };
+
+    match auth_service.revoke_token(&jti, exp).await {
+        Ok(()) => {
+            info!("Logged out {}", actor.name);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({
+                    "success": true,
+                    "message": "Logout successful"
+                })),
+                StatusCode::OK,
+            ))
+        }
+        Err(e) => {
+            info!("Failed to revoke token: {}", e);
+            Ok(json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to log out"))
+        }
+    }
+}
+
+/// Who the token belongs to and, for session tokens, how long it has left
+///
+/// API tokens are described by their id, role and scopes.
+async fn handle_whoami(actor: AuthenticatedActor) -> Result<impl warp::Reply, warp::Rejection> {
+    let AuthVia::Jwt(claims) = actor.via else {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "success": true,
+                "sub": actor.name,
+                "role": actor.role,
+                "scopes": actor.scopes
+            })),
+            StatusCode::OK,
+        ));
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "success": true,
+            "sub": claims.sub,
+            "role": claims.role,
+            "iat": claims.iat,
+            "exp": claims.exp,
+            "expires_in": claims.exp.saturating_sub(now)
+        })),
+        StatusCode::OK,
+    ))
+}
+
+fn create_token_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("tokens")
+        .and(warp::post())
+        .and(with_scope(auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(warp::body::json())
+        .and(with_auth_service(auth_service.clone()))
+        .and_then(handle_create_token)
+}
+
+fn list_tokens_route(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("tokens")
+        .and(warp::get())
+        .and(with_scope(auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(with_auth_service(auth_service.clone()))
+        .and_then(handle_list_tokens)
+}
+
+async fn handle_create_token(
+    actor: AuthenticatedActor,
+    body: serde_json::Value,
+    auth_service: Arc<AuthService>,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    let name = body.get("name").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
+    // Tokens requested without scopes can do anything, as before scopes existed
+    let scopes = match body.get("scopes").cloned().map(serde_json::from_value::<Vec<ApiScope>>) {
+        None => vec![ApiScope::Admin],
+        Some(Ok(scopes)) if !scopes.is_empty() => scopes,
+        Some(_) => {
+            return Ok(api_error(
+                ErrorCode::BadRequest,
+                "scopes must be a non-empty list of repo:read, repo:write, events:publish or admin",
+            ));
+        }
+    };
+
+    let token = auth_service.generate_api_key();
+
+    match auth_service.store_api_token(name, &token, &scopes).await {
+        Ok(_) => {
+            info!("{} created API token {} ({})", actor.name, name, ApiScope::join(&scopes));
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({
+                    "success": true,
+                    "name": name,
+                    "token": token,
+                    "scopes": scopes
+                })),
+                StatusCode::OK,
+            ))
+        }
+        Err(e @ AuthError::TokenExists(_)) => Ok(api_error(ErrorCode::from(&e), &e.to_string())),
+        Err(e) => {
+            info!("Failed to store API token: {}", e);
+            Ok(api_error(ErrorCode::from(&e), "Failed to create token"))
+        }
+    }
+}
+
+async fn handle_list_tokens(
+    _actor: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match auth_service.list_api_tokens().await {
+        Ok(tokens) => Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "success": true,
+                "tokens": tokens
+            })),
+            StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Failed to list API tokens: {}", e);
+            Ok(api_error(ErrorCode::from(&e), "Failed to list tokens"))
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! In-memory caching of expensive git reads
+//!
+//! Tree listings, commit logs, READMEs and pull request diffs are cached
+//! under the repository, the commit sha(s) they were computed at and the
+//! request parameters. Content at a sha never changes, so entries only go
+//! stale through force-pushes and deletions; [`CacheInvalidator`] drops a
+//! repository's entries whenever the event bus reports a change to it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use nimbus_git::diff::FileDiff;
+use nimbus_types::events::{Event, EventEnvelope, EventFilter, EventHandler, EventType};
+use prometheus::{IntCounterVec, register_int_counter_vec};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+/// Size and lifetime of each cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Entries kept before the least recently used is evicted; 0 disables caching
+    pub capacity: usize,
+    /// How long an entry is served after it was computed
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { capacity: 1000, ttl: Duration::from_secs(300) }
+    }
+}
+
+impl CacheConfig {
+    /// Config from `NIMBUS_CACHE_CAPACITY` and `NIMBUS_CACHE_TTL_SECS`,
+    /// keeping the defaults for unset values
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring invalid {}={}", name, value);
+            }
+            parsed
+        }
+
+        let defaults = Self::default();
+        Self {
+            capacity: var("NIMBUS_CACHE_CAPACITY").unwrap_or(defaults.capacity),
+            ttl: var("NIMBUS_CACHE_TTL_SECS").map(Duration::from_secs).unwrap_or(defaults.ttl),
+        }
+    }
+}
+
+/// Hit and miss counters shared by every cache, labelled by cache name
+struct CacheMetrics {
+    hits: IntCounterVec,
+    misses: IntCounterVec,
+}
+
+fn cache_metrics() -> &'static CacheMetrics {
+    static METRICS: OnceLock<CacheMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| CacheMetrics {
+        hits: register_int_counter_vec!(
+            "nimbus_cache_hits_total",
+            "Total number of reads served from cache",
+            &["cache"]
+        )
+        .unwrap_or_else(|_| {
+            IntCounterVec::new(
+                prometheus::Opts::new(
+                    "nimbus_cache_hits_total",
+                    "Total number of reads served from cache",
+                ),
+                &["cache"],
+            )
+            .unwrap()
+        }),
+        misses: register_int_counter_vec!(
+            "nimbus_cache_misses_total",
+            "Total number of reads that had to be computed",
+            &["cache"]
+        )
+        .unwrap_or_else(|_| {
+            IntCounterVec::new(
+                prometheus::Opts::new(
+                    "nimbus_cache_misses_total",
+                    "Total number of reads that had to be computed",
+                ),
+                &["cache"],
+            )
+            .unwrap()
+        }),
+    })
+}
+
+/// Counters and occupancy of one cache, as shown on the admin endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub ttl_secs: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    /// Position in the recency order
+    tick: u64,
+}
+
+struct Inner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Keys by last use, least recent first
+    recency: BTreeMap<u64, K>,
+    next_tick: u64,
+}
+
+/// Bounded map evicting the least recently used entry, with a TTL
+pub struct LruCache<K, V> {
+    name: &'static str,
+    config: CacheConfig,
+    inner: Mutex<Inner<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    /// A cache reported as `name` in metrics
+    pub fn new(name: &'static str, config: CacheConfig) -> Self {
+        Self {
+            name,
+            config,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                next_tick: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached value for `key`, if present and not expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let tick = inner.next_tick;
+
+        let value = match inner.entries.get_mut(key) {
+            Some(entry) if entry.inserted.elapsed() <= self.config.ttl => {
+                inner.recency.remove(&entry.tick);
+                inner.recency.insert(tick, key.clone());
+                entry.tick = tick;
+                inner.next_tick += 1;
+                Some(entry.value.clone())
+            }
+            Some(entry) => {
+                let stale = entry.tick;
+                inner.recency.remove(&stale);
+                inner.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        let (counter, metric) = match value {
+            Some(_) => (&self.hits, &cache_metrics().hits),
+            None => (&self.misses, &cache_metrics().misses),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        metric.with_label_values(&[self.name]).inc();
+        value
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        if let Some(previous) =
+            inner.entries.insert(key.clone(), Entry { value, inserted: Instant::now(), tick })
+        {
+            inner.recency.remove(&previous.tick);
+        }
+        inner.recency.insert(tick, key);
+
+        while inner.entries.len() > self.config.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+        }
+    }
+
+    /// Drop every entry whose key doesn't satisfy `keep`
+    pub fn retain(&self, mut keep: impl FnMut(&K) -> bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        inner.entries.retain(|key, _| keep(key));
+        let entries = &inner.entries;
+        inner.recency.retain(|_, key| entries.contains_key(key));
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.len(),
+            capacity: self.config.capacity,
+            ttl_secs: self.config.ttl.as_secs(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What a cached read was computed from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReadKey {
+    pub repository: String,
+    /// Commit sha the read was done at; `<base>..<head>` for diffs
+    pub sha: String,
+    /// The read and its parameters, e.g. `tree:src`
+    pub params: String,
+}
+
+/// The caches for git reads served over the API
+pub struct ReadCache {
+    /// Browsing reads (trees, commit logs, READMEs), serialized
+    pub browse: LruCache<ReadKey, serde_json::Value>,
+    /// Pull request diffs
+    pub diffs: LruCache<ReadKey, Vec<FileDiff>>,
+}
+
+impl ReadCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self { browse: LruCache::new("browse", config), diffs: LruCache::new("diffs", config) }
+    }
+
+    /// Forget everything cached for `repository`
+    pub fn invalidate_repository(&self, repository: &str) {
+        self.browse.retain(|key| key.repository != repository);
+        self.diffs.retain(|key| key.repository != repository);
+    }
+
+    /// Stats per cache, by name
+    pub fn stats(&self) -> BTreeMap<&'static str, CacheStats> {
+        BTreeMap::from([
+            (self.browse.name, self.browse.stats()),
+            (self.diffs.name, self.diffs.stats()),
+        ])
+    }
+}
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+/// Event handler dropping a repository's cached reads when it changes
+pub struct CacheInvalidator {
+    cache: Arc<ReadCache>,
+}
+
+impl CacheInvalidator {
+    pub fn new(cache: Arc<ReadCache>) -> Self {
+        Self { cache }
+    }
+}
+
+#[async_trait]
+impl EventHandler for CacheInvalidator {
+    async fn handle(&self, envelope: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        let repository = match &envelope.event {
+            Event::Push { repository, .. }
+            | Event::BranchDeleted { repository, .. }
+            | Event::TagCreated { repository, .. }
+            | Event::TagDeleted { repository, .. }
+            | Event::RepositoryDeleted { repository } => repository.as_str(),
+            Event::RepositoryCreated { repository } => repository.name.as_str(),
+            _ => return Ok(()),
+        };
+        debug!("Invalidating cached reads for {}", repository);
+        self.cache.invalidate_repository(repository);
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::for_types([EventType::Push, EventType::Tag, EventType::Repository])
+    }
+}
@@ -0,0 +1,170 @@
+//! CI run status: `GET /api/ci/runs/{id}`
+//!
+//! CI plugins report a run with `CiRunStarted` and `CiRunCompleted` events
+//! sharing its id. [`CiRunTracker`] follows both, so clients can ask whether
+//! a run is still going. Runs are only kept in memory, and forgotten on
+//! restart. Callers need `Read` on the run's repository; runs of
+//! repositories they can't see are reported as not found.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use nimbus_auth::AuthService;
+use nimbus_git::RepositoryStore;
+use nimbus_types::events::{CiStatus, Event, EventEnvelope, EventFilter, EventHandler, EventType};
+use nimbus_types::{NimbusError, Permission};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::access::authorize_actor;
+use crate::errors::{ErrorCode, api_error};
+use crate::{AuthenticatedActor, reject, with_authenticated};
+
+/// Where a CI run is at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CiRunStatus {
+    Running,
+    Success,
+    Failure,
+    Cancelled,
+    Timeout,
+}
+
+impl From<&CiStatus> for CiRunStatus {
+    fn from(status: &CiStatus) -> Self {
+        match status {
+            CiStatus::Success => CiRunStatus::Success,
+            CiStatus::Failure => CiRunStatus::Failure,
+            CiStatus::Cancelled => CiRunStatus::Cancelled,
+            CiStatus::Timeout => CiRunStatus::Timeout,
+        }
+    }
+}
+
+/// A CI run as seen through its events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CiRun {
+    pub id: Uuid,
+    pub repository: String,
+    /// Only known from the start event
+    pub branch: Option<String>,
+    pub plugin: String,
+    pub status: CiRunStatus,
+    /// `None` if the completion arrived without a start
+    pub started_at: Option<OffsetDateTime>,
+    pub finished_at: Option<OffsetDateTime>,
+}
+
+/// Event handler recording CI runs by id
+///
+/// Clones share their runs: subscribe one to the bus, query another.
+#[derive(Clone, Default)]
+pub struct CiRunTracker {
+    runs: Arc<Mutex<HashMap<Uuid, CiRun>>>,
+}
+
+impl CiRunTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The run with this id, if any of its events were seen
+    pub fn get(&self, id: Uuid) -> Option<CiRun> {
+        self.runs.lock().unwrap().get(&id).cloned()
+    }
+
+    fn record(&self, envelope: &EventEnvelope) {
+        let mut runs = self.runs.lock().unwrap();
+        match &envelope.event {
+            Event::CiRunStarted { id, repository, branch, plugin } => {
+                debug!("CI run {} started by {}", id, plugin);
+                let run = runs.entry(*id).or_insert_with(|| CiRun {
+                    id: *id,
+                    repository: repository.clone(),
+                    branch: None,
+                    plugin: plugin.clone(),
+                    status: CiRunStatus::Running,
+                    started_at: None,
+                    finished_at: None,
+                });
+                // A late start must not undo a completion already recorded
+                run.branch = Some(branch.clone());
+                run.started_at = Some(envelope.timestamp);
+            }
+            Event::CiRunCompleted { id, repository, status, plugin } => {
+                let run = runs.entry(*id).or_insert_with(|| {
+                    warn!("CI run {} completed without a recorded start", id);
+                    CiRun {
+                        id: *id,
+                        repository: repository.clone(),
+                        branch: None,
+                        plugin: plugin.clone(),
+                        status: CiRunStatus::Running,
+                        started_at: None,
+                        finished_at: None,
+                    }
+                });
+                run.status = status.into();
+                run.finished_at = Some(envelope.timestamp);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for CiRunTracker {
+    async fn handle(&self, envelope: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(&envelope);
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::for_types([EventType::CiRun])
+    }
+}
+
+/// Everything the CI routes need
+#[derive(Clone)]
+pub struct CiContext {
+    pub auth_service: Arc<AuthService>,
+    /// Repository records, for the permission checks
+    pub store: Arc<dyn RepositoryStore>,
+    pub tracker: CiRunTracker,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: CiContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let auth_service = context.auth_service.clone();
+    warp::path!("api" / "ci" / "runs" / Uuid)
+        .and(warp::get())
+        .and(with_authenticated(auth_service))
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_get_run)
+}
+
+async fn handle_get_run(
+    id: Uuid,
+    actor: AuthenticatedActor,
+    context: CiContext,
+) -> Result<Reply, warp::Rejection> {
+    let Some(run) = context.tracker.get(id) else {
+        return Ok(api_error(ErrorCode::NotFound, "CI run not found"));
+    };
+    match authorize_actor(&context.store, Some(&actor), &run.repository, Permission::Read).await {
+        Ok(_) => Ok(warp::reply::with_status(warp::reply::json(&run), StatusCode::OK)),
+        Err(NimbusError::RepositoryNotFound(_)) => {
+            Ok(api_error(ErrorCode::NotFound, "CI run not found"))
+        }
+        Err(e) => Err(reject(e)),
+    }
+}
@@ -0,0 +1,141 @@
+//! Collaborator management, owner only
+//!
+//! `POST /api/auth/register` is how the owner adds a collaborator. It is not
+//! a sign-up form: the caller must hold an owner token. `GET
+//! /api/collaborators` lists them and `DELETE /api/collaborators/{username}`
+//! removes one.
+
+use std::sync::Arc;
+
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthError, AuthService, Role};
+use nimbus_types::Collaborator;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::errors::{ErrorCode, api_error};
+use crate::{AuthenticatedActor, with_scope};
+
+/// Body of a registration request
+#[derive(Debug, Deserialize)]
+pub struct RegisterCollaborator {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// A collaborator as listed to the owner, without keys or token hashes
+#[derive(Debug, Serialize)]
+pub struct CollaboratorSummary {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+}
+
+impl From<Collaborator> for CollaboratorSummary {
+    fn from(collaborator: Collaborator) -> Self {
+        Self { id: collaborator.id, username: collaborator.username, email: collaborator.email }
+    }
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let with_owner = with_scope(auth_service.clone(), Role::Owner, ApiScope::Admin);
+    let with_auth_service = warp::any().map(move || auth_service.clone());
+
+    let register = warp::path!("api" / "auth" / "register")
+        .and(warp::post())
+        .and(with_owner.clone())
+        .and(warp::body::json())
+        .and(with_auth_service.clone())
+        .and_then(handle_register);
+    let list = warp::path!("api" / "collaborators")
+        .and(warp::get())
+        .and(with_owner.clone())
+        .and(with_auth_service.clone())
+        .and_then(handle_list);
+    let remove = warp::path!("api" / "collaborators" / String)
+        .and(warp::delete())
+        .and(with_owner)
+        .and(with_auth_service)
+        .and_then(handle_remove);
+
+    register.or(list).or(remove)
+}
+
+async fn handle_register(
+    owner: AuthenticatedActor,
+    body: RegisterCollaborator,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    match auth_service.register_collaborator(&body.username, &body.email, &body.password).await {
+        Ok(collaborator) => {
+            info!("{} registered collaborator {}", owner.name, collaborator.username);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({
+                    "success": true,
+                    "id": collaborator.id,
+                    "username": collaborator.username
+                })),
+                StatusCode::CREATED,
+            ))
+        }
+        Err(
+            e @ (AuthError::InvalidCollaborator(_)
+            | AuthError::CollaboratorExists(_)
+            | AuthError::WeakPassword(_)),
+        ) => Ok(api_error(ErrorCode::from(&e), &e.to_string())),
+        Err(e) => {
+            warn!("Failed to register collaborator: {}", e);
+            Ok(api_error(ErrorCode::from(&e), "Failed to register collaborator"))
+        }
+    }
+}
+
+async fn handle_list(
+    _owner: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    Ok(match auth_service.list_collaborators().await {
+        Ok(collaborators) => {
+            let mut collaborators: Vec<CollaboratorSummary> =
+                collaborators.into_iter().map(CollaboratorSummary::from).collect();
+            collaborators.sort_by(|a, b| a.username.cmp(&b.username));
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "collaborators": collaborators })),
+                StatusCode::OK,
+            )
+        }
+        Err(e) => {
+            warn!("Failed to list collaborators: {}", e);
+            api_error(ErrorCode::from(&e), "Failed to list collaborators")
+        }
+    })
+}
+
+async fn handle_remove(
+    username: String,
+    owner: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    Ok(match auth_service.remove_collaborator(&username).await {
+        Ok(Some(collaborator)) => {
+            info!("{} removed collaborator {}", owner.name, collaborator.username);
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "success": true })),
+                StatusCode::OK,
+            )
+        }
+        Ok(None) => api_error(ErrorCode::NotFound, "Collaborator not found"),
+        Err(e) => {
+            warn!("Failed to remove collaborator: {}", e);
+            api_error(ErrorCode::from(&e), "Failed to remove collaborator")
+        }
+    })
+}
@@ -0,0 +1,70 @@
+//! Cross-origin access to the API
+//!
+//! Browsers send credentials (the bearer token) cross-origin only to origins
+//! listed here, so the list must be exact rather than `*`. It comes from
+//! `NIMBUS_CORS_ORIGINS`, a comma-separated list such as
+//! `https://code.example.com,https://ci.example.com`. Unset, it is the
+//! instance domain over HTTPS, or the usual local dev servers when the
+//! instance runs on `localhost`.
+
+/// An entry in `NIMBUS_CORS_ORIGINS` that isn't a bare `scheme://host[:port]`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid CORS origin {0:?}: expected scheme://host[:port]")]
+pub struct InvalidOrigin(pub String);
+
+/// Origins of the UI dev servers, used when running on `localhost`
+const DEV_ORIGINS: &[&str] = &[
+    "http://localhost:3000",
+    "http://127.0.0.1:3000",
+    "http://localhost:8080",
+    "http://127.0.0.1:8080",
+];
+
+/// Request headers the API and git routes read
+const ALLOWED_HEADERS: &[&str] = &["authorization", "content-type", "accept", "git-protocol"];
+
+/// Parse a comma-separated origin list, ignoring blanks and trailing slashes
+pub fn parse_origins(value: &str) -> Result<Vec<String>, InvalidOrigin> {
+    value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"))
+                .ok_or_else(|| InvalidOrigin(origin.to_string()))?;
+            let valid = !host.is_empty()
+                && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+            if valid { Ok(origin.to_string()) } else { Err(InvalidOrigin(origin.to_string())) }
+        })
+        .collect()
+}
+
+/// Origins allowed when `NIMBUS_CORS_ORIGINS` is unset
+pub fn default_origins(instance_domain: &str) -> Vec<String> {
+    let host = instance_domain.split(':').next().unwrap_or_default();
+    if matches!(host, "localhost" | "127.0.0.1") {
+        DEV_ORIGINS.iter().map(|origin| origin.to_string()).collect()
+    } else {
+        vec![format!("https://{}", instance_domain)]
+    }
+}
+
+/// Origins from `NIMBUS_CORS_ORIGINS`, or the defaults for `instance_domain`
+pub fn origins_from_env(instance_domain: &str) -> Result<Vec<String>, InvalidOrigin> {
+    match std::env::var("NIMBUS_CORS_ORIGINS") {
+        Ok(value) => parse_origins(&value),
+        Err(_) => Ok(default_origins(instance_domain)),
+    }
+}
+
+/// CORS filter allowing credentialed requests from `origins` only
+pub fn build_cors(origins: &[String]) -> warp::cors::Builder {
+    warp::cors()
+        .allow_origins(origins.iter().map(String::as_str))
+        .allow_methods(["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+        .allow_headers(ALLOWED_HEADERS.iter().copied())
+        .allow_credentials(true)
+        .max_age(3600)
+}
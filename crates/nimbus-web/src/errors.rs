@@ -0,0 +1,190 @@
+//! Stable error codes for API error responses
+//!
+//! Every JSON error reply has the shape
+//!
+//! ```json
+//! { "success": false, "error": { "code": "repo_not_found", "message": "..." } }
+//! ```
+//!
+//! `message` is for people and may change wording at any time. `code` is a
+//! stable contract: clients branch on it, so existing codes are never renamed
+//! or repurposed. New codes may be added; clients should treat an unknown
+//! code like `internal`.
+
+use nimbus_auth::AuthError;
+use nimbus_types::NimbusError;
+use serde::Serialize;
+use warp::http::StatusCode;
+
+/// Machine-readable reason for an API error
+///
+/// The serialized names (see [`ErrorCode::as_str`]) are part of the public
+/// API and must not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// `repo_not_found`: no such repository, or it is hidden from the caller
+    RepoNotFound,
+    /// `ref_not_found`: the branch, tag or sha does not name a commit
+    RefNotFound,
+    /// `path_not_found`: nothing at that path in the tree
+    PathNotFound,
+    /// `not_found`: any other missing resource, such as a pull request
+    NotFound,
+    /// `unauthorized`: no credentials, or credentials that were not accepted
+    Unauthorized,
+    /// `invalid_token`: the token is malformed, expired or of the wrong type
+    InvalidToken,
+    /// `token_revoked`: the token was revoked or already used
+    TokenRevoked,
+    /// `forbidden`: authenticated, but lacking the required permission
+    Forbidden,
+    /// `bad_request`: the request itself is malformed
+    BadRequest,
+    /// `conflict`: the resource already exists
+    Conflict,
+    /// `invalid_git_operation`: the git operation cannot be carried out
+    InvalidGitOperation,
+    /// `plugin_error`: a plugin failed or sent an invalid response
+    PluginError,
+    /// `rate_limited`: too many requests; retry later
+    RateLimited,
+    /// `unavailable`: a backing service is unavailable; retry later
+    Unavailable,
+    /// `internal`: anything else
+    Internal,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 15] = [
+        ErrorCode::RepoNotFound,
+        ErrorCode::RefNotFound,
+        ErrorCode::PathNotFound,
+        ErrorCode::NotFound,
+        ErrorCode::Unauthorized,
+        ErrorCode::InvalidToken,
+        ErrorCode::TokenRevoked,
+        ErrorCode::Forbidden,
+        ErrorCode::BadRequest,
+        ErrorCode::Conflict,
+        ErrorCode::InvalidGitOperation,
+        ErrorCode::PluginError,
+        ErrorCode::RateLimited,
+        ErrorCode::Unavailable,
+        ErrorCode::Internal,
+    ];
+
+    /// The stable string clients match on
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::RepoNotFound => "repo_not_found",
+            ErrorCode::RefNotFound => "ref_not_found",
+            ErrorCode::PathNotFound => "path_not_found",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::TokenRevoked => "token_revoked",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::InvalidGitOperation => "invalid_git_operation",
+            ErrorCode::PluginError => "plugin_error",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// HTTP status replies with this code are sent with
+    ///
+    /// Agrees with [`NimbusError::status_code`] for the codes domain errors
+    /// map to.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::RepoNotFound
+            | ErrorCode::RefNotFound
+            | ErrorCode::PathNotFound
+            | ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Unauthorized | ErrorCode::InvalidToken | ErrorCode::TokenRevoked => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::BadRequest | ErrorCode::InvalidGitOperation => StatusCode::BAD_REQUEST,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::PluginError => StatusCode::BAD_GATEWAY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The generic code for a status, for errors without a more specific one
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::InvalidGitOperation,
+            StatusCode::BAD_GATEWAY => ErrorCode::PluginError,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&NimbusError> for ErrorCode {
+    fn from(e: &NimbusError) -> Self {
+        match e {
+            NimbusError::RepositoryNotFound(_) => ErrorCode::RepoNotFound,
+            NimbusError::RefNotFound(_) => ErrorCode::RefNotFound,
+            NimbusError::PathNotFound(_) => ErrorCode::PathNotFound,
+            NimbusError::Unauthorized(_) => ErrorCode::Unauthorized,
+            NimbusError::Forbidden(_) => ErrorCode::Forbidden,
+            NimbusError::InvalidGitOperation(_) => ErrorCode::InvalidGitOperation,
+            NimbusError::InvalidInput(_) => ErrorCode::BadRequest,
+            NimbusError::PluginError(_) => ErrorCode::PluginError,
+            NimbusError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<&AuthError> for ErrorCode {
+    fn from(e: &AuthError) -> Self {
+        match e {
+            AuthError::InvalidToken(_) | AuthError::WrongTokenType { .. } => {
+                ErrorCode::InvalidToken
+            }
+            AuthError::RefreshTokenRevoked => ErrorCode::TokenRevoked,
+            AuthError::InvalidCollaborator(_) | AuthError::WeakPassword(_) => ErrorCode::BadRequest,
+            AuthError::CollaboratorExists(_) | AuthError::TokenExists(_) | AuthError::SshKey(_) => {
+                ErrorCode::Conflict
+            }
+            AuthError::Backend(_) | AuthError::BackendUnavailable => ErrorCode::Unavailable,
+        }
+    }
+}
+
+/// Body of an error reply: `{ "success": false, "error": { "code", "message" } }`
+pub fn error_body(code: ErrorCode, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "success": false,
+        "error": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
+/// Error reply with `code`, sent with the code's status
+pub fn api_error(code: ErrorCode, message: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&error_body(code, message)), code.status())
+}
@@ -0,0 +1,264 @@
+//! Event history for auditing: `GET /api/events`, owner only
+//!
+//! History comes from the bus's event store, so it holds the events marked
+//! `persistent` and nothing else. Results are newest first; to page back,
+//! pass the timestamp of the oldest event received as `before`.
+//!
+//! Plugins publish with `POST /api/events`, sending a bare [`Event`] and
+//! authenticating with their token from [`PluginCredentials`]. The owner may
+//! publish too, with a session token or an API token holding the
+//! `events:publish` scope.
+//!
+//! `GET /api/events/stream` follows new events live as Server-Sent Events,
+//! one JSON [`EventEnvelope`] per `data:` line, for the owner's activity feed.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthService, Role};
+use nimbus_events::InMemoryEventBus;
+use nimbus_events::store::{EventStore, StoreError};
+use nimbus_types::events::{
+    Event, EventBus, EventEnvelope, EventFilter, EventMetadata, EventPriority, EventType,
+};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::Reply as _;
+use warp::http::StatusCode;
+
+use crate::errors::{ErrorCode, api_error};
+use crate::plugins::{PluginConfig, PluginCredentials};
+use crate::{AuthenticatedActor, with_role, with_scope};
+
+/// Everything the event routes need
+#[derive(Clone)]
+pub struct EventsContext {
+    pub auth_service: Arc<AuthService>,
+    /// `None` when no event store is configured, in which case there is no
+    /// history to serve
+    pub store: Option<Arc<dyn EventStore>>,
+    /// The in-memory bus specifically, as the live stream uses its channel
+    /// subscriptions
+    pub event_bus: Arc<InMemoryEventBus>,
+    pub plugins: Arc<PluginCredentials>,
+}
+
+/// Events returned when no `limit` is given
+const DEFAULT_LIMIT: usize = 50;
+/// Most events returned per request
+const MAX_LIMIT: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Repository name or pattern, as in subscription filters
+    pub repo: Option<String>,
+    /// Event type name, such as `push` or `pull_request`
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+    pub limit: Option<usize>,
+    /// Unix timestamp in seconds; only events strictly before it are returned
+    pub before: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Repository name or pattern, as in subscription filters
+    pub repo: Option<String>,
+    /// Event type name, such as `push` or `pull_request`
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: EventsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let auth_service = context.auth_service.clone();
+    let plugins = context.plugins.clone();
+    let with_context = warp::any().map(move || context.clone());
+
+    let history = warp::path!("api" / "events")
+        .and(warp::get())
+        .and(with_role(auth_service.clone(), Role::Owner))
+        .and(warp::query::<HistoryQuery>())
+        .and(with_context.clone())
+        .and_then(handle_history);
+    let publish = warp::path!("api" / "events")
+        .and(warp::post())
+        .and(with_publisher(plugins, auth_service.clone()))
+        .and(warp::body::json())
+        .and(with_context.clone())
+        .and_then(handle_publish);
+    let stream = warp::path!("api" / "events" / "stream")
+        .and(warp::get())
+        .and(with_role(auth_service, Role::Owner))
+        .and(warp::query::<StreamQuery>())
+        .and(with_context)
+        .and_then(handle_stream);
+
+    history.or(publish).or(stream)
+}
+
+/// Who may publish events
+enum Publisher {
+    Plugin(PluginConfig),
+    /// The owner, holding the `events:publish` scope
+    Owner(AuthenticatedActor),
+}
+
+/// A plugin by its token, otherwise the owner as [`with_scope`] checks them
+fn with_publisher(
+    plugins: Arc<PluginCredentials>,
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = (Publisher,), Error = warp::Rejection> + Clone {
+    // Not a plugin token is "not found", so the owner check's rejection wins
+    let plugin = warp::header::optional::<String>("authorization").and_then(
+        move |header: Option<String>| {
+            let plugins = plugins.clone();
+            async move {
+                header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .and_then(|token| plugins.authenticate(token.trim()))
+                    .cloned()
+                    .ok_or_else(warp::reject::not_found)
+            }
+        },
+    );
+    plugin
+        .map(Publisher::Plugin)
+        .or(with_scope(auth_service, Role::Owner, ApiScope::EventsPublish).map(Publisher::Owner))
+        .unify()
+}
+
+/// Filter for the `repo` and `type` query parameters, or why they are malformed
+fn event_filter(repo: Option<String>, event_type: Option<String>) -> Result<EventFilter, Reply> {
+    let mut filter = EventFilter::default();
+    if let Some(repo) = repo {
+        filter.repositories.push(repo);
+    }
+    if let Some(name) = event_type {
+        let Some(event_type) = EventType::from_name(&name) else {
+            let message = format!("Unknown event type {:?}", name);
+            return Err(api_error(ErrorCode::BadRequest, &message));
+        };
+        filter.event_types.push(event_type);
+    }
+    Ok(filter)
+}
+
+/// The store query a request asks for, or why it is malformed
+fn parse_query(query: HistoryQuery) -> Result<(EventFilter, Option<OffsetDateTime>, usize), Reply> {
+    let filter = event_filter(query.repo, query.event_type)?;
+    let before = match query.before.map(OffsetDateTime::from_unix_timestamp).transpose() {
+        Ok(before) => before,
+        Err(e) => return Err(api_error(ErrorCode::BadRequest, &format!("Invalid before: {}", e))),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    Ok((filter, before, limit))
+}
+
+async fn handle_history(
+    _owner: AuthenticatedActor,
+    query: HistoryQuery,
+    context: EventsContext,
+) -> Result<Reply, warp::Rejection> {
+    let Some(store) = context.store else {
+        return Ok(api_error(ErrorCode::Unavailable, "Event history is not enabled"));
+    };
+    let (filter, before, limit) = match parse_query(query) {
+        Ok(query) => query,
+        Err(reply) => return Ok(reply),
+    };
+
+    Ok(match store.query_events(filter, before, limit).await {
+        Ok(events) => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "events": events })),
+            StatusCode::OK,
+        ),
+        Err(e @ StoreError::InvalidFilter(_)) => api_error(ErrorCode::BadRequest, &e.to_string()),
+        Err(e) => {
+            warn!("Failed to query event history: {}", e);
+            api_error(ErrorCode::Internal, "Failed to query event history")
+        }
+    })
+}
+
+/// Publish an event sent by a plugin or the owner
+async fn handle_publish(
+    publisher: Publisher,
+    event: Event,
+    context: EventsContext,
+) -> Result<Reply, warp::Rejection> {
+    let publisher = match publisher {
+        Publisher::Plugin(plugin) => {
+            if let Err(e) = plugin.authorize(&event) {
+                return Ok(api_error(ErrorCode::Forbidden, &e.to_string()));
+            }
+            format!("Plugin {}", plugin.name)
+        }
+        Publisher::Owner(owner) => owner.name,
+    };
+
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: true,
+            simulated: false,
+        },
+    };
+    let id = envelope.id;
+
+    Ok(match context.event_bus.publish(envelope).await {
+        Ok(()) => {
+            info!("{} published event {}", publisher, id);
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "success": true, "id": id })),
+                StatusCode::ACCEPTED,
+            )
+        }
+        Err(e) => {
+            warn!("Failed to publish event from {}: {}", publisher, e);
+            api_error(ErrorCode::Unavailable, "Failed to publish event")
+        }
+    })
+}
+
+/// Follow events matching the query as they are processed
+///
+/// The bus subscription lives as long as the response stream: once the
+/// client disconnects the receiver is dropped, and the subscription goes
+/// with the next matching event.
+async fn handle_stream(
+    actor: AuthenticatedActor,
+    query: StreamQuery,
+    context: EventsContext,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let filter = match event_filter(query.repo, query.event_type) {
+        Ok(filter) => filter,
+        Err(reply) => return Ok(reply.into_response()),
+    };
+    info!("{} opened the live event stream", actor.name);
+
+    let events = context.event_bus.subscribe_channel(filter).filter_map(|envelope| async move {
+        match warp::sse::Event::default().json_data(&envelope) {
+            Ok(event) => Some(Ok::<_, Infallible>(event)),
+            Err(e) => {
+                warn!("Failed to encode event {} for the stream: {}", envelope.id, e);
+                None
+            }
+        }
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response())
+}
@@ -0,0 +1,160 @@
+//! Rate limits for git fetches and clones
+//!
+//! Serving a pack is far heavier than an API call, so fetches get their own
+//! budget: a number of fetches and a number of pack bytes per window, counted
+//! per user for session tokens, per API token, or per client address for
+//! anonymous requests.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+use uuid::Uuid;
+
+/// Windows kept before expired ones are pruned
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// How long after a fetch starts its pack requests count as part of it
+const FETCH_SESSION: Duration = Duration::from_secs(300);
+
+/// Budget each client gets per window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchLimits {
+    /// Fetches allowed per window
+    pub max_fetches: u32,
+    /// Pack bytes served per window
+    pub max_bytes: u64,
+    pub window: Duration,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self { max_fetches: 60, max_bytes: 2 * 1024 * 1024 * 1024, window: Duration::from_secs(60) }
+    }
+}
+
+impl FetchLimits {
+    /// Limits from `NIMBUS_FETCH_MAX_REQUESTS`, `NIMBUS_FETCH_MAX_BYTES` and
+    /// `NIMBUS_FETCH_WINDOW_SECS`, keeping the defaults for unset values
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring invalid {}={}", name, value);
+            }
+            parsed
+        }
+
+        let defaults = Self::default();
+        Self {
+            max_fetches: var("NIMBUS_FETCH_MAX_REQUESTS").unwrap_or(defaults.max_fetches),
+            max_bytes: var("NIMBUS_FETCH_MAX_BYTES").unwrap_or(defaults.max_bytes),
+            window: var("NIMBUS_FETCH_WINDOW_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.window),
+        }
+    }
+}
+
+/// Who a fetch is counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FetchClient {
+    /// A session token's user, by id
+    User(Uuid),
+    /// An API token, by its id
+    Token(String),
+    /// Anonymous requests, by client address
+    Address(IpAddr),
+    /// Anonymous requests whose address is unknown share one budget
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Usage {
+    started: Instant,
+    fetches: u32,
+    bytes: u64,
+    /// When the client's latest fetch started, kept across windows
+    last_fetch: Option<Instant>,
+}
+
+/// Fixed-window fetch counters per client
+#[derive(Debug)]
+pub struct FetchLimiter {
+    limits: FetchLimits,
+    usage: Mutex<HashMap<FetchClient, Usage>>,
+}
+
+impl FetchLimiter {
+    pub fn new(limits: FetchLimits) -> Self {
+        Self { limits, usage: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn limits(&self) -> FetchLimits {
+        self.limits
+    }
+
+    /// Count a new fetch for `client`, as a ref advertisement starts one
+    ///
+    /// Returns how long until the window resets if the client is over budget.
+    pub fn start_fetch(&self, client: &FetchClient) -> Result<(), Duration> {
+        self.with_usage(client, |_, _| true)
+    }
+
+    /// Check `client` may make a pack request
+    ///
+    /// Requests shortly after a fetch started are part of it, and only
+    /// limited by the byte budget. Any other is counted as a new fetch, so
+    /// skipping the ref advertisement doesn't skip the budget.
+    pub fn continue_fetch(&self, client: &FetchClient) -> Result<(), Duration> {
+        self.with_usage(client, |usage, now| {
+            usage.last_fetch.is_none_or(|last| now.duration_since(last) >= FETCH_SESSION)
+        })
+    }
+
+    /// Add pack bytes served to `client`
+    pub fn record_bytes(&self, client: &FetchClient, bytes: u64) {
+        if let Some(usage) = self.usage.lock().unwrap().get_mut(client) {
+            usage.bytes = usage.bytes.saturating_add(bytes);
+        }
+    }
+
+    /// Check `client`'s budget, counting a new fetch if `new_fetch` says so
+    fn with_usage(
+        &self,
+        client: &FetchClient,
+        new_fetch: impl FnOnce(&Usage, Instant) -> bool,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let window = self.limits.window;
+        let mut usage = self.usage.lock().unwrap();
+
+        if usage.len() >= PRUNE_THRESHOLD {
+            usage.retain(|_, entry| {
+                now.duration_since(entry.started) < window
+                    || entry.last_fetch.is_some_and(|last| now.duration_since(last) < FETCH_SESSION)
+            });
+        }
+
+        let fresh = |last_fetch| Usage { started: now, fetches: 0, bytes: 0, last_fetch };
+        let entry = usage.entry(client.clone()).or_insert_with(|| fresh(None));
+        if now.duration_since(entry.started) >= window {
+            *entry = fresh(entry.last_fetch);
+        }
+
+        let new_fetch = new_fetch(entry, now);
+        if entry.bytes >= self.limits.max_bytes
+            || (new_fetch && entry.fetches >= self.limits.max_fetches)
+        {
+            return Err(window.saturating_sub(now.duration_since(entry.started)));
+        }
+        if new_fetch {
+            entry.fetches += 1;
+            entry.last_fetch = Some(now);
+        }
+        Ok(())
+    }
+}
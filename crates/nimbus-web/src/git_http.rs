@@ -0,0 +1,409 @@
+//! Git smart HTTP routes: `/<repo>.git/info/refs` and the pack endpoints
+//!
+//! Every request is checked against the repository's permissions with
+//! [`authorize_repo`]: fetching needs `Read`, pushing `Write`. Anonymous callers
+//! can fetch public repositories; anything else gets a 401 with a `Basic`
+//! challenge so git asks for credentials.
+//!
+//! Packs are streamed to the client as git produces them. Only fetches count
+//! against the [`FetchLimiter`].
+//!
+//! Pushes are checked against the [`PushPolicy`] before git sees them (see
+//! [`nimbus_git::push`]); a push breaking it is refused as a whole, with the
+//! reasons in receive-pack's status report. Accepted updates are published
+//! as events.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use nimbus_auth::AuthService;
+use nimbus_events::{EventMetadata, EventPriority};
+use nimbus_git::policy::PushPolicy;
+use nimbus_git::push::{ReceiveRequest, check_push, push_events, rejection_report};
+use nimbus_git::signing::Keyring;
+use nimbus_git::smart_http::{self, ProtocolVersion, Service, ServiceOutput};
+use nimbus_git::{GitStorage, RepositoryStore};
+use nimbus_types::events::{Event, EventBus, EventEnvelope};
+use nimbus_types::{NimbusError, Permission};
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+
+use crate::access::{authorize_repo, credential};
+use crate::fetch_limit::{FetchClient, FetchLimiter};
+use crate::server::ClientAddr;
+use crate::{AuthVia, AuthenticatedActor, error_status};
+
+/// Largest request body accepted by the pack endpoints
+const MAX_PACK_REQUEST: u64 = 64 * 1024 * 1024;
+
+/// Bytes of git output read at a time when streaming a response
+const STREAM_CHUNK: usize = 64 * 1024;
+
+/// Everything the git transport routes need
+#[derive(Clone)]
+pub struct GitContext {
+    pub storage: Arc<GitStorage>,
+    /// Repository records, for the permission checks
+    pub store: Arc<dyn RepositoryStore>,
+    pub auth_service: Arc<AuthService>,
+    pub fetch_limiter: Arc<FetchLimiter>,
+    /// Policy pushes are checked against
+    pub push_policy: Arc<PushPolicy>,
+    /// Keys signatures of pushed commits are verified against
+    pub keyring: Keyring,
+    pub event_bus: Arc<dyn EventBus>,
+}
+
+pub fn routes(
+    context: GitContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    info_refs_route(context.clone())
+        .or(upload_pack_route(context.clone()))
+        .or(receive_pack_route(context))
+}
+
+fn with_context(
+    context: GitContext,
+) -> impl Filter<Extract = (GitContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+fn info_refs_route(
+    context: GitContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(String / "info" / "refs")
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional::<String>("git-protocol"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::ext::optional::<ClientAddr>())
+        .and(with_context(context))
+        .and_then(handle_info_refs)
+}
+
+fn upload_pack_route(
+    context: GitContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(String / "git-upload-pack")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("git-protocol"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(MAX_PACK_REQUEST))
+        .and(warp::body::bytes())
+        .and(warp::ext::optional::<ClientAddr>())
+        .and(with_context(context))
+        .and_then(handle_upload_pack)
+}
+
+fn receive_pack_route(
+    context: GitContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(String / "git-receive-pack")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("git-protocol"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(MAX_PACK_REQUEST))
+        .and(warp::body::bytes())
+        .and(with_context(context))
+        .and_then(handle_receive_pack)
+}
+
+/// Count session callers by user, API tokens by id, anyone else by address
+fn fetch_client(caller: Option<&AuthenticatedActor>, addr: Option<ClientAddr>) -> FetchClient {
+    match caller {
+        Some(AuthenticatedActor { via: AuthVia::ApiToken(identity), .. }) => {
+            FetchClient::Token(identity.token_id.clone())
+        }
+        Some(caller) => FetchClient::User(caller.id),
+        None => {
+            addr.map_or(FetchClient::Unknown, |ClientAddr(addr)| FetchClient::Address(addr.ip()))
+        }
+    }
+}
+
+/// Permission a caller needs to use `service`
+fn required_permission(service: Service) -> Permission {
+    match service {
+        Service::UploadPack => Permission::Read,
+        Service::ReceivePack => Permission::Write,
+    }
+}
+
+/// Check that the caller may use `service` on repository `name`
+///
+/// Returns the caller, `None` if anonymous. Anonymous callers who can't
+/// use it are asked for credentials; missing and private repositories look
+/// the same to them.
+async fn authorize_service(
+    context: &GitContext,
+    auth_header: Option<&str>,
+    name: &str,
+    service: Service,
+) -> Result<Option<AuthenticatedActor>, Response<Body>> {
+    let required = required_permission(service);
+    let anonymous = auth_header.and_then(credential).is_none();
+    match authorize_repo(&context.store, &context.auth_service, auth_header, name, required).await {
+        Ok(access) => Ok(access.caller),
+        Err(NimbusError::RepositoryNotFound(_) | NimbusError::Forbidden(_)) if anonymous => {
+            Err(unauthorized())
+        }
+        Err(NimbusError::Unauthorized(_)) => Err(unauthorized()),
+        Err(e) => Err(service_error(e)),
+    }
+}
+
+/// Repository name from a `<name>.git` (or bare `<name>`) path segment
+fn repository_name(segment: &str) -> &str {
+    segment.strip_suffix(".git").unwrap_or(segment)
+}
+
+fn git_response(content_type: String, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .header("content-type", content_type)
+        .header("cache-control", "no-cache")
+        .body(body.into())
+        .expect("static headers are valid")
+}
+
+fn git_error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain")
+        .body(format!("{}\n", message).into())
+        .expect("static headers are valid")
+}
+
+/// Challenge prompting git for credentials
+fn unauthorized() -> Response<Body> {
+    let mut response = git_error(StatusCode::UNAUTHORIZED, "Authentication required");
+    response
+        .headers_mut()
+        .insert("www-authenticate", "Basic realm=\"nimbus\"".parse().expect("static value"));
+    response
+}
+
+fn throttled(retry_after: Duration) -> Response<Body> {
+    let mut response = git_error(StatusCode::TOO_MANY_REQUESTS, "Fetch rate limit exceeded");
+    let seconds = retry_after.as_secs().max(1).to_string();
+    response.headers_mut().insert("retry-after", seconds.parse().expect("digits are valid"));
+    response
+}
+
+fn service_error(e: NimbusError) -> Response<Body> {
+    match e {
+        NimbusError::RepositoryNotFound(_)
+        | NimbusError::Forbidden(_)
+        | NimbusError::Unauthorized(_) => git_error(error_status(&e), &e.to_string()),
+        other => {
+            warn!("Git transport error: {}", other);
+            git_error(StatusCode::INTERNAL_SERVER_ERROR, "Git transport error")
+        }
+    }
+}
+
+async fn handle_info_refs(
+    segment: String,
+    query: HashMap<String, String>,
+    git_protocol: Option<String>,
+    auth_header: Option<String>,
+    addr: Option<ClientAddr>,
+    context: GitContext,
+) -> Result<Response<Body>, warp::Rejection> {
+    let Some(service) = query.get("service").and_then(|name| Service::from_name(name)) else {
+        return Ok(git_error(StatusCode::FORBIDDEN, "Only the smart HTTP protocol is supported"));
+    };
+    let name = repository_name(&segment).to_string();
+
+    // Checked first, so the unauthenticated probe git makes before sending
+    // credentials doesn't use up the budget
+    let caller = match authorize_service(&context, auth_header.as_deref(), &name, service).await {
+        Ok(caller) => caller,
+        Err(response) => return Ok(response),
+    };
+    // A clone or fetch normally starts with one ref advertisement
+    if service == Service::UploadPack {
+        let client = fetch_client(caller.as_ref(), addr);
+        if let Err(retry_after) = context.fetch_limiter.start_fetch(&client) {
+            warn!("Throttling fetches from {:?}", client);
+            return Ok(throttled(retry_after));
+        }
+    }
+    let storage = context.storage;
+
+    let version = ProtocolVersion::from_header(git_protocol.as_deref());
+    let result = tokio::task::spawn_blocking(move || {
+        smart_http::advertise_refs(&storage, &name, service, version)
+    })
+    .await
+    .map_err(|e| NimbusError::Internal(format!("Git task failed: {}", e)));
+
+    Ok(match result.and_then(|advert| advert) {
+        Ok(body) => git_response(service.advertisement_content_type(), body),
+        Err(e) => service_error(e),
+    })
+}
+
+async fn handle_upload_pack(
+    segment: String,
+    git_protocol: Option<String>,
+    auth_header: Option<String>,
+    request: Bytes,
+    addr: Option<ClientAddr>,
+    context: GitContext,
+) -> Result<Response<Body>, warp::Rejection> {
+    let service = Service::UploadPack;
+    let name = repository_name(&segment).to_string();
+    let caller = match authorize_service(&context, auth_header.as_deref(), &name, service).await {
+        Ok(caller) => caller,
+        Err(response) => return Ok(response),
+    };
+    let client = fetch_client(caller.as_ref(), addr);
+    if let Err(retry_after) = context.fetch_limiter.continue_fetch(&client) {
+        warn!("Throttling fetches from {:?}", client);
+        return Ok(throttled(retry_after));
+    }
+    let storage = context.storage;
+    let version = ProtocolVersion::from_header(git_protocol.as_deref());
+    // Wait for git's first output, so a request it rejects outright still
+    // gets an error status
+    let result = tokio::task::spawn_blocking(move || {
+        let mut output = smart_http::stateless_rpc(&storage, &name, service, version, &request)?;
+        let first =
+            read_chunk(&mut output).map_err(|e| NimbusError::InvalidGitOperation(e.to_string()))?;
+        Ok((output, first))
+    })
+    .await
+    .map_err(|e| NimbusError::Internal(format!("Git task failed: {}", e)));
+
+    Ok(match result.and_then(|started| started) {
+        Ok((output, first)) => {
+            let body = stream_output(output, first, context.fetch_limiter, client);
+            git_response(service.result_content_type(), body)
+        }
+        Err(e) => service_error(e),
+    })
+}
+
+async fn handle_receive_pack(
+    segment: String,
+    git_protocol: Option<String>,
+    auth_header: Option<String>,
+    request: Bytes,
+    context: GitContext,
+) -> Result<Response<Body>, warp::Rejection> {
+    let service = Service::ReceivePack;
+    let name = repository_name(&segment).to_string();
+    let pusher = match authorize_service(&context, auth_header.as_deref(), &name, service).await {
+        Ok(Some(caller)) => caller.name,
+        // Anonymous callers never hold Write
+        Ok(None) => return Ok(unauthorized()),
+        Err(response) => return Ok(response),
+    };
+    let commands = match ReceiveRequest::parse(&request) {
+        Ok(commands) => commands,
+        Err(e) => return Ok(git_error(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    let GitContext { storage, push_policy, keyring, event_bus, .. } = context;
+    let version = ProtocolVersion::from_header(git_protocol.as_deref());
+    let result = tokio::task::spawn_blocking(move || {
+        let checked = check_push(&storage, &name, &commands, &request, &push_policy)?;
+        if checked.iter().any(|update| !update.violations.is_empty()) {
+            info!("Refused a push to {} by {}", name, pusher);
+            if !commands.wants("report-status") {
+                return Err(NimbusError::Forbidden("Push rejected by policy".to_string()));
+            }
+            return Ok((rejection_report(&commands, &checked), Vec::new()));
+        }
+
+        // The status report is small, so it is sent in one piece
+        let mut output = smart_http::stateless_rpc(&storage, &name, service, version, &request)?;
+        let mut report = Vec::new();
+        output
+            .read_to_end(&mut report)
+            .map_err(|e| NimbusError::InvalidGitOperation(e.to_string()))?;
+        let events = push_events(&storage, &name, &checked, &pusher, &keyring)?;
+        Ok((report, events))
+    })
+    .await
+    .map_err(|e| NimbusError::Internal(format!("Git task failed: {}", e)));
+
+    let (report, events) = match result.and_then(|pushed| pushed) {
+        Ok(pushed) => pushed,
+        Err(e) => return Ok(service_error(e)),
+    };
+    for event in events {
+        publish(event_bus.as_ref(), event).await;
+    }
+    Ok(git_response(service.result_content_type(), report))
+}
+
+async fn publish(event_bus: &dyn EventBus, event: Event) {
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: true,
+            simulated: false,
+        },
+    };
+    if let Err(e) = event_bus.publish(envelope).await {
+        warn!("Failed to publish push event: {}", e);
+    }
+}
+
+/// Up to [`STREAM_CHUNK`] bytes of git's output; empty once it has ended
+fn read_chunk(output: &mut ServiceOutput) -> std::io::Result<Bytes> {
+    let mut chunk = vec![0; STREAM_CHUNK];
+    let read = output.read(&mut chunk)?;
+    chunk.truncate(read);
+    Ok(Bytes::from(chunk))
+}
+
+/// Response body streaming the rest of `output` after `first`, counting
+/// the bytes sent against `client`
+///
+/// If git fails part way the body ends with an error, which aborts the
+/// response rather than leaving the client with a silently truncated pack.
+fn stream_output(
+    mut output: ServiceOutput,
+    first: Bytes,
+    limiter: Arc<FetchLimiter>,
+    client: FetchClient,
+) -> Body {
+    let (sender, receiver) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let mut chunk = Ok(first);
+        loop {
+            match chunk {
+                Ok(bytes) if bytes.is_empty() => break,
+                Ok(bytes) => {
+                    limiter.record_bytes(&client, bytes.len() as u64);
+                    if sender.blocking_send(Ok(bytes)).is_err() {
+                        // The client went away; dropping the output stops git
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("Git transport error while streaming: {}", e);
+                    let _ = sender.blocking_send(Err(e));
+                    break;
+                }
+            }
+            chunk = read_chunk(&mut output);
+        }
+    });
+    Body::wrap_stream(futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }))
+}
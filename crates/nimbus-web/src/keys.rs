@@ -0,0 +1,155 @@
+//! SSH key management for the calling collaborator
+//!
+//! Keys are submitted as OpenSSH public key lines, as found in
+//! `~/.ssh/id_ed25519.pub`. A key can belong to one collaborator only, since
+//! the SSH server identifies the pusher by it.
+
+use std::sync::Arc;
+
+use nimbus_auth::ssh_keys::parse_ssh_public_key;
+use nimbus_auth::{AuthError, AuthService, Role};
+use serde::Deserialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::errors::{ErrorCode, api_error};
+use crate::{AuthVia, AuthenticatedActor, json_error, with_role};
+
+/// Body of an add-key request
+#[derive(Debug, Deserialize)]
+pub struct AddKey {
+    /// The public key line
+    pub key: String,
+    /// Label for the key; defaults to the key's comment
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let with_collaborator = with_role(auth_service.clone(), Role::Collaborator);
+    let with_auth_service = warp::any().map(move || auth_service.clone());
+
+    let list = warp::path!("api" / "keys")
+        .and(warp::get())
+        .and(with_collaborator.clone())
+        .and(with_auth_service.clone())
+        .and_then(handle_list);
+    let add = warp::path!("api" / "keys")
+        .and(warp::post())
+        .and(with_collaborator.clone())
+        .and(warp::body::json())
+        .and(with_auth_service.clone())
+        .and_then(handle_add);
+    let remove = warp::path!("api" / "keys" / Uuid)
+        .and(warp::delete())
+        .and(with_collaborator)
+        .and(with_auth_service)
+        .and_then(handle_remove);
+
+    list.or(add).or(remove)
+}
+
+/// Username of the calling collaborator; the owner's keys aren't managed here
+///
+/// API tokens name the token rather than the collaborator, so keys are
+/// managed with a session token.
+fn collaborator_username(actor: &AuthenticatedActor) -> Result<&str, Reply> {
+    if actor.role != Role::Collaborator {
+        return Err(json_error(StatusCode::FORBIDDEN, "Only collaborators have SSH keys"));
+    }
+    match &actor.via {
+        AuthVia::Jwt(claims) => Ok(&claims.sub),
+        AuthVia::ApiToken(_) => {
+            Err(json_error(StatusCode::FORBIDDEN, "SSH keys are managed with a session token"))
+        }
+    }
+}
+
+fn auth_error(e: AuthError) -> Reply {
+    match e {
+        AuthError::InvalidCollaborator(_) | AuthError::SshKey(_) => {
+            api_error(ErrorCode::from(&e), &e.to_string())
+        }
+        other => {
+            warn!("SSH key operation failed: {}", other);
+            api_error(ErrorCode::from(&other), "Failed to update SSH keys")
+        }
+    }
+}
+
+async fn handle_list(
+    actor: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    let username = match collaborator_username(&actor) {
+        Ok(username) => username,
+        Err(reply) => return Ok(reply),
+    };
+
+    Ok(match auth_service.find_collaborator(username).await {
+        Ok(collaborator) => {
+            let keys = collaborator.map(|collaborator| collaborator.ssh_keys).unwrap_or_default();
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "keys": keys })),
+                StatusCode::OK,
+            )
+        }
+        Err(e) => auth_error(e),
+    })
+}
+
+async fn handle_add(
+    actor: AuthenticatedActor,
+    body: AddKey,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    let username = match collaborator_username(&actor) {
+        Ok(username) => username,
+        Err(reply) => return Ok(reply),
+    };
+
+    let mut key = match parse_ssh_public_key(&body.key) {
+        Ok(key) => key,
+        Err(e) => return Ok(api_error(ErrorCode::BadRequest, &e.to_string())),
+    };
+    if let Some(name) = body.name.filter(|name| !name.trim().is_empty()) {
+        key.name = name;
+    }
+
+    Ok(match auth_service.add_ssh_key(username, key).await {
+        Ok(key) => {
+            info!("{} added SSH key {}", username, key.fingerprint);
+            warp::reply::with_status(warp::reply::json(&key), StatusCode::CREATED)
+        }
+        Err(e) => auth_error(e),
+    })
+}
+
+async fn handle_remove(
+    id: Uuid,
+    actor: AuthenticatedActor,
+    auth_service: Arc<AuthService>,
+) -> Result<Reply, warp::Rejection> {
+    let username = match collaborator_username(&actor) {
+        Ok(username) => username,
+        Err(reply) => return Ok(reply),
+    };
+
+    Ok(match auth_service.remove_ssh_key(username, id).await {
+        Ok(Some(key)) => {
+            info!("{} removed SSH key {}", username, key.fingerprint);
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "success": true, "key": key })),
+                StatusCode::OK,
+            )
+        }
+        Ok(None) => api_error(ErrorCode::NotFound, "SSH key not found"),
+        Err(e) => auth_error(e),
+    })
+}
@@ -2,6 +2,263 @@
 //!
 //! REST API implementation using Warp
 
-pub fn placeholder() {
-    // Placeholder until we implement web API
+use std::sync::Arc;
+
+use nimbus_auth::api_tokens::{ApiScope, TokenIdentity, scopes_cover};
+use nimbus_auth::{AuthService, Claims, Role};
+use nimbus_types::NimbusError;
+use nimbus_types::access::{Actor, OWNER_ID};
+use tracing::warn;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+use crate::errors::{ErrorCode, error_body};
+
+pub mod access;
+pub mod admin;
+pub mod auth;
+pub mod cache;
+pub mod ci;
+pub mod collaborators;
+pub mod cors;
+pub mod errors;
+pub mod events;
+pub mod fetch_limit;
+pub mod git_http;
+pub mod keys;
+pub mod logging;
+pub mod metrics;
+pub mod pagination;
+pub mod plugin_delivery;
+pub mod plugin_registry;
+pub mod plugins;
+pub mod pulls;
+pub mod repos;
+pub mod repositories;
+pub mod server;
+pub mod settings;
+
+/// How a caller proved who they are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthVia {
+    /// A session token from `/api/auth/login`, with its claims
+    Jwt(Claims),
+    /// An `nmbs_` API token, limited to its scopes
+    ApiToken(TokenIdentity),
+}
+
+/// The caller behind a session token or an API token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedActor {
+    /// [`OWNER_ID`] for the owner, otherwise the collaborator's id
+    pub id: Uuid,
+    /// Username for session tokens, the token id for API tokens; for logs
+    pub name: String,
+    pub role: Role,
+    pub via: AuthVia,
+    /// What the credential may be used for; every scope for session tokens
+    pub scopes: Vec<ApiScope>,
+}
+
+impl AuthenticatedActor {
+    /// Whether the caller can do what `minimum` can; the owner can do anything
+    pub fn has_role(&self, minimum: Role) -> bool {
+        self.role == Role::Owner || self.role == minimum
+    }
+
+    /// Whether the credential allows what `scope` does; the role still applies
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        scopes_cover(&self.scopes, scope)
+    }
+
+    /// The caller as the repository access rules see them
+    pub fn actor(&self) -> Actor {
+        match self.role {
+            Role::Owner => Actor::Owner,
+            Role::Collaborator => Actor::Collaborator { id: self.id },
+        }
+    }
+}
+
+/// Resolve a bearer credential, either a session JWT or an API token
+///
+/// `Ok(None)` for credentials that are invalid, expired or revoked, and for
+/// session tokens of collaborators who have since been removed.
+pub async fn authenticate(
+    auth_service: &AuthService,
+    token: &str,
+) -> Result<Option<AuthenticatedActor>, NimbusError> {
+    if let Ok(claims) = auth_service.validate_token(token) {
+        let Some(role) = claims.parsed_role() else {
+            return Ok(None);
+        };
+        let id = match role {
+            Role::Owner => Some(OWNER_ID),
+            Role::Collaborator => auth_service
+                .find_collaborator(&claims.sub)
+                .await
+                .map_err(|e| NimbusError::Internal(format!("Failed to load collaborator: {}", e)))?
+                .map(|collaborator| collaborator.id),
+        };
+        return Ok(id.map(|id| AuthenticatedActor {
+            id,
+            name: claims.sub.clone(),
+            role,
+            via: AuthVia::Jwt(claims),
+            scopes: ApiScope::ALL.to_vec(),
+        }));
+    }
+
+    let identity = auth_service
+        .verify_api_token(token)
+        .await
+        .map_err(|e| NimbusError::Internal(format!("Failed to verify token: {}", e)))?;
+    Ok(identity.and_then(|identity| {
+        let (id, role) = match identity.actor {
+            Actor::Owner => (OWNER_ID, Role::Owner),
+            Actor::Collaborator { id } => (id, Role::Collaborator),
+            Actor::Anonymous => return None,
+        };
+        let name = identity.token_id.clone();
+        let scopes = identity.api_scopes.clone();
+        Some(AuthenticatedActor { id, name, role, via: AuthVia::ApiToken(identity), scopes })
+    }))
+}
+
+/// Rejection for requests without a valid access token
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// The caller behind a `Bearer` session JWT or API token, `None` without one
+///
+/// Credentials that don't verify are rejected with [`Unauthorized`] rather
+/// than treated as anonymous.
+pub fn with_caller(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = (Option<AuthenticatedActor>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let auth_service = auth_service.clone();
+        async move {
+            let token = header.as_deref().and_then(|header| header.strip_prefix("Bearer "));
+            let Some(token) = token else {
+                return Ok(None);
+            };
+            match authenticate(&auth_service, token.trim()).await {
+                Ok(Some(actor)) => Ok(Some(actor)),
+                Ok(None) => Err(warp::reject::custom(Unauthorized)),
+                Err(e) => Err(reject(e)),
+            }
+        }
+    })
 }
+
+/// Require a valid session JWT or API token, extracting who it belongs to
+///
+/// Rejects with [`Unauthorized`]; install [`handle_rejection`] with
+/// `recover` to turn that into a JSON 401.
+pub fn with_authenticated(
+    auth_service: Arc<AuthService>,
+) -> impl Filter<Extract = (AuthenticatedActor,), Error = Rejection> + Clone {
+    with_caller(auth_service).and_then(|caller: Option<AuthenticatedActor>| async move {
+        caller.ok_or_else(|| warp::reject::custom(Unauthorized))
+    })
+}
+
+/// Like [`with_authenticated`], also requiring the caller to hold `minimum`
+///
+/// Callers without it are rejected with a 403, whichever way they
+/// authenticated.
+pub fn with_role(
+    auth_service: Arc<AuthService>,
+    minimum: Role,
+) -> impl Filter<Extract = (AuthenticatedActor,), Error = Rejection> + Clone {
+    with_authenticated(auth_service).and_then(move |actor: AuthenticatedActor| async move {
+        if actor.has_role(minimum) {
+            Ok(actor)
+        } else {
+            Err(reject(NimbusError::Forbidden(format!("Requires the {} role", minimum))))
+        }
+    })
+}
+
+/// Like [`with_role`], also requiring the credential to hold `scope`
+///
+/// Only API tokens can lack a scope; callers with a session token pass if
+/// they hold the role.
+pub fn with_scope(
+    auth_service: Arc<AuthService>,
+    minimum: Role,
+    scope: ApiScope,
+) -> impl Filter<Extract = (AuthenticatedActor,), Error = Rejection> + Clone {
+    with_role(auth_service, minimum).and_then(move |actor: AuthenticatedActor| async move {
+        if actor.has_scope(scope) {
+            Ok(actor)
+        } else {
+            Err(reject(NimbusError::Forbidden(format!("Token lacks the {} scope", scope))))
+        }
+    })
+}
+
+/// Rejection carrying a domain error, for filters that fail with one
+///
+/// [`handle_rejection`] renders it with [`error_status`] and its
+/// [`ErrorCode`].
+#[derive(Debug)]
+pub struct ApiRejection(pub NimbusError);
+
+impl warp::reject::Reject for ApiRejection {}
+
+/// Reject with `e`; see [`ApiRejection`]
+pub fn reject(e: NimbusError) -> Rejection {
+    warp::reject::custom(ApiRejection(e))
+}
+
+/// Map our custom rejections to JSON error replies, passing others through
+pub async fn handle_rejection(
+    err: Rejection,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        return Ok(json_error(StatusCode::UNAUTHORIZED, "Authentication required"));
+    }
+    if let Some(ApiRejection(e)) = err.find::<ApiRejection>() {
+        let message = match e {
+            // Details of internal failures are for the logs, not the caller
+            NimbusError::PluginError(_) | NimbusError::Internal(_) => {
+                warn!("Request failed: {}", e);
+                "Internal error".to_string()
+            }
+            _ => e.to_string(),
+        };
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&error_body(ErrorCode::from(e), &message)),
+            error_status(e),
+        ));
+    }
+    Err(err)
+}
+
+/// HTTP status for a domain error
+///
+/// Access checks from `nimbus_types::access::resolve_repo_access` rely on
+/// this mapping: hidden repositories report 404, visible ones lacking the
+/// required permission 403.
+pub fn error_status(e: &NimbusError) -> StatusCode {
+    StatusCode::from_u16(e.status_code()).expect("status codes are valid")
+}
+
+/// JSON error body in the shape the rest of the API uses
+///
+/// The error code is the generic one for `status`; use
+/// [`errors::api_error`] where a more specific code applies.
+pub fn json_error(status: StatusCode, message: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&error_body(ErrorCode::for_status(status), message)),
+        status,
+    )
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,32 @@
+//! Per-request tracing
+//!
+//! [`request_span`] opens a span per request carrying its method and path,
+//! so everything a handler logs can be traced back to the request.
+//! [`access_log`] then logs one line per response with its status and how
+//! long it took. Apply `access_log` first so its line lands inside the span:
+//!
+//! ```ignore
+//! routes.with(logging::access_log()).with(logging::request_span())
+//! ```
+
+use tracing::{Span, info, info_span};
+
+/// A span per request, with `method` and `path` fields
+pub fn request_span() -> warp::trace::Trace<impl Fn(warp::trace::Info<'_>) -> Span + Clone> {
+    warp::trace(
+        |info: warp::trace::Info<'_>| info_span!("request", method = %info.method(), path = %info.path()),
+    )
+}
+
+/// Logs each response's status and duration
+pub fn access_log() -> warp::log::Log<impl Fn(warp::log::Info<'_>) + Copy> {
+    warp::log::custom(|info: warp::log::Info<'_>| {
+        info!(
+            status = info.status().as_u16(),
+            duration_ms = info.elapsed().as_secs_f64() * 1000.0,
+            "{} {}",
+            info.method(),
+            info.path()
+        );
+    })
+}
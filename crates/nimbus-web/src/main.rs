@@ -1,5 +1,32 @@
 use nimbus_auth::AuthService;
-use nimbus_events::InMemoryEventBus as EventBus;
+use nimbus_events::commit_lint::{CommitLintConfig, CommitLinter};
+use nimbus_events::store::{EventStore, FileEventStore};
+use nimbus_events::subscriptions::FileSubscriptionStore;
+use nimbus_events::{EventBusConfig, InMemoryEventBus as EventBus};
+use nimbus_git::diff::DiffLimits;
+use nimbus_git::policy::PushPolicy;
+use nimbus_git::pulls::PullRequests;
+use nimbus_git::signing::Keyring;
+use nimbus_git::{FsRepositoryStore, GitStorage, RepositoryStore};
+use nimbus_types::events::{EventBus as _, EventHandler};
+use nimbus_web::admin::{self, AdminContext};
+use nimbus_web::cache::{CacheConfig, CacheInvalidator, ReadCache};
+use nimbus_web::ci::{self, CiContext, CiRunTracker};
+use nimbus_web::collaborators;
+use nimbus_web::events::{self, EventsContext};
+use nimbus_web::fetch_limit::{FetchLimiter, FetchLimits};
+use nimbus_web::git_http::{self, GitContext};
+use nimbus_web::handle_rejection;
+use nimbus_web::plugin_delivery::HttpPluginHandler;
+use nimbus_web::plugin_registry::{self, PluginRegistry};
+use nimbus_web::plugins::PluginCredentials;
+use nimbus_web::pulls::{self, PullsContext};
+use nimbus_web::repos::{self, ReposContext};
+use nimbus_web::repositories::{self, RepositoriesContext};
+use nimbus_web::server::{self, ServerLimits};
+use nimbus_web::settings::{self, SettingsContext, SettingsStore};
+use nimbus_web::{auth, cors, keys, logging, metrics};
+use nimbus_webhooks::{WebhookConfig, WebhookHandler};
 use std::sync::Arc;
 use tracing::info;
 use warp::Filter;
@@ -14,8 +41,63 @@ async fn main() {
     info!("Starting Nimbus Git Platform");
 
     // Initialize services
-    let _event_bus = Arc::new(EventBus::new(1000)); // 1000 event buffer size
-    let auth_service = Arc::new(AuthService::new().await);
+    // Events marked persistent are kept in NIMBUS_EVENT_LOG, if set, for the history API
+    let event_store: Option<Arc<dyn EventStore>> = match std::env::var("NIMBUS_EVENT_LOG") {
+        Ok(path) => {
+            info!("Persisting events to {}", path);
+            Some(Arc::new(FileEventStore::open(&path).await.expect("Failed to open event log")))
+        }
+        Err(_) => None,
+    };
+    let event_bus = EventBus::with_config(EventBusConfig::from_env());
+    let event_bus = match &event_store {
+        Some(store) => event_bus.with_store(store.clone()),
+        None => event_bus,
+    };
+    // Plugin subscriptions kept in NIMBUS_SUBSCRIPTIONS_FILE, if set, survive restarts
+    let event_bus = Arc::new(match std::env::var("NIMBUS_SUBSCRIPTIONS_FILE") {
+        Ok(path) => {
+            info!("Persisting plugin subscriptions to {}", path);
+            let store = FileSubscriptionStore::open(&path)
+                .await
+                .expect("Failed to open the subscriptions file");
+            event_bus.with_subscription_store(Arc::new(store))
+        }
+        Err(_) => event_bus,
+    });
+    let _event_processor = event_bus.clone().start();
+    let auth_service =
+        Arc::new(AuthService::new().await.expect("Failed to load the JWT signing secret"));
+    let storage = Arc::new(GitStorage::from_env());
+
+    // Cached git reads, dropped for a repository whenever it changes
+    let cache = Arc::new(ReadCache::new(CacheConfig::from_env()));
+    event_bus
+        .subscribe("read-cache".to_string(), Box::new(CacheInvalidator::new(cache.clone())))
+        .await
+        .expect("Failed to subscribe the cache invalidator");
+
+    // Outgoing webhooks
+    for config in WebhookConfig::from_env().expect("Invalid NIMBUS_WEBHOOKS") {
+        let name = format!("webhook:{}", config.name);
+        info!("Delivering events to webhook {} at {}", config.name, config.url);
+        event_bus
+            .subscribe(name, Box::new(WebhookHandler::new(config)))
+            .await
+            .expect("Failed to subscribe a webhook");
+    }
+
+    // Conventional-commit checks on pushes, reported as commit-lint findings
+    if let Some(config) = CommitLintConfig::from_env() {
+        info!("Linting pushed commit messages");
+        event_bus
+            .subscribe(
+                "commit-lint".to_string(),
+                Box::new(CommitLinter::new(config, event_bus.clone())),
+            )
+            .await
+            .expect("Failed to subscribe the commit linter");
+    }
 
     // Health check endpoint
     let health = warp::path("health").map(|| {
@@ -27,200 +109,191 @@ async fn main() {
     });
 
     // Auth endpoints
-    let auth_routes = warp::path("api").and(warp::path("auth")).and(
-        register_route(auth_service.clone())
-            .or(login_route(auth_service.clone()))
-            .or(logout_route(auth_service.clone()))
-            .or(create_token_route(auth_service.clone()))
-            .or(list_tokens_route(auth_service.clone())),
-    );
+    let auth_routes = auth::routes(auth_service.clone());
 
-    // Combine all routes
-    let routes = health.or(auth_routes).with(warp::cors().allow_any_origin());
+    // Event history for the owner, and publishing for plugins
+    let event_routes = events::routes(EventsContext {
+        auth_service: auth_service.clone(),
+        store: event_store,
+        event_bus: event_bus.clone(),
+        plugins: Arc::new(PluginCredentials::from_env().expect("Invalid NIMBUS_PLUGINS")),
+    });
 
-    let port = std::env::var("NIMBUS_PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>()
-        .expect("Invalid port number");
+    // Plugin endpoints and their health, for the settings page
+    let plugin_registry =
+        Arc::new(PluginRegistry::from_env().expect("Invalid NIMBUS_PLUGIN_ENDPOINTS"));
+    // Plugins subscribed before a restart, unless configured again below
+    let configured: Vec<String> =
+        plugin_registry.list().into_iter().map(|status| status.plugin.name).collect();
+    event_bus
+        .restore_subscriptions(|subscription| {
+            if configured.contains(&subscription.plugin.name) {
+                return None;
+            }
+            let handler = HttpPluginHandler::new(subscription.plugin.clone())?;
+            plugin_registry.register(subscription.plugin.clone());
+            Some(Box::new(handler) as Box<dyn EventHandler>)
+        })
+        .await
+        .expect("Failed to restore plugin subscriptions");
+    let _plugin_health = plugin_registry.clone().start_health_checks();
+    for status in plugin_registry.list() {
+        if !configured.contains(&status.plugin.name) {
+            continue;
+        }
+        let Some(handler) = HttpPluginHandler::new(status.plugin.clone()) else {
+            continue;
+        };
+        info!("Delivering events to plugin {}", handler.name());
+        // Subscribed under the plugin's name so events can target it
+        event_bus
+            .subscribe_persistent(handler.name().to_string(), status.plugin, Box::new(handler))
+            .await
+            .expect("Failed to subscribe a plugin");
+    }
+    let plugin_routes = plugin_registry::routes(auth_service.clone(), plugin_registry);
 
-    let host = std::env::var("NIMBUS_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+    // Collaborator management, owner only
+    let collaborator_routes = collaborators::routes(auth_service.clone());
 
-    let addr: std::net::SocketAddr = format!("{}:{}", host, port).parse().expect("Invalid address");
+    // Instance settings, owner only
+    let settings_store = SettingsStore::from_env().await.expect("Failed to load NIMBUS_SETTINGS");
+    let cors_origins = cors::origins_from_env(&settings_store.get().await.instance_domain)
+        .expect("Invalid NIMBUS_CORS_ORIGINS");
+    info!("Allowing cross-origin requests from {}", cors_origins.join(", "));
+    let settings_routes = settings::routes(SettingsContext {
+        auth_service: auth_service.clone(),
+        store: Arc::new(settings_store),
+    });
 
-    info!("Nimbus server listening on http://{}", addr);
+    // Repository records, for the permission checks
+    let repository_store: Arc<dyn RepositoryStore> =
+        Arc::new(FsRepositoryStore::new(storage.as_ref().clone()));
 
-    warp::serve(routes).run(addr).await;
-}
+    // Status of CI runs reported by plugins
+    let ci_runs = CiRunTracker::new();
+    event_bus
+        .subscribe("ci-runs".to_string(), Box::new(ci_runs.clone()))
+        .await
+        .expect("Failed to subscribe the CI run tracker");
+    let ci_routes = ci::routes(CiContext {
+        auth_service: auth_service.clone(),
+        store: repository_store.clone(),
+        tracker: ci_runs,
+    });
 
-// Auth route handlers
-fn register_route(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("register")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(with_auth_service(auth_service))
-        .and_then(handle_register)
-}
+    // SSH keys of the calling collaborator
+    let key_routes = keys::routes(auth_service.clone());
 
-fn login_route(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("login")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(with_auth_service(auth_service))
-        .and_then(handle_login)
-}
+    // Pull request endpoints
+    let pull_routes = pulls::routes(PullsContext {
+        storage: storage.clone(),
+        pulls: Arc::new(PullRequests::new()),
+        store: repository_store.clone(),
+        auth_service: auth_service.clone(),
+        event_bus: event_bus.clone(),
+        cache: cache.clone(),
+    });
 
-fn logout_route(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("logout")
-        .and(warp::post())
-        .and(warp::header::optional::<String>("authorization"))
-        .and(with_auth_service(auth_service))
-        .and_then(handle_logout)
-}
+    // Admin endpoints
+    let admin_routes = admin::routes(AdminContext {
+        auth_service: auth_service.clone(),
+        event_bus: event_bus.clone(),
+        cache: cache.clone(),
+    });
 
-fn with_auth_service(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = (Arc<AuthService>,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || auth_service.clone())
-}
+    // Repository listing, creation and deletion
+    let repository_routes = repositories::routes(RepositoriesContext {
+        store: repository_store.clone(),
+        auth_service: auth_service.clone(),
+        event_bus: event_bus.clone(),
+        idempotency_keys: repositories::idempotency_keys(),
+    });
 
-async fn handle_register(
-    body: serde_json::Value,
-    _auth_service: Arc<AuthService>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("Register request: {:?}", body);
-
-    // TODO: Implement actual registration
-    Ok(warp::reply::json(&serde_json::json!({
-        "message": "Registration endpoint - not yet implemented",
-        "user": body.get("username")
-    })))
-}
+    // Repository browsing, comparisons and push checks
+    let push_policy = Arc::new(PushPolicy::from_env());
+    let repo_routes = repos::routes(ReposContext {
+        storage: storage.clone(),
+        push_policy: push_policy.clone(),
+        cache: cache.clone(),
+        store: repository_store.clone(),
+        auth_service: auth_service.clone(),
+        diff_limits: DiffLimits::from_env(),
+    });
 
-async fn handle_login(
-    body: serde_json::Value,
-    auth_service: Arc<AuthService>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("Login request: {:?}", body);
-
-    let username =
-        body.get("username").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
-
-    let password =
-        body.get("password").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
-
-    // Validate login
-    match auth_service.validate_owner_login(username, password).await {
-        Ok(true) => {
-            // Generate JWT token
-            match auth_service.generate_token(username, "owner") {
-                Ok(token) => Ok(warp::reply::json(&serde_json::json!({
-                    "success": true,
-                    "token": token,
-                    "user": username,
-                    "role": "owner"
-                }))),
-                Err(e) => {
-                    info!("Failed to generate token: {}", e);
-                    Ok(warp::reply::json(&serde_json::json!({
-                        "success": false,
-                        "error": "Failed to generate token"
-                    })))
-                }
-            }
-        }
-        Ok(false) => Ok(warp::reply::json(&serde_json::json!({
-            "success": false,
-            "error": "Invalid credentials"
-        }))),
-        Err(e) => {
-            info!("Login error: {}", e);
-            Ok(warp::reply::json(&serde_json::json!({
-                "success": false,
-                "error": "Authentication service error"
-            })))
-        }
-    }
-}
+    // Git smart HTTP transport
+    let git_routes = git_http::routes(GitContext {
+        storage: storage.clone(),
+        store: repository_store,
+        auth_service: auth_service.clone(),
+        fetch_limiter: Arc::new(FetchLimiter::new(FetchLimits::from_env())),
+        push_policy,
+        keyring: Keyring::from_env(),
+        event_bus: event_bus.clone(),
+    });
 
-async fn handle_logout(
-    auth_header: Option<String>,
-    _auth_service: Arc<AuthService>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("Logout request with auth: {:?}", auth_header);
+    // Prometheus scrape endpoint, including the event bus metrics
+    let metrics_routes = metrics::routes();
 
-    // TODO: Implement actual logout
-    Ok(warp::reply::json(&serde_json::json!({
-        "message": "Logout successful"
-    })))
-}
+    // Combine all routes
+    let routes = health
+        .or(metrics_routes)
+        .or(auth_routes)
+        .or(collaborator_routes)
+        .or(settings_routes)
+        .or(event_routes)
+        .or(ci_routes)
+        .or(plugin_routes)
+        .or(key_routes)
+        .or(pull_routes)
+        .or(repository_routes)
+        .or(repo_routes)
+        .or(admin_routes)
+        .or(git_routes)
+        .recover(handle_rejection)
+        .with(logging::access_log())
+        .with(logging::request_span())
+        .with(cors::build_cors(&cors_origins));
 
-fn create_token_route(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("tokens")
-        .and(warp::post())
-        .and(warp::header::optional::<String>("authorization"))
-        .and(warp::body::json())
-        .and(with_auth_service(auth_service.clone()))
-        .and_then(handle_create_token)
-}
+    let port = std::env::var("NIMBUS_PORT")
+        .unwrap_or_else(|_| "3000".to_string())
+        .parse::<u16>()
+        .expect("Invalid port number");
 
-fn list_tokens_route(
-    auth_service: Arc<AuthService>,
-) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("tokens")
-        .and(warp::get())
-        .and(warp::header::optional::<String>("authorization"))
-        .and(with_auth_service(auth_service.clone()))
-        .and_then(handle_list_tokens)
-}
+    let host = std::env::var("NIMBUS_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
 
-async fn handle_create_token(
-    _auth_header: Option<String>,
-    body: serde_json::Value,
-    auth_service: Arc<AuthService>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    let name = body.get("name").and_then(|v| v.as_str()).ok_or_else(warp::reject::reject)?;
-
-    let token = auth_service.generate_api_key();
-
-    match auth_service.store_api_token(name, &token).await {
-        Ok(_) => Ok(warp::reply::json(&serde_json::json!({
-            "success": true,
-            "name": name,
-            "token": token
-        }))),
-        Err(e) => {
-            info!("Failed to store API token: {}", e);
-            Ok(warp::reply::json(&serde_json::json!({
-                "success": false,
-                "error": "Failed to create token"
-            })))
-        }
-    }
+    let addr: std::net::SocketAddr = format!("{}:{}", host, port).parse().expect("Invalid address");
+
+    let listener = tokio::net::TcpListener::bind(addr).await.expect("Failed to bind address");
+    let limits = ServerLimits::from_env();
+
+    info!("Nimbus server listening on http://{} ({:?})", addr, limits);
+
+    server::serve_with_shutdown(routes, listener, limits, shutdown_signal()).await;
+
+    // Events published by the last requests are still in the queue
+    info!("HTTP server stopped, draining the event bus");
+    event_bus.shutdown().await;
+    info!("Nimbus server stopped");
 }
 
-async fn handle_list_tokens(
-    _auth_header: Option<String>,
-    auth_service: Arc<AuthService>,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    match auth_service.list_api_tokens().await {
-        Ok(tokens) => Ok(warp::reply::json(&serde_json::json!({
-            "success": true,
-            "tokens": tokens
-        }))),
-        Err(e) => {
-            info!("Failed to list API tokens: {}", e);
-            Ok(warp::reply::json(&serde_json::json!({
-                "success": false,
-                "error": "Failed to list tokens"
-            })))
-        }
+/// Completes on ctrl-c, or on SIGTERM as sent when a pod is terminated
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => info!("Received ctrl-c, shutting down"),
+        () = terminate => info!("Received SIGTERM, shutting down"),
     }
 }
@@ -0,0 +1,30 @@
+//! Prometheus scrape endpoint
+//!
+//! Serves everything registered in the default registry, which is where the
+//! event bus records its metrics.
+
+use prometheus::{Encoder, TextEncoder};
+use tracing::warn;
+use warp::Filter;
+use warp::http::{Response, StatusCode};
+
+pub fn routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("metrics").and(warp::path::end()).and(warp::get()).map(handle_metrics)
+}
+
+fn handle_metrics() -> Response<Vec<u8>> {
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut body) {
+        warn!("Failed to encode metrics: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Vec::new())
+            .expect("static response is valid");
+    }
+
+    Response::builder()
+        .header("content-type", encoder.format_type())
+        .body(body)
+        .expect("static headers are valid")
+}
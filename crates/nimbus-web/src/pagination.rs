@@ -0,0 +1,73 @@
+//! Cursor pagination for list endpoints
+//!
+//! List routes take `?cursor=&limit=` as a [`PageQuery`] and answer with a
+//! [`Page`]. Cursors are opaque to clients: a URL-safe base64 encoding of the
+//! offset of the next item, so routes can change what they encode later
+//! without breaking anyone who just passes `next_cursor` back.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use nimbus_types::NimbusError;
+use serde::{Deserialize, Serialize};
+
+/// Items returned when no `limit` is given
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+/// Most items returned per request, whatever `limit` asks for
+pub const MAX_PAGE_LIMIT: usize = 100;
+
+/// `?cursor=&limit=` of a list request
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    /// `next_cursor` of the previous page; absent for the first page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl PageQuery {
+    /// The requested limit, defaulted and capped at [`MAX_PAGE_LIMIT`]
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+    }
+}
+
+/// One page of a list response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for the following page, `None` on the last one
+    pub next_cursor: Option<String>,
+    /// Number of items across all pages, when known
+    pub total: Option<usize>,
+}
+
+/// Cursor resuming a listing at `offset`
+pub fn encode_cursor(offset: usize) -> String {
+    URL_SAFE_NO_PAD.encode(offset.to_string())
+}
+
+/// Offset a cursor from [`encode_cursor`] resumes at
+pub fn decode_cursor(cursor: &str) -> Result<usize, NimbusError> {
+    let invalid = || NimbusError::InvalidInput(format!("Invalid cursor {:?}", cursor));
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    std::str::from_utf8(&bytes).ok().and_then(|offset| offset.parse().ok()).ok_or_else(invalid)
+}
+
+/// The page of `items` starting at `cursor`, at most `limit` long
+///
+/// `limit` is capped at [`MAX_PAGE_LIMIT`]. A cursor past the end yields an
+/// empty last page rather than an error, as items may have been removed
+/// since it was handed out.
+pub fn paginate<T>(
+    items: Vec<T>,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<Page<T>, NimbusError> {
+    let offset = cursor.map(decode_cursor).transpose()?.unwrap_or(0);
+    let limit = limit.clamp(1, MAX_PAGE_LIMIT);
+    let total = items.len();
+
+    let end = offset.saturating_add(limit);
+    let next_cursor = (end < total).then(|| encode_cursor(end));
+    let items = items.into_iter().skip(offset).take(limit).collect();
+    Ok(Page { items, next_cursor, total: Some(total) })
+}
@@ -0,0 +1,81 @@
+//! Event delivery to out-of-process plugins
+//!
+//! [`HttpPluginHandler`] POSTs each envelope matching a plugin's declared
+//! `interests` to its `endpoint` as JSON. Anything but a 2xx answer fails
+//! the handler run, so the bus retries it and, once out of attempts, hands
+//! it to the dead-letter sink.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use nimbus_types::Plugin;
+use nimbus_types::events::{EventEnvelope, EventFilter, EventHandler};
+use tracing::debug;
+
+/// Why a plugin didn't accept an event
+#[derive(Debug, thiserror::Error)]
+pub enum PluginDeliveryError {
+    #[error("Request to plugin failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Plugin answered {0}")]
+    Status(reqwest::StatusCode),
+
+    #[error("Failed to encode event: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+/// Event handler delivering envelopes to a registered plugin
+pub struct HttpPluginHandler {
+    plugin: Plugin,
+    filter: EventFilter,
+    client: reqwest::Client,
+}
+
+impl HttpPluginHandler {
+    /// Handler for `plugin`, or `None` if it declared no interests
+    ///
+    /// Requests time out after 10 seconds each.
+    pub fn new(plugin: Plugin) -> Option<Self> {
+        let filter = plugin.interests.clone()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("nimbus-web/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("static client configuration is valid");
+        Some(Self { plugin, filter, client })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.plugin.name
+    }
+
+    /// POST `envelope` to the plugin once
+    pub async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), PluginDeliveryError> {
+        let body = serde_json::to_vec(envelope)?;
+        let response = self
+            .client
+            .post(&self.plugin.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(PluginDeliveryError::Status(response.status()));
+        }
+        debug!("Delivered event {} to plugin {}", envelope.id, self.plugin.name);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventHandler for HttpPluginHandler {
+    async fn handle(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.deliver(&event).await?;
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        self.filter.clone()
+    }
+}
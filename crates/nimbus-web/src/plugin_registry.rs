@@ -0,0 +1,203 @@
+//! Registered out-of-process plugins and whether they are up
+//!
+//! The owner lists plugin endpoints in `NIMBUS_PLUGIN_ENDPOINTS`, a JSON
+//! array of [`Plugin`] registrations (the `id` may be left out):
+//!
+//! ```json
+//! [{ "name": "ci-runner", "plugin_type": "CiRunner",
+//!    "endpoint": "http://ci-runner:8080/events",
+//!    "health_check": "http://ci-runner:8080/health",
+//!    "interests": { "event_types": ["CiRun"], "repositories": [], "branches": [] } }]
+//! ```
+//!
+//! Plugins declaring `interests` are sent matching events, see
+//! [`crate::plugin_delivery`].
+//!
+//! [`PluginRegistry::start_health_checks`] polls each plugin's
+//! `health_check` URL; any 2xx answer counts as up. `GET /api/plugins`
+//! lists the plugins with their last known health, owner only.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::future;
+use nimbus_auth::{AuthService, Role};
+use nimbus_types::Plugin;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::{AuthenticatedActor, with_role};
+
+/// How often plugins are polled unless `NIMBUS_PLUGIN_HEALTH_INTERVAL_SECS` is set
+const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a health check may take before the plugin counts as down
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of the last health check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginHealth {
+    /// Not checked yet
+    Unknown,
+    Up,
+    Down,
+}
+
+/// A registered plugin with its last known health
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStatus {
+    #[serde(flatten)]
+    pub plugin: Plugin,
+    pub health: PluginHealth,
+    pub last_checked: Option<OffsetDateTime>,
+}
+
+/// Every registered plugin, by id
+pub struct PluginRegistry {
+    plugins: RwLock<HashMap<Uuid, PluginStatus>>,
+    client: reqwest::Client,
+    /// Time between health checks of each plugin
+    interval: Duration,
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .user_agent(concat!("nimbus-web/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("static client configuration is valid");
+        Self { plugins: RwLock::new(HashMap::new()), client, interval: DEFAULT_HEALTH_INTERVAL }
+    }
+
+    /// Check health every `interval` instead of every 30 seconds
+    pub fn with_health_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Plugins from `NIMBUS_PLUGIN_ENDPOINTS`, none if unset, checked every
+    /// `NIMBUS_PLUGIN_HEALTH_INTERVAL_SECS`
+    pub fn from_env() -> Result<Self, serde_json::Error> {
+        let mut registry = Self::new();
+        if let Ok(value) = std::env::var("NIMBUS_PLUGIN_HEALTH_INTERVAL_SECS") {
+            match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => registry.interval = Duration::from_secs(secs),
+                _ => warn!("Ignoring invalid NIMBUS_PLUGIN_HEALTH_INTERVAL_SECS={}", value),
+            }
+        }
+        if let Ok(json) = std::env::var("NIMBUS_PLUGIN_ENDPOINTS") {
+            let plugins: Vec<Plugin> = serde_json::from_str(&json)?;
+            for plugin in plugins {
+                registry.register(plugin);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Add `plugin`, replacing any registration with the same id
+    ///
+    /// Its health is unknown until the next check.
+    pub fn register(&self, plugin: Plugin) {
+        info!("Registered plugin {} ({:?})", plugin.name, plugin.plugin_type);
+        let status = PluginStatus { plugin, health: PluginHealth::Unknown, last_checked: None };
+        self.plugins.write().unwrap().insert(status.plugin.id, status);
+    }
+
+    /// Every plugin, by name
+    pub fn list(&self) -> Vec<PluginStatus> {
+        let mut plugins: Vec<_> = self.plugins.read().unwrap().values().cloned().collect();
+        plugins.sort_by(|a, b| a.plugin.name.cmp(&b.plugin.name));
+        plugins
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<PluginStatus> {
+        self.plugins.read().unwrap().get(&id).cloned()
+    }
+
+    /// Check every plugin once, concurrently
+    pub async fn check_all(&self) {
+        // Collect first so no lock is held across the requests
+        let plugins: Vec<(Uuid, String)> = self
+            .plugins
+            .read()
+            .unwrap()
+            .values()
+            .map(|status| (status.plugin.id, status.plugin.health_check.clone()))
+            .collect();
+
+        let results = future::join_all(plugins.into_iter().map(|(id, url)| async move {
+            let health = match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => PluginHealth::Up,
+                Ok(response) => {
+                    debug!("Plugin health check {} returned {}", url, response.status());
+                    PluginHealth::Down
+                }
+                Err(e) => {
+                    debug!("Plugin health check {} failed: {}", url, e);
+                    PluginHealth::Down
+                }
+            };
+            (id, health)
+        }))
+        .await;
+
+        let now = OffsetDateTime::now_utc();
+        let mut plugins = self.plugins.write().unwrap();
+        for (id, health) in results {
+            // Unregistered while the check ran
+            let Some(status) = plugins.get_mut(&id) else {
+                continue;
+            };
+            if status.health != health && health == PluginHealth::Down {
+                warn!("Plugin {} is down", status.plugin.name);
+            }
+            status.health = health;
+            status.last_checked = Some(now);
+        }
+    }
+
+    /// Poll every plugin at the health check interval, starting now
+    pub fn start_health_checks(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(self.interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                self.check_all().await;
+            }
+        })
+    }
+}
+
+pub fn routes(
+    auth_service: Arc<AuthService>,
+    registry: Arc<PluginRegistry>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "plugins")
+        .and(warp::get())
+        .and(with_role(auth_service, Role::Owner))
+        .and(warp::any().map(move || registry.clone()))
+        .and_then(handle_list)
+}
+
+async fn handle_list(
+    _actor: AuthenticatedActor,
+    registry: Arc<PluginRegistry>,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "plugins": registry.list() })),
+        StatusCode::OK,
+    ))
+}
@@ -0,0 +1,88 @@
+//! Credentials for out-of-process plugins
+//!
+//! Plugins post their results (CI runs, reviews, AI analyses) back to the
+//! bus through `POST /api/events`, authenticating with a bearer token of
+//! their own. The owner lists them in `NIMBUS_PLUGINS`, a JSON array:
+//!
+//! ```json
+//! [{ "name": "ci-runner", "token": "...", "events": ["CiRun"] }]
+//! ```
+//!
+//! A plugin may only emit the event types listed for it, and only under its
+//! own name.
+
+use nimbus_types::events::{Event, EventType};
+use serde::Deserialize;
+
+/// One plugin allowed to publish events
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginConfig {
+    /// Must match the `plugin` field of every event the plugin emits
+    pub name: String,
+    /// Bearer token the plugin authenticates with
+    pub token: String,
+    /// Event types the plugin may emit
+    pub events: Vec<EventType>,
+}
+
+/// Why a plugin may not publish an event
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PublishDenied {
+    #[error("Plugin {plugin} may not emit {event_type} events")]
+    EventType { plugin: String, event_type: &'static str },
+
+    #[error("Plugin {plugin} may not emit events on behalf of {claimed:?}")]
+    WrongPlugin { plugin: String, claimed: Option<String> },
+}
+
+impl PluginConfig {
+    /// Check that this plugin may publish `event`
+    pub fn authorize(&self, event: &Event) -> Result<(), PublishDenied> {
+        let event_type = event.event_type();
+        if !self.events.contains(&event_type) {
+            return Err(PublishDenied::EventType {
+                plugin: self.name.clone(),
+                event_type: event_type.as_str(),
+            });
+        }
+        if event.plugin() != Some(self.name.as_str()) {
+            return Err(PublishDenied::WrongPlugin {
+                plugin: self.name.clone(),
+                claimed: event.plugin().map(str::to_string),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Every configured plugin
+#[derive(Debug, Clone, Default)]
+pub struct PluginCredentials {
+    plugins: Vec<PluginConfig>,
+}
+
+impl PluginCredentials {
+    pub fn new(plugins: Vec<PluginConfig>) -> Self {
+        Self { plugins }
+    }
+
+    /// Plugins from `NIMBUS_PLUGINS`; none if unset
+    pub fn from_env() -> Result<Self, serde_json::Error> {
+        match std::env::var("NIMBUS_PLUGINS") {
+            Ok(json) => serde_json::from_str(&json).map(Self::new),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// The plugin `token` belongs to, if any
+    pub fn authenticate(&self, token: &str) -> Option<&PluginConfig> {
+        self.plugins
+            .iter()
+            .find(|plugin| constant_time_eq(plugin.token.as_bytes(), token.as_bytes()))
+    }
+}
+
+/// Compare without exiting early, so timing doesn't reveal a prefix match
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,324 @@
+//! Pull request routes: opening, diffs and inline review comments
+//!
+//! Reading pull requests needs `Read` on the repository, opening one or
+//! commenting `Write`.
+
+use std::sync::Arc;
+
+use nimbus_auth::AuthService;
+use nimbus_events::{EventMetadata, EventPriority};
+use nimbus_git::diff::{FileDiff, diff_revisions};
+use nimbus_git::pulls::{PullRequest, PullRequests};
+use nimbus_git::refs::resolve_ref;
+use nimbus_git::{GitStorage, RepositoryStore};
+use nimbus_types::events::{Event, EventBus, EventEnvelope};
+use nimbus_types::{NimbusError, Permission};
+use serde::Deserialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::access::{RepoAccess, authorize_repo, credential};
+use crate::cache::{ReadCache, ReadKey};
+use crate::errors::{ErrorCode, api_error};
+use crate::json_error;
+
+/// Everything the pull request routes need
+#[derive(Clone)]
+pub struct PullsContext {
+    pub storage: Arc<GitStorage>,
+    pub pulls: Arc<PullRequests>,
+    /// Repository records, for the permission checks
+    pub store: Arc<dyn RepositoryStore>,
+    pub auth_service: Arc<AuthService>,
+    pub event_bus: Arc<dyn EventBus>,
+    pub cache: Arc<ReadCache>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenPullRequest {
+    pub from_branch: String,
+    pub to_branch: String,
+    pub title: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewReviewComment {
+    /// `position` of the diff line being commented on
+    pub position: String,
+    pub body: String,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    open_route(context.clone())
+        .or(list_route(context.clone()))
+        .or(diff_route(context.clone()))
+        .or(list_comments_route(context.clone()))
+        .or(create_comment_route(context))
+}
+
+fn with_context(
+    context: PullsContext,
+) -> impl Filter<Extract = (PullsContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+fn open_route(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "pulls")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(with_context(context))
+        .and_then(handle_open)
+}
+
+fn list_route(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "pulls")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_list)
+}
+
+fn diff_route(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "pulls" / Uuid / "diff")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_diff)
+}
+
+fn list_comments_route(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "pulls" / Uuid / "comments")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_list_comments)
+}
+
+fn create_comment_route(
+    context: PullsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "pulls" / Uuid / "comments")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(with_context(context))
+        .and_then(handle_create_comment)
+}
+
+/// Current diff of a pull request, computed off the async runtime
+///
+/// Cached by the commits both branches point at, so it is recomputed only
+/// once either moves.
+async fn pull_diff(
+    context: &PullsContext,
+    pull: &PullRequest,
+) -> Result<Vec<FileDiff>, NimbusError> {
+    let storage = context.storage.clone();
+    let cache = context.cache.clone();
+    let pull = pull.clone();
+    tokio::task::spawn_blocking(move || {
+        let repo = storage.open(&pull.repository)?;
+        let base = resolve_ref(&repo, &pull.to_branch)?.target_sha;
+        let head = resolve_ref(&repo, &pull.from_branch)?.target_sha;
+        let key = ReadKey {
+            repository: pull.repository.clone(),
+            sha: format!("{}..{}", base, head),
+            params: "diff".to_string(),
+        };
+        if let Some(files) = cache.diffs.get(&key) {
+            return Ok(files);
+        }
+        let files = diff_revisions(&repo, &base, &head)?;
+        cache.diffs.insert(key, files.clone());
+        Ok(files)
+    })
+    .await
+    .map_err(|e| NimbusError::Internal(format!("Diff task failed: {}", e)))?
+}
+
+/// Check that the caller holds `required` on repository `name`
+///
+/// Anonymous callers are asked to authenticate for anything beyond `Read`.
+async fn authorize(
+    context: &PullsContext,
+    auth_header: Option<&str>,
+    name: &str,
+    required: Permission,
+) -> Result<RepoAccess, Reply> {
+    if required > Permission::Read && auth_header.and_then(credential).is_none() {
+        return Err(json_error(StatusCode::UNAUTHORIZED, "Authentication required"));
+    }
+    authorize_repo(&context.store, &context.auth_service, auth_header, name, required)
+        .await
+        .map_err(access_error)
+}
+
+fn access_error(e: NimbusError) -> Reply {
+    match e {
+        NimbusError::RepositoryNotFound(_)
+        | NimbusError::Unauthorized(_)
+        | NimbusError::Forbidden(_) => api_error(ErrorCode::from(&e), &e.to_string()),
+        other => {
+            warn!("Failed to check repository access: {}", other);
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check repository access")
+        }
+    }
+}
+
+fn diff_error(e: NimbusError) -> Reply {
+    match e {
+        NimbusError::RepositoryNotFound(_) | NimbusError::Forbidden(_) => {
+            api_error(ErrorCode::from(&e), &e.to_string())
+        }
+        other => {
+            warn!("Failed to compute pull request diff: {}", other);
+            json_error(StatusCode::UNPROCESSABLE_ENTITY, "Failed to compute diff")
+        }
+    }
+}
+
+async fn handle_open(
+    name: String,
+    auth_header: Option<String>,
+    body: OpenPullRequest,
+    context: PullsContext,
+) -> Result<Reply, warp::Rejection> {
+    let author = match authorize(&context, auth_header.as_deref(), &name, Permission::Write).await {
+        Ok(RepoAccess { caller: Some(caller), .. }) => caller.name,
+        Ok(_) => return Ok(json_error(StatusCode::UNAUTHORIZED, "Authentication required")),
+        Err(reply) => return Ok(reply),
+    };
+
+    let pull = context.pulls.open(&name, &body.from_branch, &body.to_branch, &body.title, &author);
+    info!("Opened pull request {} on {}", pull.id, name);
+
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event: Event::PullRequestOpened {
+            id: pull.id,
+            repository: pull.repository.clone(),
+            from_branch: pull.from_branch.clone(),
+            to_branch: pull.to_branch.clone(),
+            title: pull.title.clone(),
+            author: pull.author.clone(),
+        },
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: true,
+            simulated: false,
+        },
+    };
+    if let Err(e) = context.event_bus.publish(envelope).await {
+        warn!("Failed to publish PullRequestOpened: {}", e);
+    }
+
+    Ok(warp::reply::with_status(warp::reply::json(&pull), StatusCode::CREATED))
+}
+
+async fn handle_list(
+    name: String,
+    auth_header: Option<String>,
+    context: PullsContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(reply) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(reply);
+    }
+    let pulls = context.pulls.list(&name);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "pulls": pulls })),
+        StatusCode::OK,
+    ))
+}
+
+async fn handle_diff(
+    name: String,
+    id: Uuid,
+    auth_header: Option<String>,
+    context: PullsContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(reply) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(reply);
+    }
+    let Some(pull) = context.pulls.get(&name, id) else {
+        return Ok(json_error(StatusCode::NOT_FOUND, "Pull request not found"));
+    };
+
+    match pull_diff(&context, &pull).await {
+        Ok(files) => Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "pull_request": pull, "files": files })),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(diff_error(e)),
+    }
+}
+
+async fn handle_list_comments(
+    name: String,
+    id: Uuid,
+    auth_header: Option<String>,
+    context: PullsContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(reply) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(reply);
+    }
+    let Some(pull) = context.pulls.get(&name, id) else {
+        return Ok(json_error(StatusCode::NOT_FOUND, "Pull request not found"));
+    };
+
+    match pull_diff(&context, &pull).await {
+        Ok(files) => Ok(warp::reply::with_status(
+            warp::reply::json(
+                &serde_json::json!({ "comments": context.pulls.comments(id, &files) }),
+            ),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(diff_error(e)),
+    }
+}
+
+async fn handle_create_comment(
+    name: String,
+    id: Uuid,
+    auth_header: Option<String>,
+    body: NewReviewComment,
+    context: PullsContext,
+) -> Result<Reply, warp::Rejection> {
+    let author = match authorize(&context, auth_header.as_deref(), &name, Permission::Write).await {
+        Ok(RepoAccess { caller: Some(caller), .. }) => caller.name,
+        Ok(_) => return Ok(json_error(StatusCode::UNAUTHORIZED, "Authentication required")),
+        Err(reply) => return Ok(reply),
+    };
+    let Some(pull) = context.pulls.get(&name, id) else {
+        return Ok(json_error(StatusCode::NOT_FOUND, "Pull request not found"));
+    };
+
+    let files = match pull_diff(&context, &pull).await {
+        Ok(files) => files,
+        Err(e) => return Ok(diff_error(e)),
+    };
+
+    match context.pulls.add_comment(id, &files, &body.position, &body.body, &author) {
+        Ok(comment) => {
+            Ok(warp::reply::with_status(warp::reply::json(&comment), StatusCode::CREATED))
+        }
+        Err(e) => Ok(json_error(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string())),
+    }
+}
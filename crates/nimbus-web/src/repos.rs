@@ -0,0 +1,444 @@
+//! Repository browsing routes
+//!
+//! Anything taking a `?ref=` goes through [`resolve`], which accepts `HEAD`,
+//! branch and tag names, and full or abbreviated shas. Reads of a repository
+//! without commits answer `{ "empty": true }` rather than a missing `HEAD`.
+//!
+//! `GET /api/repos/:name/tree/:ref/*path` and `GET /api/repos/:name/blob/:ref/*path`
+//! back the file explorer: a directory's entries with their git types, and
+//! a file's contents. Segments are percent-decoded, so refs and paths may
+//! contain `%2F`.
+//!
+//! `GET /api/repos/:name/compare/:base...:head` diffs `head` against its
+//! merge base with `base`, cut off past [`DiffLimits`].
+//!
+//! The resolve, browsing and compare routes check the caller holds `Read` on the
+//! repository.
+//!
+//! `POST /api/repos/:name/push-check` is a dry run of the push policy: it
+//! reports what a push would be rejected for without touching the
+//! repository, for callers holding `Write`.
+
+use std::sync::Arc;
+
+use git2::Repository;
+use nimbus_auth::AuthService;
+use nimbus_git::browse::{self, list_commits, list_tree, read_blob};
+use nimbus_git::diff::{DiffLimits, compare};
+use nimbus_git::policy::{ProposedPush, PushPolicy};
+use nimbus_git::refs::{ResolvedRef, resolve_ref};
+use nimbus_git::{GitStorage, RepositoryStore};
+use nimbus_types::{NimbusError, Permission};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use tracing::warn;
+use warp::Filter;
+use warp::http::StatusCode;
+use warp::path::Tail;
+
+use crate::access::authorize_repo;
+use crate::cache::{ReadCache, ReadKey};
+use crate::errors::{ErrorCode, api_error};
+use crate::json_error;
+
+/// Everything the repository routes need
+#[derive(Clone)]
+pub struct ReposContext {
+    pub storage: Arc<GitStorage>,
+    /// Policy pushes are checked against
+    pub push_policy: Arc<PushPolicy>,
+    pub cache: Arc<ReadCache>,
+    /// Repository records, for the permission checks
+    pub store: Arc<dyn RepositoryStore>,
+    pub auth_service: Arc<AuthService>,
+    /// How much of a comparison is returned
+    pub diff_limits: DiffLimits,
+}
+
+/// Commits listed when no `limit` is given
+const DEFAULT_COMMIT_LIMIT: usize = 30;
+/// Most commits listed per request
+const MAX_COMMIT_LIMIT: usize = 100;
+
+/// Query shared by the browsing routes; `ref` defaults to `HEAD`
+#[derive(Debug, Deserialize)]
+pub struct RefQuery {
+    #[serde(rename = "ref")]
+    pub reference: Option<String>,
+    /// Number of commits, for the commits route
+    pub limit: Option<usize>,
+}
+
+impl RefQuery {
+    pub fn spec(&self) -> &str {
+        self.reference.as_deref().unwrap_or("HEAD")
+    }
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    resolve_route(context.clone())
+        .or(tree_route(context.clone()))
+        .or(commits_route(context.clone()))
+        .or(readme_route(context.clone()))
+        .or(blob_route(context.clone()))
+        .or(compare_route(context.clone()))
+        .or(push_check_route(context))
+}
+
+fn with_context(
+    context: ReposContext,
+) -> impl Filter<Extract = (ReposContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+fn resolve_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "resolve")
+        .and(warp::get())
+        .and(warp::query::<RefQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_resolve)
+}
+
+fn commits_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "commits")
+        .and(warp::get())
+        .and(warp::query::<RefQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_commits)
+}
+
+fn readme_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "readme")
+        .and(warp::get())
+        .and(warp::query::<RefQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_readme)
+}
+
+fn tree_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "tree" / String / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_tree)
+}
+
+fn blob_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "blob" / String / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_blob)
+}
+
+fn compare_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "compare" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_context(context))
+        .and_then(handle_compare)
+}
+
+fn push_check_route(
+    context: ReposContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String / "push-check")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .and(with_context(context))
+        .and_then(handle_push_check)
+}
+
+/// A read of repository contents at a ref
+pub enum Browse<T> {
+    /// The repository has no commits yet
+    Empty,
+    At(ResolvedRef, T),
+}
+
+/// Resolve `spec` in repository `name` and `read` at it, off the async runtime
+pub async fn read_at_ref<T, F>(
+    context: &ReposContext,
+    name: &str,
+    spec: &str,
+    read: F,
+) -> Result<Browse<T>, NimbusError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Repository, &ResolvedRef) -> Result<T, NimbusError> + Send + 'static,
+{
+    let storage = context.storage.clone();
+    let name = name.to_string();
+    let spec = spec.to_string();
+    tokio::task::spawn_blocking(move || {
+        let repo = storage.open(&name)?;
+        if browse::is_empty(&repo)? {
+            return Ok(Browse::Empty);
+        }
+        let resolved = resolve_ref(&repo, &spec)?;
+        let value = read(&repo, &resolved)?;
+        Ok(Browse::At(resolved, value))
+    })
+    .await
+    .map_err(|e| NimbusError::Internal(format!("Browse task failed: {}", e)))?
+}
+
+/// [`read_at_ref`], served from the read cache when the same read (named
+/// by `params`) was recently done at the same commit
+pub async fn cached_read_at_ref<T, F>(
+    context: &ReposContext,
+    name: &str,
+    spec: &str,
+    params: String,
+    read: F,
+) -> Result<Browse<serde_json::Value>, NimbusError>
+where
+    T: serde::Serialize,
+    F: FnOnce(&Repository, &ResolvedRef) -> Result<T, NimbusError> + Send + 'static,
+{
+    let cache = context.cache.clone();
+    let repository = name.to_string();
+    read_at_ref(context, name, spec, move |repo, resolved| {
+        let key = ReadKey { repository, sha: resolved.target_sha.clone(), params };
+        if let Some(value) = cache.browse.get(&key) {
+            return Ok(value);
+        }
+        let value = serde_json::to_value(read(repo, resolved)?)
+            .map_err(|e| NimbusError::Internal(format!("Failed to serialize read: {}", e)))?;
+        cache.browse.insert(key, value.clone());
+        Ok(value)
+    })
+    .await
+}
+
+/// `{ "empty": true }`, or the resolved ref with the value under `key`
+fn browse_reply<T: serde::Serialize>(key: &str, result: Result<Browse<T>, NimbusError>) -> Reply {
+    match result {
+        Ok(Browse::Empty) => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "empty": true })),
+            StatusCode::OK,
+        ),
+        Ok(Browse::At(resolved, value)) => {
+            let mut body = serde_json::json!({ "empty": false, "ref": resolved });
+            body[key] = serde_json::json!(value);
+            warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+        }
+        Err(e) => repo_error(e),
+    }
+}
+
+/// Resolve `spec` in repository `name`, off the async runtime
+pub async fn resolve(
+    context: &ReposContext,
+    name: &str,
+    spec: &str,
+) -> Result<ResolvedRef, NimbusError> {
+    let storage = context.storage.clone();
+    let name = name.to_string();
+    let spec = spec.to_string();
+    tokio::task::spawn_blocking(move || resolve_ref(&storage.open(&name)?, &spec))
+        .await
+        .map_err(|e| NimbusError::Internal(format!("Resolve task failed: {}", e)))?
+}
+
+/// A percent-encoded path or segment, decoded
+fn decode(raw: &str) -> Result<String, NimbusError> {
+    percent_decode_str(raw)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| NimbusError::InvalidGitOperation(format!("Invalid encoding in {}", raw)))
+}
+
+/// Check that the caller holds `required` on repository `name`
+async fn authorize(
+    context: &ReposContext,
+    auth_header: Option<&str>,
+    name: &str,
+    required: Permission,
+) -> Result<(), NimbusError> {
+    authorize_repo(&context.store, &context.auth_service, auth_header, name, required)
+        .await
+        .map(|_| ())
+}
+
+fn repo_error(e: NimbusError) -> Reply {
+    match e {
+        NimbusError::RepositoryNotFound(_)
+        | NimbusError::Unauthorized(_)
+        | NimbusError::RefNotFound(_)
+        | NimbusError::PathNotFound(_)
+        | NimbusError::Forbidden(_)
+        | NimbusError::InvalidGitOperation(_) => api_error(ErrorCode::from(&e), &e.to_string()),
+        other => {
+            warn!("Failed to read repository: {}", other);
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read repository")
+        }
+    }
+}
+
+async fn handle_resolve(
+    name: String,
+    query: RefQuery,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+    Ok(match resolve(&context, &name, query.spec()).await {
+        Ok(resolved) => warp::reply::with_status(warp::reply::json(&resolved), StatusCode::OK),
+        Err(e) => repo_error(e),
+    })
+}
+
+async fn handle_commits(
+    name: String,
+    query: RefQuery,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_COMMIT_LIMIT).clamp(1, MAX_COMMIT_LIMIT);
+    let params = format!("commits:{}", limit);
+    let result =
+        cached_read_at_ref(&context, &name, query.spec(), params, move |repo, resolved| {
+            list_commits(repo, resolved.oid(), limit)
+        })
+        .await;
+    Ok(browse_reply("commits", result))
+}
+
+async fn handle_readme(
+    name: String,
+    query: RefQuery,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+    let params = "readme".to_string();
+    let result = cached_read_at_ref(&context, &name, query.spec(), params, |repo, resolved| {
+        browse::readme(repo, resolved.oid())
+    })
+    .await;
+    Ok(browse_reply("readme", result))
+}
+
+async fn handle_tree(
+    name: String,
+    reference: String,
+    path: Tail,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    let (reference, path) = match (decode(&reference), decode(path.as_str())) {
+        (Ok(reference), Ok(path)) => (reference, path),
+        (Err(e), _) | (_, Err(e)) => return Ok(repo_error(e)),
+    };
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+
+    let params = format!("tree:{}", path.trim_matches('/'));
+    let result = cached_read_at_ref(&context, &name, &reference, params, move |repo, resolved| {
+        list_tree(repo, resolved.oid(), &path)
+    })
+    .await;
+    Ok(browse_reply("entries", result))
+}
+
+async fn handle_blob(
+    name: String,
+    reference: String,
+    path: Tail,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    let (reference, path) = match (decode(&reference), decode(path.as_str())) {
+        (Ok(reference), Ok(path)) => (reference, path),
+        (Err(e), _) | (_, Err(e)) => return Ok(repo_error(e)),
+    };
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+
+    let result = read_at_ref(&context, &name, &reference, move |repo, resolved| {
+        read_blob(repo, resolved.oid(), &path)
+    })
+    .await;
+    Ok(browse_reply("blob", result))
+}
+
+async fn handle_compare(
+    name: String,
+    range: String,
+    auth_header: Option<String>,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    let Some((base, head)) = range.split_once("...") else {
+        let message = format!("Expected base...head, got {}", range);
+        return Ok(api_error(ErrorCode::BadRequest, &message));
+    };
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Read).await {
+        return Ok(repo_error(e));
+    }
+
+    let storage = context.storage.clone();
+    let (base, head) = (base.to_string(), head.to_string());
+    let limits = context.diff_limits;
+    let result =
+        tokio::task::spawn_blocking(move || compare(&storage.open(&name)?, &base, &head, &limits))
+            .await
+            .map_err(|e| NimbusError::Internal(format!("Compare task failed: {}", e)))
+            .and_then(|result| result);
+
+    Ok(match result {
+        Ok(comparison) => warp::reply::with_status(warp::reply::json(&comparison), StatusCode::OK),
+        Err(e) => repo_error(e),
+    })
+}
+
+async fn handle_push_check(
+    name: String,
+    auth_header: Option<String>,
+    push: ProposedPush,
+    context: ReposContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Err(e) = authorize(&context, auth_header.as_deref(), &name, Permission::Write).await {
+        return Ok(repo_error(e));
+    }
+
+    let violations = context.push_policy.check(&push);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "allowed": violations.is_empty(),
+            "violations": violations
+        })),
+        StatusCode::OK,
+    ))
+}
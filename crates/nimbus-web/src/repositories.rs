@@ -0,0 +1,261 @@
+//! Repository lifecycle routes: listing, creating and deleting
+//!
+//! Following the single-owner model, only the owner creates and deletes
+//! repositories. Listing and fetching apply the usual visibility rules, so
+//! private repositories stay invisible to anyone who can't see them.
+//!
+//! `POST /api/repos` honours an `Idempotency-Key` header: retrying a create
+//! with the same key within a day answers with the repository the first
+//! request made, rather than failing on the now taken name.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthService, Role};
+use nimbus_events::{EventMetadata, EventPriority};
+use nimbus_git::store::{NewRepository, RepositoryStore};
+use nimbus_types::access::{Actor, list_visible_repositories};
+use nimbus_types::events::{Event, EventBus, EventEnvelope};
+use nimbus_types::{NimbusError, Permission};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::cache::{CacheConfig, LruCache};
+use crate::errors::{ErrorCode, api_error};
+use crate::{AuthVia, AuthenticatedActor, access, json_error, with_caller, with_scope};
+
+/// Everything the repository lifecycle routes need
+#[derive(Clone)]
+pub struct RepositoriesContext {
+    pub store: Arc<dyn RepositoryStore>,
+    pub auth_service: Arc<AuthService>,
+    pub event_bus: Arc<dyn EventBus>,
+    /// Repositories created per `Idempotency-Key`, see [`idempotency_keys`]
+    pub idempotency_keys: Arc<LruCache<String, CreatedRepository>>,
+}
+
+/// What an `Idempotency-Key` was used to create
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedRepository {
+    pub name: String,
+    pub id: Uuid,
+}
+
+/// Idempotency keys remembered for a day, at most 1000 of them
+pub fn idempotency_keys() -> Arc<LruCache<String, CreatedRepository>> {
+    let config = CacheConfig { capacity: 1000, ttl: Duration::from_secs(86400) };
+    Arc::new(LruCache::new("idempotency", config))
+}
+
+/// Repositories listed when no `limit` is given
+const DEFAULT_LIST_LIMIT: usize = 50;
+/// Most repositories listed per request
+const MAX_LIST_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    list_route(context.clone())
+        .or(create_route(context.clone()))
+        .or(get_route(context.clone()))
+        .or(delete_route(context))
+}
+
+fn with_context(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = (RepositoriesContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
+fn list_route(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos")
+        .and(warp::get())
+        .and(with_caller(context.auth_service.clone()))
+        .and(warp::query::<ListQuery>())
+        .and(with_context(context))
+        .and_then(handle_list)
+}
+
+fn create_route(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos")
+        .and(warp::post())
+        .and(with_scope(context.auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::json())
+        .and(with_context(context))
+        .and_then(handle_create)
+}
+
+fn get_route(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String)
+        .and(warp::get())
+        .and(with_caller(context.auth_service.clone()))
+        .and(with_context(context))
+        .and_then(handle_get)
+}
+
+fn delete_route(
+    context: RepositoriesContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "repos" / String)
+        .and(warp::delete())
+        .and(with_scope(context.auth_service.clone(), Role::Owner, ApiScope::Admin))
+        .and(with_context(context))
+        .and_then(handle_delete)
+}
+
+/// Run `operation` on the store off the async runtime
+async fn with_store<T, F>(context: &RepositoriesContext, operation: F) -> Result<T, NimbusError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn RepositoryStore) -> Result<T, NimbusError> + Send + 'static,
+{
+    let store = context.store.clone();
+    tokio::task::spawn_blocking(move || operation(store.as_ref()))
+        .await
+        .map_err(|e| NimbusError::Internal(format!("Repository store task failed: {}", e)))?
+}
+
+fn store_error(e: NimbusError) -> Reply {
+    match e {
+        NimbusError::RepositoryNotFound(_)
+        | NimbusError::Forbidden(_)
+        | NimbusError::InvalidGitOperation(_) => api_error(ErrorCode::from(&e), &e.to_string()),
+        other => {
+            warn!("Repository store failed: {}", other);
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "Repository store failed")
+        }
+    }
+}
+
+async fn publish(context: &RepositoriesContext, event: Event) {
+    let envelope = EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event,
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: true,
+            simulated: false,
+        },
+    };
+    if let Err(e) = context.event_bus.publish(envelope).await {
+        warn!("Failed to publish repository event: {}", e);
+    }
+}
+
+async fn handle_list(
+    caller: Option<AuthenticatedActor>,
+    query: ListQuery,
+    context: RepositoriesContext,
+) -> Result<Reply, warp::Rejection> {
+    let actor = caller.as_ref().map_or(Actor::Anonymous, AuthenticatedActor::actor);
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+
+    Ok(match with_store(&context, |store| store.list()).await {
+        Ok(mut repositories) => {
+            // API tokens only see what they are scoped for, as in `authorize_actor`
+            if let Some(AuthVia::ApiToken(identity)) = caller.map(|caller| caller.via) {
+                repositories.retain(|repo| identity.permission_on(repo).is_some());
+            }
+            warp::reply::with_status(
+                warp::reply::json(&list_visible_repositories(&actor, &repositories, offset, limit)),
+                StatusCode::OK,
+            )
+        }
+        Err(e) => store_error(e),
+    })
+}
+
+async fn handle_create(
+    owner: AuthenticatedActor,
+    idempotency_key: Option<String>,
+    new: NewRepository,
+    context: RepositoriesContext,
+) -> Result<Reply, warp::Rejection> {
+    if let Some(created) =
+        idempotency_key.as_ref().and_then(|key| context.idempotency_keys.get(key))
+    {
+        if created.name != new.name {
+            let message = "Idempotency-Key was already used to create another repository";
+            return Ok(api_error(ErrorCode::Conflict, message));
+        }
+        // Replay the first response, unless the repository has since gone
+        let name = created.name.clone();
+        match with_store(&context, move |store| store.get(&name)).await {
+            Ok(repository) if repository.id == created.id => {
+                debug!("Replaying creation of {} for a retried request", repository.name);
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&repository),
+                    StatusCode::CREATED,
+                ));
+            }
+            Ok(_) | Err(NimbusError::RepositoryNotFound(_)) => {}
+            Err(e) => return Ok(store_error(e)),
+        }
+    }
+
+    let repository = match with_store(&context, move |store| store.create(new)).await {
+        Ok(repository) => repository,
+        Err(e) => return Ok(store_error(e)),
+    };
+    info!("{} created repository {}", owner.name, repository.name);
+    if let Some(key) = idempotency_key {
+        let created = CreatedRepository { name: repository.name.clone(), id: repository.id };
+        context.idempotency_keys.insert(key, created);
+    }
+
+    publish(&context, Event::RepositoryCreated { repository: repository.clone() }).await;
+    Ok(warp::reply::with_status(warp::reply::json(&repository), StatusCode::CREATED))
+}
+
+async fn handle_get(
+    name: String,
+    caller: Option<AuthenticatedActor>,
+    context: RepositoriesContext,
+) -> Result<Reply, warp::Rejection> {
+    let access = access::authorize_actor(&context.store, caller.as_ref(), &name, Permission::Read);
+    Ok(match access.await {
+        Ok(repository) => warp::reply::with_status(warp::reply::json(&repository), StatusCode::OK),
+        Err(e) => store_error(e),
+    })
+}
+
+async fn handle_delete(
+    name: String,
+    owner: AuthenticatedActor,
+    context: RepositoriesContext,
+) -> Result<Reply, warp::Rejection> {
+    let repository = match with_store(&context, move |store| store.delete(&name)).await {
+        Ok(repository) => repository,
+        Err(e) => return Ok(store_error(e)),
+    };
+    info!("{} deleted repository {}", owner.name, repository.name);
+
+    publish(&context, Event::RepositoryDeleted { repository: repository.name.clone() }).await;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "success": true, "repository": repository })),
+        StatusCode::OK,
+    ))
+}
@@ -0,0 +1,209 @@
+//! HTTP server with connection limits and read timeouts
+//!
+//! `warp::serve` accepts unlimited connections and waits forever for slow
+//! clients. This accept loop caps concurrent connections (answering 503 past
+//! the cap), bounds how long a client may take to send its headers, and how
+//! long a request body may stall between chunks.
+//!
+//! [`serve_with_shutdown`] also stops cleanly: once its signal fires it stops
+//! accepting, lets open connections finish their current request, and
+//! returns when they have closed or `shutdown_timeout` has passed.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::service::{Service, service_fn};
+use hyper::{Body, Request};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Semaphore, watch};
+use tracing::{debug, info, warn};
+use warp::{Filter, Rejection, Reply};
+
+/// Sent to connections beyond the cap before closing them
+const SERVICE_UNAVAILABLE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\n\
+    content-type: application/json\r\n\
+    content-length: 53\r\n\
+    connection: close\r\n\r\n\
+    {\"success\":false,\"error\":\"Too many open connections\"}";
+
+/// Peer address of the connection a request arrived on
+///
+/// Added to every request's extensions; read it with
+/// `warp::ext::optional::<ClientAddr>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// Limits applied to every connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerLimits {
+    /// Connections served at once; further ones get a 503
+    pub max_connections: usize,
+    /// Time a client has to send its complete request headers
+    pub header_read_timeout: Duration,
+    /// Longest a request body may stall between chunks
+    pub body_read_timeout: Duration,
+    /// How long shutdown waits for open connections before dropping them
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            header_read_timeout: Duration::from_secs(10),
+            body_read_timeout: Duration::from_secs(30),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ServerLimits {
+    /// Limits from `NIMBUS_MAX_CONNECTIONS`, `NIMBUS_HEADER_TIMEOUT_SECS`,
+    /// `NIMBUS_BODY_TIMEOUT_SECS` and `NIMBUS_SHUTDOWN_TIMEOUT_SECS`, keeping
+    /// the defaults for unset values
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            let value = std::env::var(name).ok()?;
+            let parsed = value.parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring invalid {}={}", name, value);
+            }
+            parsed
+        }
+
+        let defaults = Self::default();
+        Self {
+            max_connections: var("NIMBUS_MAX_CONNECTIONS").unwrap_or(defaults.max_connections),
+            header_read_timeout: var("NIMBUS_HEADER_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.header_read_timeout),
+            body_read_timeout: var("NIMBUS_BODY_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.body_read_timeout),
+            shutdown_timeout: var("NIMBUS_SHUTDOWN_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.shutdown_timeout),
+        }
+    }
+}
+
+/// Serve `routes` on `listener`, enforcing `limits`; runs until the task is dropped
+pub async fn serve<F, R>(routes: F, listener: TcpListener, limits: ServerLimits)
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    serve_with_shutdown(routes, listener, limits, futures::future::pending()).await
+}
+
+/// Like [`serve`], but shuts down gracefully once `signal` completes
+pub async fn serve_with_shutdown<F, R>(
+    routes: F,
+    listener: TcpListener,
+    limits: ServerLimits,
+    signal: impl Future<Output = ()>,
+) where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let connections = Arc::new(Semaphore::new(limits.max_connections));
+    let warp_service = warp::service(routes);
+    let (stopping, stop) = watch::channel(false);
+
+    let mut http = Http::new();
+    http.http1_header_read_timeout(limits.header_read_timeout);
+
+    tokio::pin!(signal);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            () = &mut signal => break,
+        };
+        let (stream, addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Typically out of file descriptors; back off instead of spinning
+                warn!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let Ok(permit) = connections.clone().try_acquire_owned() else {
+            tokio::spawn(reject_connection(stream));
+            continue;
+        };
+
+        let warp_service = warp_service.clone();
+        let service = service_fn(move |request: Request<Body>| {
+            let mut warp_service = warp_service.clone();
+            let mut request = request.map(|body| with_body_timeout(body, limits.body_read_timeout));
+            request.extensions_mut().insert(ClientAddr(addr));
+            async move { warp_service.call(request).await }
+        });
+
+        let connection = http.serve_connection(stream, service);
+        let mut stop = stop.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = stop.changed() => {
+                    // Finish the request in progress, then close
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(e) = result {
+                debug!("Connection closed with error: {}", e);
+            }
+        });
+    }
+
+    drop(listener);
+    let open = limits.max_connections - connections.available_permits();
+    info!("Shutting down, waiting for {} open connections", open);
+    let _ = stopping.send(true);
+    let all = u32::try_from(limits.max_connections).unwrap_or(u32::MAX);
+    if tokio::time::timeout(limits.shutdown_timeout, connections.acquire_many(all)).await.is_err() {
+        warn!(
+            "Dropping {} connections still open after the shutdown timeout",
+            limits.max_connections - connections.available_permits()
+        );
+    }
+}
+
+/// Answer 503 and close, without waiting on the client
+async fn reject_connection(mut stream: TcpStream) {
+    debug!("Connection limit reached, rejecting connection");
+    let _ = tokio::time::timeout(Duration::from_secs(1), async {
+        stream.write_all(SERVICE_UNAVAILABLE).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+/// Fail the body if no chunk arrives within `timeout`
+fn with_body_timeout(body: Body, timeout: Duration) -> Body {
+    let chunks = futures::stream::unfold(Some(body), move |body| async move {
+        let mut body = body?;
+        match tokio::time::timeout(timeout, body.data()).await {
+            Ok(Some(chunk)) => Some((chunk.map_err(std::io::Error::other), Some(body))),
+            Ok(None) => None,
+            Err(_) => Some((
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "request body read timed out",
+                )),
+                None,
+            )),
+        }
+    });
+    Body::wrap_stream(chunks)
+}
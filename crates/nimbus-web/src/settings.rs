@@ -0,0 +1,157 @@
+//! Instance settings: `GET` and `PUT /api/settings`, owner only
+//!
+//! Settings are kept as JSON in the file named by `NIMBUS_SETTINGS`, or only
+//! in memory when it is unset. Until the first save they default to the
+//! environment. Saving doesn't reconfigure running services: tokens are still
+//! issued for the `NIMBUS_INSTANCE_DOMAIN` the server started with.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use nimbus_auth::api_tokens::ApiScope;
+use nimbus_auth::{AuthService, Role};
+use nimbus_types::InstanceSettings;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use warp::Filter;
+use warp::http::StatusCode;
+
+use crate::errors::{ErrorCode, api_error};
+use crate::{AuthenticatedActor, with_scope};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("Failed to save settings: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Current instance settings, saved to disk when a path is configured
+pub struct SettingsStore {
+    path: Option<PathBuf>,
+    current: Mutex<InstanceSettings>,
+}
+
+impl SettingsStore {
+    /// Settings kept only for the life of the process
+    pub fn in_memory(settings: InstanceSettings) -> Self {
+        Self { path: None, current: Mutex::new(settings) }
+    }
+
+    /// Settings saved in `path`, starting from `defaults` if it doesn't exist yet
+    pub async fn open(
+        path: impl Into<PathBuf>,
+        defaults: InstanceSettings,
+    ) -> Result<Self, SettingsError> {
+        let path = path.into();
+        let settings = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                SettingsError::Invalid(format!("Malformed settings in {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => defaults,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path: Some(path), current: Mutex::new(settings) })
+    }
+
+    /// Store configured by `NIMBUS_SETTINGS`, with defaults from the environment
+    pub async fn from_env() -> Result<Self, SettingsError> {
+        let defaults = default_settings();
+        match std::env::var("NIMBUS_SETTINGS") {
+            Ok(path) => Self::open(path, defaults).await,
+            Err(_) => Ok(Self::in_memory(defaults)),
+        }
+    }
+
+    pub async fn get(&self) -> InstanceSettings {
+        self.current.lock().await.clone()
+    }
+
+    /// Validate and save `settings`, replacing the current ones
+    ///
+    /// Nothing changes if saving fails.
+    pub async fn update(&self, settings: InstanceSettings) -> Result<(), SettingsError> {
+        settings.validate().map_err(SettingsError::Invalid)?;
+        let mut current = self.current.lock().await;
+        if let Some(path) = &self.path {
+            let json = serde_json::to_vec_pretty(&settings)
+                .map_err(|e| SettingsError::Invalid(e.to_string()))?;
+            // Write then rename, so a crash can't leave a truncated file
+            let temp = path.with_extension("tmp");
+            tokio::fs::write(&temp, json).await?;
+            tokio::fs::rename(&temp, path).await?;
+        }
+        *current = settings;
+        Ok(())
+    }
+}
+
+/// Settings before the owner has saved any
+fn default_settings() -> InstanceSettings {
+    let instance_domain =
+        std::env::var("NIMBUS_INSTANCE_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
+    InstanceSettings {
+        instance_name: "Nimbus".to_string(),
+        owner_email: format!("owner@{}", instance_domain),
+        instance_domain,
+    }
+}
+
+/// Everything the settings routes need
+#[derive(Clone)]
+pub struct SettingsContext {
+    pub auth_service: Arc<AuthService>,
+    pub store: Arc<SettingsStore>,
+}
+
+type Reply = warp::reply::WithStatus<warp::reply::Json>;
+
+pub fn routes(
+    context: SettingsContext,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let with_owner = with_scope(context.auth_service.clone(), Role::Owner, ApiScope::Admin);
+    let with_context = warp::any().map(move || context.clone());
+
+    let get = warp::path!("api" / "settings")
+        .and(warp::get())
+        .and(with_owner.clone())
+        .and(with_context.clone())
+        .and_then(handle_get);
+    let put = warp::path!("api" / "settings")
+        .and(warp::put())
+        .and(with_owner)
+        .and(warp::body::json())
+        .and(with_context)
+        .and_then(handle_put);
+
+    get.or(put)
+}
+
+async fn handle_get(
+    _owner: AuthenticatedActor,
+    context: SettingsContext,
+) -> Result<Reply, warp::Rejection> {
+    let settings = context.store.get().await;
+    Ok(warp::reply::with_status(warp::reply::json(&settings), StatusCode::OK))
+}
+
+async fn handle_put(
+    owner: AuthenticatedActor,
+    settings: InstanceSettings,
+    context: SettingsContext,
+) -> Result<Reply, warp::Rejection> {
+    Ok(match context.store.update(settings.clone()).await {
+        Ok(()) => {
+            info!("{} updated the instance settings", owner.name);
+            warp::reply::with_status(warp::reply::json(&settings), StatusCode::OK)
+        }
+        Err(SettingsError::Invalid(message)) => api_error(ErrorCode::BadRequest, &message),
+        Err(e) => {
+            warn!("{}", e);
+            api_error(ErrorCode::Internal, "Failed to save settings")
+        }
+    })
+}
@@ -0,0 +1,197 @@
+//! Webhook delivery for Nimbus events
+//!
+//! A [`WebhookHandler`] is an ordinary event bus subscriber that POSTs each
+//! envelope matching its filter to an external URL. The body is the
+//! envelope as canonical JSON (see [`nimbus_events::signing`]) and carries
+//! an `X-Nimbus-Signature: sha256=<hex>` header, the HMAC-SHA256 of the body
+//! under the webhook's secret, so receivers can check it came from us:
+//!
+//! ```
+//! use nimbus_events::signing;
+//!
+//! fn is_authentic(secret: &[u8], body: &[u8], signature_header: &str) -> bool {
+//!     signing::verify(secret, body, signature_header)
+//! }
+//! ```
+//!
+//! Deliveries that fail with a 5xx status or never get a response are
+//! retried with exponential backoff; other failures are not, since sending
+//! the same request again won't change the answer. Retries happen inside
+//! the handler, on top of any the bus itself does for failed handlers.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use nimbus_events::RetryPolicy;
+use nimbus_events::signing;
+use nimbus_types::events::{EventEnvelope, EventFilter, EventHandler};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+/// Header carrying the HMAC-SHA256 signature of the body
+pub const SIGNATURE_HEADER: &str = "X-Nimbus-Signature";
+/// Header carrying the envelope id, for receivers deduplicating deliveries
+pub const DELIVERY_HEADER: &str = "X-Nimbus-Delivery";
+
+/// One webhook, as configured by the owner
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    /// Identifies the webhook in logs and in the subscription list
+    pub name: String,
+    pub url: String,
+    /// Key the body is signed with; shared with the receiver
+    pub secret: String,
+    /// Which events are delivered; all of them by default
+    #[serde(default)]
+    pub filter: EventFilter,
+}
+
+impl WebhookConfig {
+    /// Webhooks from `NIMBUS_WEBHOOKS`, a JSON array of configs; none if unset
+    pub fn from_env() -> Result<Vec<Self>, serde_json::Error> {
+        match std::env::var("NIMBUS_WEBHOOKS") {
+            Ok(json) => serde_json::from_str(&json),
+            Err(_) => Ok(vec![]),
+        }
+    }
+}
+
+/// Why a delivery failed
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Failed to serialize event: {0}")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Webhook request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Webhook responded with {0}")]
+    Status(reqwest::StatusCode),
+}
+
+impl WebhookError {
+    /// Whether sending the same delivery again might succeed
+    fn is_retryable(&self) -> bool {
+        match self {
+            WebhookError::Serialize(_) => false,
+            WebhookError::Request(e) => !e.is_builder(),
+            WebhookError::Status(status) => status.is_server_error(),
+        }
+    }
+}
+
+/// Event handler delivering envelopes to a webhook
+pub struct WebhookHandler {
+    config: WebhookConfig,
+    client: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl WebhookHandler {
+    /// Requests time out after 10 seconds each
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            config,
+            client: Self::client(Duration::from_secs(10)),
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_secs(1),
+                multiplier: 2.0,
+            },
+        }
+    }
+
+    /// Retry failed deliveries by `policy` instead of three attempts one and
+    /// two seconds apart
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Give up on a request after `timeout` instead of 10 seconds
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = Self::client(timeout);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn client(timeout: Duration) -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(concat!("nimbus-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("static client configuration is valid")
+    }
+
+    /// POST `body` once
+    async fn send(
+        &self,
+        envelope: &EventEnvelope,
+        body: &[u8],
+        signature: &str,
+    ) -> Result<(), WebhookError> {
+        let response = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(DELIVERY_HEADER, envelope.id.to_string())
+            .body(body.to_vec())
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(WebhookError::Status(response.status()))
+        }
+    }
+
+    /// Deliver `envelope`, retrying transient failures
+    pub async fn deliver(&self, envelope: &EventEnvelope) -> Result<(), WebhookError> {
+        let (body, signature) = signing::sign_json(self.config.secret.as_bytes(), envelope)?;
+
+        let mut attempt = 1;
+        loop {
+            match self.send(envelope, &body, &signature).await {
+                Ok(()) => {
+                    debug!("Delivered event {} to webhook {}", envelope.id, self.config.name);
+                    return Ok(());
+                }
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.delay_after(attempt);
+                    warn!(
+                        "Webhook {} failed on event {} (attempt {}): {}; retrying in {:?}",
+                        self.config.name, envelope.id, attempt, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    info!(
+                        "Giving up on event {} for webhook {} after {} attempt(s)",
+                        envelope.id, self.config.name, attempt
+                    );
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for WebhookHandler {
+    async fn handle(&self, event: EventEnvelope) -> Result<(), Box<dyn std::error::Error>> {
+        self.deliver(&event).await?;
+        Ok(())
+    }
+
+    fn filter(&self) -> EventFilter {
+        self.config.filter.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests;
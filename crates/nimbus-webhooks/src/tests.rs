@@ -0,0 +1,130 @@
+//! Tests for webhook delivery against a local mock receiver
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use nimbus_events::{EventMetadata, EventPriority, InMemoryEventBus, RetryPolicy, signing};
+use nimbus_types::events::{Event, EventBus, EventEnvelope, EventFilter};
+use uuid::Uuid;
+use warp::Filter;
+use warp::http::StatusCode;
+
+use super::*;
+
+const SECRET: &str = "webhook-secret";
+
+/// A delivery as the receiver saw it: signature header and raw body
+type Received = Arc<Mutex<Vec<(String, bytes::Bytes)>>>;
+
+/// Start a receiver answering 503 to the first `failures` requests and 200
+/// after that, recording every request
+fn mock_receiver(failures: usize) -> (SocketAddr, Received, Arc<AtomicUsize>) {
+    let received: Received = Default::default();
+    let attempts = Arc::new(AtomicUsize::new(0));
+
+    let routes = warp::post()
+        .and(warp::path("hook"))
+        .and(warp::header::<String>("x-nimbus-signature"))
+        .and(warp::body::bytes())
+        .map({
+            let received = received.clone();
+            let attempts = attempts.clone();
+            move |signature: String, body: bytes::Bytes| {
+                received.lock().unwrap().push((signature, body));
+                if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::OK
+                }
+            }
+        });
+    let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (addr, received, attempts)
+}
+
+fn handler(addr: SocketAddr, filter: EventFilter) -> WebhookHandler {
+    WebhookHandler::new(WebhookConfig {
+        name: "test-hook".to_string(),
+        url: format!("http://{}/hook", addr),
+        secret: SECRET.to_string(),
+        filter,
+    })
+    .with_retry_policy(RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        multiplier: 2.0,
+    })
+}
+
+fn push_envelope(repository: &str) -> EventEnvelope {
+    EventEnvelope {
+        id: Uuid::new_v4(),
+        timestamp: time::OffsetDateTime::now_utc(),
+        seq: 0,
+        event: Event::Push {
+            repository: repository.to_string(),
+            branch: "main".to_string(),
+            commits: vec![],
+            pusher: "user".to_string(),
+        },
+        metadata: EventMetadata {
+            target_plugins: vec![],
+            priority: EventPriority::Normal,
+            persistent: false,
+            simulated: false,
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_delivery_is_signed_and_retried_on_server_errors() {
+    let (addr, received, attempts) = mock_receiver(1);
+    let envelope = push_envelope("project");
+
+    handler(addr, EventFilter::default()).deliver(&envelope).await.unwrap();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    let received = received.lock().unwrap();
+    let (signature, body) = received.last().unwrap();
+    assert!(signing::verify(SECRET.as_bytes(), body, signature));
+    assert!(!signing::verify(b"wrong-secret", body, signature));
+
+    let delivered: EventEnvelope = serde_json::from_slice(body).unwrap();
+    assert_eq!(delivered.id, envelope.id);
+    assert_eq!(serde_json::to_value(&delivered).unwrap(), serde_json::to_value(&envelope).unwrap());
+}
+
+#[tokio::test]
+async fn test_delivery_gives_up_after_max_attempts() {
+    let (addr, _received, attempts) = mock_receiver(usize::MAX);
+
+    let error =
+        handler(addr, EventFilter::default()).deliver(&push_envelope("project")).await.unwrap_err();
+
+    assert!(matches!(error, WebhookError::Status(status) if status.as_u16() == 503));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_webhook_scoped_to_one_repository() {
+    let (addr, received, _attempts) = mock_receiver(0);
+    let bus = Arc::new(InMemoryEventBus::new(100));
+    let _processor = bus.clone().start();
+
+    let filter = EventFilter { repositories: vec!["project".to_string()], ..Default::default() };
+    bus.subscribe("test-hook".to_string(), Box::new(handler(addr, filter))).await.unwrap();
+
+    bus.publish(push_envelope("other")).await.unwrap();
+    bus.publish(push_envelope("project")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    let delivered: EventEnvelope = serde_json::from_slice(&received[0].1).unwrap();
+    assert!(
+        matches!(delivered.event, Event::Push { ref repository, .. } if repository == "project")
+    );
+}